@@ -0,0 +1,1191 @@
+use super::super::schema::{Alteration, Catalog, Index, Indexes, Sequence, Table, Tables};
+use super::super::types::{Expression, Row, Value};
+use super::{IndexScan, Limits, Mode, Scan, Transaction as _};
+use crate::error::{Error, Result};
+use crate::storage::kv::{self, encoding};
+
+use serde_derive::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+/// A SQL engine based on an underlying MVCC key/value store
+#[derive(Clone)]
+pub struct KV {
+    /// The underlying key/value store
+    pub(super) kv: kv::MVCC,
+    /// The size limits of written values
+    limits: Limits,
+}
+
+impl KV {
+    /// Creates a new key/value-based SQL engine, with the default limits
+    pub fn new(kv: kv::MVCC) -> Self {
+        Self { kv, limits: Limits::default() }
+    }
+
+    /// Sets the size limits of written values
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+impl super::Engine for KV {
+    type Transaction = Transaction;
+
+    fn begin_with_mode(&self, mode: Mode) -> Result<Self::Transaction> {
+        Ok(Self::Transaction::new(self.kv.begin_with_mode(mode)?, self.limits))
+    }
+
+    fn resume(&self, id: u64) -> Result<Self::Transaction> {
+        Ok(Self::Transaction::new(self.kv.resume(id)?, self.limits))
+    }
+}
+
+/// Serializes SQL metadata
+fn serialize<V: serde::Serialize>(value: &V) -> Result<Vec<u8>> {
+    Ok(bincode::serialize(value)?)
+}
+
+/// Deserializes SQL metadata
+fn deserialize<'a, V: serde::Deserialize<'a>>(bytes: &'a [u8]) -> Result<V> {
+    Ok(bincode::deserialize(bytes)?)
+}
+
+/// Strings and binary values larger than this many bytes are stored out of line under their own
+/// key, rather than in the row. The MVCC store keeps a new version of a row for every write, so
+/// this avoids copying large values which an update didn't change.
+const OVERFLOW_SIZE: usize = 2048;
+
+/// A stored row. Values stored out of line under Key::Overflow are empty in the row, and their
+/// column indexes are listed in overflow.
+#[derive(Serialize, Deserialize)]
+struct StoredRow<'a> {
+    values: Cow<'a, [Value]>,
+    overflow: Vec<usize>,
+}
+
+impl StoredRow<'_> {
+    /// Returns true if a column value is stored out of line. Primary key values are always
+    /// stored in the row, since they are needed to find the out-of-line values.
+    fn overflows(table: &Table, column: usize, value: &Value) -> bool {
+        match value {
+            Value::String(s) if s.len() > OVERFLOW_SIZE => !table.columns[column].primary_key,
+            Value::Binary(b) if b.len() > OVERFLOW_SIZE => !table.columns[column].primary_key,
+            _ => false,
+        }
+    }
+
+    /// Returns the row, loading the out-of-line values of the given column indexes
+    fn load(self, mut load: impl FnMut(usize) -> Result<Option<Vec<u8>>>) -> Result<Row> {
+        let mut row = self.values.into_owned();
+        for i in self.overflow {
+            let bytes = load(i)?.ok_or_else(|| {
+                Error::Internal(format!("Out-of-line value for column {} not found", i))
+            })?;
+            row[i] = match row[i] {
+                Value::Binary(_) => Value::Binary(bytes),
+                _ => Value::String(String::from_utf8(bytes)?),
+            };
+        }
+        Ok(row)
+    }
+}
+
+/// An SQL transaction based on an MVCC key/value transaction
+pub struct Transaction {
+    txn: kv::Transaction,
+    /// The values most recently fetched by nextval() in this transaction, for currval()
+    sequences: HashMap<String, i64>,
+    /// The size limits of written values
+    limits: Limits,
+}
+
+impl Transaction {
+    /// Creates a new SQL transaction from an MVCC transaction
+    fn new(txn: kv::Transaction, limits: Limits) -> Self {
+        Self { txn, sequences: HashMap::new(), limits }
+    }
+
+    /// Writes a row, storing large values out of line. When replacing a row, out-of-line values
+    /// which haven't changed are kept as is, and ones which are no longer needed are removed.
+    fn row_write(
+        &mut self,
+        table: &Table,
+        id: &[Value],
+        row: &Row,
+        old: Option<&Row>,
+    ) -> Result<()> {
+        let mut stored = StoredRow { values: Cow::Borrowed(row), overflow: Vec::new() };
+        for (i, value) in row.iter().enumerate() {
+            let previous = old.and_then(|old| old.get(i));
+            let key = || Key::Overflow((&table.name).into(), Some((i, id.into()))).encode();
+            if StoredRow::overflows(table, i, value) {
+                let (bytes, empty) = match value {
+                    Value::String(s) => (s.as_bytes(), Value::String(String::new())),
+                    Value::Binary(b) => (b.as_slice(), Value::Binary(Vec::new())),
+                    value => {
+                        return Err(Error::Internal(format!("Can't store {} out of line", value)))
+                    }
+                };
+                if previous != Some(value) {
+                    self.txn.set(&key(), bytes.to_vec())?;
+                }
+                stored.values.to_mut()[i] = empty;
+                stored.overflow.push(i);
+            } else if previous.is_some_and(|v| StoredRow::overflows(table, i, v)) {
+                self.txn.delete(&key())?;
+            }
+        }
+        self.txn.set(&Key::Row((&table.name).into(), Some(id.into())).encode(), serialize(&stored)?)
+    }
+
+    /// Deletes a row, along with its out-of-line values
+    fn row_delete(&mut self, table: &Table, id: &[Value], row: &Row) -> Result<()> {
+        for (i, value) in row.iter().enumerate() {
+            if StoredRow::overflows(table, i, value) {
+                self.txn
+                    .delete(&Key::Overflow((&table.name).into(), Some((i, id.into()))).encode())?;
+            }
+        }
+        self.txn.delete(&Key::Row((&table.name).into(), Some(id.into())).encode())
+    }
+
+    /// Loads an index entry, as the primary keys of the rows with the given index key
+    fn index_load(
+        &self,
+        table: &str,
+        columns: &[String],
+        values: &[Value],
+    ) -> Result<HashSet<Vec<Value>>> {
+        Ok(self
+            .txn
+            .get(&Key::Index(table.into(), columns.into(), Some(values.into())).encode())?
+            .map(|v| deserialize(&v))
+            .transpose()?
+            .unwrap_or_default())
+    }
+
+    /// Saves an index entry, deleting it if there are no primary keys left
+    fn index_save(
+        &mut self,
+        table: &str,
+        columns: &[String],
+        values: &[Value],
+        pks: HashSet<Vec<Value>>,
+    ) -> Result<()> {
+        let key = Key::Index(table.into(), columns.into(), Some(values.into())).encode();
+        if pks.is_empty() {
+            self.txn.delete(&key)
+        } else {
+            self.txn.set(&key, serialize(&pks)?)
+        }
+    }
+
+    /// Adds a row to an index
+    fn index_insert(
+        &mut self,
+        table: &Table,
+        index: &Index,
+        id: &[Value],
+        row: &[Value],
+    ) -> Result<()> {
+        let key = index.get_row_key(table, row)?;
+        let mut pks = self.index_load(&table.name, &index.columns, &key)?;
+        pks.insert(id.to_vec());
+        self.index_save(&table.name, &index.columns, &key, pks)
+    }
+
+    /// Removes a row from an index
+    fn index_remove(
+        &mut self,
+        table: &Table,
+        index: &Index,
+        id: &[Value],
+        row: &[Value],
+    ) -> Result<()> {
+        let key = index.get_row_key(table, row)?;
+        let mut pks = self.index_load(&table.name, &index.columns, &key)?;
+        pks.remove(id);
+        self.index_save(&table.name, &index.columns, &key, pks)
+    }
+
+    /// Deletes all entries of an index
+    fn index_clear(&mut self, table: &str, columns: &[String]) -> Result<()> {
+        let keys = self
+            .txn
+            .scan_prefix(&Key::Index(table.into(), columns.into(), None).encode())?
+            .map(|r| r.map(|(k, _)| k))
+            .collect::<Result<Vec<_>>>()?;
+        for key in keys {
+            self.txn.delete(&key)?;
+        }
+        Ok(())
+    }
+
+    /// Builds an index from the table's existing rows. Errors if the index is unique and the
+    /// rows contain duplicate keys, ignoring keys containing NULL.
+    fn index_build(&mut self, table: &Table, index: &Index) -> Result<()> {
+        let mut entries: HashMap<Vec<Value>, HashSet<Vec<Value>>> = HashMap::new();
+        for row in self.scan(&table.name, None)? {
+            let row = row?;
+            let key = index.get_row_key(table, &row)?;
+            let pks = entries.entry(key).or_default();
+            pks.insert(table.get_row_key(&row)?);
+        }
+        for (key, pks) in entries {
+            if index.unique && pks.len() > 1 && !key.contains(&Value::Null) {
+                return Err(Error::Value(format!(
+                    "Unique value ({}) already exists for index {}",
+                    key.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "),
+                    index.name
+                )));
+            }
+            self.index_save(&table.name, &index.columns, &key, pks)?;
+        }
+        Ok(())
+    }
+}
+
+impl super::Transaction for Transaction {
+    fn id(&self) -> u64 {
+        self.txn.id()
+    }
+
+    fn mode(&self) -> Mode {
+        self.txn.mode()
+    }
+
+    fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    fn commit(self) -> Result<()> {
+        self.txn.commit()
+    }
+
+    fn rollback(self) -> Result<()> {
+        self.txn.rollback()
+    }
+
+    fn create(&mut self, table: &str, row: Row) -> Result<()> {
+        let table = self.must_read_table(table)?;
+        let row = table.coerce_row(row)?;
+        table.validate_row(&row, self)?;
+        let id = table.get_row_key(&row)?;
+        if self.read(&table.name, &id)?.is_some() {
+            return Err(Error::Value(format!(
+                "Primary key ({}) already exists for table {}",
+                id.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "),
+                table.name
+            )));
+        }
+        self.row_write(&table, &id, &row, None)?;
+        for index in self.table_indexes(&table)? {
+            self.index_insert(&table, &index, &id, &row)?;
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, table: &str, id: &[Value]) -> Result<()> {
+        let table = self.must_read_table(table)?;
+        let row = self.read(&table.name, id)?.ok_or_else(|| {
+            Error::Value(format!(
+                "Primary key ({}) not found in table {}",
+                id.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "),
+                table.name
+            ))
+        })?;
+        for index in self.table_indexes(&table)? {
+            self.index_remove(&table, &index, id, &row)?;
+        }
+        self.row_delete(&table, id, &row)
+    }
+
+    fn read(&self, table: &str, id: &[Value]) -> Result<Option<Row>> {
+        let Some(bytes) = self.txn.get(&Key::Row(table.into(), Some(id.into())).encode())? else {
+            return Ok(None);
+        };
+        let stored: StoredRow = deserialize(&bytes)?;
+        let row = stored
+            .load(|i| self.txn.get(&Key::Overflow(table.into(), Some((i, id.into()))).encode()))?;
+        Ok(Some(row))
+    }
+
+    fn read_index(
+        &self,
+        table: &str,
+        columns: &[String],
+        values: &[Value],
+    ) -> Result<HashSet<Vec<Value>>> {
+        self.index_load(table, columns, values)
+    }
+
+    fn scan(&self, table: &str, filter: Option<Expression>) -> Result<Scan> {
+        let table = self.must_read_table(table)?;
+        // Out-of-line values are loaded as each row is scanned, through a reader since the scan
+        // can't borrow the transaction. A row's values only change when the row itself is
+        // written, and executors collect rows before writing them.
+        let reader = self.txn.reader();
+        Ok(Box::new(
+            self.txn
+                .scan_prefix(&Key::Row((&table.name).into(), None).encode())?
+                .map(move |r| {
+                    let stored: StoredRow = deserialize(&r?.1)?;
+                    if stored.overflow.is_empty() {
+                        return Ok(stored.values.into_owned());
+                    }
+                    let id = table.get_row_key(&stored.values)?;
+                    let key = |i| Key::Overflow((&table.name).into(), Some((i, (&id).into())));
+                    stored.load(|i| reader.get(&key(i).encode()))
+                })
+                .filter_map(move |r| match r {
+                    Ok(row) => match &filter {
+                        Some(filter) => match filter.evaluate(Some(&row)) {
+                            Ok(Value::Boolean(b)) if b => Some(Ok(row)),
+                            Ok(Value::Boolean(_)) | Ok(Value::Null) => None,
+                            Ok(v) => Some(Err(Error::Value(format!(
+                                "Filter returned {}, expected boolean",
+                                v
+                            )))),
+                            Err(err) => Some(Err(err)),
+                        },
+                        None => Some(Ok(row)),
+                    },
+                    err => Some(err),
+                }),
+        ))
+    }
+
+    fn scan_index(&self, table: &str, columns: &[String]) -> Result<IndexScan> {
+        let table = self.must_read_table(table)?;
+        Ok(Box::new(
+            self.txn
+                .scan_prefix(&Key::Index((&table.name).into(), columns.into(), None).encode())?
+                .map(|r| -> Result<_> {
+                    let (k, v) = r?;
+                    let values = match Key::decode(&k)? {
+                        Key::Index(_, _, Some(values)) => values.into_owned(),
+                        key => return Err(Error::Internal(format!("Invalid index key {:?}", key))),
+                    };
+                    Ok((values, deserialize(&v)?))
+                }),
+        ))
+    }
+
+    fn update(&mut self, table: &str, id: &[Value], row: Row) -> Result<()> {
+        let table = self.must_read_table(table)?;
+        let row = table.coerce_row(row)?;
+        // If the primary key changes we do a delete and create, otherwise we replace the row
+        if id != table.get_row_key(&row)? {
+            self.delete(&table.name, id)?;
+            self.create(&table.name, row)?;
+            return Ok(());
+        }
+        let old = self.read(&table.name, id)?.ok_or_else(|| {
+            Error::Value(format!(
+                "Primary key ({}) not found in table {}",
+                id.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "),
+                table.name
+            ))
+        })?;
+        table.validate_row(&row, self)?;
+        for index in self.table_indexes(&table)? {
+            if index.get_row_key(&table, &old)? != index.get_row_key(&table, &row)? {
+                self.index_remove(&table, &index, id, &old)?;
+                self.index_insert(&table, &index, id, &row)?;
+            }
+        }
+        self.row_write(&table, id, &row, Some(&old))
+    }
+
+    fn nextval(&mut self, sequence: &str) -> Result<i64> {
+        let sequence = self.must_read_sequence(sequence)?;
+        let key = Key::SequenceValue((&sequence.name).into()).encode();
+        let previous = self.txn.get(&key)?.map(|v| deserialize(&v)).transpose()?;
+        let value = sequence.next(previous)?;
+        self.txn.set(&key, serialize(&value)?)?;
+        self.sequences.insert(sequence.name, value);
+        Ok(value)
+    }
+
+    fn currval(&self, sequence: &str) -> Result<i64> {
+        self.sequences.get(sequence).copied().ok_or_else(|| {
+            Error::Value(format!(
+                "currval of sequence {} is not yet defined in this transaction",
+                sequence
+            ))
+        })
+    }
+}
+
+impl Catalog for Transaction {
+    fn create_table(&mut self, table: Table) -> Result<()> {
+        if self.read_table(&table.name)?.is_some() {
+            return Err(Error::Value(format!("Table {} already exists", table.name)));
+        }
+        table.validate(self)?;
+        for sequence in table.get_column_sequences() {
+            self.create_sequence(sequence)?;
+        }
+        self.txn.set(&Key::Table(Some((&table.name).into())).encode(), serialize(&table)?)
+    }
+
+    fn detele_table(&mut self, table: &str) -> Result<()> {
+        let table = self.must_read_table(table)?;
+        if let Some((t, fks)) = self.table_references(&table.name, false)?.into_iter().next() {
+            return Err(Error::Value(format!(
+                "Table {} is referenced by table {} foreign key ({})",
+                table.name,
+                t,
+                fks[0].columns.join(", ")
+            )));
+        }
+        for index in self.table_indexes(&table)? {
+            self.index_clear(&table.name, &index.columns)?;
+        }
+        let indexes = self.scan_indexes()?.filter(|i| i.table == table.name).collect::<Vec<_>>();
+        for index in indexes {
+            self.txn.delete(&Key::IndexSchema(Some(index.name.into())).encode())?;
+        }
+        let mut keys = self
+            .txn
+            .scan_prefix(&Key::Row((&table.name).into(), None).encode())?
+            .map(|r| r.map(|(k, _)| k))
+            .collect::<Result<Vec<_>>>()?;
+        for r in self.txn.scan_prefix(&Key::Overflow((&table.name).into(), None).encode())? {
+            keys.push(r?.0);
+        }
+        for key in keys {
+            self.txn.delete(&key)?;
+        }
+        self.txn.delete(&Key::Table(Some(table.name.as_str().into())).encode())?;
+        for sequence in table.get_column_sequences() {
+            self.delete_sequence(&sequence.name)?;
+        }
+        Ok(())
+    }
+
+    fn read_table(&self, table: &str) -> Result<Option<Table>> {
+        self.txn.get(&Key::Table(Some(table.into())).encode())?.map(|v| deserialize(&v)).transpose()
+    }
+
+    fn scan_tables(&self) -> Result<Tables> {
+        Ok(Box::new(
+            self.txn
+                .scan_prefix(&Key::Table(None).encode())?
+                .map(|r| r.and_then(|(_, v)| deserialize(&v)))
+                .collect::<Result<Vec<_>>>()?
+                .into_iter(),
+        ))
+    }
+
+    fn alter_table(&mut self, table: &str, alteration: Alteration) -> Result<()> {
+        let old = self.must_read_table(table)?;
+        let mut new = old.alter(&alteration, self)?;
+
+        // Column sequences are named after their table and column, so they follow renames.
+        if matches!(alteration, Alteration::RenameColumn { .. } | Alteration::RenameTable(_)) {
+            for (column, renamed) in old.columns.iter().zip(new.columns.iter_mut()) {
+                let from = Sequence::column_sequence_name(&old.name, &column.name);
+                let to = Sequence::column_sequence_name(&new.name, &renamed.name);
+                if from == to || renamed.default != Some(Expression::NextVal(from.clone())) {
+                    continue;
+                }
+                let sequence = self.must_read_sequence(&from)?;
+                self.create_sequence(Sequence { name: to.clone(), ..sequence })?;
+                if let Some(value) = self.txn.get(&Key::SequenceValue((&from).into()).encode())? {
+                    self.txn.set(&Key::SequenceValue((&to).into()).encode(), value)?;
+                }
+                self.txn.delete(&Key::SequenceValue((&from).into()).encode())?;
+                self.txn.delete(&Key::Sequence(Some((&from).into())).encode())?;
+                if let Some(value) = self.sequences.remove(&from) {
+                    self.sequences.insert(to.clone(), value);
+                }
+                renamed.default = Some(Expression::NextVal(to));
+            }
+        }
+
+        // Remove the existing rows and index entries, to rewrite them for the new schema.
+        let rows = self.scan(&old.name, None)?.collect::<Result<Vec<_>>>()?;
+        for index in self.table_indexes(&old)? {
+            self.index_clear(&old.name, &index.columns)?;
+        }
+        for row in &rows {
+            self.row_delete(&old, &old.get_row_key(row)?, row)?;
+        }
+        let indexes = self.scan_indexes()?.filter(|i| i.table == old.name).collect::<Vec<_>>();
+        for index in indexes {
+            let index = alteration.transform_index(&old, index)?;
+            let key = Key::IndexSchema(Some((&index.name).into())).encode();
+            self.txn.set(&key, serialize(&index)?)?;
+        }
+
+        // Store the new schema, and point references from other tables at a renamed table.
+        if new.name != old.name {
+            self.txn.delete(&Key::Table(Some((&old.name).into())).encode())?;
+            for (name, _) in self.table_references(&old.name, false)? {
+                let mut referencing = self.must_read_table(&name)?;
+                referencing.rename_references(&old.name, &new.name);
+                self.txn
+                    .set(&Key::Table(Some((&name).into())).encode(), serialize(&referencing)?)?;
+            }
+        }
+        self.txn.set(&Key::Table(Some((&new.name).into())).encode(), serialize(&new)?)?;
+
+        // Rewrite the rows and rebuild the indexes, then validate the rows once all of them are
+        // in place, such that rows may reference each other and duplicates are detected.
+        let rows = rows
+            .into_iter()
+            .map(|row| alteration.transform_row(&old, row, self))
+            .collect::<Result<Vec<_>>>()?;
+        let indexes = self.table_indexes(&new)?;
+        for row in &rows {
+            let id = new.get_row_key(row)?;
+            self.row_write(&new, &id, row, None)?;
+            for index in &indexes {
+                self.index_insert(&new, index, &id, row)?;
+            }
+        }
+        for row in &rows {
+            new.validate_row(row, self)?;
+        }
+        Ok(())
+    }
+
+    fn create_index(&mut self, index: Index) -> Result<()> {
+        index.validate(self)?;
+        let table = self.must_read_table(&index.table)?;
+        self.index_build(&table, &index)?;
+        self.txn.set(&Key::IndexSchema(Some((&index.name).into())).encode(), serialize(&index)?)
+    }
+
+    fn delete_index(&mut self, name: &str) -> Result<()> {
+        let index = self.must_get_index(name)?;
+        if !self.scan_indexes()?.any(|i| i.name == index.name) {
+            return Err(Error::Value(format!(
+                "Can't drop index {}, it is the implicit index of table {} column {}",
+                index.name, index.table, index.columns[0]
+            )));
+        }
+        self.index_clear(&index.table, &index.columns)?;
+        self.txn.delete(&Key::IndexSchema(Some(index.name.into())).encode())
+    }
+
+    fn scan_indexes(&self) -> Result<Indexes> {
+        Ok(Box::new(
+            self.txn
+                .scan_prefix(&Key::IndexSchema(None).encode())?
+                .map(|r| r.and_then(|(_, v)| deserialize(&v)))
+                .collect::<Result<Vec<_>>>()?
+                .into_iter(),
+        ))
+    }
+
+    fn create_sequence(&mut self, sequence: Sequence) -> Result<()> {
+        sequence.validate(self)?;
+        self.txn.set(&Key::Sequence(Some((&sequence.name).into())).encode(), serialize(&sequence)?)
+    }
+
+    fn delete_sequence(&mut self, name: &str) -> Result<()> {
+        let sequence = self.must_read_sequence(name)?;
+        let nextval = Expression::NextVal(sequence.name.clone());
+        for table in self.scan_tables()? {
+            if let Some(column) = table
+                .columns
+                .iter()
+                .find(|c| c.default.as_ref().is_some_and(|d| d.contains(&|e| e == &nextval)))
+            {
+                return Err(Error::Value(format!(
+                    "Sequence {} is used by table {} column {}",
+                    sequence.name, table.name, column.name
+                )));
+            }
+        }
+        self.txn.delete(&Key::SequenceValue((&sequence.name).into()).encode())?;
+        self.txn.delete(&Key::Sequence(Some(sequence.name.into())).encode())
+    }
+
+    fn read_sequence(&self, name: &str) -> Result<Option<Sequence>> {
+        self.txn
+            .get(&Key::Sequence(Some(name.into())).encode())?
+            .map(|v| deserialize(&v))
+            .transpose()
+    }
+}
+
+/// Encodes SQL keys, using an order-preserving encoding - see storage::kv::encoding for details.
+/// Primary keys are encoded as tuples of values, such that composite keys sort by each column in
+/// turn and single-column keys are simply one-element tuples.
+#[derive(Debug, PartialEq)]
+enum Key<'a> {
+    /// A table schema key for the given table name
+    Table(Option<Cow<'a, str>>),
+    /// A key for an index entry, identified by table name, indexed columns and column values
+    Index(Cow<'a, str>, Cow<'a, [String]>, Option<Cow<'a, [Value]>>),
+    /// A key for a row identified by table name and row primary key
+    Row(Cow<'a, str>, Option<Cow<'a, [Value]>>),
+    /// A sequence schema key for the given sequence name
+    Sequence(Option<Cow<'a, str>>),
+    /// A key for the last value fetched from a sequence, stored apart from the schema
+    SequenceValue(Cow<'a, str>),
+    /// An index schema key for the given index name, for indexes created with CREATE INDEX
+    IndexSchema(Option<Cow<'a, str>>),
+    /// A key for a value stored out of line, identified by table name, column index and row
+    /// primary key
+    Overflow(Cow<'a, str>, Option<(usize, Cow<'a, [Value]>)>),
+}
+
+impl<'a> Key<'a> {
+    /// Encodes the key as a byte vector
+    fn encode(self) -> Vec<u8> {
+        use encoding::*;
+        match self {
+            Self::Table(None) => vec![0x01],
+            Self::Table(Some(name)) => [&[0x01][..], &encode_string(&name)].concat(),
+            Self::Index(table, columns, None) => {
+                [&[0x02][..], &encode_string(&table), &encode_columns(&columns)].concat()
+            }
+            Self::Index(table, columns, Some(values)) => [
+                &[0x02][..],
+                &encode_string(&table),
+                &encode_columns(&columns),
+                &encode_values(&values),
+            ]
+            .concat(),
+            Self::Row(table, None) => [&[0x03][..], &encode_string(&table)].concat(),
+            Self::Row(table, Some(pk)) => {
+                [&[0x03][..], &encode_string(&table), &encode_values(&pk)].concat()
+            }
+            Self::Sequence(None) => vec![0x04],
+            Self::Sequence(Some(name)) => [&[0x04][..], &encode_string(&name)].concat(),
+            Self::SequenceValue(name) => [&[0x05][..], &encode_string(&name)].concat(),
+            Self::IndexSchema(None) => vec![0x06],
+            Self::IndexSchema(Some(name)) => [&[0x06][..], &encode_string(&name)].concat(),
+            Self::Overflow(table, None) => [&[0x07][..], &encode_string(&table)].concat(),
+            Self::Overflow(table, Some((column, pk))) => [
+                &[0x07][..],
+                &encode_string(&table),
+                &encode_u64(column as u64)[..],
+                &encode_values(&pk),
+            ]
+            .concat(),
+        }
+    }
+
+    /// Decodes a key from a byte representation
+    fn decode(mut bytes: &[u8]) -> Result<Self> {
+        use encoding::*;
+        let bytes = &mut bytes;
+        let key = match take_byte(bytes)? {
+            0x01 => Self::Table(Some(take_string(bytes)?.into())),
+            0x02 => {
+                let table = take_string(bytes)?.into();
+                let columns = take_columns(bytes)?;
+                let values = take_values(bytes, columns.len())?;
+                Self::Index(table, columns.into(), Some(values.into()))
+            }
+            0x03 => {
+                let table = take_string(bytes)?.into();
+                let mut pk = Vec::new();
+                while !bytes.is_empty() {
+                    pk.push(take_value(bytes)?);
+                }
+                Self::Row(table, Some(pk.into()))
+            }
+            0x04 => Self::Sequence(Some(take_string(bytes)?.into())),
+            0x05 => Self::SequenceValue(take_string(bytes)?.into()),
+            0x06 => Self::IndexSchema(Some(take_string(bytes)?.into())),
+            0x07 => {
+                let table = take_string(bytes)?.into();
+                let column = take_u64(bytes)? as usize;
+                let mut pk = Vec::new();
+                while !bytes.is_empty() {
+                    pk.push(take_value(bytes)?);
+                }
+                Self::Overflow(table, Some((column, pk.into())))
+            }
+            b => return Err(Error::Internal(format!("Unknown SQL key prefix {:x?}", b))),
+        };
+        if !bytes.is_empty() {
+            return Err(Error::Internal("Unexpected data remaining at end of key".into()));
+        }
+        Ok(key)
+    }
+}
+
+/// Encodes a list of column names, prefixed by the number of columns such that the list can't be
+/// confused with the values following it.
+fn encode_columns(columns: &[String]) -> Vec<u8> {
+    let mut bytes = encoding::encode_u64(columns.len() as u64).to_vec();
+    for column in columns {
+        bytes.extend(encoding::encode_string(column));
+    }
+    bytes
+}
+
+/// Decodes a list of column names from a slice and shrinks the slice
+fn take_columns(bytes: &mut &[u8]) -> Result<Vec<String>> {
+    let count = encoding::take_u64(bytes)?;
+    (0..count).map(|_| encoding::take_string(bytes)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::schema::{Column, StringLength};
+    use super::super::super::types::DataType;
+    use super::super::{Engine, Transaction as _};
+    use super::*;
+
+    fn setup() -> Result<Transaction> {
+        KV::new(kv::MVCC::new(Box::new(kv::Memory::new()))).begin()
+    }
+
+    /// Creates the table t (id INTEGER PRIMARY KEY, a INTEGER INDEX, b STRING)
+    fn create_table(txn: &mut Transaction) -> Result<Table> {
+        let table = Table::new(
+            "t".into(),
+            vec![
+                Column { primary_key: true, unique: true, ..Column::new("id", DataType::Integer) },
+                Column { index: true, ..Column::new("a", DataType::Integer) },
+                Column::new("b", DataType::String),
+            ],
+        )?;
+        txn.create_table(table.clone())?;
+        Ok(table)
+    }
+
+    fn row(id: i64, a: i64, b: &str) -> Row {
+        vec![Value::Integer(id), Value::Integer(a), Value::String(b.into())]
+    }
+
+    fn pks(ids: &[i64]) -> HashSet<Vec<Value>> {
+        ids.iter().map(|id| vec![Value::Integer(*id)]).collect()
+    }
+
+    #[test]
+    fn index_maintenance() -> Result<()> {
+        let mut txn = setup()?;
+        create_table(&mut txn)?;
+        let columns = ["a".to_string()];
+        txn.create("t", row(1, 10, "x"))?;
+        txn.create("t", row(2, 10, "y"))?;
+        txn.create("t", row(3, 20, "z"))?;
+        assert_eq!(pks(&[1, 2]), txn.read_index("t", &columns, &[Value::Integer(10)])?);
+        assert_eq!(pks(&[3]), txn.read_index("t", &columns, &[Value::Integer(20)])?);
+
+        txn.update("t", &[Value::Integer(2)], row(2, 20, "y"))?;
+        assert_eq!(pks(&[1]), txn.read_index("t", &columns, &[Value::Integer(10)])?);
+        assert_eq!(pks(&[2, 3]), txn.read_index("t", &columns, &[Value::Integer(20)])?);
+
+        // Changing the primary key rekeys the index entry.
+        txn.update("t", &[Value::Integer(3)], row(4, 20, "z"))?;
+        assert_eq!(pks(&[2, 4]), txn.read_index("t", &columns, &[Value::Integer(20)])?);
+
+        txn.delete("t", &[Value::Integer(1)])?;
+        assert_eq!(pks(&[]), txn.read_index("t", &columns, &[Value::Integer(10)])?);
+        assert_eq!(
+            vec![(vec![Value::Integer(20)], pks(&[2, 4]))],
+            txn.scan_index("t", &columns)?.collect::<Result<Vec<_>>>()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn create_index() -> Result<()> {
+        let mut txn = setup()?;
+        create_table(&mut txn)?;
+        txn.create("t", row(1, 10, "x"))?;
+        txn.create("t", row(2, 10, "y"))?;
+        txn.create("t", row(3, 10, "x"))?;
+
+        // Existing rows are backfilled into multi-column indexes.
+        let columns = vec!["b".to_string(), "a".to_string()];
+        let index = Index {
+            name: "t_ba".into(),
+            table: "t".into(),
+            columns: columns.clone(),
+            expressions: Vec::new(),
+            unique: false,
+        };
+        txn.create_index(index.clone())?;
+        let key = [Value::String("x".into()), Value::Integer(10)];
+        assert_eq!(pks(&[1, 3]), txn.read_index("t", &columns, &key)?);
+
+        // New rows are added to the index.
+        txn.create("t", row(4, 10, "x"))?;
+        assert_eq!(pks(&[1, 3, 4]), txn.read_index("t", &columns, &key)?);
+
+        // Unique indexes can't be built over duplicate rows, and aren't created.
+        let unique = Index { name: "t_b".into(), columns: vec!["b".into()], unique: true, ..index };
+        assert!(txn.create_index(unique).is_err());
+        assert_eq!(None, txn.get_index("t_b")?);
+
+        // Dropping the index deletes its entries.
+        txn.delete_index("t_ba")?;
+        assert_eq!(pks(&[]), txn.read_index("t", &columns, &key)?);
+        Ok(())
+    }
+
+    #[test]
+    fn unique_conflicts() -> Result<()> {
+        let mut txn = setup()?;
+        txn.create_table(Table::new(
+            "t".into(),
+            vec![
+                Column { primary_key: true, unique: true, ..Column::new("id", DataType::Integer) },
+                Column {
+                    unique: true,
+                    nullable: true,
+                    default: Some(Expression::Constant(Value::Null)),
+                    ..Column::new("a", DataType::Integer)
+                },
+                Column::new("b", DataType::String),
+            ],
+        )?)?;
+        txn.create("t", row(1, 10, "x"))?;
+        txn.create("t", row(2, 20, "x"))?;
+
+        // Duplicate values of a unique column conflict, on insert and on update.
+        assert!(txn.create("t", row(3, 10, "y")).is_err());
+        assert!(txn.update("t", &[Value::Integer(2)], row(2, 10, "x")).is_err());
+        txn.update("t", &[Value::Integer(1)], row(1, 10, "y"))?;
+
+        // Deleted and updated values are released again, and NULLs never conflict.
+        txn.delete("t", &[Value::Integer(1)])?;
+        txn.create("t", row(3, 10, "y"))?;
+        txn.update("t", &[Value::Integer(2)], row(2, 30, "x"))?;
+        txn.create("t", row(4, 20, "z"))?;
+        let null = |id| vec![Value::Integer(id), Value::Null, Value::String("n".into())];
+        txn.create("t", null(5))?;
+        txn.create("t", null(6))?;
+
+        // The same holds for unique indexes created with CREATE INDEX.
+        let index = Index {
+            name: "t_b".into(),
+            table: "t".into(),
+            columns: vec!["b".into()],
+            expressions: Vec::new(),
+            unique: true,
+        };
+        assert!(txn.create_index(index.clone()).is_err());
+        txn.delete("t", &[Value::Integer(6)])?;
+        txn.create_index(index)?;
+        assert!(txn.create("t", row(7, 70, "z")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn unique_conflicts_concurrent() -> Result<()> {
+        let engine = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())));
+        let mut txn = engine.begin()?;
+        txn.create_table(Table::new(
+            "t".into(),
+            vec![
+                Column { primary_key: true, unique: true, ..Column::new("id", DataType::Integer) },
+                Column { unique: true, ..Column::new("a", DataType::Integer) },
+                Column::new("b", DataType::String),
+            ],
+        )?)?;
+        txn.create_index(Index {
+            name: "t_b".into(),
+            table: "t".into(),
+            columns: vec!["b".into()],
+            expressions: Vec::new(),
+            unique: true,
+        })?;
+        txn.commit()?;
+
+        // Concurrent transactions writing the same unique value can't see each other's rows,
+        // but write the same index entry, so the latter fails with a serialization conflict,
+        // whether or not the former has committed. This holds both for unique columns and for
+        // unique indexes.
+        let mut t1 = engine.begin()?;
+        let mut t2 = engine.begin()?;
+        let mut t3 = engine.begin()?;
+        t1.create("t", row(1, 10, "x"))?;
+        assert_eq!(Err(Error::Serialization), t2.create("t", row(2, 10, "y")));
+        t1.commit()?;
+        assert_eq!(Err(Error::Serialization), t3.create("t", row(3, 30, "x")));
+        t2.rollback()?;
+        t3.rollback()?;
+
+        // Different values don't conflict.
+        let mut t4 = engine.begin()?;
+        let mut t5 = engine.begin()?;
+        t4.create("t", row(4, 40, "y"))?;
+        t5.create("t", row(5, 50, "z"))?;
+        t4.commit()?;
+        t5.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn alter_table() -> Result<()> {
+        let mut txn = setup()?;
+        create_table(&mut txn)?;
+        txn.create("t", row(1, 10, "x"))?;
+        txn.create("t", row(2, 20, "y"))?;
+        txn.create_index(Index {
+            name: "t_ba".into(),
+            table: "t".into(),
+            columns: vec!["b".into(), "a".into()],
+            expressions: Vec::new(),
+            unique: false,
+        })?;
+
+        // Added columns are backfilled with their default, and their indexes are built.
+        let c = Column {
+            default: Some(Expression::Constant(Value::Integer(7))),
+            index: true,
+            ..Column::new("c", DataType::Integer)
+        };
+        txn.alter_table("t", Alteration::AddColumn(c))?;
+        let mut expect = row(1, 10, "x");
+        expect.push(Value::Integer(7));
+        assert_eq!(Some(expect), txn.read("t", &[Value::Integer(1)])?);
+        assert_eq!(pks(&[1, 2]), txn.read_index("t", &["c".into()], &[Value::Integer(7)])?);
+
+        // Indexed columns can't be dropped, other columns can.
+        assert!(txn.alter_table("t", Alteration::DropColumn("b".into())).is_err());
+        txn.alter_table("t", Alteration::DropColumn("c".into()))?;
+        assert_eq!(Some(row(1, 10, "x")), txn.read("t", &[Value::Integer(1)])?);
+        assert_eq!(pks(&[]), txn.read_index("t", &["c".into()], &[Value::Integer(7)])?);
+
+        // Renamed columns are followed by indexes.
+        let rename = Alteration::RenameColumn { from: "a".into(), to: "x".into() };
+        txn.alter_table("t", rename)?;
+        let columns = vec!["b".to_string(), "x".to_string()];
+        assert_eq!(Some(columns.clone()), txn.get_index("t_ba")?.map(|i| i.columns));
+        let key = [Value::String("x".into()), Value::Integer(10)];
+        assert_eq!(pks(&[1]), txn.read_index("t", &columns, &key)?);
+        assert_eq!(pks(&[1]), txn.read_index("t", &["x".into()], &[Value::Integer(10)])?);
+        assert_eq!(None, txn.get_index("t_a_idx")?);
+        assert!(txn.get_index("t_x_idx")?.is_some());
+
+        // Rows must be valid for the new schema.
+        txn.alter_table("t", Alteration::SetNullable { column: "b".into(), nullable: true })?;
+        let null = vec![Value::Integer(2), Value::Integer(20), Value::Null];
+        txn.update("t", &[Value::Integer(2)], null)?;
+        let not_null = Alteration::SetNullable { column: "b".into(), nullable: false };
+        assert!(txn.alter_table("t", not_null).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn alter_table_rename() -> Result<()> {
+        let mut txn = setup()?;
+        create_table(&mut txn)?;
+        txn.create("t", row(1, 10, "x"))?;
+        txn.create_table(Table::new(
+            "u".into(),
+            vec![
+                Column {
+                    primary_key: true,
+                    unique: true,
+                    default: Some(Expression::NextVal("u_id_seq".into())),
+                    ..Column::new("id", DataType::Integer)
+                },
+                Column { references: Some("t".into()), ..Column::new("t_id", DataType::Integer) },
+            ],
+        )?)?;
+        let id = txn.nextval("u_id_seq")?;
+        txn.create("u", vec![Value::Integer(id), Value::Integer(1)])?;
+
+        // Renamed tables keep their rows and indexes, and references follow them.
+        txn.alter_table("t", Alteration::RenameTable("v".into()))?;
+        assert_eq!(None, txn.read_table("t")?);
+        assert_eq!(Some(row(1, 10, "x")), txn.read("v", &[Value::Integer(1)])?);
+        assert_eq!(pks(&[1]), txn.read_index("v", &["a".into()], &[Value::Integer(10)])?);
+        assert!(txn.get_index("v_a_idx")?.is_some());
+        let u = txn.must_read_table("u")?;
+        assert_eq!(Some("v".to_string()), u.columns[1].references);
+        assert!(txn.create("u", vec![Value::Integer(9), Value::Integer(2)]).is_err());
+        assert!(txn.detele_table("v").is_err());
+
+        // Column sequences are renamed along with their column.
+        let rename = Alteration::RenameColumn { from: "id".into(), to: "uid".into() };
+        txn.alter_table("u", rename)?;
+        assert_eq!(None, txn.read_sequence("u_id_seq")?);
+        assert_eq!(
+            Some(Expression::NextVal("u_uid_seq".into())),
+            txn.must_read_table("u")?.columns[0].default
+        );
+        assert_eq!(id + 1, txn.nextval("u_uid_seq")?);
+        txn.detele_table("u")?;
+        assert_eq!(None, txn.read_sequence("u_uid_seq")?);
+        Ok(())
+    }
+
+    #[test]
+    fn implicit_index_names() -> Result<()> {
+        let mut txn = setup()?;
+        let table = create_table(&mut txn)?;
+        assert_eq!(
+            vec!["t_a_idx".to_string()],
+            txn.table_indexes(&table)?.into_iter().map(|i| i.name).collect::<Vec<_>>()
+        );
+        assert_eq!(Some(vec!["a".to_string()]), txn.get_index("t_a_idx")?.map(|i| i.columns));
+
+        // Implicit index names are reserved, and implicit indexes can't be dropped.
+        let index = Index {
+            name: "t_b_idx".into(),
+            table: "t".into(),
+            columns: vec!["b".into()],
+            expressions: Vec::new(),
+            unique: false,
+        };
+        assert!(txn.create_index(index.clone()).is_err());
+        assert!(txn.delete_index("t_a_idx").is_err());
+
+        // A user index named like another table's implicit index blocks that table.
+        txn.create_index(Index { name: "u_a_idx".into(), ..index })?;
+        let u = Table::new(
+            "u".into(),
+            vec![
+                Column { primary_key: true, unique: true, ..Column::new("id", DataType::Integer) },
+                Column { index: true, ..Column::new("a", DataType::Integer) },
+            ],
+        )?;
+        assert!(txn.create_table(u).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn coerce_row() -> Result<()> {
+        let mut txn = setup()?;
+        txn.create_table(Table::new(
+            "d".into(),
+            vec![
+                Column {
+                    primary_key: true,
+                    unique: true,
+                    ..Column::new("id", DataType::Decimal(5, 1))
+                },
+                Column {
+                    length: Some(StringLength::Char(3)),
+                    ..Column::new("c", DataType::String)
+                },
+                Column::new("j", DataType::Json),
+            ],
+        )?)?;
+        let decimal = |s: &str| -> Result<Value> { Ok(Value::Decimal(s.parse()?)) };
+
+        // Rows are coerced to the column datatypes when created, including the primary key.
+        txn.create("d", vec![Value::Integer(1), Value::String("a ".into()), "[1, 2]".into()])?;
+        let row = txn.read("d", &[decimal("1.0")?])?.expect("row not found");
+        assert_eq!(Value::String("a".into()), row[1]);
+        assert!(matches!(row[2], Value::Json(_)), "{:?}", row[2]);
+        assert!(txn.create("d", vec![Value::Integer(2), "a".into(), "[1,".into()]).is_err());
+
+        // And when updated, also when the primary key changes.
+        txn.update("d", &[decimal("1")?], vec![decimal("2.25")?, "b".into(), "{}".into()])?;
+        assert_eq!(None, txn.read("d", &[decimal("1")?])?);
+        let row = txn.read("d", &[decimal("2.3")?])?.expect("row not found");
+        assert_eq!(vec![decimal("2.3")?, "b".into(), row[2].clone()], row);
+        assert!(matches!(row[2], Value::Json(_)), "{:?}", row[2]);
+        Ok(())
+    }
+
+    #[test]
+    fn sequences() -> Result<()> {
+        let kv = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())));
+        let mut txn = kv.begin()?;
+        txn.create_sequence(Sequence { increment: 2, ..Sequence::new("s") })?;
+        assert_eq!(1, txn.nextval("s")?);
+        txn.commit()?;
+
+        // Sequence values are transactional: they're rolled back with the transaction, and
+        // currval() only returns values fetched in the same transaction.
+        let mut txn = kv.begin()?;
+        assert!(txn.currval("s").is_err());
+        assert_eq!(3, txn.nextval("s")?);
+        assert_eq!(3, txn.currval("s")?);
+        txn.rollback()?;
+
+        let mut txn = kv.begin()?;
+        assert_eq!(3, txn.nextval("s")?);
+        txn.delete_sequence("s")?;
+        assert!(txn.nextval("s").is_err());
+        txn.create_sequence(Sequence::new("s"))?;
+        assert_eq!(1, txn.nextval("s")?);
+        Ok(())
+    }
+
+    #[test]
+    fn large_strings() -> Result<()> {
+        let kv = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())));
+        let mut txn = kv.begin()?;
+        create_table(&mut txn)?;
+        txn.commit()?;
+        let large = "x".repeat(OVERFLOW_SIZE + 1);
+
+        // Strings are limited to 1024 bytes by default.
+        let mut txn = kv.begin()?;
+        assert!(txn.create("t", row(1, 10, &"x".repeat(1024))).is_ok());
+        assert!(txn.create("t", row(2, 10, &"x".repeat(1025))).is_err());
+        txn.rollback()?;
+
+        let limits = Limits { max_string_size: 1 << 20, ..Limits::default() };
+        let mut txn = kv.with_limits(limits).begin()?;
+        assert!(txn.create("t", row(2, 10, &"x".repeat(1 << 20))).is_ok());
+        assert!(txn.create("t", row(3, 10, &"x".repeat((1 << 20) + 1))).is_err());
+
+        // Large strings are stored out of line, and are removed along with their rows.
+        let overflow = |txn: &Transaction| -> Result<usize> {
+            Ok(txn.txn.scan_prefix(&Key::Overflow("t".into(), None).encode())?.count())
+        };
+        txn.create("t", row(1, 10, &large))?;
+        assert_eq!(2, overflow(&txn)?);
+        assert_eq!(Some(row(1, 10, &large)), txn.read("t", &[Value::Integer(1)])?);
+        txn.update("t", &[Value::Integer(1)], row(1, 20, &large))?;
+        assert_eq!(Some(row(1, 20, &large)), txn.read("t", &[Value::Integer(1)])?);
+        let rows = txn.scan("t", None)?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(vec![row(1, 20, &large), row(2, 10, &"x".repeat(1 << 20))], rows);
+        let filter = Expression::Equal(
+            Expression::Field(1, None).into(),
+            Expression::Constant(Value::Integer(20)).into(),
+        );
+        let rows = txn.scan("t", Some(filter))?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(vec![row(1, 20, &large)], rows);
+
+        txn.update("t", &[Value::Integer(1)], row(1, 20, "small"))?;
+        assert_eq!(Some(row(1, 20, "small")), txn.read("t", &[Value::Integer(1)])?);
+        assert_eq!(1, overflow(&txn)?);
+        txn.delete("t", &[Value::Integer(2)])?;
+        assert_eq!(0, overflow(&txn)?);
+
+        txn.update("t", &[Value::Integer(1)], row(1, 20, &large))?;
+        txn.detele_table("t")?;
+        assert_eq!(0, overflow(&txn)?);
+        Ok(())
+    }
+
+    #[test]
+    fn large_binary() -> Result<()> {
+        let kv = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())));
+        let limits = Limits { max_binary_size: OVERFLOW_SIZE * 2, ..Limits::default() };
+        let mut txn = kv.with_limits(limits).begin()?;
+        txn.create_table(Table::new(
+            "b".into(),
+            vec![
+                Column { primary_key: true, unique: true, ..Column::new("id", DataType::Integer) },
+                Column::new("data", DataType::Binary),
+            ],
+        )?)?;
+        let row = |id: i64, size: usize| vec![Value::Integer(id), Value::Binary(vec![7; size])];
+
+        // Binary values are limited by their own configurable limit, and large ones are stored
+        // out of line as well.
+        assert!(txn.create("b", row(1, OVERFLOW_SIZE * 2 + 1)).is_err());
+        txn.create("b", row(1, OVERFLOW_SIZE * 2))?;
+        txn.create("b", row(2, 1))?;
+        assert_eq!(Some(row(1, OVERFLOW_SIZE * 2)), txn.read("b", &[Value::Integer(1)])?);
+        assert_eq!(
+            vec![row(1, OVERFLOW_SIZE * 2), row(2, 1)],
+            txn.scan("b", None)?.collect::<Result<Vec<_>>>()?
+        );
+        assert_eq!(1, txn.txn.scan_prefix(&Key::Overflow("b".into(), None).encode())?.count());
+        Ok(())
+    }
+}
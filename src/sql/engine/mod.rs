@@ -1,16 +1,41 @@
 mod kv;
 pub mod raft;
+pub use crate::storage::kv::Mode;
+pub use kv::KV;
 
-use super::schema::Catalog;
+use super::schema::{Catalog, ReferentialAction};
 use super::types::{Expression, Row, Value};
 use crate::error::{Error, Result};
 
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// The SQL engine interface
+pub trait Engine: Clone {
+    /// The transaction type
+    type Transaction: Transaction;
+
+    /// Begins a read-write transaction.
+    fn begin(&self) -> Result<Self::Transaction> {
+        self.begin_with_mode(Mode::ReadWrite)
+    }
+
+    /// Begins a transaction in the given mode.
+    fn begin_with_mode(&self, mode: Mode) -> Result<Self::Transaction>;
+
+    /// Resumes an active transaction with the given ID.
+    fn resume(&self, id: u64) -> Result<Self::Transaction>;
+}
+
 /// An SQL transaction
 pub trait Transaction: Catalog {
     /// The transaction ID
     fn id(&self) -> u64;
     /// The transaction mode
     fn mode(&self) -> Mode;
+    /// The size limits of written values
+    fn limits(&self) -> &Limits;
     /// Commits the transaction
     fn commit(self) -> Result<()>;
     /// Rolls back the transaction
@@ -18,16 +43,213 @@ pub trait Transaction: Catalog {
 
     /// Creates a new table row
     fn create(&mut self, table: &str, row: Row) -> Result<()>;
-    /// Deletes a table row
-    fn delete(&mut self, table: &str, id: &Value) -> Result<()>;
+    /// Deletes a table row, given its (possibly composite) primary key
+    fn delete(&mut self, table: &str, id: &[Value]) -> Result<()>;
     /// Reads a table row, if it exists
-    fn read(&self, table: &str, id: &Value) -> Result<Option<Row>>;
-    /// Reads an index entry, if it exists
-    fn read_index(&self, table: &str, column: &str, value: &Value) -> Result<HashSet<Value>>;
+    fn read(&self, table: &str, id: &[Value]) -> Result<Option<Row>>;
+    /// Reads an index entry, if it exists, as the primary keys of the matching rows. The index is
+    /// identified by its table and columns, and looked up by a tuple of column values.
+    fn read_index(
+        &self,
+        table: &str,
+        columns: &[String],
+        values: &[Value],
+    ) -> Result<HashSet<Vec<Value>>>;
     /// Scans a table's rows
     fn scan(&self, table: &str, filter: Option<Expression>) -> Result<Scan>;
-    /// Scans a column's index entries
-    fn scan_index(&self, table: &str, column: &str) -> Result<IndexScan>;
+    /// Scans an index's entries
+    fn scan_index(&self, table: &str, columns: &[String]) -> Result<IndexScan>;
     /// Updates a table row
-    fn update(&mut self, table: &str, id: &Value, row: Row) -> Result<()>;
+    fn update(&mut self, table: &str, id: &[Value], row: Row) -> Result<()>;
+
+    /// Fetches the next value of a sequence, and records it as the sequence's current value in
+    /// this transaction
+    fn nextval(&mut self, sequence: &str) -> Result<i64>;
+    /// Returns the value most recently fetched by nextval() for a sequence in this transaction,
+    /// erroring if none has been fetched
+    fn currval(&self, sequence: &str) -> Result<i64>;
+
+    /// Resolves sequence operations in an expression, replacing nextval() and currval() calls with
+    /// the sequence values. Must be called by executors before evaluating an expression for each
+    /// row, e.g. for column defaults, since the values are fetched through the transaction.
+    fn resolve_sequences(&mut self, expr: Expression) -> Result<Expression> {
+        let txn = RefCell::new(self);
+        expr.transform(
+            &|e| match e {
+                Expression::CurrVal(sequence) => {
+                    Ok(Expression::Constant(Value::Integer(txn.borrow().currval(&sequence)?)))
+                }
+                Expression::NextVal(sequence) => {
+                    Ok(Expression::Constant(Value::Integer(txn.borrow_mut().nextval(&sequence)?)))
+                }
+                e => Ok(e),
+            },
+            &|e| Ok(e),
+        )
+    }
+
+    /// Deletes a row, or updates it to the given row, applying the referential actions of foreign
+    /// keys referencing it when it's deleted or its primary key changes. RESTRICT references are
+    /// checked before the row is written, and other actions applied after it, such that cascaded
+    /// references to a new primary key are valid. Used by the Delete and Update executors.
+    fn delete_or_update(&mut self, table: &str, id: &[Value], new: Option<Row>) -> Result<()> {
+        let new_id = match &new {
+            Some(row) => Some(self.must_read_table(table)?.get_row_key(row)?),
+            None => None,
+        };
+        let mut referencing = Vec::new();
+        if new_id.as_deref() != Some(id) {
+            for (source, foreign_keys) in self.table_references(table, true)? {
+                let source = self.must_read_table(&source)?;
+                for foreign_key in foreign_keys {
+                    let indexes = foreign_key
+                        .columns
+                        .iter()
+                        .map(|c| source.get_column_index(c))
+                        .collect::<Result<Vec<_>>>()?;
+                    // Referencing rows are looked up through an index on exactly the foreign
+                    // key columns if there is one, otherwise the table is scanned.
+                    let index = self.table_indexes(&source)?.into_iter().find(|index| {
+                        index.columns == foreign_key.columns
+                            && (0..index.columns.len()).all(|i| index.expression(i).is_none())
+                    });
+                    let rows = match index {
+                        Some(index) => {
+                            // Index keys are collated, so the rows are filtered on the raw
+                            // values below.
+                            let key = indexes
+                                .iter()
+                                .zip(id)
+                                .map(|(i, v)| source.columns[*i].collation.key(v.clone()))
+                                .collect::<Vec<_>>();
+                            let mut pks = self
+                                .read_index(&source.name, &index.columns, &key)?
+                                .into_iter()
+                                .collect::<Vec<_>>();
+                            pks.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                            let mut rows = Vec::new();
+                            for pk in pks {
+                                rows.extend(self.read(&source.name, &pk)?);
+                            }
+                            rows
+                        }
+                        None => {
+                            let filter = Expression::from_cnf_vec(
+                                indexes
+                                    .iter()
+                                    .zip(id)
+                                    .map(|(i, v)| {
+                                        Expression::Equal(
+                                            Expression::Field(*i, None).into(),
+                                            Expression::Constant(v.clone()).into(),
+                                        )
+                                    })
+                                    .collect(),
+                            );
+                            self.scan(&source.name, filter)?.collect::<Result<Vec<_>>>()?
+                        }
+                    };
+                    let mut pks = Vec::new();
+                    for row in rows {
+                        if !indexes.iter().zip(id).all(|(i, v)| &row[*i] == v) {
+                            continue;
+                        }
+                        let pk = source.get_row_key(&row)?;
+                        // A self-referencing row doesn't restrict itself.
+                        if source.name != table || pk != id {
+                            pks.push(pk);
+                        }
+                    }
+                    let action = match new_id {
+                        Some(_) => foreign_key.on_update,
+                        None => foreign_key.on_delete,
+                    };
+                    if pks.is_empty() {
+                        continue;
+                    } else if action == ReferentialAction::Restrict {
+                        return Err(Error::Value(format!(
+                            "Primary key ({}) is referenced by table {} foreign key ({})",
+                            id.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "),
+                            source.name,
+                            foreign_key.columns.join(", "),
+                        )));
+                    }
+                    referencing.push((source.clone(), indexes, action, pks));
+                }
+            }
+        }
+
+        match new {
+            Some(row) => self.update(table, id, row)?,
+            None => self.delete(table, id)?,
+        }
+
+        for (source, indexes, action, pks) in referencing {
+            for pk in pks {
+                // Earlier actions may have changed or deleted the referencing row.
+                let mut row = match self.read(&source.name, &pk)? {
+                    Some(row) if indexes.iter().zip(id).all(|(i, v)| &row[*i] == v) => row,
+                    _ => continue,
+                };
+                match (&action, &new_id) {
+                    (ReferentialAction::Cascade, None) => {
+                        self.delete_or_update(&source.name, &pk, None)?;
+                        continue;
+                    }
+                    (ReferentialAction::Cascade, Some(new_id)) => {
+                        for (i, v) in indexes.iter().zip(new_id) {
+                            row[*i] = v.clone();
+                        }
+                    }
+                    (ReferentialAction::SetNull, _) => {
+                        for i in &indexes {
+                            row[*i] = Value::Null;
+                        }
+                    }
+                    (ReferentialAction::SetDefault, _) => {
+                        for i in &indexes {
+                            row[*i] = source.columns[*i].evaluate_default(self)?;
+                        }
+                    }
+                    (ReferentialAction::Restrict, _) => {
+                        return Err(Error::Internal("Unexpected RESTRICT reference".into()))
+                    }
+                }
+                // The foreign key may be part of the primary key, in which case the
+                // referencing row is rekeyed and its own dependents must follow.
+                self.delete_or_update(&source.name, &pk, Some(row))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The default maximum size of strings, in bytes
+pub const DEFAULT_MAX_STRING_SIZE: usize = 1024;
+
+/// The default maximum size of BINARY values, in bytes
+pub const DEFAULT_MAX_BINARY_SIZE: usize = 1024 * 1024;
+
+/// Size limits for values written to tables, e.g. from the server configuration. Values
+/// written before a limit was lowered are not affected.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    /// The maximum size of strings, in bytes. This bounds TEXT columns, and VARCHAR(n) columns
+    /// in addition to their length.
+    pub max_string_size: usize,
+    /// The maximum size of BINARY values, in bytes
+    pub max_binary_size: usize,
 }
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { max_string_size: DEFAULT_MAX_STRING_SIZE, max_binary_size: DEFAULT_MAX_BINARY_SIZE }
+    }
+}
+
+/// A row scan iterator
+pub type Scan = Box<dyn DoubleEndedIterator<Item = Result<Row>> + Send>;
+
+/// An index scan iterator, over index keys and the primary keys of the matching rows
+pub type IndexScan =
+    Box<dyn DoubleEndedIterator<Item = Result<(Vec<Value>, HashSet<Vec<Value>>)>> + Send>;
@@ -0,0 +1,729 @@
+use super::super::plan::{Aggregate, AggregateFunction};
+use super::super::types::{Collation, Decimal, Row, Rows, Value};
+use crate::error::{Error, Result};
+
+use std::cmp::Ordering;
+use std::collections::hash_map::{Entry, HashMap};
+
+/// Computes aggregates for each group of rows, for the Aggregation plan node. Source rows contain
+/// the aggregate arguments followed by group_by grouping values, and each group emits a row
+/// with the aggregate values followed by the grouping values. Without grouping, a single row is
+/// emitted even if there are no source rows.
+///
+/// With grouping sets, each row is aggregated once per grouping set, grouped by the set's
+/// columns with NULLs for the others, and the grouping ID is appended to the emitted rows.
+/// Empty grouping sets emit a row even if there are no source rows.
+///
+/// Grouping values are compared using the given collations, by position, where missing
+/// collations are the default. Each group emits the grouping values of its first row.
+pub fn aggregate(
+    source: Rows,
+    aggregates: &[Aggregate],
+    group_by: usize,
+    grouping_sets: Option<&[Vec<usize>]>,
+    collations: &[Collation],
+) -> Result<Rows> {
+    let all = [(0..group_by).collect::<Vec<_>>()];
+    let sets = grouping_sets.unwrap_or(&all);
+    if let Some(i) = sets.iter().flatten().find(|i| **i >= group_by) {
+        return Err(Error::Internal(format!("Grouping column {} out of bounds", i)));
+    }
+
+    let mut groups: Vec<(Row, Vec<Box<dyn Accumulator>>)> = Vec::new();
+    let mut index: HashMap<(usize, Row), usize> = HashMap::new();
+    let mut empty = true;
+    for row in source {
+        let mut row = row?;
+        empty = false;
+        if row.len() != aggregates.len() + group_by {
+            return Err(Error::Internal(format!(
+                "Expected {} aggregation columns, got {}",
+                aggregates.len() + group_by,
+                row.len()
+            )));
+        }
+        let values = row.split_off(aggregates.len());
+        for (s, set) in sets.iter().enumerate() {
+            let key = grouping_key(&values, set, grouping_sets.is_some());
+            let collated: Row = key
+                .iter()
+                .enumerate()
+                .map(|(i, v)| collations.get(i).copied().unwrap_or_default().key(v.clone()))
+                .collect();
+            let i = match index.get(&(s, collated.clone())) {
+                Some(i) => *i,
+                None => {
+                    groups.push((key, aggregates.iter().map(accumulator).collect()));
+                    index.insert((s, collated), groups.len() - 1);
+                    groups.len() - 1
+                }
+            };
+            for (accumulator, value) in groups[i].1.iter_mut().zip(row.iter()) {
+                if value != &Value::Null {
+                    accumulator.accumulate(value.clone())?;
+                }
+            }
+        }
+    }
+    if empty {
+        for set in sets.iter().filter(|set| set.is_empty()) {
+            let key = grouping_key(&vec![Value::Null; group_by], set, grouping_sets.is_some());
+            groups.push((key, aggregates.iter().map(accumulator).collect()));
+        }
+    }
+
+    let rows = groups
+        .into_iter()
+        .map(|(key, accumulators)| {
+            let mut row = accumulators.iter().map(|a| a.finish()).collect::<Result<Row>>()?;
+            row.extend(key);
+            Ok(row)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Box::new(rows.into_iter().map(Ok)))
+}
+
+/// Returns the group key of grouping values for a grouping set, with NULLs for grouping columns
+/// not in the set, optionally followed by the grouping ID. The grouping ID has bit
+/// values.len() - 1 - i set for each grouping column i not in the set.
+fn grouping_key(values: &[Value], set: &[usize], grouping_id: bool) -> Row {
+    let mut key = vec![Value::Null; values.len()];
+    let mut id = (1i64 << values.len()) - 1;
+    for &i in set {
+        key[i] = values[i].clone();
+        id &= !(1 << (values.len() - 1 - i));
+    }
+    if grouping_id {
+        key.push(Value::Integer(id));
+    }
+    key
+}
+
+/// An aggregate accumulator, which accumulates the values of a single group. Partial aggregates
+/// can be computed separately and combined by merging the state of one accumulator into
+/// another, which allows aggregation to be parallelized.
+pub trait Accumulator: Send {
+    /// Accumulates a value. NULLs are skipped by the caller.
+    fn accumulate(&mut self, value: Value) -> Result<()>;
+
+    /// Returns the accumulator's partial state, for merging into another accumulator
+    fn state(&self) -> Vec<Value>;
+
+    /// Merges the partial state of another accumulator for the same aggregate
+    fn merge(&mut self, state: Vec<Value>) -> Result<()>;
+
+    /// Returns the aggregate value
+    fn finish(&self) -> Result<Value>;
+
+    /// Removes a previously accumulated value, for sliding window frames. Returns false if the
+    /// value can't be removed exactly, e.g. for floats or extreme values, in which case the
+    /// accumulator is unchanged and must be recomputed without the value.
+    fn retract(&mut self, _value: &Value) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+/// Creates an accumulator for an aggregate
+pub fn accumulator(aggregate: &Aggregate) -> Box<dyn Accumulator> {
+    let accumulator: Box<dyn Accumulator> = match &aggregate.function {
+        AggregateFunction::Average => Box::new(Average::default()),
+        AggregateFunction::BoolAnd => Box::new(Bool { and: true, value: None }),
+        AggregateFunction::BoolOr => Box::new(Bool { and: false, value: None }),
+        AggregateFunction::Count => Box::new(Count(0)),
+        AggregateFunction::Max => {
+            Box::new(Extreme { max: true, collation: aggregate.collation, value: None })
+        }
+        AggregateFunction::Min => {
+            Box::new(Extreme { max: false, collation: aggregate.collation, value: None })
+        }
+        AggregateFunction::PercentileCont(fraction) => {
+            Box::new(PercentileCont { fraction: *fraction, values: Vec::new() })
+        }
+        AggregateFunction::StdDev => Box::new(Variance::new(true, false)),
+        AggregateFunction::StdDevPop => Box::new(Variance::new(true, true)),
+        AggregateFunction::StringAgg(separator) => {
+            Box::new(StringAgg { separator: separator.clone(), value: None })
+        }
+        AggregateFunction::Sum => Box::new(Sum(None)),
+        AggregateFunction::Variance => Box::new(Variance::new(false, false)),
+        AggregateFunction::VariancePop => Box::new(Variance::new(false, true)),
+    };
+    if aggregate.distinct {
+        Box::new(Distinct {
+            inner: accumulator,
+            collation: aggregate.collation,
+            seen: HashMap::new(),
+        })
+    } else {
+        accumulator
+    }
+}
+
+/// Returns the single value of a partial state
+fn single(state: Vec<Value>) -> Result<Value> {
+    match <[Value; 1]>::try_from(state) {
+        Ok([value]) => Ok(value),
+        Err(state) => Err(Error::Internal(format!("Invalid accumulator state {:?}", state))),
+    }
+}
+
+/// Adds two numeric values, with the same promotion rules as Expression::Add: integers and
+/// decimals sum to exact decimals, while floats make the sum a float.
+fn add(a: Value, b: Value) -> Result<Value> {
+    Ok(match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => {
+            Value::Integer(a.checked_add(b).ok_or_else(|| Error::Value("Integer overflow".into()))?)
+        }
+        (Value::Integer(a), Value::Float(b)) => Value::Float(a as f64 + b),
+        (Value::Float(a), Value::Integer(b)) => Value::Float(a + b as f64),
+        (Value::Float(a), Value::Float(b)) => Value::Float(a + b),
+        (Value::Decimal(a), Value::Decimal(b)) => Value::Decimal(a.checked_add(&b)?),
+        (Value::Decimal(a), Value::Integer(b)) | (Value::Integer(b), Value::Decimal(a)) => {
+            Value::Decimal(a.checked_add(&Decimal::from(b))?)
+        }
+        (Value::Decimal(a), Value::Float(b)) | (Value::Float(b), Value::Decimal(a)) => {
+            Value::Float(a.to_f64() + b)
+        }
+        (a, b) => return Err(Error::Value(format!("Can't add {} and {}", a, b))),
+    })
+}
+
+/// Converts a numeric value to a float
+fn numeric(value: Value) -> Result<f64> {
+    match value {
+        Value::Integer(i) => Ok(i as f64),
+        Value::Float(f) => Ok(f),
+        Value::Decimal(d) => Ok(d.to_f64()),
+        value => Err(Error::Value(format!("Can't aggregate {}", value))),
+    }
+}
+
+/// Ignores values that have already been accumulated, for DISTINCT aggregates. Values are
+/// deduplicated by their collation key, keeping the first one. The partial state is the
+/// distinct values, since the inner states of two accumulators can't be merged without
+/// counting values seen by both twice.
+struct Distinct {
+    inner: Box<dyn Accumulator>,
+    collation: Collation,
+    seen: HashMap<Value, Value>,
+}
+
+impl Accumulator for Distinct {
+    fn accumulate(&mut self, value: Value) -> Result<()> {
+        if let Entry::Vacant(entry) = self.seen.entry(self.collation.key(value.clone())) {
+            entry.insert(value.clone());
+            self.inner.accumulate(value)?;
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> Vec<Value> {
+        self.seen.values().cloned().collect()
+    }
+
+    fn merge(&mut self, state: Vec<Value>) -> Result<()> {
+        state.into_iter().try_for_each(|value| self.accumulate(value))
+    }
+
+    fn finish(&self) -> Result<Value> {
+        self.inner.finish()
+    }
+}
+
+/// Averages numeric values
+#[derive(Default)]
+struct Average {
+    sum: Sum,
+    count: Count,
+}
+
+impl Accumulator for Average {
+    fn accumulate(&mut self, value: Value) -> Result<()> {
+        self.sum.accumulate(value)?;
+        self.count.0 += 1;
+        Ok(())
+    }
+
+    fn state(&self) -> Vec<Value> {
+        vec![self.sum.0.clone().unwrap_or(Value::Null), Value::Integer(self.count.0)]
+    }
+
+    fn merge(&mut self, state: Vec<Value>) -> Result<()> {
+        match <[Value; 2]>::try_from(state) {
+            Ok([sum, count]) => {
+                self.sum.merge(vec![sum])?;
+                self.count.merge(vec![count])
+            }
+            Err(state) => Err(Error::Internal(format!("Invalid accumulator state {:?}", state))),
+        }
+    }
+
+    fn retract(&mut self, value: &Value) -> Result<bool> {
+        Ok(self.sum.retract(value)? && self.count.retract(value)?)
+    }
+
+    fn finish(&self) -> Result<Value> {
+        Ok(match &self.sum.0 {
+            // The average of decimals is an exact decimal, rounded like decimal division.
+            Some(Value::Decimal(sum)) => {
+                Value::Decimal(sum.checked_div(&Decimal::from(self.count.0))?)
+            }
+            Some(sum) => Value::Float(numeric(sum.clone())? / self.count.0 as f64),
+            None => Value::Null,
+        })
+    }
+}
+
+/// Computes the logical AND or OR of boolean values
+struct Bool {
+    and: bool,
+    value: Option<bool>,
+}
+
+impl Accumulator for Bool {
+    fn accumulate(&mut self, value: Value) -> Result<()> {
+        let value = value.boolean()?;
+        self.value = Some(match self.value {
+            Some(current) if self.and => current && value,
+            Some(current) => current || value,
+            None => value,
+        });
+        Ok(())
+    }
+
+    fn state(&self) -> Vec<Value> {
+        vec![self.value.map(Value::Boolean).unwrap_or(Value::Null)]
+    }
+
+    fn merge(&mut self, state: Vec<Value>) -> Result<()> {
+        match single(state)? {
+            Value::Null => Ok(()),
+            value => self.accumulate(value),
+        }
+    }
+
+    fn finish(&self) -> Result<Value> {
+        Ok(self.state().remove(0))
+    }
+}
+
+/// Counts values
+#[derive(Default)]
+struct Count(i64);
+
+impl Accumulator for Count {
+    fn accumulate(&mut self, _: Value) -> Result<()> {
+        self.0 += 1;
+        Ok(())
+    }
+
+    fn state(&self) -> Vec<Value> {
+        vec![Value::Integer(self.0)]
+    }
+
+    fn merge(&mut self, state: Vec<Value>) -> Result<()> {
+        self.0 += single(state)?.integer()?;
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Value> {
+        Ok(Value::Integer(self.0))
+    }
+
+    fn retract(&mut self, _: &Value) -> Result<bool> {
+        self.0 -= 1;
+        Ok(true)
+    }
+}
+
+/// Finds the maximum or minimum value, comparing strings using the collation. Of equal values,
+/// the first one is kept.
+struct Extreme {
+    max: bool,
+    collation: Collation,
+    value: Option<Value>,
+}
+
+impl Accumulator for Extreme {
+    fn accumulate(&mut self, value: Value) -> Result<()> {
+        let replace = match &self.value {
+            Some(current) => {
+                let ordering = self.collation.compare_values(&value, current).ok_or_else(|| {
+                    Error::Value(format!("Can't compare {} and {}", value, current))
+                })?;
+                ordering == if self.max { Ordering::Greater } else { Ordering::Less }
+            }
+            None => true,
+        };
+        if replace {
+            self.value = Some(value);
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> Vec<Value> {
+        vec![self.value.clone().unwrap_or(Value::Null)]
+    }
+
+    fn merge(&mut self, state: Vec<Value>) -> Result<()> {
+        match single(state)? {
+            Value::Null => Ok(()),
+            value => self.accumulate(value),
+        }
+    }
+
+    fn finish(&self) -> Result<Value> {
+        Ok(self.value.clone().unwrap_or(Value::Null))
+    }
+}
+
+/// Computes a continuous percentile, by linear interpolation between the nearest values. All
+/// values are buffered.
+struct PercentileCont {
+    fraction: f64,
+    values: Vec<f64>,
+}
+
+impl Accumulator for PercentileCont {
+    fn accumulate(&mut self, value: Value) -> Result<()> {
+        self.values.push(numeric(value)?);
+        Ok(())
+    }
+
+    fn state(&self) -> Vec<Value> {
+        self.values.iter().map(|v| Value::Float(*v)).collect()
+    }
+
+    fn merge(&mut self, state: Vec<Value>) -> Result<()> {
+        state.into_iter().try_for_each(|value| self.accumulate(value))
+    }
+
+    fn finish(&self) -> Result<Value> {
+        if self.values.is_empty() {
+            return Ok(Value::Null);
+        }
+        let mut values = self.values.clone();
+        values.sort_by(|a, b| a.total_cmp(b));
+        let position = self.fraction * (values.len() - 1) as f64;
+        let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+        let weight = position - lower as f64;
+        Ok(Value::Float(values[lower] + (values[upper] - values[lower]) * weight))
+    }
+}
+
+/// Concatenates values as strings, with a separator
+struct StringAgg {
+    separator: String,
+    value: Option<String>,
+}
+
+impl Accumulator for StringAgg {
+    fn accumulate(&mut self, value: Value) -> Result<()> {
+        let value = value.to_string();
+        match &mut self.value {
+            Some(current) => {
+                current.push_str(&self.separator);
+                current.push_str(&value);
+            }
+            None => self.value = Some(value),
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> Vec<Value> {
+        vec![self.value.clone().map(Value::String).unwrap_or(Value::Null)]
+    }
+
+    fn merge(&mut self, state: Vec<Value>) -> Result<()> {
+        match single(state)? {
+            Value::Null => Ok(()),
+            value => self.accumulate(value),
+        }
+    }
+
+    fn finish(&self) -> Result<Value> {
+        Ok(self.state().remove(0))
+    }
+}
+
+/// Sums numeric values
+#[derive(Default)]
+struct Sum(Option<Value>);
+
+impl Accumulator for Sum {
+    fn accumulate(&mut self, value: Value) -> Result<()> {
+        self.0 = Some(match self.0.take() {
+            Some(sum) => add(sum, value)?,
+            None => add(Value::Integer(0), value)?,
+        });
+        Ok(())
+    }
+
+    fn state(&self) -> Vec<Value> {
+        vec![self.0.clone().unwrap_or(Value::Null)]
+    }
+
+    fn merge(&mut self, state: Vec<Value>) -> Result<()> {
+        match single(state)? {
+            Value::Null => Ok(()),
+            value => self.accumulate(value),
+        }
+    }
+
+    fn finish(&self) -> Result<Value> {
+        Ok(self.0.clone().unwrap_or(Value::Null))
+    }
+
+    /// Only integer sums are retracted. Float rounding errors would accumulate, and decimal sums
+    /// would keep the scale of retracted values.
+    fn retract(&mut self, value: &Value) -> Result<bool> {
+        match (&mut self.0, value) {
+            (Some(Value::Integer(sum)), Value::Integer(value)) => match sum.checked_sub(*value) {
+                Some(result) => *sum = result,
+                None => return Ok(false),
+            },
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// Computes the variance or standard deviation, for the sample or the population. Uses
+/// Welford's online algorithm, which is numerically stable, and Chan's method for merging.
+struct Variance {
+    sqrt: bool,
+    population: bool,
+    count: i64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Variance {
+    fn new(sqrt: bool, population: bool) -> Self {
+        Self { sqrt, population, count: 0, mean: 0.0, m2: 0.0 }
+    }
+}
+
+impl Accumulator for Variance {
+    fn accumulate(&mut self, value: Value) -> Result<()> {
+        let value = numeric(value)?;
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        Ok(())
+    }
+
+    fn state(&self) -> Vec<Value> {
+        vec![Value::Integer(self.count), Value::Float(self.mean), Value::Float(self.m2)]
+    }
+
+    fn merge(&mut self, state: Vec<Value>) -> Result<()> {
+        let (count, mean, m2) = match <[Value; 3]>::try_from(state) {
+            Ok([count, mean, m2]) => (count.integer()?, mean.float()?, m2.float()?),
+            Err(state) => {
+                return Err(Error::Internal(format!("Invalid accumulator state {:?}", state)))
+            }
+        };
+        if count == 0 {
+            return Ok(());
+        }
+        let total = self.count + count;
+        let delta = mean - self.mean;
+        self.m2 += m2 + delta * delta * (self.count * count) as f64 / total as f64;
+        self.mean += delta * count as f64 / total as f64;
+        self.count = total;
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<Value> {
+        let divisor = if self.population { self.count } else { self.count - 1 };
+        if divisor <= 0 {
+            return Ok(Value::Null);
+        }
+        let variance = self.m2 / divisor as f64;
+        Ok(Value::Float(if self.sqrt { variance.sqrt() } else { variance }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(values: &[i64]) -> Vec<Value> {
+        values.iter().map(|v| Value::Integer(*v)).collect()
+    }
+
+    /// Accumulates the values, and also merges partial aggregates of the values split at every
+    /// position, asserting that the results are equal. Floats are compared approximately.
+    fn run(function: AggregateFunction, distinct: bool, values: Vec<Value>) -> Result<Value> {
+        let aggregate = Aggregate { function, distinct, collation: Collation::Binary };
+        let mut whole = accumulator(&aggregate);
+        for value in values.iter().cloned() {
+            whole.accumulate(value)?;
+        }
+        let result = whole.finish()?;
+        for split in 0..=values.len() {
+            let (mut left, mut right) = (accumulator(&aggregate), accumulator(&aggregate));
+            for value in values[..split].iter().cloned() {
+                left.accumulate(value)?;
+            }
+            for value in values[split..].iter().cloned() {
+                right.accumulate(value)?;
+            }
+            left.merge(right.state())?;
+            match (&result, left.finish()?) {
+                (Value::Float(a), Value::Float(b)) => assert!((a - b).abs() < 1e-9, "{} {}", a, b),
+                (result, merged) => assert_eq!(*result, merged, "split at {}", split),
+            }
+        }
+        Ok(result)
+    }
+
+    #[test]
+    fn grouping_sets() -> Result<()> {
+        let source = || -> Rows {
+            let rows: Vec<Row> = [(1, "a", "x"), (2, "a", "y"), (4, "b", "x")]
+                .into_iter()
+                .map(|(v, g, h)| vec![Value::Integer(v), g.into(), h.into()])
+                .collect();
+            Box::new(rows.into_iter().map(Ok))
+        };
+        let sum = [Aggregate {
+            function: AggregateFunction::Sum,
+            distinct: false,
+            collation: Collation::Binary,
+        }];
+        let row = |sum: i64, g: Option<&str>, h: Option<&str>, id: i64| {
+            let value = |v: Option<&str>| v.map(Value::from).unwrap_or(Value::Null);
+            vec![Value::Integer(sum), value(g), value(h), Value::Integer(id)]
+        };
+
+        // ROLLUP (g, h): rolled-up columns are NULL, and flagged in the grouping ID.
+        let sets = [vec![0, 1], vec![0], vec![]];
+        let rows = aggregate(source(), &sum, 2, Some(&sets), &[])?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            vec![
+                row(1, Some("a"), Some("x"), 0),
+                row(3, Some("a"), None, 1),
+                row(7, None, None, 3),
+                row(2, Some("a"), Some("y"), 0),
+                row(4, Some("b"), Some("x"), 0),
+                row(4, Some("b"), None, 1),
+            ],
+            rows
+        );
+
+        // Empty grouping sets emit a row even without source rows, unlike other sets.
+        let empty: Rows = Box::new(std::iter::empty());
+        let rows = aggregate(empty, &sum, 2, Some(&sets), &[])?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(vec![vec![Value::Null, Value::Null, Value::Null, Value::Integer(3)]], rows);
+
+        // Without grouping sets, there is no grouping ID.
+        let rows = aggregate(source(), &sum, 2, None, &[])?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(3, rows.len());
+        assert_eq!(vec![Value::Integer(1), "a".into(), "x".into()], rows[0]);
+        assert!(aggregate(source(), &sum, 2, Some(&[vec![2]]), &[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn aggregates() -> Result<()> {
+        use AggregateFunction::*;
+        let numbers = values(&[2, 4, 4, 4, 5, 5, 7, 9]);
+        assert_eq!(Value::Integer(8), run(Count, false, numbers.clone())?);
+        assert_eq!(Value::Integer(5), run(Count, true, numbers.clone())?);
+        assert_eq!(Value::Integer(40), run(Sum, false, numbers.clone())?);
+        assert_eq!(Value::Integer(27), run(Sum, true, numbers.clone())?);
+        assert_eq!(Value::Float(5.0), run(Average, false, numbers.clone())?);
+        assert_eq!(Value::Integer(2), run(Min, false, numbers.clone())?);
+        assert_eq!(Value::Integer(9), run(Max, false, numbers.clone())?);
+        assert_eq!(Value::Float(4.0), run(VariancePop, false, numbers.clone())?);
+        assert_eq!(Value::Float(2.0), run(StdDevPop, false, numbers.clone())?);
+        assert_eq!(Value::Float(32.0 / 7.0), run(Variance, false, numbers.clone())?);
+        assert_eq!(Value::Float(4.5), run(PercentileCont(0.5), false, numbers.clone())?);
+        assert_eq!(Value::Float(9.0), run(PercentileCont(1.0), false, numbers)?);
+
+        let strings = vec![Value::String("a".into()), Value::String("b".into())];
+        assert_eq!(Value::String("a, b".into()), run(StringAgg(", ".into()), false, strings)?);
+        let booleans = vec![Value::Boolean(true), Value::Boolean(false)];
+        assert_eq!(Value::Boolean(false), run(BoolAnd, false, booleans.clone())?);
+        assert_eq!(Value::Boolean(true), run(BoolOr, false, booleans)?);
+
+        // Sums and averages of decimals are exact, and floats make them floats.
+        let decimals = vec![Value::Decimal("1.5".parse()?), Value::Integer(1)];
+        assert_eq!(Value::Decimal("2.5".parse()?), run(Sum, false, decimals.clone())?);
+        assert_eq!(Value::Decimal("1.25".parse()?), run(Average, false, decimals)?);
+        assert_eq!(Value::Float(3.5), run(Sum, false, vec![Value::Float(2.5), Value::Integer(1)])?);
+
+        // Aggregates of no values are NULL, except for COUNT, and the sample variance needs
+        // at least two values.
+        assert_eq!(Value::Integer(0), run(Count, false, Vec::new())?);
+        let empty = [Sum, Average, Min, Max, StdDev, PercentileCont(0.5), StringAgg("".into())];
+        for function in empty {
+            assert_eq!(Value::Null, run(function, false, Vec::new())?);
+        }
+        assert_eq!(Value::Null, run(Variance, false, values(&[1]))?);
+        assert_eq!(Value::Float(0.0), run(VariancePop, false, values(&[1]))?);
+
+        assert!(run(Sum, false, values(&[i64::MAX, 1])).is_err());
+        assert!(run(Sum, false, vec![Value::String("a".into())]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn collation() -> Result<()> {
+        let strings = |s: &[&str]| -> Vec<Value> { s.iter().map(|s| Value::from(*s)).collect() };
+
+        // DISTINCT aggregates deduplicate strings by their collation key, also when merging.
+        let count = Aggregate {
+            function: AggregateFunction::Count,
+            distinct: true,
+            collation: Collation::NoCase,
+        };
+        let mut left = accumulator(&count);
+        let mut right = accumulator(&count);
+        for value in strings(&["a", "A", "b"]) {
+            left.accumulate(value)?;
+        }
+        for value in strings(&["B", "c", "C"]) {
+            right.accumulate(value)?;
+        }
+        assert_eq!(Value::Integer(2), left.finish()?);
+        left.merge(right.state())?;
+        assert_eq!(Value::Integer(3), left.finish()?);
+
+        // MIN and MAX compare strings using the collation, keeping the first of equal values.
+        for (function, collation, expect) in [
+            (AggregateFunction::Max, Collation::NoCase, "B"),
+            (AggregateFunction::Min, Collation::NoCase, "a"),
+            (AggregateFunction::Max, Collation::Binary, "b"),
+            (AggregateFunction::Min, Collation::Binary, "B"),
+        ] {
+            let mut accumulator = accumulator(&Aggregate { function, distinct: false, collation });
+            for value in strings(&["a", "B", "b"]) {
+                accumulator.accumulate(value)?;
+            }
+            assert_eq!(Value::from(expect), accumulator.finish()?);
+        }
+
+        // Groups are compared using their collations, and emit the values of their first row.
+        let rows: Vec<Row> =
+            strings(&["x", "X", "y"]).into_iter().map(|v| vec![Value::Integer(1), v]).collect();
+        let sum = [Aggregate {
+            function: AggregateFunction::Sum,
+            distinct: false,
+            collation: Collation::Binary,
+        }];
+        let groups = |collations: &[Collation]| -> Result<Vec<Row>> {
+            let source = Box::new(rows.clone().into_iter().map(Ok));
+            aggregate(source, &sum, 1, None, collations)?.collect()
+        };
+        assert_eq!(
+            vec![vec![Value::Integer(2), "x".into()], vec![Value::Integer(1), "y".into()]],
+            groups(&[Collation::NoCase])?
+        );
+        assert_eq!(3, groups(&[])?.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use super::super::types::{Expression, Rows};
+use crate::error::Result;
+
+use std::collections::HashSet;
+
+/// Removes duplicate rows, keeping the first one, for the Distinct plan node. If on is
+/// non-empty, rows are deduplicated by the values of its expressions instead, as for SELECT
+/// DISTINCT ON, where strings are compared using the expressions' collations. Rows are
+/// streamed, and only the distinct values seen so far are buffered.
+pub fn distinct(source: Rows, on: Vec<Expression>) -> Rows {
+    let mut seen = HashSet::new();
+    Box::new(source.filter_map(move |row| {
+        let row = match row {
+            Ok(row) => row,
+            Err(err) => return Some(Err(err)),
+        };
+        let key = if on.is_empty() {
+            row.clone()
+        } else {
+            let key =
+                |e: &Expression| Ok(e.collation().unwrap_or_default().key(e.evaluate(Some(&row))?));
+            match on.iter().map(key).collect::<Result<Vec<_>>>() {
+                Ok(key) => key,
+                Err(err) => return Some(Err(err)),
+            }
+        };
+        if seen.insert(key) {
+            Some(Ok(row))
+        } else {
+            None
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::types::{Collation, Row, Value};
+    use super::*;
+
+    #[test]
+    fn distinct() -> Result<()> {
+        let rows = || -> Rows {
+            let rows: Vec<Row> = [(1, "a"), (2, "b"), (1, "a"), (1, "c")]
+                .into_iter()
+                .map(|(i, s)| vec![Value::Integer(i), Value::String(s.into())])
+                .collect();
+            Box::new(rows.into_iter().map(Ok))
+        };
+        let row = |i, s: &str| vec![Value::Integer(i), Value::String(s.into())];
+
+        // Entire rows are deduplicated, keeping the first in source order.
+        assert_eq!(
+            vec![row(1, "a"), row(2, "b"), row(1, "c")],
+            super::distinct(rows(), Vec::new()).collect::<Result<Vec<_>>>()?
+        );
+
+        // With DISTINCT ON, rows are deduplicated by the given expressions.
+        assert_eq!(
+            vec![row(1, "a"), row(2, "b")],
+            super::distinct(rows(), vec![Expression::Field(0, None)])
+                .collect::<Result<Vec<_>>>()?
+        );
+
+        // Strings are compared using the collations of the expressions.
+        let nocase = Expression::Collate(Box::new(Expression::Field(1, None)), Collation::NoCase);
+        let rows = Box::new(vec![row(1, "a"), row(2, "A"), row(3, "b")].into_iter().map(Ok));
+        assert_eq!(
+            vec![row(1, "a"), row(3, "b")],
+            super::distinct(rows, vec![nocase]).collect::<Result<Vec<_>>>()?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use super::super::plan::JoinType;
+use super::super::types::{Expression, Row, Rows, Value};
+use crate::error::{Error, Result};
+
+use std::collections::HashMap;
+
+/// Joins rows by evaluating the predicate for every pair of left and right rows, for the
+/// NestedLoopJoin plan node. Joined rows are the left row followed by the right row, and
+/// unmatched rows are padded with NULLs for the other side as given by the join type. The row
+/// widths are needed for padding, since either side may be empty.
+pub fn nested_loop_join(
+    left: Rows,
+    left_size: usize,
+    right: Rows,
+    right_size: usize,
+    predicate: Option<&Expression>,
+    r#type: &JoinType,
+) -> Result<Rows> {
+    let right = right.collect::<Result<Vec<_>>>()?;
+    let mut right_matched = vec![false; right.len()];
+    let mut rows = Vec::new();
+    for left_row in left {
+        let left_row = left_row?;
+        let mut matched = false;
+        for (right_row, right_matched) in right.iter().zip(right_matched.iter_mut()) {
+            let row: Row = left_row.iter().chain(right_row).cloned().collect();
+            let is_match = match predicate.map(|p| p.evaluate(Some(&row))).transpose()? {
+                None | Some(Value::Boolean(true)) => true,
+                Some(Value::Boolean(false)) | Some(Value::Null) => false,
+                Some(value) => {
+                    return Err(Error::Value(format!("Join predicate returned {}", value)))
+                }
+            };
+            if is_match {
+                rows.push(row);
+                matched = true;
+                *right_matched = true;
+            }
+        }
+        if !matched && r#type.emits_left() {
+            rows.push(pad_right(left_row, right_size));
+        }
+    }
+    if r#type.emits_right() {
+        rows.extend(unmatched_right(right, right_matched, left_size));
+    }
+    Ok(Box::new(rows.into_iter().map(Ok)))
+}
+
+/// Joins rows on equality of a left and a right field, by building a hash table of the right
+/// rows and probing it with the left rows, for the HashJoin plan node. Like in SQL, NULL (and
+/// NaN) keys never match. Unmatched rows are padded as for nested_loop_join().
+pub fn hash_join(
+    left: Rows,
+    left_field: usize,
+    left_size: usize,
+    right: Rows,
+    right_field: usize,
+    right_size: usize,
+    r#type: &JoinType,
+) -> Result<Rows> {
+    let right = right.collect::<Result<Vec<_>>>()?;
+    let mut right_matched = vec![false; right.len()];
+    let mut table: HashMap<&Value, Vec<usize>> = HashMap::new();
+    for (i, row) in right.iter().enumerate() {
+        let key = row.get(right_field).ok_or_else(|| {
+            Error::Internal(format!("Right hash join field {} out of bounds", right_field))
+        })?;
+        if is_joinable(key) {
+            table.entry(key).or_default().push(i);
+        }
+    }
+
+    let mut rows = Vec::new();
+    for left_row in left {
+        let left_row = left_row?;
+        let key = left_row.get(left_field).ok_or_else(|| {
+            Error::Internal(format!("Left hash join field {} out of bounds", left_field))
+        })?;
+        match table.get(key) {
+            Some(matches) => {
+                for i in matches {
+                    rows.push(left_row.iter().chain(&right[*i]).cloned().collect());
+                    right_matched[*i] = true;
+                }
+            }
+            None if r#type.emits_left() => rows.push(pad_right(left_row, right_size)),
+            None => {}
+        }
+    }
+    if r#type.emits_right() {
+        rows.extend(unmatched_right(right, right_matched, left_size));
+    }
+    Ok(Box::new(rows.into_iter().map(Ok)))
+}
+
+/// Whether a join key can match other keys
+fn is_joinable(key: &Value) -> bool {
+    match key {
+        Value::Null => false,
+        Value::Float(f) => !f.is_nan(),
+        _ => true,
+    }
+}
+
+/// Pads an unmatched left row with NULLs for the right columns
+fn pad_right(mut row: Row, right_size: usize) -> Row {
+    row.extend(std::iter::repeat_n(Value::Null, right_size));
+    row
+}
+
+/// Returns the unmatched right rows, padded with NULLs for the left columns
+fn unmatched_right(right: Vec<Row>, matched: Vec<bool>, left_size: usize) -> Vec<Row> {
+    right
+        .into_iter()
+        .zip(matched)
+        .filter(|(_, matched)| !matched)
+        .map(|(row, _)| std::iter::repeat_n(Value::Null, left_size).chain(row).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(rows: &[&[Value]]) -> Rows {
+        let rows: Vec<Row> = rows.iter().map(|row| row.to_vec()).collect();
+        Box::new(rows.into_iter().map(Ok))
+    }
+
+    fn int(i: i64) -> Value {
+        Value::Integer(i)
+    }
+
+    #[test]
+    fn join() -> Result<()> {
+        use Value::Null;
+        let left: &[&[Value]] = &[&[int(1), int(10)], &[int(2), int(20)], &[Null, int(30)]];
+        let right: &[&[Value]] = &[&[int(1)], &[int(1)], &[int(3)], &[Null]];
+        let predicate =
+            Expression::Equal(Expression::Field(0, None).into(), Expression::Field(2, None).into());
+
+        // Both join algorithms give the same rows, and NULL keys never match.
+        for (r#type, expect) in [
+            (JoinType::Inner, vec![vec![int(1), int(10), int(1)], vec![int(1), int(10), int(1)]]),
+            (
+                JoinType::Left,
+                vec![
+                    vec![int(1), int(10), int(1)],
+                    vec![int(1), int(10), int(1)],
+                    vec![int(2), int(20), Null],
+                    vec![Null, int(30), Null],
+                ],
+            ),
+            (
+                JoinType::Right,
+                vec![
+                    vec![int(1), int(10), int(1)],
+                    vec![int(1), int(10), int(1)],
+                    vec![Null, Null, int(3)],
+                    vec![Null, Null, Null],
+                ],
+            ),
+            (
+                JoinType::Full,
+                vec![
+                    vec![int(1), int(10), int(1)],
+                    vec![int(1), int(10), int(1)],
+                    vec![int(2), int(20), Null],
+                    vec![Null, int(30), Null],
+                    vec![Null, Null, int(3)],
+                    vec![Null, Null, Null],
+                ],
+            ),
+        ] {
+            let nested =
+                nested_loop_join(rows(left), 2, rows(right), 1, Some(&predicate), &r#type)?;
+            assert_eq!(expect, nested.collect::<Result<Vec<_>>>()?, "{:?}", r#type);
+            let hashed = hash_join(rows(left), 0, 2, rows(right), 0, 1, &r#type)?;
+            assert_eq!(expect, hashed.collect::<Result<Vec<_>>>()?, "{:?}", r#type);
+        }
+
+        // Unmatched rows are padded even when the other side is empty.
+        let full = nested_loop_join(rows(&[]), 2, rows(right), 1, None, &JoinType::Full)?;
+        assert_eq!(vec![Null, Null, int(1)], full.collect::<Result<Vec<_>>>()?[0]);
+        Ok(())
+    }
+}
@@ -0,0 +1,1910 @@
+mod aggregate;
+mod distinct;
+mod join;
+mod set;
+mod window;
+
+pub use aggregate::{accumulator, aggregate, Accumulator};
+pub use distinct::distinct;
+pub use join::{hash_join, nested_loop_join};
+pub use set::set_operation;
+pub use window::window;
+
+use super::engine::{Mode, Transaction};
+use super::plan::{Direction, Node, Plan};
+use super::schema::{self, Table};
+use super::types::{Column, Columns, Expression, Row, Rows, Value};
+use crate::error::{Error, Result};
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/// Executes plan nodes in a transaction. Subquery expressions are executed as they are
+/// evaluated, with the rows of their enclosing queries kept on a stack for binding correlated
+/// subqueries. The rows of uncorrelated subqueries are cached, since they never change.
+pub struct Executor<'a, T: Transaction> {
+    txn: &'a mut T,
+    /// The plan's subqueries, looked up by the subquery expressions
+    subqueries: &'a [Node],
+    /// The rows of the enclosing queries, innermost last
+    outer: Vec<Row>,
+    /// The rows of executed uncorrelated subqueries, by subquery index
+    cache: HashMap<usize, Vec<Row>>,
+    /// The materialized common table expressions in scope, innermost last
+    ctes: Vec<(String, Columns, Vec<Row>)>,
+}
+
+impl<'a, T: Transaction> Executor<'a, T> {
+    /// Creates a new executor for a plan's nodes.
+    pub fn new(txn: &'a mut T, subqueries: &'a [Node]) -> Self {
+        Self { txn, subqueries, outer: Vec::new(), cache: HashMap::new(), ctes: Vec::new() }
+    }
+
+    /// Executes a plan's root node, returning its result set.
+    pub fn execute(&mut self, node: Node) -> Result<ResultSet> {
+        Ok(match node {
+            Node::AlterTable { table, alteration } => {
+                self.txn.alter_table(&table, alteration)?;
+                ResultSet::AlterTable { name: table }
+            }
+            Node::CreateIndex { schema } => {
+                let name = schema.name.clone();
+                self.txn.create_index(schema)?;
+                ResultSet::CreateIndex { name }
+            }
+            Node::CreateSequence { schema } => {
+                let name = schema.name.clone();
+                self.txn.create_sequence(schema)?;
+                ResultSet::CreateSequence { name }
+            }
+            Node::CreateTable { schema } => {
+                let name = schema.name.clone();
+                self.txn.create_table(schema)?;
+                ResultSet::CreateTable { name }
+            }
+            Node::Delete { table, source } => {
+                let schema = self.txn.must_read_table(&table)?;
+                let (_, rows) = self.query(*source)?;
+                let ids = rows
+                    .map(|r| r.and_then(|row| schema.get_row_key(&row)))
+                    .collect::<Result<Vec<_>>>()?;
+                let mut count = 0;
+                for id in ids {
+                    // The row may already have been deleted by a cascading foreign key.
+                    if self.txn.read(&table, &id)?.is_none() {
+                        continue;
+                    }
+                    self.txn.delete_or_update(&table, &id, None)?;
+                    count += 1;
+                }
+                ResultSet::Delete { count }
+            }
+            Node::DropIndex { name } => {
+                self.txn.delete_index(&name)?;
+                ResultSet::DropIndex { name }
+            }
+            Node::DropSequence { name } => {
+                self.txn.delete_sequence(&name)?;
+                ResultSet::DropSequence { name }
+            }
+            Node::DropTable { table } => {
+                self.txn.detele_table(&table)?;
+                ResultSet::DropTable { name: table }
+            }
+            Node::Insert { table, columns, expressions } => {
+                let schema = self.txn.must_read_table(&table)?;
+                let mut count = 0;
+                for expressions in expressions {
+                    let mut values = Vec::with_capacity(expressions.len());
+                    for expr in &expressions {
+                        values.push(self.evaluate(expr, None)?);
+                    }
+                    let row = self.make_row(&schema, &columns, values)?;
+                    self.txn.create(&table, row)?;
+                    count += 1;
+                }
+                ResultSet::Create { count }
+            }
+            Node::Update { table, source, expressions } => {
+                let schema = self.txn.must_read_table(&table)?;
+                // Rows are collected first, since updates may move them within the scan.
+                let (_, rows) = self.query(*source)?;
+                let rows = rows.collect::<Result<Vec<_>>>()?;
+                let mut count = 0;
+                for row in rows {
+                    let mut new = row.clone();
+                    for (i, _, expr) in &expressions {
+                        new[*i] = self.evaluate(expr, Some(&row))?;
+                    }
+                    let id = schema.get_row_key(&row)?;
+                    self.txn.delete_or_update(&table, &id, Some(new))?;
+                    count += 1;
+                }
+                ResultSet::Update { count }
+            }
+            node => {
+                let (columns, rows) = self.query(node)?;
+                ResultSet::Query { columns, rows }
+            }
+        })
+    }
+
+    /// Executes a query node, returning its columns and rows.
+    fn query(&mut self, node: Node) -> Result<(Columns, Rows)> {
+        Ok(match node {
+            Node::Aggregation { source, aggregates, group_by, grouping_sets, collations } => {
+                let (columns, rows) = self.query(*source)?;
+                let mut columns: Columns = (0..aggregates.len())
+                    .map(|_| Column { name: None })
+                    .chain(columns.into_iter().skip(aggregates.len()))
+                    .collect();
+                if grouping_sets.is_some() {
+                    columns.push(Column { name: None });
+                }
+                let rows =
+                    aggregate(rows, &aggregates, group_by, grouping_sets.as_deref(), &collations)?;
+                (columns, rows)
+            }
+            Node::Distinct { source, on } => {
+                let (columns, rows) = self.query(*source)?;
+                (columns, distinct(rows, on))
+            }
+            Node::Filter { source, predicate } => {
+                let (columns, rows) = self.query(*source)?;
+                (columns, self.filter(rows, predicate)?)
+            }
+            Node::HashJoin { left, left_field, right, right_field, r#type } => {
+                let (left_columns, left) = self.query(*left)?;
+                let (right_columns, right) = self.query(*right)?;
+                let (left_size, right_size) = (left_columns.len(), right_columns.len());
+                let rows = hash_join(
+                    left,
+                    left_field.0,
+                    left_size,
+                    right,
+                    right_field.0,
+                    right_size,
+                    &r#type,
+                )?;
+                (left_columns.into_iter().chain(right_columns).collect(), rows)
+            }
+            Node::IndexLookup { table, alias: _, columns, values } => {
+                let schema = self.txn.must_read_table(&table)?;
+                let mut ids = Vec::new();
+                for value in values {
+                    ids.extend(self.txn.read_index(&table, &columns, &value)?);
+                }
+                (Self::table_columns(&schema.columns), self.lookup(&table, ids)?)
+            }
+            Node::KeyLookup { table, alias: _, keys } => {
+                let schema = self.txn.must_read_table(&table)?;
+                (Self::table_columns(&schema.columns), self.lookup(&table, keys)?)
+            }
+            Node::Limit { source, limit } => {
+                let (columns, rows) = self.query(*source)?;
+                (columns, Box::new(rows.take(limit as usize)))
+            }
+            Node::NestedLoopJoin { left, left_size, right, predicate, r#type } => {
+                let (left_columns, left) = self.query(*left)?;
+                let (right_columns, right) = self.query(*right)?;
+                let right_size = right_columns.len();
+                let rows = nested_loop_join(
+                    left,
+                    left_size,
+                    right,
+                    right_size,
+                    predicate.as_ref(),
+                    &r#type,
+                )?;
+                (left_columns.into_iter().chain(right_columns).collect(), rows)
+            }
+            Node::Nothing => (Vec::new(), Box::new(std::iter::once(Ok(Vec::new())))),
+            Node::Offset { source, offset } => {
+                let (columns, rows) = self.query(*source)?;
+                (columns, Box::new(rows.skip(offset as usize)))
+            }
+            Node::Order { source, orders } => {
+                let (columns, rows) = self.query(*source)?;
+                (columns, self.order(rows, orders)?)
+            }
+            Node::Projection { source, expressions } => {
+                let (columns, rows) = self.query(*source)?;
+                let columns = expressions
+                    .iter()
+                    .map(|(expr, label)| Column {
+                        name: label.clone().or_else(|| match expr {
+                            Expression::Field(i, _) => columns.get(*i)?.name.clone(),
+                            Expression::Collate(expr, _) => match &**expr {
+                                Expression::Field(i, _) => columns.get(*i)?.name.clone(),
+                                _ => None,
+                            },
+                            _ => None,
+                        }),
+                    })
+                    .collect();
+                let expressions = expressions.into_iter().map(|(e, _)| e).collect();
+                (columns, self.project(rows, expressions)?)
+            }
+            Node::Scan { table, alias: _, filter } => {
+                let schema = self.txn.must_read_table(&table)?;
+                (Self::table_columns(&schema.columns), Box::new(self.txn.scan(&table, filter)?))
+            }
+            Node::SemiJoin { left, right, predicate, anti, null_aware } => {
+                let (columns, left) = self.query(*left)?;
+                let (_, right) = self.query(*right)?;
+                let right = right.collect::<Result<Vec<_>>>()?;
+                let mut rows = Vec::new();
+                for row in left {
+                    let row = row?;
+                    let matched = match &predicate {
+                        Some(predicate) => self.semi_match(&row, &right, predicate)?,
+                        None => Value::Boolean(!right.is_empty()),
+                    };
+                    let keep = match matched {
+                        Value::Boolean(true) => !anti,
+                        Value::Null if null_aware => false,
+                        _ => anti,
+                    };
+                    if keep {
+                        rows.push(row);
+                    }
+                }
+                (columns, Box::new(rows.into_iter().map(Ok)))
+            }
+            Node::SetOperation { operator, all, left, right } => {
+                let (columns, left) = self.query(*left)?;
+                let (_, right) = self.query(*right)?;
+                (columns, set_operation(&operator, all, left, right)?)
+            }
+            Node::Window { source, functions } => {
+                let (columns, rows) = self.query(*source)?;
+                let columns = columns
+                    .into_iter()
+                    .chain(functions.iter().map(|_| Column { name: None }))
+                    .collect();
+                (columns, window(rows, &functions)?)
+            }
+            Node::CteScan { name, alias: _ } => {
+                let (_, columns, rows) =
+                    self.ctes.iter().rev().find(|(n, _, _)| n == &name).ok_or_else(|| {
+                        Error::Internal(format!("Unknown common table expression {}", name))
+                    })?;
+                (columns.clone(), Box::new(rows.clone().into_iter().map(Ok)))
+            }
+            Node::Materialize { ctes, source } => {
+                let depth = self.ctes.len();
+                let result = self.materialize(ctes, *source);
+                self.truncate_ctes(depth);
+                result?
+            }
+            Node::RecursiveUnion { name, anchor, recursive, union_all, max_iterations } => {
+                self.recursive_union(name, *anchor, *recursive, union_all, max_iterations)?
+            }
+            Node::TableFunction { function, args, alias: _ } => {
+                let args =
+                    args.iter().map(|arg| self.evaluate(arg, None)).collect::<Result<Vec<_>>>()?;
+                let columns = function
+                    .columns()
+                    .into_iter()
+                    .map(|name| Column { name: Some(name.to_string()) })
+                    .collect();
+                (columns, function.evaluate(args)?)
+            }
+            node => return Err(Error::Internal(format!("Unexpected query node {:?}", node))),
+        })
+    }
+
+    /// Materializes common table expressions in order, such that each can refer to the previous
+    /// ones, and executes the source with them in scope. The caller must remove them afterwards.
+    fn materialize(&mut self, ctes: Vec<(String, Node)>, source: Node) -> Result<(Columns, Rows)> {
+        for (name, node) in ctes {
+            let (columns, rows) = self.query(node)?;
+            let rows = rows.collect::<Result<Vec<_>>>()?;
+            self.ctes.push((name, columns, rows));
+            self.cache.clear();
+        }
+        self.query(source)
+    }
+
+    /// Evaluates a recursive common table expression by iterating until a fixpoint. The anchor
+    /// is evaluated once, and the recursive term is then evaluated with the common table
+    /// expression emitting the previous iteration's new rows, until no new rows are produced.
+    /// Errors if the recursive term is evaluated more than max_iterations times.
+    fn recursive_union(
+        &mut self,
+        name: String,
+        anchor: Node,
+        recursive: Node,
+        union_all: bool,
+        max_iterations: u64,
+    ) -> Result<(Columns, Rows)> {
+        let mut seen = HashSet::new();
+        let mut keep = |rows: Rows| -> Result<Vec<Row>> {
+            let mut new = Vec::new();
+            for row in rows {
+                let row = row?;
+                if union_all || seen.insert(row.clone()) {
+                    new.push(row);
+                }
+            }
+            Ok(new)
+        };
+        let (columns, rows) = self.query(anchor)?;
+        let mut working = keep(rows)?;
+        let mut result = working.clone();
+        let mut iterations = 0;
+        while !working.is_empty() {
+            if iterations >= max_iterations {
+                return Err(Error::Value(format!(
+                    "Recursive common table expression {} exceeded {} iterations",
+                    name, max_iterations
+                )));
+            }
+            iterations += 1;
+            let depth = self.ctes.len();
+            self.ctes.push((name.clone(), columns.clone(), working));
+            self.cache.clear();
+            let rows = self.query(recursive.clone()).and_then(|(_, rows)| keep(rows));
+            self.truncate_ctes(depth);
+            working = rows?;
+            result.extend(working.iter().cloned());
+        }
+        Ok((columns, Box::new(result.into_iter().map(Ok))))
+    }
+
+    /// Removes common table expressions going out of scope. Cached subquery results are
+    /// discarded, since they may have read them.
+    fn truncate_ctes(&mut self, depth: usize) {
+        self.ctes.truncate(depth);
+        self.cache.clear();
+    }
+
+    /// Returns the result columns of a table's rows
+    fn table_columns(columns: &[schema::Column]) -> Columns {
+        columns.iter().map(|c| Column { name: Some(c.name.clone()) }).collect()
+    }
+
+    /// Reads table rows by primary key, in primary key order and without duplicates
+    fn lookup(&mut self, table: &str, mut ids: Vec<Vec<Value>>) -> Result<Rows> {
+        ids.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        ids.dedup();
+        let mut rows = Vec::with_capacity(ids.len());
+        for id in ids {
+            rows.extend(self.txn.read(table, &id)?);
+        }
+        Ok(Box::new(rows.into_iter().map(Ok)))
+    }
+
+    /// Builds a table row from INSERT values, given the names of the inserted columns, or
+    /// values for the leading table columns if none are given. Missing columns get their
+    /// default value.
+    fn make_row(
+        &mut self,
+        table: &Table,
+        columns: &[String],
+        mut values: Vec<Value>,
+    ) -> Result<Row> {
+        if values.len() > table.columns.len() {
+            return Err(Error::Value(format!(
+                "Too many values for table {}, expected at most {}",
+                table.name,
+                table.columns.len()
+            )));
+        }
+        if columns.is_empty() {
+            for column in table.columns.iter().skip(values.len()) {
+                values.push(column.evaluate_default(self.txn)?);
+            }
+            return Ok(values);
+        }
+        if columns.len() != values.len() {
+            return Err(Error::Value(format!(
+                "Column and value counts do not match, got {} columns and {} values",
+                columns.len(),
+                values.len()
+            )));
+        }
+        let mut inputs = HashMap::new();
+        for (column, value) in columns.iter().zip(values) {
+            table.get_column(column)?;
+            if inputs.insert(column.clone(), value).is_some() {
+                return Err(Error::Value(format!("Column {} given multiple times", column)));
+            }
+        }
+        let mut row = Vec::with_capacity(table.columns.len());
+        for column in &table.columns {
+            match inputs.remove(&column.name) {
+                Some(value) => row.push(value),
+                None => row.push(column.evaluate_default(self.txn)?),
+            }
+        }
+        Ok(row)
+    }
+
+    /// Filters rows by a predicate, keeping rows where it is true
+    fn filter(&mut self, rows: Rows, predicate: Expression) -> Result<Rows> {
+        if predicate.is_resolved() {
+            return Ok(Box::new(rows.filter_map(move |r| {
+                r.and_then(|row| match predicate.evaluate(Some(&row))? {
+                    Value::Boolean(true) => Ok(Some(row)),
+                    Value::Boolean(false) | Value::Null => Ok(None),
+                    value => {
+                        Err(Error::Value(format!("Filter returned {}, expected boolean", value)))
+                    }
+                })
+                .transpose()
+            })));
+        }
+        let mut filtered = Vec::new();
+        for row in rows {
+            let row = row?;
+            match self.evaluate(&predicate, Some(&row))? {
+                Value::Boolean(true) => filtered.push(row),
+                Value::Boolean(false) | Value::Null => {}
+                value => {
+                    return Err(Error::Value(format!(
+                        "Filter returned {}, expected boolean",
+                        value
+                    )))
+                }
+            }
+        }
+        Ok(Box::new(filtered.into_iter().map(Ok)))
+    }
+
+    /// Projects rows by evaluating expressions for each row
+    fn project(&mut self, rows: Rows, expressions: Vec<Expression>) -> Result<Rows> {
+        if expressions.iter().all(|e| e.is_resolved()) {
+            return Ok(Box::new(rows.map(move |r| {
+                r.and_then(|row| expressions.iter().map(|e| e.evaluate(Some(&row))).collect())
+            })));
+        }
+        let mut projected = Vec::new();
+        for row in rows {
+            let row = row?;
+            let mut values = Vec::with_capacity(expressions.len());
+            for expr in &expressions {
+                values.push(self.evaluate(expr, Some(&row))?);
+            }
+            projected.push(values);
+        }
+        Ok(Box::new(projected.into_iter().map(Ok)))
+    }
+
+    /// Sorts rows by the order expressions, comparing strings using the expressions' collations
+    fn order(&mut self, rows: Rows, orders: Vec<(Expression, Direction)>) -> Result<Rows> {
+        let mut keyed = Vec::new();
+        for row in rows {
+            let row = row?;
+            let mut keys = Vec::with_capacity(orders.len());
+            for (expr, _) in &orders {
+                keys.push(self.evaluate(expr, Some(&row))?);
+            }
+            keyed.push((keys, row));
+        }
+        let mut error = None;
+        keyed.sort_by(|(a, _), (b, _)| {
+            for ((a, b), (expr, direction)) in a.iter().zip(b).zip(&orders) {
+                let collation = expr.collation().unwrap_or_default();
+                let ordering = match collation.compare_values(a, b) {
+                    Some(ordering) => ordering,
+                    None => {
+                        error.get_or_insert_with(|| {
+                            Error::Value(format!("Can't compare {} and {}", a, b))
+                        });
+                        Ordering::Equal
+                    }
+                };
+                let ordering = match direction {
+                    Direction::Ascending => ordering,
+                    Direction::Descending => ordering.reverse(),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+        if let Some(error) = error {
+            return Err(error);
+        }
+        Ok(Box::new(keyed.into_iter().map(|(_, row)| Ok(row))))
+    }
+
+    /// Evaluates a semi-join predicate for a left row against the right rows. Returns true if
+    /// any right row matches, otherwise NULL if the predicate was NULL for any row, else false.
+    fn semi_match(&mut self, left: &Row, right: &[Row], predicate: &Expression) -> Result<Value> {
+        let predicate = predicate.clone().bind_outer(0, left)?;
+        self.outer.push(left.clone());
+        let result = (|| {
+            let mut matched = Value::Boolean(false);
+            for row in right {
+                match self.evaluate(&predicate, Some(row))? {
+                    Value::Boolean(true) => return Ok(Value::Boolean(true)),
+                    Value::Boolean(false) => {}
+                    Value::Null => matched = Value::Null,
+                    value => {
+                        return Err(Error::Value(format!("Join predicate returned {}", value)))
+                    }
+                }
+            }
+            Ok(matched)
+        })();
+        self.outer.pop();
+        result
+    }
+
+    /// Evaluates an expression for a row, first resolving any subquery and sequence operations.
+    fn evaluate(&mut self, expr: &Expression, row: Option<&Row>) -> Result<Value> {
+        if expr.is_resolved() {
+            return expr.evaluate(row);
+        }
+        self.resolve(expr.clone(), row)?.evaluate(row)
+    }
+
+    /// Resolves the subquery and sequence operations of an expression into constants, by
+    /// executing the subqueries with the row as their enclosing row and fetching sequence values.
+    fn resolve(&mut self, expr: Expression, row: Option<&Row>) -> Result<Expression> {
+        let this = RefCell::new(self);
+        expr.transform(
+            &|e| match e {
+                Expression::Exists(id) => {
+                    let rows = this.borrow_mut().subquery(id, row)?;
+                    Ok(Expression::Constant(Value::Boolean(!rows.is_empty())))
+                }
+                Expression::InSubquery(lhs, id) => {
+                    let rows = this.borrow_mut().subquery(id, row)?;
+                    let list = rows
+                        .into_iter()
+                        .map(|r| Expression::Constant(r.into_iter().next().unwrap_or(Value::Null)))
+                        .collect();
+                    Ok(Expression::InList(lhs, list))
+                }
+                Expression::Subquery(id) => {
+                    let mut rows = this.borrow_mut().subquery(id, row)?.into_iter();
+                    let value = match (rows.next(), rows.next()) {
+                        (Some(row), None) => row.into_iter().next().unwrap_or(Value::Null),
+                        (None, _) => Value::Null,
+                        (Some(_), Some(_)) => {
+                            return Err(Error::Value(
+                                "Subquery used as an expression returned more than one row".into(),
+                            ))
+                        }
+                    };
+                    Ok(Expression::Constant(value))
+                }
+                e @ (Expression::CurrVal(_) | Expression::NextVal(_)) => {
+                    this.borrow_mut().txn.resolve_sequences(e)
+                }
+                e => Ok(e),
+            },
+            &Ok,
+        )
+    }
+
+    /// Executes a subquery for a row of the enclosing query, returning its rows. The subquery
+    /// is bound to the rows of all enclosing queries, and cached if it is uncorrelated.
+    fn subquery(&mut self, id: usize, row: Option<&Row>) -> Result<Vec<Row>> {
+        if let Some(rows) = self.cache.get(&id) {
+            return Ok(rows.clone());
+        }
+        let subqueries = self.subqueries;
+        let node = subqueries
+            .get(id)
+            .ok_or_else(|| Error::Internal(format!("Unknown subquery {}", id)))?
+            .clone();
+        let correlated = node.references_outer(0, subqueries);
+        self.outer.push(row.cloned().unwrap_or_default());
+        let result = (|| {
+            let mut node = node;
+            for (depth, row) in self.outer.iter().rev().enumerate() {
+                node = node.bind_outer(depth, row)?;
+            }
+            let (_, rows) = self.query(node)?;
+            rows.collect::<Result<Vec<_>>>()
+        })();
+        self.outer.pop();
+        let rows = result?;
+        if !correlated {
+            self.cache.insert(id, rows.clone());
+        }
+        Ok(rows)
+    }
+}
+
+/// An executor result set
+pub enum ResultSet {
+    // Transaction started
+    Begin { id: u64, mode: Mode },
+    // Transaction committed
+    Commit { id: u64 },
+    // Transaction rolled back
+    Rollback { id: u64 },
+    // Rows created
+    Create { count: u64 },
+    // Rows deleted
+    Delete { count: u64 },
+    // Rows updated
+    Update { count: u64 },
+    // Table created
+    CreateTable { name: String },
+    // Table dropped
+    DropTable { name: String },
+    // Table altered
+    AlterTable { name: String },
+    // Index created
+    CreateIndex { name: String },
+    // Index dropped
+    DropIndex { name: String },
+    // Sequence created
+    CreateSequence { name: String },
+    // Sequence dropped
+    DropSequence { name: String },
+    // Query result
+    Query { columns: Columns, rows: Rows },
+    // Explain result
+    Explain(Plan),
+}
+
+impl std::fmt::Debug for ResultSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Begin { id, mode } => write!(f, "Begin {{ id: {}, mode: {:?} }}", id, mode),
+            Self::Commit { id } => write!(f, "Commit {{ id: {} }}", id),
+            Self::Rollback { id } => write!(f, "Rollback {{ id: {} }}", id),
+            Self::Create { count } => write!(f, "Create {{ count: {} }}", count),
+            Self::Delete { count } => write!(f, "Delete {{ count: {} }}", count),
+            Self::Update { count } => write!(f, "Update {{ count: {} }}", count),
+            Self::CreateTable { name } => write!(f, "CreateTable {{ name: {} }}", name),
+            Self::DropTable { name } => write!(f, "DropTable {{ name: {} }}", name),
+            Self::AlterTable { name } => write!(f, "AlterTable {{ name: {} }}", name),
+            Self::CreateIndex { name } => write!(f, "CreateIndex {{ name: {} }}", name),
+            Self::DropIndex { name } => write!(f, "DropIndex {{ name: {} }}", name),
+            Self::CreateSequence { name } => write!(f, "CreateSequence {{ name: {} }}", name),
+            Self::DropSequence { name } => write!(f, "DropSequence {{ name: {} }}", name),
+            Self::Query { columns, .. } => write!(f, "Query {{ columns: {:?}, .. }}", columns),
+            Self::Explain(plan) => write!(f, "Explain({:?})", plan),
+        }
+    }
+}
+
+impl ResultSet {
+    /// Collects the rows of a query result set, erroring for other result sets
+    pub fn into_rows(self) -> Result<Vec<Row>> {
+        match self {
+            Self::Query { rows, .. } => rows.collect(),
+            result => Err(Error::Value(format!("Not a query result: {:?}", result))),
+        }
+    }
+
+    /// Returns the first value of the first row of a query result set, or NULL if empty
+    pub fn into_value(self) -> Result<Value> {
+        Ok(self
+            .into_rows()?
+            .into_iter()
+            .next()
+            .and_then(|r| r.into_iter().next())
+            .unwrap_or(Value::Null))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::engine::{Engine, KV};
+    use super::super::parser::Parser;
+    use super::super::plan::Planner;
+    use super::*;
+    use crate::storage::kv;
+
+    /// Sets up a transaction with the tables a (id, x) and b (id, a_id, y)
+    fn setup() -> Result<impl Transaction> {
+        let mut txn = KV::new(kv::MVCC::new(Box::new(kv::Memory::new()))).begin()?;
+        for statement in [
+            "CREATE TABLE a (id INTEGER PRIMARY KEY, x INTEGER)",
+            "CREATE TABLE b (id INTEGER PRIMARY KEY, a_id INTEGER, y INTEGER)",
+            "INSERT INTO a VALUES (1, 10), (2, 20), (3, NULL)",
+            "INSERT INTO b VALUES (1, 1, 10), (2, 1, 20), (3, 2, 30), (4, NULL, 40)",
+        ] {
+            execute(&mut txn, statement)?;
+        }
+        Ok(txn)
+    }
+
+    fn execute<T: Transaction>(txn: &mut T, query: &str) -> Result<ResultSet> {
+        let statement = Parser::new(query).parse()?;
+        Plan::build(statement, txn)?.optimize(txn)?.execute(txn)
+    }
+
+    fn execute_with_recursion_limit<T: Transaction>(
+        txn: &mut T,
+        query: &str,
+        limit: u64,
+    ) -> Result<ResultSet> {
+        let statement = Parser::new(query).parse()?;
+        let plan = Planner::new(txn).with_recursion_limit(limit).build(statement)?;
+        plan.optimize(txn)?.execute(txn)
+    }
+
+    /// Runs a query, returning its rows as integers (NULL as None)
+    fn query<T: Transaction>(txn: &mut T, query: &str) -> Result<Vec<Vec<Option<i64>>>> {
+        execute(txn, query)?
+            .into_rows()?
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|v| match v {
+                        Value::Integer(i) => Ok(Some(i)),
+                        Value::Null => Ok(None),
+                        v => Err(Error::Value(format!("Unexpected value {}", v))),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn select() -> Result<()> {
+        let mut txn = setup()?;
+        assert_eq!(
+            vec![vec![Some(2), Some(21)], vec![Some(1), Some(11)]],
+            query(&mut txn, "SELECT id, x + 1 FROM a WHERE x IS NOT NULL ORDER BY id DESC")?
+        );
+        assert_eq!(
+            vec![vec![Some(1), Some(2), Some(30)], vec![Some(2), Some(1), Some(30)]],
+            query(
+                &mut txn,
+                "SELECT a.id, COUNT(*), SUM(b.y) FROM a JOIN b ON b.a_id = a.id
+                 GROUP BY a.id ORDER BY a.id"
+            )?
+        );
+        assert_eq!(
+            vec![vec![Some(1), Some(1)], vec![Some(1), Some(2)], vec![Some(2), Some(3)]],
+            query(&mut txn, "SELECT a.id, b.id FROM a, b WHERE a.id = b.a_id ORDER BY b.id")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn scalar_subquery() -> Result<()> {
+        let mut txn = setup()?;
+        // An uncorrelated subquery is executed once.
+        assert_eq!(
+            vec![vec![Some(2)]],
+            query(&mut txn, "SELECT id FROM a WHERE x = (SELECT MAX(x) FROM a)")?
+        );
+        // A correlated subquery is executed per row.
+        assert_eq!(
+            vec![vec![Some(1), Some(30)], vec![Some(2), Some(30)], vec![Some(3), None]],
+            query(
+                &mut txn,
+                "SELECT id, (SELECT SUM(y) FROM b WHERE b.a_id = a.id) FROM a ORDER BY id"
+            )?
+        );
+        // A subquery nested in a subquery can refer to either enclosing query.
+        assert_eq!(
+            vec![vec![Some(1)], vec![Some(2)]],
+            query(
+                &mut txn,
+                "SELECT id FROM a WHERE EXISTS (SELECT 1 FROM b WHERE b.a_id = a.id
+                 AND b.y >= (SELECT MIN(y) FROM b AS c WHERE c.a_id = a.id)) ORDER BY id"
+            )?
+        );
+        // A scalar subquery must return at most one row.
+        assert!(matches!(
+            query(&mut txn, "SELECT (SELECT y FROM b WHERE b.a_id = a.id) FROM a"),
+            Err(Error::Value(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn exists() -> Result<()> {
+        let mut txn = setup()?;
+        assert_eq!(
+            vec![vec![Some(1)], vec![Some(2)]],
+            query(
+                &mut txn,
+                "SELECT id FROM a WHERE EXISTS (SELECT * FROM b WHERE b.a_id = a.id) ORDER BY id"
+            )?
+        );
+        assert_eq!(
+            vec![vec![Some(3)]],
+            query(
+                &mut txn,
+                "SELECT id FROM a WHERE NOT EXISTS (SELECT * FROM b WHERE b.a_id = a.id)"
+            )?
+        );
+        // EXISTS can be used outside of a filter, where it's evaluated per row.
+        assert_eq!(
+            vec![vec![Some(1)], vec![Some(1)], vec![Some(0)]],
+            query(
+                &mut txn,
+                "SELECT CASE WHEN EXISTS (SELECT 1 FROM b WHERE b.a_id = a.id) THEN 1 ELSE 0 END
+                 FROM a ORDER BY id"
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn in_subquery() -> Result<()> {
+        let mut txn = setup()?;
+        assert_eq!(
+            vec![vec![Some(1)], vec![Some(2)]],
+            query(&mut txn, "SELECT id FROM a WHERE id IN (SELECT a_id FROM b) ORDER BY id")?
+        );
+        // The subquery returns a NULL, so NOT IN is never true.
+        assert_eq!(
+            Vec::<Vec<Option<i64>>>::new(),
+            query(&mut txn, "SELECT id FROM a WHERE id NOT IN (SELECT a_id FROM b)")?
+        );
+        assert_eq!(
+            vec![vec![Some(3)]],
+            query(
+                &mut txn,
+                "SELECT id FROM a WHERE id NOT IN (SELECT a_id FROM b WHERE a_id IS NOT NULL)"
+            )?
+        );
+        // Prefix NOT negates the whole IN.
+        assert_eq!(
+            vec![vec![Some(3)]],
+            query(
+                &mut txn,
+                "SELECT id FROM a WHERE NOT id IN (SELECT a_id FROM b WHERE a_id IS NOT NULL)"
+            )?
+        );
+        // A correlated IN subquery.
+        assert_eq!(
+            vec![vec![Some(1)]],
+            query(&mut txn, "SELECT id FROM a WHERE x IN (SELECT y FROM b WHERE b.a_id = a.id)")?
+        );
+        // IN subqueries must return a single column.
+        assert!(matches!(
+            query(&mut txn, "SELECT id FROM a WHERE id IN (SELECT id, y FROM b)"),
+            Err(Error::Value(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn from_subquery() -> Result<()> {
+        let mut txn = setup()?;
+        assert_eq!(
+            vec![vec![Some(1), Some(2)], vec![Some(2), Some(1)]],
+            query(
+                &mut txn,
+                "SELECT s.a_id, s.n FROM (SELECT a_id, COUNT(*) AS n FROM b GROUP BY a_id) AS s
+                 WHERE s.a_id IS NOT NULL ORDER BY s.a_id"
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn join() -> Result<()> {
+        let mut txn = setup()?;
+        assert_eq!(
+            vec![
+                vec![Some(1), Some(1)],
+                vec![Some(1), Some(2)],
+                vec![Some(2), Some(3)],
+                vec![Some(3), None],
+                vec![None, Some(4)],
+            ],
+            query(&mut txn, "SELECT a.id, b.id FROM a FULL OUTER JOIN b ON a.id = b.a_id")?
+        );
+
+        // USING and NATURAL joins output the join columns once, followed by the other columns.
+        let using = vec![
+            vec![Some(1), Some(10), Some(1), Some(10)],
+            vec![Some(2), Some(20), Some(1), Some(20)],
+            vec![Some(3), None, Some(2), Some(30)],
+        ];
+        assert_eq!(using, query(&mut txn, "SELECT * FROM a JOIN b USING (id)")?);
+        assert_eq!(using, query(&mut txn, "SELECT * FROM a NATURAL JOIN b")?);
+        assert_eq!(
+            vec![vec![Some(1)], vec![Some(2)], vec![Some(3)]],
+            query(&mut txn, "SELECT id FROM a NATURAL JOIN b")?
+        );
+
+        // Join columns are taken from the side that is always emitted, or coalesced from both
+        // sides for full joins, and can be referred to via either table.
+        execute(&mut txn, "DELETE FROM a WHERE id = 3")?;
+        assert_eq!(
+            vec![
+                vec![Some(1), Some(1), Some(10)],
+                vec![Some(2), Some(2), Some(20)],
+                vec![Some(3), Some(3), None],
+                vec![Some(4), Some(4), None],
+            ],
+            query(&mut txn, "SELECT a.id, b.id, x FROM a RIGHT JOIN b USING (id)")?
+        );
+        assert_eq!(
+            vec![vec![Some(1)], vec![Some(2)], vec![Some(3)], vec![Some(4)]],
+            query(&mut txn, "SELECT id FROM a FULL JOIN b USING (id) ORDER BY id")?
+        );
+        assert!(query(&mut txn, "SELECT * FROM a JOIN b USING (y)").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn distinct() -> Result<()> {
+        let mut txn = setup()?;
+        execute(&mut txn, "INSERT INTO b VALUES (5, 2, 30), (6, NULL, 40), (7, 1, NULL)")?;
+        assert_eq!(
+            vec![vec![Some(1)], vec![Some(2)], vec![None]],
+            query(&mut txn, "SELECT DISTINCT a_id FROM b")?
+        );
+        assert_eq!(
+            vec![vec![Some(1), Some(10)], vec![Some(2), Some(30)], vec![None, Some(40)]],
+            query(&mut txn, "SELECT DISTINCT ON (a_id) a_id, y FROM b")?
+        );
+
+        // COUNT(*) counts rows, while other aggregates skip NULLs and, with DISTINCT, duplicates.
+        assert_eq!(
+            vec![vec![Some(7), Some(6), Some(4), Some(2), Some(100)]],
+            query(
+                &mut txn,
+                "SELECT COUNT(*), COUNT(y), COUNT(DISTINCT y), COUNT(DISTINCT a_id), \
+                    SUM(DISTINCT y) FROM b",
+            )?
+        );
+        assert_eq!(
+            vec![vec![Some(1), Some(2)], vec![Some(2), Some(1)], vec![None, Some(1)]],
+            query(&mut txn, "SELECT a_id, COUNT(DISTINCT y) FROM b GROUP BY a_id")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn aggregate() -> Result<()> {
+        let mut txn = setup()?;
+        let rows = |txn: &mut _, query: &str| execute(txn, query)?.into_rows();
+        assert_eq!(
+            vec![vec![
+                Value::String("10;20;30;40".into()),
+                Value::String("1,1,2".into()),
+                Value::Boolean(true),
+                Value::Boolean(false),
+                Value::Float(25.0),
+                Value::Float(125.0),
+            ]],
+            rows(
+                &mut txn,
+                "SELECT string_agg(y, ';'), group_concat(a_id), bool_or(y > 30), \
+                    every(y > 10), percentile_cont(y, 0.5), var_pop(y) FROM b",
+            )?
+        );
+        assert_eq!(
+            vec![
+                vec![Value::Integer(1), Value::Float(15.0)],
+                vec![Value::Integer(2), Value::Float(30.0)],
+            ],
+            rows(&mut txn, "SELECT a_id, avg(y) FROM b WHERE a_id IS NOT NULL GROUP BY a_id")?
+        );
+        assert!(rows(&mut txn, "SELECT percentile_cont(y, 2) FROM b").is_err());
+        assert!(rows(&mut txn, "SELECT string_agg(y) FROM b").is_err());
+        assert!(rows(&mut txn, "SELECT sum(y, 1) FROM b").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn grouping_sets() -> Result<()> {
+        let mut txn = setup()?;
+        execute(&mut txn, "INSERT INTO b VALUES (5, 2, NULL)")?;
+
+        // Rolled-up columns are NULL, and grouping() distinguishes them from NULL values.
+        // Groups are emitted in order of first appearance.
+        assert_eq!(
+            vec![
+                vec![Some(1), Some(10), Some(10), Some(0)],
+                vec![Some(1), None, Some(30), Some(1)],
+                vec![None, None, Some(100), Some(3)],
+                vec![Some(1), Some(20), Some(20), Some(0)],
+                vec![Some(2), Some(30), Some(30), Some(0)],
+                vec![Some(2), None, Some(30), Some(1)],
+                vec![None, Some(40), Some(40), Some(0)],
+                vec![None, None, Some(40), Some(1)],
+                vec![Some(2), None, None, Some(0)],
+            ],
+            query(
+                &mut txn,
+                "SELECT a_id, y, SUM(y), grouping(a_id, y) FROM b GROUP BY ROLLUP (a_id, y)",
+            )?
+        );
+
+        // CUBE groups by every subset of the columns.
+        assert_eq!(
+            vec![
+                vec![Some(0), Some(5)],
+                vec![Some(1), Some(3)],
+                vec![Some(2), Some(5)],
+                vec![Some(3), Some(1)],
+            ],
+            query(
+                &mut txn,
+                "SELECT g, COUNT(*) FROM (SELECT grouping(a_id, y) AS g FROM b \
+                    GROUP BY CUBE (a_id, y)) s GROUP BY g ORDER BY g",
+            )?
+        );
+
+        // Explicit grouping sets, where the empty set gives a row even without source rows.
+        assert_eq!(
+            vec![vec![Some(2), Some(1)], vec![Some(2), Some(2)], vec![Some(1), None]],
+            query(&mut txn, "SELECT COUNT(*), a_id FROM b GROUP BY GROUPING SETS ((a_id))")?
+        );
+        assert_eq!(
+            vec![vec![Some(0), None, Some(1)]],
+            query(
+                &mut txn,
+                "SELECT COUNT(*), a_id, grouping(a_id) FROM b WHERE y > 100 \
+                    GROUP BY GROUPING SETS ((a_id), ())",
+            )?
+        );
+        assert!(query(&mut txn, "SELECT grouping(y) FROM b GROUP BY a_id").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn mutations() -> Result<()> {
+        let mut txn = setup()?;
+        assert!(matches!(
+            execute(
+                &mut txn,
+                "UPDATE b SET y = y + 1 WHERE a_id IN (SELECT id FROM a WHERE x = 10)"
+            )?,
+            ResultSet::Update { count: 2 }
+        ));
+        assert!(matches!(
+            execute(
+                &mut txn,
+                "DELETE FROM b WHERE NOT EXISTS (SELECT 1 FROM a WHERE a.id = b.a_id)"
+            )?,
+            ResultSet::Delete { count: 1 }
+        ));
+        assert_eq!(
+            vec![vec![Some(11)], vec![Some(21)], vec![Some(30)]],
+            query(&mut txn, "SELECT y FROM b ORDER BY id")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn referential_actions() -> Result<()> {
+        let mut txn = setup()?;
+        for statement in [
+            "CREATE TABLE c (id INTEGER PRIMARY KEY, \
+                a_id INTEGER REFERENCES a ON DELETE CASCADE ON UPDATE CASCADE)",
+            "CREATE TABLE d (id INTEGER PRIMARY KEY, a_id INTEGER DEFAULT 3 \
+                REFERENCES a ON DELETE SET DEFAULT ON UPDATE SET NULL)",
+            "CREATE TABLE e (id INTEGER PRIMARY KEY, c_id INTEGER REFERENCES c ON DELETE SET NULL)",
+            "CREATE TABLE f (id INTEGER PRIMARY KEY, a_id INTEGER REFERENCES a)",
+            "INSERT INTO c VALUES (1, 1), (2, 2)",
+            "INSERT INTO d VALUES (1, 1), (2, 2)",
+            "INSERT INTO e VALUES (1, 1), (2, 2)",
+            "INSERT INTO f VALUES (1, 1), (2, 2)",
+        ] {
+            execute(&mut txn, statement)?;
+        }
+
+        // f references a with the default RESTRICT action.
+        assert!(execute(&mut txn, "DELETE FROM a WHERE id = 1").is_err());
+        execute(&mut txn, "DELETE FROM f WHERE a_id = 1")?;
+
+        // Deleting cascades to c, which in turn sets e's reference to NULL, and sets d's
+        // reference to its default.
+        execute(&mut txn, "DELETE FROM a WHERE id = 1")?;
+        assert_eq!(vec![vec![Some(2), Some(2)]], query(&mut txn, "SELECT * FROM c")?);
+        assert_eq!(
+            vec![vec![Some(1), Some(3)], vec![Some(2), Some(2)]],
+            query(&mut txn, "SELECT * FROM d ORDER BY id")?
+        );
+        assert_eq!(
+            vec![vec![Some(1), None], vec![Some(2), Some(2)]],
+            query(&mut txn, "SELECT * FROM e ORDER BY id")?
+        );
+
+        // Updating a primary key cascades to c, and sets d's reference to NULL. f restricts
+        // updates of referenced keys, which are checked before anything is written.
+        assert!(execute(&mut txn, "UPDATE a SET id = 4 WHERE id = 2").is_err());
+        assert_eq!(vec![vec![Some(2)]], query(&mut txn, "SELECT a_id FROM c")?);
+        execute(&mut txn, "DELETE FROM f")?;
+        execute(&mut txn, "UPDATE a SET id = 4 WHERE id = 2")?;
+        assert_eq!(vec![vec![Some(2), Some(4)]], query(&mut txn, "SELECT * FROM c")?);
+        assert_eq!(
+            vec![vec![Some(1), Some(3)], vec![Some(2), None]],
+            query(&mut txn, "SELECT * FROM d ORDER BY id")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn referential_actions_indexed() -> Result<()> {
+        let mut txn = setup()?;
+        for statement in [
+            "CREATE TABLE c (id INTEGER PRIMARY KEY, \
+                a_id INTEGER REFERENCES a ON DELETE CASCADE ON UPDATE CASCADE)",
+            "CREATE INDEX c_a_id ON c (a_id)",
+            "CREATE TABLE n (name TEXT PRIMARY KEY)",
+            "CREATE TABLE m (id INTEGER PRIMARY KEY, \
+                name TEXT COLLATE NOCASE REFERENCES n ON DELETE CASCADE)",
+            "CREATE INDEX m_name ON m (name)",
+            "INSERT INTO c VALUES (1, 1), (2, 2), (3, 1)",
+            "INSERT INTO n VALUES ('a'), ('A')",
+            "INSERT INTO m VALUES (1, 'a'), (2, 'A')",
+        ] {
+            execute(&mut txn, statement)?;
+        }
+
+        // Referencing rows are found through the index on the foreign key columns.
+        execute(&mut txn, "DELETE FROM a WHERE id = 1")?;
+        assert_eq!(vec![vec![Some(2), Some(2)]], query(&mut txn, "SELECT * FROM c")?);
+        execute(&mut txn, "UPDATE a SET id = 4 WHERE id = 2")?;
+        assert_eq!(vec![vec![Some(2), Some(4)]], query(&mut txn, "SELECT * FROM c")?);
+
+        // The index is collated, but only rows with the exact referenced value cascade.
+        execute(&mut txn, "DELETE FROM n WHERE name = 'a'")?;
+        assert_eq!(vec![vec![Some(2)]], query(&mut txn, "SELECT id FROM m")?);
+        Ok(())
+    }
+
+    #[test]
+    fn check() -> Result<()> {
+        let mut txn = setup()?;
+        execute(
+            &mut txn,
+            "CREATE TABLE c (id INTEGER PRIMARY KEY, x INTEGER CHECK (x > 0), y INTEGER, \
+                CHECK (y > x), CONSTRAINT y_small CHECK (y < 100))",
+        )?;
+        execute(&mut txn, "INSERT INTO c VALUES (1, 1, 2), (2, 1, NULL)")?;
+
+        // Unnamed constraints are named after the table and column.
+        let error = |txn: &mut _, statement| match execute(txn, statement) {
+            Err(Error::Value(message)) => message,
+            result => panic!("Unexpected result {:?}", result.map(|_| ())),
+        };
+        assert!(error(&mut txn, "INSERT INTO c VALUES (3, 0, 1)").contains("c_x_check"));
+        assert!(error(&mut txn, "INSERT INTO c VALUES (3, 2, 1)").contains("c_check"));
+        assert!(error(&mut txn, "INSERT INTO c VALUES (3, 1, 100)").contains("y_small"));
+        assert!(error(&mut txn, "UPDATE c SET y = 0 WHERE id = 1").contains("c_check"));
+        assert_eq!(
+            vec![vec![Some(1), Some(1), Some(2)], vec![Some(2), Some(1), None]],
+            query(&mut txn, "SELECT * FROM c ORDER BY id")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sequences() -> Result<()> {
+        let mut txn = setup()?;
+        for statement in [
+            "CREATE TABLE s (id SERIAL PRIMARY KEY, x INTEGER DEFAULT 2 * 3, \
+                t TIMESTAMP DEFAULT now())",
+            "CREATE TABLE u (id INTEGER PRIMARY KEY AUTOINCREMENT, y INTEGER)",
+            "CREATE SEQUENCE down START WITH 10 INCREMENT BY -5",
+            "INSERT INTO s (x) VALUES (1)",
+            "INSERT INTO s (t) VALUES (NULL), (NULL)",
+            "INSERT INTO u (y) VALUES (1)",
+            "INSERT INTO u VALUES (10, 2)",
+        ] {
+            execute(&mut txn, statement)?;
+        }
+
+        // Serial and autoincrement columns take values from their sequences, and defaults are
+        // evaluated for each row.
+        assert_eq!(
+            vec![vec![Some(1), Some(1)], vec![Some(2), Some(6)], vec![Some(3), Some(6)]],
+            query(&mut txn, "SELECT id, x FROM s ORDER BY id")?
+        );
+        assert_eq!(
+            vec![vec![Some(1)]],
+            query(&mut txn, "SELECT COUNT(*) FROM s WHERE t IS NOT NULL")?
+        );
+        assert_eq!(
+            vec![vec![Some(1), Some(1)], vec![Some(10), Some(2)]],
+            query(&mut txn, "SELECT * FROM u ORDER BY id")?
+        );
+        assert_eq!(vec![vec![Some(3)]], query(&mut txn, "SELECT currval('s_id_seq')")?);
+
+        // Explicit sequences, with nextval() and currval().
+        assert!(query(&mut txn, "SELECT currval('down')").is_err());
+        assert_eq!(vec![vec![Some(10)]], query(&mut txn, "SELECT nextval('down')")?);
+        assert_eq!(vec![vec![Some(5)]], query(&mut txn, "SELECT nextval('down')")?);
+        assert_eq!(vec![vec![Some(5)]], query(&mut txn, "SELECT currval('down')")?);
+
+        // Comparisons evaluate nextval() once.
+        assert_eq!(
+            vec![vec![Value::Boolean(true)]],
+            execute(&mut txn, "SELECT nextval('down') >= 0")?.into_rows()?
+        );
+        assert_eq!(
+            vec![vec![Value::Boolean(true)]],
+            execute(&mut txn, "SELECT nextval('down') <= -5")?.into_rows()?
+        );
+        assert_eq!(vec![vec![Some(-5)]], query(&mut txn, "SELECT currval('down')")?);
+        assert!(query(&mut txn, "SELECT nextval('missing')").is_err());
+        assert!(execute(&mut txn, "CREATE SEQUENCE down").is_err());
+        assert!(execute(&mut txn, "CREATE SEQUENCE zero INCREMENT BY 0").is_err());
+        execute(&mut txn, "DROP SEQUENCE down")?;
+        assert!(query(&mut txn, "SELECT nextval('down')").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn cte() -> Result<()> {
+        let mut txn = setup()?;
+        // Later CTEs can refer to earlier ones, and CTEs can be scanned several times.
+        assert_eq!(
+            vec![vec![Some(1), Some(2)], vec![Some(2), Some(1)]],
+            query(
+                &mut txn,
+                "WITH c (a_id, n) AS (SELECT a_id, COUNT(*) FROM b GROUP BY a_id),
+                      d AS (SELECT * FROM c WHERE a_id IS NOT NULL)
+                 SELECT d.a_id, d.n FROM d JOIN a ON a.id = d.a_id
+                 WHERE d.n <= (SELECT MAX(n) FROM d) ORDER BY d.a_id"
+            )?
+        );
+        // An inner CTE shadows an outer one of the same name.
+        assert_eq!(
+            vec![vec![Some(2)]],
+            query(
+                &mut txn,
+                "WITH c AS (SELECT 1 AS n) SELECT * FROM (WITH c AS (SELECT 2 AS n)
+                 SELECT n FROM c) AS s"
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cte_recursive() -> Result<()> {
+        let mut txn = setup()?;
+        assert_eq!(
+            (1..=5).map(|i| vec![Some(i)]).collect::<Vec<_>>(),
+            query(
+                &mut txn,
+                "WITH RECURSIVE r (n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM r WHERE n < 5)
+                 SELECT n FROM r"
+            )?
+        );
+
+        // A hierarchy, walked from its root.
+        for statement in [
+            "CREATE TABLE emp (id INTEGER PRIMARY KEY, boss INTEGER REFERENCES emp)",
+            "INSERT INTO emp VALUES (1, NULL), (2, 1), (3, 1), (4, 2), (5, 4), (6, NULL)",
+        ] {
+            execute(&mut txn, statement)?;
+        }
+        assert_eq!(
+            vec![
+                vec![Some(1), Some(0)],
+                vec![Some(2), Some(1)],
+                vec![Some(3), Some(1)],
+                vec![Some(4), Some(2)],
+                vec![Some(5), Some(3)],
+            ],
+            query(
+                &mut txn,
+                "WITH RECURSIVE t (id, depth) AS (
+                     SELECT id, 0 FROM emp WHERE id = 1
+                     UNION ALL
+                     SELECT emp.id, t.depth + 1 FROM emp JOIN t ON emp.boss = t.id)
+                 SELECT id, depth FROM t ORDER BY id"
+            )?
+        );
+
+        // UNION discards rows already seen, so cycles reach a fixpoint.
+        assert_eq!(
+            vec![vec![Some(0)], vec![Some(1)], vec![Some(2)]],
+            query(
+                &mut txn,
+                "WITH RECURSIVE r (n) AS (SELECT 0 UNION SELECT (n + 1) % 3 FROM r)
+                 SELECT n FROM r ORDER BY n"
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cte_recursion_limit() -> Result<()> {
+        let mut txn = setup()?;
+        let count = "WITH RECURSIVE r (n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM r WHERE n < 10)
+                     SELECT COUNT(*) FROM r";
+        assert_eq!(
+            Value::Integer(10),
+            execute_with_recursion_limit(&mut txn, count, 10)?.into_value()?
+        );
+        assert!(matches!(execute_with_recursion_limit(&mut txn, count, 8), Err(Error::Value(_))));
+        // UNION ALL never reaches a fixpoint on a cycle.
+        assert!(matches!(
+            execute(
+                &mut txn,
+                "WITH RECURSIVE r (n) AS (SELECT 0 UNION ALL SELECT (n + 1) % 3 FROM r)
+                               SELECT * FROM r"
+            ),
+            Err(Error::Value(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn set_operation() -> Result<()> {
+        let mut txn = setup()?;
+        let rows = |txn: &mut _, query: &str| execute(txn, query)?.into_rows();
+
+        // Column counts and datatypes are checked during planning, even without any rows.
+        for query in [
+            "SELECT id FROM a WHERE FALSE UNION SELECT id, x FROM b WHERE FALSE",
+            "SELECT id FROM a WHERE FALSE UNION SELECT 'x'",
+            "SELECT COUNT(*) FROM a EXCEPT SELECT TRUE",
+        ] {
+            assert!(matches!(rows(&mut txn, query), Err(Error::Value(_))), "{}", query);
+        }
+
+        // Numeric columns are coerced to a common datatype, also for deduplication.
+        assert_eq!(
+            vec![vec![Value::Float(1.0)], vec![Value::Float(2.5)]],
+            rows(&mut txn, "SELECT 1 UNION SELECT 1.0 UNION SELECT 2.5")?
+        );
+        assert_eq!(
+            vec![vec![Value::Float(10.0)], vec![Value::Float(20.0)]],
+            rows(&mut txn, "SELECT x FROM a WHERE x IS NOT NULL INTERSECT SELECT y * 1.0 FROM b")?
+        );
+        assert_eq!(
+            vec![vec![Value::Decimal("1".parse()?)], vec![Value::Decimal("1.5".parse()?)]],
+            rows(&mut txn, "SELECT 1 UNION ALL SELECT CAST(1.5 AS DECIMAL(2, 1))")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn window() -> Result<()> {
+        let mut txn = setup()?;
+        execute(&mut txn, "CREATE TABLE w (id INTEGER PRIMARY KEY, g INTEGER, v INTEGER)")?;
+        execute(
+            &mut txn,
+            "INSERT INTO w VALUES (1, 1, 1), (2, 1, 2), (3, 1, 2), (4, 1, NULL), (5, 1, 5), \
+             (6, 2, 6), (7, 2, 7)",
+        )?;
+        let column = |txn: &mut _, window: &str| -> Result<Vec<Option<i64>>> {
+            let statement = format!("SELECT {} FROM w ORDER BY id", window);
+            Ok(query(txn, &statement)?.into_iter().map(|row| row[0]).collect())
+        };
+
+        // Peers are included in RANGE frames and share their rank.
+        assert_eq!(
+            vec![Some(1), Some(5), Some(5), None, Some(10), Some(6), Some(13)],
+            column(&mut txn, "SUM(v) OVER (PARTITION BY g ORDER BY v)")?
+        );
+        assert_eq!(
+            vec![Some(2), Some(3), Some(3), Some(1), Some(5), Some(6), Some(7)],
+            column(&mut txn, "RANK() OVER (ORDER BY v)")?
+        );
+
+        // Sliding frames retract values as they leave the frame, and empty frames are NULL.
+        assert_eq!(
+            vec![Some(1), Some(3), Some(4), Some(2), Some(5), Some(11), Some(13)],
+            column(&mut txn, "SUM(v) OVER (ORDER BY id ROWS BETWEEN 1 PRECEDING AND CURRENT ROW)")?
+        );
+        assert_eq!(
+            vec![Some(1), Some(2), Some(2), None, Some(5), Some(6), Some(7)],
+            column(&mut txn, "SUM(v) OVER (ORDER BY id ROWS BETWEEN CURRENT ROW AND CURRENT ROW)")?
+        );
+        assert_eq!(
+            vec![Some(2), Some(3), Some(2), Some(2), Some(2), Some(3), Some(2)],
+            column(
+                &mut txn,
+                "COUNT(v) OVER (ORDER BY id ROWS BETWEEN 1 PRECEDING AND 1 FOLLOWING)"
+            )?
+        );
+        assert_eq!(
+            vec![Some(1), Some(1), Some(2), Some(2), Some(5), Some(5), Some(6)],
+            column(&mut txn, "MIN(v) OVER (ORDER BY id ROWS BETWEEN 1 PRECEDING AND 1 FOLLOWING)")?
+        );
+        assert_eq!(
+            vec![Some(23), Some(22), Some(20), Some(18), Some(18), Some(13), Some(7)],
+            column(
+                &mut txn,
+                "SUM(v) OVER (ORDER BY id ROWS BETWEEN CURRENT ROW AND UNBOUNDED FOLLOWING)"
+            )?
+        );
+
+        // RANGE offsets are relative to the ORDER BY value, and NULL rows frame their peers.
+        assert_eq!(
+            vec![Some(5), Some(5), Some(5), None, Some(11), Some(18), Some(13)],
+            column(&mut txn, "SUM(v) OVER (ORDER BY v RANGE BETWEEN 1 PRECEDING AND 1 FOLLOWING)")?
+        );
+
+        // A running sum over a larger input.
+        let statement = "WITH RECURSIVE t (n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM t \
+                         WHERE n < 2000) SELECT n, SUM(n) OVER (ORDER BY n), \
+                         SUM(n) OVER (ORDER BY n ROWS 9 PRECEDING) FROM t ORDER BY n";
+        let rows = execute_with_recursion_limit(&mut txn, statement, 2000)?.into_rows()?;
+        assert_eq!(2000, rows.len());
+        for (i, row) in rows.iter().enumerate() {
+            let n = i as i64 + 1;
+            let window = n - (n - 10).max(0);
+            let expect = [n, n * (n + 1) / 2, window * (2 * n - window + 1) / 2];
+            assert_eq!(expect.map(Value::Integer).to_vec(), *row);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn char() -> Result<()> {
+        let mut txn = setup()?;
+        execute(&mut txn, "CREATE TABLE c (id INTEGER PRIMARY KEY, c CHAR(3) UNIQUE, d CHAR)")?;
+        execute(
+            &mut txn,
+            "INSERT INTO c VALUES (1, 'a', 'x'), (2, 'ab ', 'y '), (3, 'abc', NULL)",
+        )?;
+
+        // Trailing spaces are insignificant, for comparisons, grouping and unique values.
+        assert_eq!(vec![vec![Some(1)]], query(&mut txn, "SELECT id FROM c WHERE c = 'a'")?);
+        assert_eq!(vec![vec![Some(2)]], query(&mut txn, "SELECT id FROM c WHERE c = 'ab'")?);
+        assert_eq!(
+            vec![vec![Some(1)]],
+            query(&mut txn, "SELECT COUNT(*) FROM c WHERE d = 'y' GROUP BY d")?
+        );
+        assert!(execute(&mut txn, "INSERT INTO c VALUES (4, 'a  ', NULL)").is_err());
+
+        // CHAR is CHAR(1), and lengths exclude trailing spaces.
+        assert!(execute(&mut txn, "INSERT INTO c VALUES (4, 'abcd', NULL)").is_err());
+        assert!(execute(&mut txn, "INSERT INTO c VALUES (4, NULL, 'xy')").is_err());
+        execute(&mut txn, "INSERT INTO c VALUES (4, 'abd   ', 'z   ')")?;
+        Ok(())
+    }
+
+    #[test]
+    fn table_function() -> Result<()> {
+        let mut txn = setup()?;
+        let rows = |txn: &mut _, query: &str| execute(txn, query)?.into_rows();
+        let json = |s: &str| Value::Json(s.into());
+
+        assert_eq!(
+            vec![
+                vec![Value::String("a".into()), json("1")],
+                vec![Value::String("b".into()), json("[2,3]")],
+            ],
+            rows(&mut txn, r#"SELECT "key", value FROM json_each('{"a": 1, "b": [2, 3]}')"#)?
+        );
+        // Table functions can be aliased and joined like tables.
+        assert_eq!(
+            vec![vec![Value::Integer(1), json("20")]],
+            rows(
+                &mut txn,
+                "SELECT a.id, e.value FROM a JOIN json_each('[10, 20]') e ON a.id = e.\"key\"",
+            )?
+        );
+        assert_eq!(Vec::<Row>::new(), rows(&mut txn, "SELECT * FROM json_each(NULL)")?);
+        assert!(rows(&mut txn, "SELECT * FROM json_each('[1,')").is_err());
+        assert_eq!(
+            vec![vec![Value::String("a".into())], vec![Value::String("b".into())]],
+            rows(&mut txn, "SELECT * FROM regexp_split_to_table('a, b', ',\\s*')")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn case_in_between_cast() -> Result<()> {
+        let mut txn = setup()?;
+
+        // A NULL operand or condition falls through to ELSE, which defaults to NULL.
+        assert_eq!(
+            vec![vec![Some(1), Some(1)], vec![Some(2), Some(2)], vec![Some(3), None]],
+            query(&mut txn, "SELECT id, CASE x WHEN 10 THEN 1 WHEN 20 THEN 2 END FROM a")?
+        );
+        assert_eq!(
+            vec![vec![Some(1), Some(0)], vec![Some(2), Some(1)], vec![Some(3), Some(0)]],
+            query(&mut txn, "SELECT id, CASE WHEN x > 15 THEN 1 ELSE 0 END FROM a")?
+        );
+
+        // IN lists containing NULL never give false, so NOT IN doesn't match either.
+        assert_eq!(vec![vec![Some(1)]], query(&mut txn, "SELECT id FROM a WHERE x IN (10, NULL)")?);
+        assert!(query(&mut txn, "SELECT id FROM a WHERE x NOT IN (10, NULL)")?.is_empty());
+        assert_eq!(vec![vec![Some(2)]], query(&mut txn, "SELECT id FROM a WHERE x NOT IN (10)")?);
+
+        assert_eq!(
+            vec![vec![Some(2)], vec![Some(3)]],
+            query(&mut txn, "SELECT id FROM b WHERE y BETWEEN 15 AND 30")?
+        );
+        assert_eq!(
+            vec![vec![Some(1)], vec![Some(4)]],
+            query(&mut txn, "SELECT id FROM b WHERE y NOT BETWEEN 15 AND 30")?
+        );
+
+        assert_eq!(
+            vec![vec![Some(15), Some(3)]],
+            query(&mut txn, "SELECT CAST('12' AS INTEGER) + '3'::INTEGER, CAST(2.6 AS INTEGER)")?
+        );
+        assert_eq!(vec![vec![Some(2)]], query(&mut txn, "SELECT id FROM a WHERE x::TEXT = '20'")?);
+        assert!(query(&mut txn, "SELECT CAST('x' AS INTEGER)").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn collation() -> Result<()> {
+        let mut txn = setup()?;
+        execute(
+            &mut txn,
+            "CREATE TABLE c (id INTEGER PRIMARY KEY, s TEXT COLLATE NOCASE UNIQUE, \
+                u TEXT COLLATE UNICODE, b TEXT)",
+        )?;
+        execute(&mut txn, "INSERT INTO c VALUES (1, 'a', 'É', 'a'), (2, 'B', 'e', 'B')")?;
+        execute(&mut txn, "INSERT INTO c VALUES (3, 'c', 'x', 'A')")?;
+
+        // Column collations apply to comparisons and unique keys, including index lookups.
+        assert_eq!(vec![vec![Some(1)]], query(&mut txn, "SELECT id FROM c WHERE s = 'A'")?);
+        assert_eq!(
+            vec![vec![Some(2)], vec![Some(3)]],
+            query(&mut txn, "SELECT id FROM c WHERE s IN ('b', 'C')")?
+        );
+        assert_eq!(vec![vec![Some(1)]], query(&mut txn, "SELECT id FROM c WHERE u = 'é'")?);
+        assert_eq!(vec![vec![Some(1)]], query(&mut txn, "SELECT id FROM c WHERE b = 'a'")?);
+        assert!(query(&mut txn, "INSERT INTO c VALUES (4, 'A', NULL, NULL)").is_err());
+        assert!(
+            execute(&mut txn, "CREATE TABLE n (id INTEGER PRIMARY KEY COLLATE NOCASE)").is_err()
+        );
+        assert!(execute(&mut txn, "CREATE TABLE n (id TEXT PRIMARY KEY COLLATE NOCASE)").is_err());
+        assert!(execute(
+            &mut txn,
+            "CREATE TABLE n (id INTEGER, s TEXT COLLATE NOCASE, PRIMARY KEY (id, s))"
+        )
+        .is_err());
+
+        // COLLATE overrides the default collation, including on constants, but conflicting
+        // explicit collations are an error.
+        assert_eq!(
+            vec![vec![Some(1)], vec![Some(3)]],
+            query(&mut txn, "SELECT id FROM c WHERE b = 'A' COLLATE NOCASE")?
+        );
+        assert_eq!(
+            vec![vec![Some(1)]],
+            query(&mut txn, "SELECT 1 WHERE 'a' COLLATE NOCASE = 'A'")?
+        );
+        assert!(query(&mut txn, "SELECT id FROM c WHERE s = b COLLATE BINARY").is_err());
+
+        // ORDER BY, GROUP BY and DISTINCT use the collation too.
+        assert_eq!(
+            vec![vec![Some(3)], vec![Some(2)], vec![Some(1)]],
+            query(&mut txn, "SELECT id FROM c ORDER BY b")?
+        );
+        assert_eq!(
+            vec![vec![Some(1)], vec![Some(3)], vec![Some(2)]],
+            query(&mut txn, "SELECT id FROM c ORDER BY b COLLATE NOCASE, id")?
+        );
+        assert_eq!(
+            vec![vec![Some(1)], vec![Some(2)]],
+            query(&mut txn, "SELECT COUNT(*) FROM c GROUP BY b COLLATE NOCASE ORDER BY 1")?
+        );
+        assert_eq!(
+            vec![vec![Some(2), Some(3)]],
+            query(&mut txn, "SELECT COUNT(DISTINCT b COLLATE NOCASE), COUNT(DISTINCT b) FROM c")?
+        );
+        assert_eq!(
+            vec![vec![Some(2)]],
+            query(&mut txn, "SELECT COUNT(*) FROM (SELECT DISTINCT b COLLATE NOCASE FROM c) d")?
+        );
+
+        // MIN and MAX use the collation like ORDER BY, also as window functions.
+        assert_eq!(
+            vec![vec![Value::from("B"), Value::from("a")]],
+            execute(&mut txn, "SELECT MAX(s), MIN(s) FROM c WHERE id < 3")?.into_rows()?
+        );
+        assert_eq!(
+            vec![vec![Value::from("B")]],
+            execute(&mut txn, "SELECT s FROM c WHERE id < 3 ORDER BY s DESC LIMIT 1")?
+                .into_rows()?
+        );
+        assert_eq!(
+            vec![vec![Value::from("a"), Value::from("B")]],
+            execute(&mut txn, "SELECT MAX(b), MIN(b) FROM c WHERE id < 3")?.into_rows()?
+        );
+        assert_eq!(
+            vec![vec![Value::from("B")], vec![Value::from("B")]],
+            execute(&mut txn, "SELECT MAX(s) OVER () FROM c WHERE id < 3")?.into_rows()?
+        );
+
+        // LIKE uses the collation, while ILIKE is always case-insensitive.
+        assert_eq!(vec![vec![Some(1)]], query(&mut txn, "SELECT id FROM c WHERE b LIKE 'a%'")?);
+        assert_eq!(
+            vec![vec![Some(1)], vec![Some(3)]],
+            query(&mut txn, "SELECT id FROM c WHERE b ILIKE 'a%'")?
+        );
+        assert_eq!(vec![vec![Some(1)]], query(&mut txn, "SELECT id FROM c WHERE s LIKE 'A%'")?);
+        Ok(())
+    }
+
+    #[test]
+    fn datetime() -> Result<()> {
+        let mut txn = setup()?;
+        execute(&mut txn, "CREATE TABLE e (id INTEGER PRIMARY KEY, d DATE, ts TIMESTAMP)")?;
+        execute(
+            &mut txn,
+            "INSERT INTO e VALUES (1, DATE '2024-01-31', TIMESTAMP '2024-01-31 10:00'), \
+                (2, DATE '2024-02-29', TIMESTAMP '2024-03-01T00:00'), (3, NULL, NULL)",
+        )?;
+        assert!(query(&mut txn, "INSERT INTO e VALUES (4, DATE '2023-02-29', NULL)").is_err());
+        assert!(query(&mut txn, "INSERT INTO e VALUES (4, '2024-01-01', NULL)").is_err());
+
+        // Months are added with the day clamped to the end of the month, and date differences
+        // are days.
+        assert_eq!(
+            vec![vec![Some(1)]],
+            query(&mut txn, "SELECT id FROM e WHERE d + INTERVAL '1 month' = DATE '2024-02-29'")?
+        );
+        assert_eq!(
+            vec![vec![Some(30)], vec![Some(1)], vec![None]],
+            query(&mut txn, "SELECT DATE '2024-03-01' - d FROM e ORDER BY id")?
+        );
+        assert_eq!(
+            vec![vec![Some(2)], vec![Some(1)]],
+            query(&mut txn, "SELECT id FROM e WHERE ts > d ORDER BY ts DESC")?
+        );
+        assert_eq!(
+            vec![vec![Some(2024), Some(3)], vec![Some(2024), Some(1)]],
+            query(
+                &mut txn,
+                "SELECT extract('year', ts), extract('month', ts) FROM e WHERE ts IS NOT NULL \
+                    ORDER BY id DESC",
+            )?
+        );
+
+        // Intervals compare by length assuming 30 day months, also when deduplicated.
+        assert_eq!(
+            vec![vec![Some(1)]],
+            query(&mut txn, "SELECT 1 WHERE INTERVAL '1 month' = INTERVAL '720 hours'")?
+        );
+        assert_eq!(
+            vec![vec![Some(1)]],
+            query(
+                &mut txn,
+                "SELECT COUNT(*) FROM \
+                    (SELECT INTERVAL '1 month' UNION SELECT INTERVAL '30 days') i",
+            )?
+        );
+
+        // Equal intervals are the same primary key, and are found by either spelling, while rows
+        // keep the written components.
+        execute(&mut txn, "CREATE TABLE iv (id INTERVAL PRIMARY KEY, n INTEGER)")?;
+        execute(&mut txn, "INSERT INTO iv VALUES (INTERVAL '1 month', 1), (INTERVAL '1 day', 2)")?;
+        assert!(query(&mut txn, "INSERT INTO iv VALUES (INTERVAL '30 days', 3)").is_err());
+        assert_eq!(
+            vec![vec![Some(1)]],
+            query(&mut txn, "SELECT n FROM iv WHERE id = INTERVAL '30 days'")?
+        );
+        assert_eq!(
+            vec![vec![Some(1)]],
+            query(
+                &mut txn,
+                "SELECT COUNT(*) FROM iv WHERE id = INTERVAL '30 days' OR id = INTERVAL '1 month'",
+            )?
+        );
+        execute(&mut txn, "UPDATE iv SET n = 4 WHERE id = INTERVAL '24 hours'")?;
+        assert_eq!(
+            vec![vec![Some(1), Some(4)], vec![Some(0), Some(1)]],
+            query(&mut txn, "SELECT extract('day', id), n FROM iv ORDER BY id")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decimal() -> Result<()> {
+        let mut txn = setup()?;
+        execute(
+            &mut txn,
+            "CREATE TABLE m (id INTEGER PRIMARY KEY, v DECIMAL(5, 2), \
+                e DECIMAL(3, 0) ROUNDING HALF_EVEN)",
+        )?;
+        execute(&mut txn, "INSERT INTO m VALUES (1, 1.125, 2.5), (2, 0.1, 3.5), (3, 0.2, NULL)")?;
+        assert!(query(&mut txn, "INSERT INTO m VALUES (4, 1000, NULL)").is_err());
+        assert!(
+            execute(&mut txn, "CREATE TABLE n (id INTEGER PRIMARY KEY, v DECIMAL(2, 3))").is_err()
+        );
+
+        // Values are rounded to the column scale with its rounding mode, and sums are exact.
+        assert_eq!(
+            vec![vec![Some(113), Some(2)], vec![Some(10), Some(4)], vec![Some(20), None]],
+            query(&mut txn, "SELECT CAST(v * 100 AS INTEGER), CAST(e AS INTEGER) FROM m")?
+        );
+        assert_eq!(vec![vec![Some(1)]], query(&mut txn, "SELECT 1 FROM m WHERE v = 1.13")?);
+        assert_eq!(
+            vec![vec![Some(143)]],
+            query(&mut txn, "SELECT CAST(SUM(v) * 100 AS INTEGER) FROM m")?
+        );
+        assert_eq!(
+            vec![vec![Some(1)]],
+            query(
+                &mut txn,
+                "SELECT 1 WHERE CAST(0.1 AS DECIMAL(2, 1)) + CAST(0.2 AS DECIMAL(2, 1)) = \
+                    CAST(0.3 AS DECIMAL(2, 1))",
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn binary() -> Result<()> {
+        let mut txn = setup()?;
+        execute(&mut txn, "CREATE TABLE bin (id INTEGER PRIMARY KEY, v BYTEA UNIQUE)")?;
+        execute(
+            &mut txn,
+            "INSERT INTO bin VALUES (1, X'0102'), (2, X'01'), (3, X'ff'), (4, NULL)",
+        )?;
+        assert!(query(&mut txn, "INSERT INTO bin VALUES (5, X'0102')").is_err());
+        assert!(query(&mut txn, "INSERT INTO bin VALUES (5, 'text')").is_err());
+
+        // Values compare and sort bytewise, and can be looked up through the unique index.
+        assert_eq!(vec![vec![Some(1)]], query(&mut txn, "SELECT id FROM bin WHERE v = X'0102'")?);
+        assert_eq!(
+            vec![vec![Some(4)], vec![Some(2)], vec![Some(1)], vec![Some(3)]],
+            query(&mut txn, "SELECT id FROM bin ORDER BY v")?
+        );
+        assert_eq!(
+            vec![vec![Some(1), Some(2)], vec![Some(2), Some(1)], vec![Some(3), Some(1)]],
+            query(&mut txn, "SELECT id, length(v) FROM bin WHERE v < X'ff01' ORDER BY id")?
+        );
+        assert_eq!(
+            vec![vec![Some(1)]],
+            query(
+                &mut txn,
+                "SELECT id FROM bin WHERE substr(v, 2) = X'02' AND v = CAST('\\x0102' AS BYTEA)",
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn json() -> Result<()> {
+        let mut txn = setup()?;
+        let rows = |txn: &mut _, query: &str| execute(txn, query)?.into_rows();
+        let json = |s: &str| Value::Json(s.into());
+        for statement in [
+            "CREATE TABLE doc (id INTEGER PRIMARY KEY, d JSON)",
+            r#"INSERT INTO doc VALUES (1, '{"b": [1, 2], "a": "x"}'), (2, '[1, 2, 3]'), (3, NULL),
+               (4, '{"a": null}')"#,
+        ] {
+            execute(&mut txn, statement)?;
+        }
+
+        // Documents are validated and normalized on write.
+        assert!(query(&mut txn, r#"INSERT INTO doc VALUES (5, '{"a":')"#).is_err());
+        assert!(query(&mut txn, "UPDATE doc SET d = '[' WHERE id = 2").is_err());
+        assert_eq!(
+            vec![vec![json(r#"{"a":"x","b":[1,2]}"#)]],
+            rows(&mut txn, "SELECT d FROM doc WHERE id = 1")?
+        );
+        assert_eq!(
+            vec![vec![Some(1)]],
+            query(
+                &mut txn,
+                r#"SELECT id FROM doc WHERE d = CAST('{"b":[1,2], "a":"x"}' AS JSON)"#
+            )?
+        );
+        assert_eq!(vec![vec![json("1.5")]], rows(&mut txn, "SELECT CAST(1.5 AS JSON)")?);
+
+        // -> returns JSON and ->> text, or NULL for missing fields and non-objects.
+        assert_eq!(
+            vec![vec![json("[1,2]"), Value::String("x".into())]],
+            rows(&mut txn, "SELECT d -> 'b', d ->> 'a' FROM doc WHERE id = 1")?
+        );
+        assert_eq!(
+            vec![vec![Some(1)]],
+            query(&mut txn, "SELECT id FROM doc WHERE d ->> 'a' = 'x'")?
+        );
+        assert_eq!(
+            vec![vec![Some(2)], vec![Some(3)], vec![Some(4)]],
+            query(&mut txn, "SELECT id FROM doc WHERE d ->> 'a' IS NULL ORDER BY id")?
+        );
+        assert_eq!(
+            vec![vec![Value::String("2".into())]],
+            rows(&mut txn, "SELECT d -> 'b' ->> -1 FROM doc WHERE id = 1")?
+        );
+
+        // json_extract() returns scalars as SQL values, and json_array_length() counts elements.
+        assert_eq!(
+            vec![
+                vec![Some(1), Some(2)],
+                vec![Some(2), None],
+                vec![Some(3), None],
+                vec![Some(4), None]
+            ],
+            query(&mut txn, "SELECT id, json_extract(d, '$.b[-1]') FROM doc ORDER BY id")?
+        );
+        assert_eq!(
+            vec![
+                vec![Some(1), Some(2)],
+                vec![Some(2), None],
+                vec![Some(3), None],
+                vec![Some(4), None]
+            ],
+            query(&mut txn, "SELECT id, json_array_length(d, '$.b') FROM doc ORDER BY id")?
+        );
+        assert_eq!(
+            vec![vec![Some(3)]],
+            query(&mut txn, "SELECT json_array_length(d) FROM doc WHERE id = 2")?
+        );
+        assert!(query(&mut txn, "SELECT json_array_length(d) FROM doc WHERE id = 1").is_err());
+        assert!(query(&mut txn, "SELECT json_extract(d, 'b') FROM doc WHERE id = 1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn pattern() -> Result<()> {
+        let mut txn = setup()?;
+        for statement in [
+            "CREATE TABLE p (id INTEGER PRIMARY KEY, s STRING, pattern STRING)",
+            "INSERT INTO p VALUES (1, 'abc', 'a%'), (2, 'ABC', '_b_'), (3, 'xyz', NULL)",
+        ] {
+            execute(&mut txn, statement)?;
+        }
+
+        // Constant patterns are compiled during planning.
+        let like = "SELECT id FROM p WHERE s LIKE 'a%'";
+        let plan = Plan::build(Parser::new(like).parse()?, &mut txn)?.optimize(&txn)?;
+        assert!(format!("{:?}", plan.root).contains("Pattern("), "{:?}", plan.root);
+        assert_eq!(vec![vec![Some(1)]], query(&mut txn, like)?);
+        assert_eq!(
+            vec![vec![Some(1)], vec![Some(2)]],
+            query(&mut txn, "SELECT id FROM p WHERE s ILIKE 'a%' ORDER BY id")?
+        );
+        assert_eq!(
+            vec![vec![Some(1)], vec![Some(2)]],
+            query(&mut txn, "SELECT id FROM p WHERE s ~* '^a' ORDER BY id")?
+        );
+
+        // Patterns from columns are compiled for each row.
+        assert_eq!(
+            vec![vec![Some(1)]],
+            query(&mut txn, "SELECT id FROM p WHERE s LIKE pattern ORDER BY id")?
+        );
+        assert_eq!(
+            vec![vec![Some(1)], vec![Some(2)]],
+            query(&mut txn, "SELECT id FROM p WHERE s ILIKE pattern ORDER BY id")?
+        );
+
+        // Regular expression functions use compiled patterns too.
+        assert_eq!(
+            vec![vec![Value::String("Xbc".into())], vec![Value::String("XBC".into())]],
+            execute(
+                &mut txn,
+                "SELECT regexp_replace(s, '[a-z]', 'X', 'i') FROM p WHERE id < 3 ORDER BY id",
+            )?
+            .into_rows()?
+        );
+
+        // Invalid patterns and trailing escape characters are errors.
+        assert!(query(&mut txn, "SELECT id FROM p WHERE s LIKE 'a\\'").is_err());
+        assert!(query(&mut txn, "SELECT id FROM p WHERE s SIMILAR TO 'a\\'").is_err());
+        assert!(query(&mut txn, "SELECT id FROM p WHERE s ~ '('").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn expression_index() -> Result<()> {
+        let mut txn = setup()?;
+        for statement in [
+            "CREATE TABLE j (id INTEGER PRIMARY KEY, doc JSON)",
+            r#"INSERT INTO j VALUES (1, '{"name": "x"}'), (2, '{"name": "y"}'), (3, '{}')"#,
+            "CREATE UNIQUE INDEX j_name ON j ((doc ->> 'name'))",
+        ] {
+            execute(&mut txn, statement)?;
+        }
+        let lookup = "SELECT id FROM j WHERE doc ->> 'name' = 'y'";
+        let statement = Parser::new(lookup).parse()?;
+        let plan = Plan::build(statement, &mut txn)?.optimize(&txn)?;
+        assert!(format!("{:?}", plan.root).contains("IndexLookup"), "{:?}", plan.root);
+
+        // The index is maintained on writes, and enforces uniqueness of the path value.
+        assert_eq!(vec![vec![Some(2)]], query(&mut txn, lookup)?);
+        execute(&mut txn, r#"UPDATE j SET doc = '{"name": "z"}' WHERE id = 2"#)?;
+        assert_eq!(Vec::<Vec<Option<i64>>>::new(), query(&mut txn, lookup)?);
+        execute(&mut txn, r#"INSERT INTO j VALUES (4, '{"name": "y"}')"#)?;
+        assert_eq!(vec![vec![Some(4)]], query(&mut txn, lookup)?);
+        assert!(execute(&mut txn, r#"INSERT INTO j VALUES (5, '{"name": "x"}')"#).is_err());
+        execute(&mut txn, "DELETE FROM j WHERE id = 4")?;
+        assert_eq!(Vec::<Vec<Option<i64>>>::new(), query(&mut txn, lookup)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use super::super::plan::SetOperator;
+use super::super::types::{DataType, Row, Rows};
+use crate::error::{Error, Result};
+
+use std::collections::{HashMap, HashSet};
+
+/// Combines the rows of two queries with a set operator, for the SetOperation plan node. Rows are
+/// deduplicated by hashing, which relies on Hash for Value being consistent with Value equality.
+/// With all, rows are instead treated as multisets: UNION ALL keeps every row, INTERSECT ALL keeps
+/// min(m, n) copies of a row occurring m and n times, and EXCEPT ALL keeps max(m - n, 0) copies.
+pub fn set_operation(operator: &SetOperator, all: bool, left: Rows, right: Rows) -> Result<Rows> {
+    let left = left.collect::<Result<Vec<_>>>()?;
+    let right = right.collect::<Result<Vec<_>>>()?;
+    check_columns(operator, &left, &right)?;
+
+    let rows: Vec<Row> = match (operator, all) {
+        (SetOperator::Union, true) => left.into_iter().chain(right).collect(),
+        (SetOperator::Union, false) => {
+            let mut seen = HashSet::new();
+            left.into_iter().chain(right).filter(|row| seen.insert(row.clone())).collect()
+        }
+        (SetOperator::Intersect, true) => {
+            let mut counts = count_rows(right);
+            left.into_iter().filter(|row| take_count(&mut counts, row)).collect()
+        }
+        (SetOperator::Intersect, false) => {
+            let right: HashSet<Row> = right.into_iter().collect();
+            let mut seen = HashSet::new();
+            left.into_iter().filter(|row| right.contains(row) && seen.insert(row.clone())).collect()
+        }
+        (SetOperator::Except, true) => {
+            let mut counts = count_rows(right);
+            left.into_iter().filter(|row| !take_count(&mut counts, row)).collect()
+        }
+        (SetOperator::Except, false) => {
+            let right: HashSet<Row> = right.into_iter().collect();
+            let mut seen = HashSet::new();
+            left.into_iter()
+                .filter(|row| !right.contains(row) && seen.insert(row.clone()))
+                .collect()
+        }
+    };
+    Ok(Box::new(rows.into_iter().map(Ok)))
+}
+
+/// Checks that all rows have the same number of columns, and that the non-NULL values of each
+/// column have the same datatype. The planner checks and coerces the column datatypes it can
+/// determine, so this only catches columns whose datatypes weren't known until execution.
+fn check_columns(operator: &SetOperator, left: &[Row], right: &[Row]) -> Result<()> {
+    let mut datatypes: Option<Vec<Option<DataType>>> = None;
+    for row in left.iter().chain(right) {
+        let datatypes = datatypes.get_or_insert_with(|| vec![None; row.len()]);
+        if row.len() != datatypes.len() {
+            return Err(Error::Value(format!(
+                "Queries combined by {} must have the same number of columns, got {} and {}",
+                operator,
+                datatypes.len(),
+                row.len()
+            )));
+        }
+        for (i, (datatype, value)) in datatypes.iter_mut().zip(row).enumerate() {
+            match (datatype.as_ref(), value.datatype()) {
+                (Some(expect), Some(actual)) if !expect.matches(&actual) => {
+                    return Err(Error::Value(format!(
+                        "Column {} of queries combined by {} has incompatible datatypes {} and {}",
+                        i + 1,
+                        operator,
+                        expect,
+                        actual
+                    )))
+                }
+                (None, Some(actual)) => *datatype = Some(actual),
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Counts the occurrences of each row
+fn count_rows(rows: Vec<Row>) -> HashMap<Row, usize> {
+    let mut counts = HashMap::new();
+    for row in rows {
+        *counts.entry(row).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Takes one occurrence of a row from the counts, returning false if none are left
+fn take_count(counts: &mut HashMap<Row, usize>, row: &Row) -> bool {
+    match counts.get_mut(row) {
+        Some(count) if *count > 0 => {
+            *count -= 1;
+            true
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,420 @@
+use super::super::plan::{
+    Aggregate, AggregateFunction, Direction, FrameBound, FrameUnits, WindowFrame, WindowFunction,
+    WindowKind,
+};
+use super::super::types::{Expression, Row, Rows, Value};
+use super::aggregate::accumulator;
+use crate::error::{Error, Result};
+
+use std::cmp::Ordering;
+use std::ops::Range;
+
+/// Computes window functions over the source rows, for the Window plan node. Each function's
+/// value is appended to every row, in function order, and rows are emitted in source order.
+pub fn window(source: Rows, functions: &[WindowFunction]) -> Result<Rows> {
+    let mut rows = source.collect::<Result<Vec<_>>>()?;
+    let mut columns = Vec::with_capacity(functions.len());
+    for function in functions {
+        columns.push(compute(&rows, function)?);
+    }
+    for (i, row) in rows.iter_mut().enumerate() {
+        row.extend(columns.iter_mut().map(|c| std::mem::replace(&mut c[i], Value::Null)));
+    }
+    Ok(Box::new(rows.into_iter().map(Ok)))
+}
+
+/// Computes a window function for all rows, returning the values in row order
+fn compute(rows: &[Row], function: &WindowFunction) -> Result<Vec<Value>> {
+    let (min, max) = function.kind.arity();
+    if function.args.len() < min || function.args.len() > max {
+        return Err(Error::Value(format!(
+            "Window function {:?} takes {} to {} arguments, got {}",
+            function.kind,
+            min,
+            max,
+            function.args.len()
+        )));
+    }
+
+    // Evaluate the partition and sort keys, and sort the rows by them.
+    let mut keys = Vec::with_capacity(rows.len());
+    // Strings are keyed by their collation key, which also orders them by the collation.
+    let key = |e: &Expression, row: &Row| -> Result<Value> {
+        Ok(e.collation().unwrap_or_default().key(e.evaluate(Some(row))?))
+    };
+    for row in rows {
+        let partition =
+            function.partition_by.iter().map(|e| key(e, row)).collect::<Result<Vec<_>>>()?;
+        let order =
+            function.order_by.iter().map(|(e, _)| key(e, row)).collect::<Result<Vec<_>>>()?;
+        keys.push((partition, order));
+    }
+    let directions: Vec<&Direction> = function.order_by.iter().map(|(_, d)| d).collect();
+    let mut error = None;
+    let mut order: Vec<usize> = (0..rows.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&keys[a], &keys[b]);
+        let directions =
+            std::iter::repeat_n(&Direction::Ascending, a.0.len()).chain(directions.iter().copied());
+        for ((a, b), direction) in
+            a.0.iter().chain(&a.1).zip(b.0.iter().chain(&b.1)).zip(directions)
+        {
+            match compare(a, b, direction) {
+                Ok(Ordering::Equal) => {}
+                Ok(ordering) => return ordering,
+                Err(err) => {
+                    error.get_or_insert(err);
+                }
+            }
+        }
+        Ordering::Equal
+    });
+    if let Some(error) = error {
+        return Err(error);
+    }
+
+    // Compute the function for each partition, i.e. each run of rows with equal partition keys.
+    let mut values = vec![Value::Null; rows.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let end = (start..order.len())
+            .find(|&i| keys[order[i]].0 != keys[order[start]].0)
+            .unwrap_or(order.len());
+        let partition = Partition::new(
+            order[start..end].iter().map(|&i| &rows[i]).collect(),
+            order[start..end].iter().map(|&i| &keys[i].1).collect(),
+            &directions,
+        );
+        for (position, value) in partition.compute(function)?.into_iter().enumerate() {
+            values[order[start + position]] = value;
+        }
+        start = end;
+    }
+    Ok(values)
+}
+
+/// Compares two sort values in the given direction, erroring if they're incomparable
+fn compare(a: &Value, b: &Value, direction: &Direction) -> Result<Ordering> {
+    let ordering =
+        a.partial_cmp(b).ok_or_else(|| Error::Value(format!("Can't compare {} and {}", a, b)))?;
+    Ok(match direction {
+        Direction::Ascending => ordering,
+        Direction::Descending => ordering.reverse(),
+    })
+}
+
+/// A sorted window partition
+struct Partition<'a> {
+    rows: Vec<&'a Row>,
+    order: Vec<&'a Vec<Value>>,
+    directions: &'a [&'a Direction],
+    /// The peer group of each row, i.e. the rows with equal ORDER BY values
+    peers: Vec<Range<usize>>,
+}
+
+impl<'a> Partition<'a> {
+    /// Creates a partition from its sorted rows and their ORDER BY values
+    fn new(
+        rows: Vec<&'a Row>,
+        order: Vec<&'a Vec<Value>>,
+        directions: &'a [&'a Direction],
+    ) -> Self {
+        let mut peers = Vec::with_capacity(rows.len());
+        let mut start = 0;
+        for end in 1..=order.len() {
+            if end == order.len() || order[end] != order[start] {
+                peers.extend(std::iter::repeat_n(start..end, end - start));
+                start = end;
+            }
+        }
+        Self { rows, order, directions, peers }
+    }
+
+    /// Computes a window function for each row of the partition, in partition order
+    fn compute(&self, function: &WindowFunction) -> Result<Vec<Value>> {
+        if let WindowKind::Aggregate(aggregate) = &function.kind {
+            return self.compute_aggregate(function, aggregate);
+        }
+        let n = self.rows.len();
+        let mut values = Vec::with_capacity(n);
+        let mut dense_rank = 0;
+        for position in 0..n {
+            let row = self.rows[position];
+            let peers = &self.peers[position];
+            if peers.start == position {
+                dense_rank += 1;
+            }
+            let value = match function.kind {
+                WindowKind::RowNumber => Value::Integer(position as i64 + 1),
+                WindowKind::Rank => Value::Integer(peers.start as i64 + 1),
+                WindowKind::DenseRank => Value::Integer(dense_rank),
+                WindowKind::PercentRank if n > 1 => {
+                    Value::Float(peers.start as f64 / (n - 1) as f64)
+                }
+                WindowKind::PercentRank => Value::Float(0.0),
+                WindowKind::CumeDist => Value::Float(peers.end as f64 / n as f64),
+                WindowKind::Ntile => {
+                    let buckets = match function.args[0].evaluate(Some(row))? {
+                        Value::Integer(b) if b > 0 => b as usize,
+                        value => {
+                            return Err(Error::Value(format!("Invalid ntile buckets {}", value)))
+                        }
+                    };
+                    // The first n % buckets buckets get an extra row.
+                    let (size, extra) = (n / buckets, n % buckets);
+                    let bucket = if position < extra * (size + 1) {
+                        position / (size + 1)
+                    } else {
+                        extra + (position - extra * (size + 1)) / size.max(1)
+                    };
+                    Value::Integer(bucket as i64 + 1)
+                }
+                WindowKind::Lag | WindowKind::Lead => {
+                    let offset = match function.args.get(1) {
+                        Some(expr) => match expr.evaluate(Some(row))? {
+                            Value::Integer(o) if o >= 0 => o as usize,
+                            value => return Err(Error::Value(format!("Invalid offset {}", value))),
+                        },
+                        None => 1,
+                    };
+                    let target = match function.kind {
+                        WindowKind::Lag => position.checked_sub(offset),
+                        _ => position.checked_add(offset).filter(|&t| t < n),
+                    };
+                    match (target, function.args.get(2)) {
+                        (Some(target), _) => function.args[0].evaluate(Some(self.rows[target]))?,
+                        (None, Some(default)) => default.evaluate(Some(row))?,
+                        (None, None) => Value::Null,
+                    }
+                }
+                _ => {
+                    let frame = self.frame(position, &function.frame)?;
+                    self.compute_framed(function, row, frame)?
+                }
+            };
+            values.push(value);
+        }
+        Ok(values)
+    }
+
+    /// Computes a framed value or aggregate function over the given frame
+    fn compute_framed(
+        &self,
+        function: &WindowFunction,
+        row: &Row,
+        frame: Range<usize>,
+    ) -> Result<Value> {
+        let expr = &function.args[0];
+        let value = match function.kind {
+            WindowKind::FirstValue if !frame.is_empty() => {
+                expr.evaluate(Some(self.rows[frame.start]))?
+            }
+            WindowKind::LastValue if !frame.is_empty() => {
+                expr.evaluate(Some(self.rows[frame.end - 1]))?
+            }
+            WindowKind::NthValue => match function.args[1].evaluate(Some(row))? {
+                Value::Integer(nth) if nth > 0 => match frame.start.checked_add(nth as usize - 1) {
+                    Some(i) if i < frame.end => expr.evaluate(Some(self.rows[i]))?,
+                    _ => Value::Null,
+                },
+                value => return Err(Error::Value(format!("Invalid nth_value {}", value))),
+            },
+            WindowKind::FirstValue | WindowKind::LastValue => Value::Null,
+            _ => return Err(Error::Internal(format!("{:?} is not framed", function.kind))),
+        };
+        Ok(value)
+    }
+
+    /// Computes an aggregate function over each row's frame. Frame bounds never move backwards,
+    /// so values are accumulated as they enter the frame and retracted as they leave it, and
+    /// the accumulator is only recomputed if it can't retract a value. If all frames extend to
+    /// the end of the partition, values are instead accumulated backwards from the end, unless
+    /// the aggregate depends on the value order.
+    fn compute_aggregate(
+        &self,
+        function: &WindowFunction,
+        aggregate: &AggregateFunction,
+    ) -> Result<Vec<Value>> {
+        let n = self.rows.len();
+        let aggregate = Aggregate {
+            function: aggregate.clone(),
+            distinct: false,
+            collation: function.args[0].collation().unwrap_or_default(),
+        };
+        let args = self
+            .rows
+            .iter()
+            .map(|row| function.args[0].evaluate(Some(row)))
+            .collect::<Result<Vec<_>>>()?;
+        let frames = (0..n).map(|p| self.frame(p, &function.frame)).collect::<Result<Vec<_>>>()?;
+        let mut values = vec![Value::Null; n];
+
+        if frames.iter().all(|f| f.end == n)
+            && !matches!(aggregate.function, AggregateFunction::StringAgg(_))
+        {
+            let mut accumulator = accumulator(&aggregate);
+            let mut start = n;
+            for position in (0..n).rev() {
+                let frame = &frames[position];
+                if frame.start > start {
+                    return Err(Error::Internal(format!("Frame {:?} moved backwards", frame)));
+                }
+                for value in args[frame.start..start].iter().rev() {
+                    if value != &Value::Null {
+                        accumulator.accumulate(value.clone())?;
+                    }
+                }
+                start = frame.start;
+                values[position] = accumulator.finish()?;
+            }
+            return Ok(values);
+        }
+
+        let mut accumulator = accumulator(&aggregate);
+        let mut current = 0..0;
+        let mut count = 0; // the number of accumulated values
+        for (position, frame) in frames.into_iter().enumerate() {
+            if frame.start < current.start || frame.end < current.end {
+                return Err(Error::Internal(format!("Frame {:?} moved backwards", frame)));
+            }
+            let mut retracted = true;
+            for value in &args[current.start..frame.start.min(current.end)] {
+                if value != &Value::Null {
+                    retracted = accumulator.retract(value)?;
+                    if !retracted {
+                        break;
+                    }
+                    count -= 1;
+                }
+            }
+            // Start over if a value couldn't be retracted, or if the frame is empty, since an
+            // aggregate of no values isn't necessarily the same as one with values retracted.
+            if !retracted || count == 0 {
+                accumulator = super::aggregate::accumulator(&aggregate);
+                count = 0;
+                current = frame.start..frame.start;
+            }
+            for value in &args[current.end.max(frame.start)..frame.end] {
+                if value != &Value::Null {
+                    accumulator.accumulate(value.clone())?;
+                    count += 1;
+                }
+            }
+            values[position] = accumulator.finish()?;
+            current = frame;
+        }
+        Ok(values)
+    }
+
+    /// Returns the frame of a row, as a range of partition positions
+    fn frame(&self, position: usize, frame: &WindowFrame) -> Result<Range<usize>> {
+        let n = self.rows.len();
+        let peers = &self.peers[position];
+        let start = match &frame.start {
+            FrameBound::UnboundedPreceding => 0,
+            FrameBound::UnboundedFollowing => n,
+            FrameBound::CurrentRow => match frame.units {
+                FrameUnits::Rows => position,
+                FrameUnits::Range => peers.start,
+            },
+            FrameBound::Preceding(offset) | FrameBound::Following(offset) => {
+                let preceding = matches!(frame.start, FrameBound::Preceding(_));
+                match frame.units {
+                    FrameUnits::Rows if preceding => position.saturating_sub(rows_offset(offset)?),
+                    FrameUnits::Rows => position.saturating_add(rows_offset(offset)?).min(n),
+                    FrameUnits::Range => match self.range_target(position, offset, preceding)? {
+                        Some(target) => self
+                            .order
+                            .partition_point(|o| self.compare_target(o, &target) == Ordering::Less),
+                        None => peers.start,
+                    },
+                }
+            }
+        };
+        let end = match &frame.end {
+            FrameBound::UnboundedPreceding => 0,
+            FrameBound::UnboundedFollowing => n,
+            FrameBound::CurrentRow => match frame.units {
+                FrameUnits::Rows => position + 1,
+                FrameUnits::Range => peers.end,
+            },
+            FrameBound::Preceding(offset) | FrameBound::Following(offset) => {
+                let preceding = matches!(frame.end, FrameBound::Preceding(_));
+                match frame.units {
+                    FrameUnits::Rows if preceding => {
+                        (position + 1).saturating_sub(rows_offset(offset)?)
+                    }
+                    FrameUnits::Rows => position.saturating_add(rows_offset(offset)? + 1).min(n),
+                    FrameUnits::Range => match self.range_target(position, offset, preceding)? {
+                        Some(target) => self.order.partition_point(|o| {
+                            self.compare_target(o, &target) != Ordering::Greater
+                        }),
+                        None => peers.end,
+                    },
+                }
+            }
+        };
+        Ok(start..end.max(start))
+    }
+
+    /// Returns the ORDER BY value a RANGE offset refers to, in sort order, or None if the current
+    /// row's value is NULL (in which case the frame bound is its peer group).
+    fn range_target(
+        &self,
+        position: usize,
+        offset: &Value,
+        preceding: bool,
+    ) -> Result<Option<Value>> {
+        let (value, direction) = match (self.order[position].as_slice(), self.directions) {
+            ([value], [direction]) => (value, direction),
+            _ => return Err(Error::Value("RANGE offsets require a single ORDER BY value".into())),
+        };
+        let subtract = preceding == (**direction == Direction::Ascending);
+        Ok(match (value, offset) {
+            (Value::Null, _) => None,
+            (_, Value::Integer(o)) if *o < 0 => {
+                return Err(Error::Value(format!("Invalid frame offset {}", offset)))
+            }
+            (_, Value::Float(o)) if o.is_nan() || *o < 0.0 => {
+                return Err(Error::Value(format!("Invalid frame offset {}", offset)))
+            }
+            (Value::Integer(v), Value::Integer(o)) => Some(Value::Integer(
+                if subtract { v.checked_sub(*o) } else { v.checked_add(*o) }
+                    .ok_or_else(|| Error::Value("Integer overflow".into()))?,
+            )),
+            (Value::Integer(v), Value::Float(o)) => {
+                Some(Value::Float(if subtract { *v as f64 - o } else { *v as f64 + o }))
+            }
+            (Value::Float(v), Value::Integer(o)) => {
+                Some(Value::Float(if subtract { v - *o as f64 } else { v + *o as f64 }))
+            }
+            (Value::Float(v), Value::Float(o)) => {
+                Some(Value::Float(if subtract { v - o } else { v + o }))
+            }
+            (value, offset) => {
+                return Err(Error::Value(format!(
+                    "Can't offset {} by {} in RANGE frame",
+                    value, offset
+                )))
+            }
+        })
+    }
+
+    /// Compares the ORDER BY value of a row with a RANGE target value, in sort order. Rows are
+    /// sorted by this, so frame bounds can be found by binary search.
+    fn compare_target(&self, order: &[Value], target: &Value) -> Ordering {
+        let ordering = order[0].partial_cmp(target).unwrap_or(Ordering::Less);
+        match self.directions[0] {
+            Direction::Ascending => ordering,
+            Direction::Descending => ordering.reverse(),
+        }
+    }
+}
+
+/// Returns a ROWS frame offset
+fn rows_offset(offset: &Value) -> Result<usize> {
+    match offset {
+        Value::Integer(o) if *o >= 0 => Ok(*o as usize),
+        offset => Err(Error::Value(format!("Invalid frame offset {}", offset))),
+    }
+}
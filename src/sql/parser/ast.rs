@@ -1,4 +1,5 @@
-use super::super::types::DataType;
+use super::super::schema::{ReferentialAction, StringLength};
+use super::super::types::{Collation, DataType, RoundingMode};
 use crate::error::Result;
 
 use std::collections::BTreeMap;
@@ -20,8 +21,31 @@ pub enum Statement {
     CreateTable {
         name: String,
         columns: Vec<Column>,
+        /// The columns of a table-level PRIMARY KEY, in key order. Empty if the primary key is
+        /// given inline, by Column::primary_key.
+        primary_key: Vec<String>,
+        foreign_keys: Vec<ForeignKey>,
+        checks: Vec<Check>,
     },
     DropTable(String),
+    AlterTable {
+        table: String,
+        alteration: Alteration,
+    },
+    CreateIndex {
+        name: String,
+        table: String,
+        /// The indexed columns as field expressions, or arbitrary indexed expressions
+        columns: Vec<Expression>,
+        unique: bool,
+    },
+    DropIndex(String),
+    CreateSequence {
+        name: String,
+        start: Option<i64>,
+        increment: Option<i64>,
+    },
+    DropSequence(String),
 
     Delete {
         table: String,
@@ -39,15 +63,106 @@ pub enum Statement {
     },
 
     Select {
+        with: Vec<CommonTableExpression>,
+        distinct: Option<Distinct>,
         select: Vec<(Expression, Option<String>)>,
         from: Vec<FromItem>,
         r#where: Option<Expression>,
-        group_by: Vec<Expression>,
+        group_by: Vec<GroupingElement>,
         having: Option<Expression>,
         order: Vec<(Expression, Order)>,
         offset: Option<Expression>,
         limit: Option<Expression>,
     },
+    /// A compound query, combining two queries with a set operator. ORDER BY, OFFSET and LIMIT
+    /// apply to the combined result.
+    SetOperation {
+        with: Vec<CommonTableExpression>,
+        operator: SetOperator,
+        all: bool,
+        left: Box<Statement>,
+        right: Box<Statement>,
+        order: Vec<(Expression, Order)>,
+        offset: Option<Expression>,
+        limit: Option<Expression>,
+    },
+}
+
+impl Statement {
+    /// Returns true if the query refers to the given table name anywhere, including in FROM
+    /// items, subqueries and common table expressions. Used to find the recursive term of a
+    /// recursive common table expression.
+    pub fn references_table(&self, name: &str) -> bool {
+        let references = |expr: &Expression| expr.references_table(name);
+        match self {
+            Self::Select {
+                with,
+                distinct,
+                select,
+                from,
+                r#where,
+                group_by,
+                having,
+                order,
+                offset,
+                limit,
+            } => {
+                let mut exprs: Vec<&Expression> = select.iter().map(|(e, _)| e).collect();
+                if let Some(Distinct::On(on)) = distinct {
+                    exprs.extend(on);
+                }
+                for element in group_by {
+                    match element {
+                        GroupingElement::Expression(expr) => exprs.push(expr),
+                        GroupingElement::Rollup(e) | GroupingElement::Cube(e) => exprs.extend(e),
+                        GroupingElement::GroupingSets(sets) => exprs.extend(sets.iter().flatten()),
+                    }
+                }
+                exprs.extend(r#where.iter().chain(having).chain(offset).chain(limit));
+                exprs.extend(order.iter().map(|(e, _)| e));
+                with.iter().any(|cte| cte.references_table(name))
+                    || from.iter().any(|item| item.references_table(name))
+                    || exprs.into_iter().any(references)
+            }
+            Self::SetOperation { with, left, right, order, offset, limit, .. } => {
+                with.iter().any(|cte| cte.references_table(name))
+                    || left.references_table(name)
+                    || right.references_table(name)
+                    || order.iter().map(|(e, _)| e).chain(offset).chain(limit).any(references)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Set operators for compound queries
+#[derive(Clone, Debug, PartialEq)]
+pub enum SetOperator {
+    Except,
+    Intersect,
+    Union,
+}
+
+/// A common table expression, given in a WITH clause
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommonTableExpression {
+    pub name: String,
+    pub columns: Option<Vec<String>>,
+    pub query: Box<Statement>,
+    /// For WITH RECURSIVE, the recursive term following UNION [ALL], which may refer to the
+    /// common table expression itself
+    pub recursive: Option<Box<Statement>>,
+    /// Whether the recursive term is joined with UNION ALL, i.e. duplicates are kept
+    pub union_all: bool,
+}
+
+impl CommonTableExpression {
+    /// Returns true if the common table expression's query refers to the given table name, see
+    /// Statement::references_table().
+    pub fn references_table(&self, name: &str) -> bool {
+        self.query.references_table(name)
+            || self.recursive.as_ref().is_some_and(|r| r.references_table(name))
+    }
 }
 
 /// A FROM item
@@ -61,11 +176,37 @@ pub enum FromItem {
         left: Box<FromItem>,
         right: Box<FromItem>,
         r#type: JoinType,
-        // 谓词
-        predicate: Option<Expression>,
+        constraint: JoinConstraint,
+    },
+    Subquery {
+        query: Box<Statement>,
+        alias: String,
+    },
+    /// A table function call, e.g. json_each(doc)
+    Function {
+        name: String,
+        args: Vec<Expression>,
+        alias: Option<String>,
     },
 }
 
+impl FromItem {
+    /// Returns true if the FROM item refers to the given table name, see
+    /// Statement::references_table().
+    pub fn references_table(&self, name: &str) -> bool {
+        match self {
+            Self::Table { name: table, .. } => table == name,
+            Self::Join { left, right, constraint, .. } => {
+                left.references_table(name)
+                    || right.references_table(name)
+                    || matches!(constraint, JoinConstraint::On(e) if e.references_table(name))
+            }
+            Self::Subquery { query, .. } => query.references_table(name),
+            Self::Function { args, .. } => args.iter().any(|e| e.references_table(name)),
+        }
+    }
+}
+
 /// A JOIN type
 #[derive(Clone, Debug, PartialEq)]
 pub enum JoinType {
@@ -73,6 +214,20 @@ pub enum JoinType {
     Inner,
     Left,
     Right,
+    Full,
+}
+
+/// A JOIN constraint, which determines the join predicate
+#[derive(Clone, Debug, PartialEq)]
+pub enum JoinConstraint {
+    /// No constraint, for cross joins
+    None,
+    /// An ON predicate
+    On(Expression),
+    /// USING (columns), joining on equality of the named columns, which are output once
+    Using(Vec<String>),
+    /// NATURAL, i.e. USING all columns with the same name on both sides
+    Natural,
 }
 
 /// A column
@@ -86,7 +241,41 @@ pub struct Column {
     pub default: Option<Expression>,
     pub unique: bool,
     pub index: bool,
+    pub autoincrement: bool,
     pub references: Option<String>,
+    pub on_delete: ReferentialAction,
+    pub on_update: ReferentialAction,
+    pub checks: Vec<Expression>,
+    pub rounding: RoundingMode,
+    pub length: Option<StringLength>,
+    pub collation: Collation,
+}
+
+/// An ALTER TABLE operation
+#[derive(Clone, Debug, PartialEq)]
+pub enum Alteration {
+    AddColumn(Column),
+    DropColumn(String),
+    RenameColumn { from: String, to: String },
+    RenameTable(String),
+    SetDefault { column: String, default: Option<Expression> },
+    SetNullable { column: String, nullable: bool },
+}
+
+/// A table-level foreign key
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForeignKey {
+    pub columns: Vec<String>,
+    pub references: String,
+    pub on_delete: ReferentialAction,
+    pub on_update: ReferentialAction,
+}
+
+/// A table-level CHECK constraint, with an optional constraint name
+#[derive(Clone, Debug, PartialEq)]
+pub struct Check {
+    pub name: Option<String>,
+    pub expression: Expression,
 }
 
 /// Sort orders
@@ -96,14 +285,136 @@ pub enum Order {
     Descending,
 }
 
+/// A GROUP BY element. The grouping sets of a GROUP BY clause are the cross product of the
+/// grouping sets of its elements.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GroupingElement {
+    /// A plain grouping expression
+    Expression(Expression),
+    /// ROLLUP (a, b, ...), i.e. the grouping sets (a, b, ...), (a, ...), ..., ()
+    Rollup(Vec<Expression>),
+    /// CUBE (a, b, ...), i.e. grouping sets for all subsets of the expressions
+    Cube(Vec<Expression>),
+    /// GROUPING SETS (...), with explicit grouping sets
+    GroupingSets(Vec<Vec<Expression>>),
+}
+
+impl GroupingElement {
+    /// Returns the element's grouping sets
+    pub fn grouping_sets(&self) -> Vec<Vec<Expression>> {
+        match self {
+            Self::Expression(expr) => vec![vec![expr.clone()]],
+            Self::Rollup(exprs) => (0..=exprs.len()).rev().map(|i| exprs[..i].to_vec()).collect(),
+            Self::Cube(exprs) => (0..1usize << exprs.len())
+                .rev()
+                .map(|mask| {
+                    exprs
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| mask & (1 << (exprs.len() - 1 - i)) != 0)
+                        .map(|(_, e)| e.clone())
+                        .collect()
+                })
+                .collect(),
+            Self::GroupingSets(sets) => sets.clone(),
+        }
+    }
+
+    /// Expands GROUP BY elements into grouping sets, as the cross product of the elements'
+    /// grouping sets
+    pub fn expand(elements: &[GroupingElement]) -> Vec<Vec<Expression>> {
+        let mut sets = vec![Vec::new()];
+        for element in elements {
+            let element_sets = element.grouping_sets();
+            sets = sets
+                .iter()
+                .flat_map(|set| {
+                    element_sets.iter().map(move |s| set.iter().chain(s).cloned().collect())
+                })
+                .collect();
+        }
+        sets
+    }
+}
+
+/// SELECT DISTINCT modes
+#[derive(Clone, Debug, PartialEq)]
+pub enum Distinct {
+    /// SELECT DISTINCT, removing duplicate rows
+    Rows,
+    /// SELECT DISTINCT ON (expressions), keeping the first row for each distinct set of values
+    On(Vec<Expression>),
+}
+
+/// A window specification, from an OVER clause
+#[derive(Clone, Debug, PartialEq)]
+pub struct Window {
+    pub partition_by: Vec<Expression>,
+    pub order_by: Vec<(Expression, Order)>,
+    pub frame: Option<WindowFrame>,
+}
+
+/// A window frame, i.e. the rows of the partition that framed functions operate on
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowFrame {
+    pub units: FrameUnits,
+    pub start: FrameBound,
+    pub end: FrameBound,
+}
+
+/// Window frame units
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrameUnits {
+    /// Bounds are row offsets from the current row
+    Rows,
+    /// Bounds are value offsets from the current row's ORDER BY value
+    Range,
+}
+
+/// A window frame bound
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrameBound {
+    UnboundedPreceding,
+    Preceding(Box<Expression>),
+    CurrentRow,
+    Following(Box<Expression>),
+    UnboundedFollowing,
+}
+
 /// Expressions
 #[derive(Clone, Debug, PartialEq)]
 pub enum Expression {
     Field(Option<String>, String),
     Column(usize), // only used during plan building to break off expression subtrees
     Literal(Literal),
-    Function(String, Vec<Expression>),
+    /// `*` as a function argument, e.g. COUNT(*)
+    All,
+    /// A function call. If distinct is set, duplicate argument values are ignored, e.g. for
+    /// COUNT(DISTINCT expr).
+    Function {
+        name: String,
+        args: Vec<Expression>,
+        distinct: bool,
+    },
+    /// A window function call, i.e. function(args) OVER (window)
+    WindowFunction {
+        name: String,
+        args: Vec<Expression>,
+        over: Window,
+    },
+    /// CASE [operand] WHEN .. THEN .. [ELSE ..] END
+    Case {
+        operand: Option<Box<Expression>>,
+        branches: Vec<(Expression, Expression)>,
+        r#else: Option<Box<Expression>>,
+    },
+    /// CAST(expr AS datatype) or expr::datatype
+    Cast(Box<Expression>, DataType),
+    /// expr COLLATE collation
+    Collate(Box<Expression>, Collation),
     Operation(Operation),
+    Subquery(Box<Statement>),
+    Exists(Box<Statement>),
 }
 
 impl From<Literal> for Expression {
@@ -127,6 +438,7 @@ pub enum Literal {
     Integer(i64),
     Float(f64),
     String(String),
+    Binary(Vec<u8>),
 }
 
 /// Operations (done by operators)
@@ -138,9 +450,11 @@ pub enum Operation {
     Or(Box<Expression>, Box<Expression>),
 
     // Comparison operators
+    Between(Box<Expression>, Box<Expression>, Box<Expression>),
     Equal(Box<Expression>, Box<Expression>),
     GreaterThan(Box<Expression>, Box<Expression>),
     GreaterThanOrEqual(Box<Expression>, Box<Expression>),
+    InList(Box<Expression>, Vec<Expression>),
     IsNull(Box<Expression>),
     LessThan(Box<Expression>, Box<Expression>),
     LessThanOrEqual(Box<Expression>, Box<Expression>),
@@ -158,7 +472,18 @@ pub enum Operation {
     Subtract(Box<Expression>, Box<Expression>),
 
     // String operators
+    ILike(Box<Expression>, Box<Expression>),
+    IRegexMatch(Box<Expression>, Box<Expression>),
     Like(Box<Expression>, Box<Expression>),
+    RegexMatch(Box<Expression>, Box<Expression>),
+    SimilarTo(Box<Expression>, Box<Expression>),
+
+    // JSON operators
+    JsonGet(Box<Expression>, Box<Expression>),
+    JsonGetText(Box<Expression>, Box<Expression>),
+
+    // Subquery operators
+    InSubquery(Box<Expression>, Box<Statement>),
 }
 
 impl Expression {
@@ -168,6 +493,17 @@ impl Expression {
         !self.walk(&(|e| !visitor(e)))
     }
 
+    /// Returns true if a subquery in the expression refers to the given table name, see
+    /// Statement::references_table().
+    pub fn references_table(&self, name: &str) -> bool {
+        self.contains(&|e| match e {
+            Self::Subquery(query)
+            | Self::Exists(query)
+            | Self::Operation(Operation::InSubquery(_, query)) => query.references_table(name),
+            _ => false,
+        })
+    }
+
     /// Replaces the expression with result of the closure. Helper function for transform().
     fn replace_with<F: FnMut(Self) -> Result<Self>>(&mut self, mut f: F) -> Result<()> {
         // Temporarily replace expression with a null value, in case closure panics. May consider
@@ -191,6 +527,10 @@ impl Expression {
             | Self::Operation(Exponentiate(lhs, rhs))
             | Self::Operation(GreaterThan(lhs, rhs))
             | Self::Operation(GreaterThanOrEqual(lhs, rhs))
+            | Self::Operation(ILike(lhs, rhs))
+            | Self::Operation(IRegexMatch(lhs, rhs))
+            | Self::Operation(JsonGet(lhs, rhs))
+            | Self::Operation(JsonGetText(lhs, rhs))
             | Self::Operation(LessThan(lhs, rhs))
             | Self::Operation(LessThanOrEqual(lhs, rhs))
             | Self::Operation(Like(lhs, rhs))
@@ -198,26 +538,68 @@ impl Expression {
             | Self::Operation(Multiply(lhs, rhs))
             | Self::Operation(NotEqual(lhs, rhs))
             | Self::Operation(Or(lhs, rhs))
+            | Self::Operation(RegexMatch(lhs, rhs))
+            | Self::Operation(SimilarTo(lhs, rhs))
             | Self::Operation(Subtract(lhs, rhs)) => {
                 Self::replace_with(lhs, |e| e.transform(before, after))?;
                 Self::replace_with(rhs, |e| e.transform(before, after))?;
             }
 
+            Self::Operation(Between(expr, low, high)) => {
+                Self::replace_with(expr, |e| e.transform(before, after))?;
+                Self::replace_with(low, |e| e.transform(before, after))?;
+                Self::replace_with(high, |e| e.transform(before, after))?;
+            }
+
+            Self::Cast(expr, _)
+            | Self::Collate(expr, _)
             | Self::Operation(Assert(expr))
             | Self::Operation(Factorial(expr))
             | Self::Operation(IsNull(expr))
+            | Self::Operation(InSubquery(expr, _))
             | Self::Operation(Negate(expr))
             | Self::Operation(Not(expr)) => {
                 Self::replace_with(expr, |e| e.transform(before, after))?;
             }
 
-            Self::Function(_, exprs) => {
+            Self::Operation(InList(expr, list)) => {
+                Self::replace_with(expr, |e| e.transform(before, after))?;
+                for item in list {
+                    Self::replace_with(item, |e| e.transform(before, after))?;
+                }
+            }
+
+            Self::Case { operand, branches, r#else } => {
+                let exprs = operand.iter_mut().map(|e| &mut **e);
+                let exprs = exprs.chain(branches.iter_mut().flat_map(|(w, t)| [w, t]));
+                for expr in exprs.chain(r#else.iter_mut().map(|e| &mut **e)) {
+                    Self::replace_with(expr, |e| e.transform(before, after))?;
+                }
+            }
+
+            Self::Function { args: exprs, .. } => {
                 for expr in exprs {
                     Self::replace_with(expr, |e| e.transform(before, after))?;
                 }
             }
 
-            Self::Literal(_) | Self::Field(_, _) | Self::Column(_) => {}
+            Self::WindowFunction { args, over, .. } => {
+                for expr in args
+                    .iter_mut()
+                    .chain(over.partition_by.iter_mut())
+                    .chain(over.order_by.iter_mut().map(|(e, _)| e))
+                {
+                    Self::replace_with(expr, |e| e.transform(before, after))?;
+                }
+            }
+
+            // Subqueries have their own scope, and are not descended into.
+            Self::All
+            | Self::Literal(_)
+            | Self::Field(_, _)
+            | Self::Column(_)
+            | Self::Subquery(_)
+            | Self::Exists(_) => {}
         }
         after(self)
     }
@@ -241,6 +623,10 @@ impl Expression {
                 | Self::Operation(Exponentiate(lhs, rhs))
                 | Self::Operation(GreaterThan(lhs, rhs))
                 | Self::Operation(GreaterThanOrEqual(lhs, rhs))
+                | Self::Operation(ILike(lhs, rhs))
+                | Self::Operation(IRegexMatch(lhs, rhs))
+                | Self::Operation(JsonGet(lhs, rhs))
+                | Self::Operation(JsonGetText(lhs, rhs))
                 | Self::Operation(LessThan(lhs, rhs))
                 | Self::Operation(LessThanOrEqual(lhs, rhs))
                 | Self::Operation(Like(lhs, rhs))
@@ -248,15 +634,35 @@ impl Expression {
                 | Self::Operation(Multiply(lhs, rhs))
                 | Self::Operation(NotEqual(lhs, rhs))
                 | Self::Operation(Or(lhs, rhs))
+                | Self::Operation(RegexMatch(lhs, rhs))
+                | Self::Operation(SimilarTo(lhs, rhs))
                 | Self::Operation(Subtract(lhs, rhs)) => lhs.walk(visitor) && rhs.walk(visitor),
 
+                Self::Operation(Between(expr, low, high)) => {
+                    expr.walk(visitor) && low.walk(visitor) && high.walk(visitor)
+                }
+
+                Self::Cast(expr, _)
+                | Self::Collate(expr, _)
                 | Self::Operation(Assert(expr))
                 | Self::Operation(Factorial(expr))
                 | Self::Operation(IsNull(expr))
+                | Self::Operation(InSubquery(expr, _))
                 | Self::Operation(Negate(expr))
                 | Self::Operation(Not(expr)) => expr.walk(visitor),
 
-                Self::Function(_, exprs) => {
+                Self::Operation(InList(expr, list)) => {
+                    expr.walk(visitor) && list.iter().all(|e| e.walk(visitor))
+                }
+
+                Self::Case { operand, branches, r#else } => operand
+                    .iter()
+                    .map(|e| &**e)
+                    .chain(branches.iter().flat_map(|(w, t)| [w, t]))
+                    .chain(r#else.as_deref())
+                    .all(|e| e.walk(visitor)),
+
+                Self::Function { args: exprs, .. } => {
                     for expr in exprs {
                         if !expr.walk(visitor) {
                             return false;
@@ -265,7 +671,18 @@ impl Expression {
                     true
                 }
 
-                Self::Literal(_) | Self::Field(_, _) | Self::Column(_) => true,
+                Self::WindowFunction { args, over, .. } => args
+                    .iter()
+                    .chain(&over.partition_by)
+                    .chain(over.order_by.iter().map(|(e, _)| e))
+                    .all(|e| e.walk(visitor)),
+
+                Self::All
+                | Self::Literal(_)
+                | Self::Field(_, _)
+                | Self::Column(_)
+                | Self::Subquery(_)
+                | Self::Exists(_) => true,
             })
     }
 }
@@ -8,9 +8,13 @@ use std::str::Chars;
 pub enum Token {
     Number(String),
     String(String),
+    /// A hex string literal, e.g. X'0aff', containing the hex digits
+    Hex(String),
     Ident(String),
     Keyword(Keyword),
     Period,
+    Colon,
+    DoubleColon,
     Equal,
     GreaterThan,
     GreaterThanOrEqual,
@@ -19,6 +23,8 @@ pub enum Token {
     LessOrGreaterThan,
     Plus,
     Minus,
+    Arrow,
+    LongArrow,
     Asterisk,
     Slash,
     Caret,
@@ -26,6 +32,8 @@ pub enum Token {
     Exclamation,
     NotEqual,
     Question,
+    Tilde,
+    TildeAsterisk,
     OpenParen,
     CloseParen,
     Comma,
@@ -37,9 +45,12 @@ impl std::fmt::Display for Token {
         f.write_str(match self {
             Token::Number(n) => n,
             Token::String(s) => s,
+            Token::Hex(s) => s,
             Token::Ident(s) => s,
             Token::Keyword(k) => k.to_str(),
             Token::Period => ".",
+            Token::Colon => ":",
+            Token::DoubleColon => "::",
             Token::Equal => "=",
             Token::GreaterThan => ">",
             Token::GreaterThanOrEqual => ">=",
@@ -48,6 +59,8 @@ impl std::fmt::Display for Token {
             Token::LessOrGreaterThan => "<>",
             Token::Plus => "+",
             Token::Minus => "-",
+            Token::Arrow => "->",
+            Token::LongArrow => "->>",
             Token::Asterisk => "*",
             Token::Slash => "/",
             Token::Caret => "^",
@@ -55,6 +68,8 @@ impl std::fmt::Display for Token {
             Token::Exclamation => "!",
             Token::NotEqual => "!=",
             Token::Question => "?",
+            Token::Tilde => "~",
+            Token::TildeAsterisk => "~*",
             Token::OpenParen => "(",
             Token::CloseParen => ")",
             Token::Comma => ",",
@@ -72,44 +87,79 @@ impl From<Keyword> for Token {
 /// Lexer keywords
 #[derive(Clone, Debug, PartialEq)]
 pub enum Keyword {
+    Add,
+    All,
+    Alter,
     And,
     As,
     Asc,
+    Autoincrement,
     Begin,
+    Between,
+    Binary,
+    Blob,
     Bool,
     Boolean,
     By,
+    Bytea,
+    Cascade,
+    Case,
+    Cast,
     Char,
+    Check,
+    Collate,
+    Column,
     Commit,
+    Constraint,
     Create,
+    Current,
     Cross,
+    Cube,
+    Date,
+    Decimal,
     Default,
     Delete,
     Desc,
+    Distinct,
     Double,
     Drop,
+    Else,
+    End,
+    Except,
+    Exists,
     Explain,
     False,
     Float,
+    Following,
+    Foreign,
     From,
+    Full,
     Group,
     Having,
+    ILike,
+    In,
+    Increment,
     Index,
     Infinity,
     Inner,
     Insert,
     Int,
     Integer,
+    Intersect,
+    Interval,
     Into,
     Is,
     Join,
+    Json,
     Key,
     Left,
     Like,
     Limit,
     NaN,
+    Natural,
     Not,
     Null,
+    Numeric,
     Of,
     Offset,
     On,
@@ -117,25 +167,49 @@ pub enum Keyword {
     Or,
     Order,
     Outer,
+    Over,
+    Partition,
+    Preceding,
     Primary,
+    Range,
     Read,
+    Recursive,
     References,
+    Rename,
+    Restrict,
     Right,
     Rollback,
+    Rollup,
+    Rounding,
+    Row,
+    Rows,
     Select,
+    Sequence,
+    Serial,
     Set,
+    Sets,
+    Similar,
+    Start,
     String,
     System,
     Table,
     Text,
+    Then,
     Time,
+    Timestamp,
+    To,
     Transaction,
     True,
+    Unbounded,
+    Union,
     Unique,
     Update,
+    Using,
     Values,
     Varchar,
+    When,
     Where,
+    With,
     Write,
 }
 
@@ -143,44 +217,79 @@ impl Keyword {
     #[allow(clippy::should_implement_trait)]
     pub fn from_str(ident: &str) -> Option<Self> {
         Some(match ident.to_uppercase().as_ref() {
+            "ADD" => Self::Add,
+            "ALL" => Self::All,
+            "ALTER" => Self::Alter,
             "AS" => Self::As,
             "ASC" => Self::Asc,
+            "AUTOINCREMENT" => Self::Autoincrement,
             "AND" => Self::And,
             "BEGIN" => Self::Begin,
+            "BETWEEN" => Self::Between,
+            "BINARY" => Self::Binary,
+            "BLOB" => Self::Blob,
             "BOOL" => Self::Bool,
             "BOOLEAN" => Self::Boolean,
             "BY" => Self::By,
+            "BYTEA" => Self::Bytea,
+            "CASCADE" => Self::Cascade,
+            "CASE" => Self::Case,
+            "CAST" => Self::Cast,
             "CHAR" => Self::Char,
+            "CHECK" => Self::Check,
+            "COLLATE" => Self::Collate,
+            "COLUMN" => Self::Column,
             "COMMIT" => Self::Commit,
+            "CONSTRAINT" => Self::Constraint,
             "CREATE" => Self::Create,
+            "CURRENT" => Self::Current,
             "CROSS" => Self::Cross,
+            "CUBE" => Self::Cube,
+            "DATE" => Self::Date,
+            "DECIMAL" => Self::Decimal,
             "DEFAULT" => Self::Default,
             "DELETE" => Self::Delete,
             "DESC" => Self::Desc,
+            "DISTINCT" => Self::Distinct,
             "DOUBLE" => Self::Double,
             "DROP" => Self::Drop,
+            "ELSE" => Self::Else,
+            "END" => Self::End,
+            "EXCEPT" => Self::Except,
+            "EXISTS" => Self::Exists,
             "EXPLAIN" => Self::Explain,
             "FALSE" => Self::False,
             "FLOAT" => Self::Float,
+            "FOLLOWING" => Self::Following,
+            "FOREIGN" => Self::Foreign,
             "FROM" => Self::From,
+            "FULL" => Self::Full,
             "GROUP" => Self::Group,
             "HAVING" => Self::Having,
+            "ILIKE" => Self::ILike,
+            "IN" => Self::In,
+            "INCREMENT" => Self::Increment,
             "INDEX" => Self::Index,
             "INFINITY" => Self::Infinity,
             "INNER" => Self::Inner,
             "INSERT" => Self::Insert,
             "INT" => Self::Int,
             "INTEGER" => Self::Integer,
+            "INTERSECT" => Self::Intersect,
+            "INTERVAL" => Self::Interval,
             "INTO" => Self::Into,
             "IS" => Self::Is,
             "JOIN" => Self::Join,
+            "JSON" => Self::Json,
             "KEY" => Self::Key,
             "LEFT" => Self::Left,
             "LIKE" => Self::Like,
             "LIMIT" => Self::Limit,
             "NAN" => Self::NaN,
+            "NATURAL" => Self::Natural,
             "NOT" => Self::Not,
             "NULL" => Self::Null,
+            "NUMERIC" => Self::Numeric,
             "OF" => Self::Of,
             "OFFSET" => Self::Offset,
             "ON" => Self::On,
@@ -188,25 +297,49 @@ impl Keyword {
             "OR" => Self::Or,
             "ORDER" => Self::Order,
             "OUTER" => Self::Outer,
+            "OVER" => Self::Over,
+            "PARTITION" => Self::Partition,
+            "PRECEDING" => Self::Preceding,
             "PRIMARY" => Self::Primary,
+            "RANGE" => Self::Range,
             "READ" => Self::Read,
+            "RECURSIVE" => Self::Recursive,
             "REFERENCES" => Self::References,
+            "RENAME" => Self::Rename,
+            "RESTRICT" => Self::Restrict,
             "RIGHT" => Self::Right,
             "ROLLBACK" => Self::Rollback,
+            "ROLLUP" => Self::Rollup,
+            "ROUNDING" => Self::Rounding,
+            "ROW" => Self::Row,
+            "ROWS" => Self::Rows,
             "SELECT" => Self::Select,
+            "SEQUENCE" => Self::Sequence,
+            "SERIAL" => Self::Serial,
             "SET" => Self::Set,
+            "SETS" => Self::Sets,
+            "SIMILAR" => Self::Similar,
+            "START" => Self::Start,
             "STRING" => Self::String,
             "SYSTEM" => Self::System,
             "TABLE" => Self::Table,
             "TEXT" => Self::Text,
+            "THEN" => Self::Then,
             "TIME" => Self::Time,
+            "TIMESTAMP" => Self::Timestamp,
+            "TO" => Self::To,
             "TRANSACTION" => Self::Transaction,
             "TRUE" => Self::True,
+            "UNBOUNDED" => Self::Unbounded,
+            "UNION" => Self::Union,
             "UNIQUE" => Self::Unique,
             "UPDATE" => Self::Update,
+            "USING" => Self::Using,
             "VALUES" => Self::Values,
             "VARCHAR" => Self::Varchar,
+            "WHEN" => Self::When,
             "WHERE" => Self::Where,
+            "WITH" => Self::With,
             "WRITE" => Self::Write,
             _ => return None,
         })
@@ -214,70 +347,129 @@ impl Keyword {
 
     pub fn to_str(&self) -> &str {
         match self {
+            Self::Add => "ADD",
+            Self::All => "ALL",
+            Self::Alter => "ALTER",
             Self::As => "AS",
             Self::Asc => "ASC",
+            Self::Autoincrement => "AUTOINCREMENT",
             Self::And => "AND",
             Self::Begin => "BEGIN",
+            Self::Between => "BETWEEN",
+            Self::Binary => "BINARY",
+            Self::Blob => "BLOB",
             Self::Bool => "BOOL",
             Self::Boolean => "BOOLEAN",
             Self::By => "BY",
+            Self::Bytea => "BYTEA",
+            Self::Cascade => "CASCADE",
+            Self::Case => "CASE",
+            Self::Cast => "CAST",
             Self::Char => "CHAR",
+            Self::Check => "CHECK",
+            Self::Collate => "COLLATE",
+            Self::Column => "COLUMN",
             Self::Commit => "COMMIT",
+            Self::Constraint => "CONSTRAINT",
             Self::Create => "CREATE",
+            Self::Current => "CURRENT",
             Self::Cross => "CROSS",
+            Self::Cube => "CUBE",
+            Self::Date => "DATE",
+            Self::Decimal => "DECIMAL",
             Self::Default => "DEFAULT",
             Self::Delete => "DELETE",
             Self::Desc => "DESC",
+            Self::Distinct => "DISTINCT",
             Self::Double => "DOUBLE",
             Self::Drop => "DROP",
+            Self::Else => "ELSE",
+            Self::End => "END",
+            Self::Except => "EXCEPT",
+            Self::Exists => "EXISTS",
             Self::Explain => "EXPLAIN",
             Self::False => "FALSE",
             Self::Float => "FLOAT",
+            Self::Following => "FOLLOWING",
+            Self::Foreign => "FOREIGN",
             Self::From => "FROM",
+            Self::Full => "FULL",
             Self::Group => "GROUP",
             Self::Having => "HAVING",
+            Self::ILike => "ILIKE",
+            Self::In => "IN",
+            Self::Increment => "INCREMENT",
             Self::Index => "INDEX",
             Self::Infinity => "INFINITY",
             Self::Inner => "INNER",
             Self::Insert => "INSERT",
             Self::Int => "INT",
             Self::Integer => "INTEGER",
+            Self::Intersect => "INTERSECT",
+            Self::Interval => "INTERVAL",
             Self::Into => "INTO",
             Self::Is => "IS",
             Self::Join => "JOIN",
+            Self::Json => "JSON",
             Self::Key => "KEY",
             Self::Left => "LEFT",
             Self::Like => "LIKE",
             Self::Limit => "LIMIT",
             Self::NaN => "NAN",
+            Self::Natural => "NATURAL",
             Self::Not => "NOT",
             Self::Null => "NULL",
+            Self::Numeric => "NUMERIC",
             Self::Of => "OF",
             Self::Offset => "OFFSET",
             Self::On => "ON",
             Self::Only => "ONLY",
             Self::Outer => "OUTER",
+            Self::Over => "OVER",
+            Self::Partition => "PARTITION",
+            Self::Preceding => "PRECEDING",
             Self::Or => "OR",
             Self::Order => "ORDER",
             Self::Primary => "PRIMARY",
+            Self::Range => "RANGE",
             Self::Read => "READ",
+            Self::Recursive => "RECURSIVE",
             Self::References => "REFERENCES",
+            Self::Rename => "RENAME",
+            Self::Restrict => "RESTRICT",
             Self::Right => "RIGHT",
             Self::Rollback => "ROLLBACK",
+            Self::Rollup => "ROLLUP",
+            Self::Rounding => "ROUNDING",
+            Self::Row => "ROW",
+            Self::Rows => "ROWS",
             Self::Select => "SELECT",
+            Self::Sequence => "SEQUENCE",
+            Self::Serial => "SERIAL",
             Self::Set => "SET",
+            Self::Sets => "SETS",
+            Self::Similar => "SIMILAR",
+            Self::Start => "START",
             Self::String => "STRING",
             Self::System => "SYSTEM",
             Self::Table => "TABLE",
             Self::Text => "TEXT",
+            Self::Then => "THEN",
             Self::Time => "TIME",
+            Self::Timestamp => "TIMESTAMP",
+            Self::To => "TO",
             Self::Transaction => "TRANSACTION",
             Self::True => "TRUE",
+            Self::Unbounded => "UNBOUNDED",
+            Self::Union => "UNION",
             Self::Unique => "UNIQUE",
             Self::Update => "UPDATE",
+            Self::Using => "USING",
             Self::Values => "VALUES",
             Self::Varchar => "VARCHAR",
+            Self::When => "WHEN",
             Self::Where => "WHERE",
+            Self::With => "WITH",
             Self::Write => "WRITE",
         }
     }
@@ -290,6 +482,7 @@ impl std::fmt::Display for Keyword {
 }
 
 /// A lexer tokenizes an input string as an iterator
+#[derive(Clone)]
 pub struct Lexer<'a> {
     iter: Peekable<Chars<'a>>,
 }
@@ -346,22 +539,30 @@ impl<'a> Lexer<'a> {
         match self.iter.peek() {
             Some('\'') => self.scan_string(),
             Some('"') => self.scan_ident_quoted(),
-            Some(c) if c.is_digit(10) => Ok(self.scan_number()),
-            Some(c) if c.is_alphabetic() => Ok(self.scan_ident()),
+            Some(c) if c.is_ascii_digit() => Ok(self.scan_number()),
+            Some(c) if c.is_alphabetic() => self.scan_ident(),
             Some(_) => Ok(self.scan_symbol()),
             None => Ok(None),
         }
     }
 
-    /// Scans the input for the next ident or keyword token, if any
-    fn scan_ident(&mut self) -> Option<Token> {
-        let mut name = self.next_if(|c| c.is_alphabetic())?.to_string();
+    /// Scans the input for the next ident or keyword token, if any. An X immediately followed by
+    /// a string literal is a hex string literal instead.
+    fn scan_ident(&mut self) -> Result<Option<Token>> {
+        let Some(first) = self.next_if(|c| c.is_alphabetic()) else { return Ok(None) };
+        if matches!(first, 'x' | 'X') && self.iter.peek() == Some(&'\'') {
+            return match self.scan_string()? {
+                Some(Token::String(hex)) => Ok(Some(Token::Hex(hex))),
+                _ => Err(Error::Parse("Expected hex string literal".into())),
+            };
+        }
+        let mut name = first.to_string();
         while let Some(c) = self.next_if(|c| c.is_alphanumeric() || c == '_') {
             name.push(c)
         }
-        Keyword::from_str(&name)
+        Ok(Keyword::from_str(&name)
             .map(Token::Keyword)
-            .or_else(|| Some(Token::Ident(name.to_lowercase())))
+            .or_else(|| Some(Token::Ident(name.to_lowercase()))))
     }
 
     /// Scans the input for the next quoted ident, if any
@@ -383,10 +584,10 @@ impl<'a> Lexer<'a> {
 
     /// Scans the input for the next number token, if any
     fn scan_number(&mut self) -> Option<Token> {
-        let mut num = self.next_while(|c| c.is_digit(10))?;
+        let mut num = self.next_while(|c| c.is_ascii_digit())?;
         if let Some(sep) = self.next_if(|c| c == '.') {
             num.push(sep);
-            while let Some(dec) = self.next_if(|c| c.is_digit(10)) {
+            while let Some(dec) = self.next_if(|c| c.is_ascii_digit()) {
                 num.push(dec)
             }
         }
@@ -395,7 +596,7 @@ impl<'a> Lexer<'a> {
             if let Some(sign) = self.next_if(|c| c == '+' || c == '-') {
                 num.push(sign)
             }
-            while let Some(c) = self.next_if(|c| c.is_digit(10)) {
+            while let Some(c) = self.next_if(|c| c.is_ascii_digit()) {
                 num.push(c)
             }
         }
@@ -424,6 +625,7 @@ impl<'a> Lexer<'a> {
     fn scan_symbol(&mut self) -> Option<Token> {
         self.next_if_token(|c| match c {
             '.' => Some(Token::Period),
+            ':' => Some(Token::Colon),
             '=' => Some(Token::Equal),
             '>' => Some(Token::GreaterThan),
             '<' => Some(Token::LessThan),
@@ -435,6 +637,7 @@ impl<'a> Lexer<'a> {
             '%' => Some(Token::Percent),
             '!' => Some(Token::Exclamation),
             '?' => Some(Token::Question),
+            '~' => Some(Token::Tilde),
             '(' => Some(Token::OpenParen),
             ')' => Some(Token::CloseParen),
             ',' => Some(Token::Comma),
@@ -442,6 +645,13 @@ impl<'a> Lexer<'a> {
             _ => None,
         })
         .map(|token| match token {
+            Token::Colon => {
+                if self.next_if(|c| c == ':').is_some() {
+                    Token::DoubleColon
+                } else {
+                    token
+                }
+            }
             Token::Exclamation => {
                 if self.next_if(|c| c == '=').is_some() {
                     Token::NotEqual
@@ -449,6 +659,13 @@ impl<'a> Lexer<'a> {
                     token
                 }
             }
+            Token::Tilde => {
+                if self.next_if(|c| c == '*').is_some() {
+                    Token::TildeAsterisk
+                } else {
+                    token
+                }
+            }
             Token::LessThan => {
                 if self.next_if(|c| c == '>').is_some() {
                     Token::LessOrGreaterThan
@@ -465,6 +682,15 @@ impl<'a> Lexer<'a> {
                     token
                 }
             }
+            Token::Minus => {
+                if self.next_if(|c| c == '>').is_none() {
+                    token
+                } else if self.next_if(|c| c == '>').is_some() {
+                    Token::LongArrow
+                } else {
+                    Token::Arrow
+                }
+            }
             _ => token,
         })
     }
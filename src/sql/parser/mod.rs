@@ -2,7 +2,8 @@ pub mod ast;
 mod lexer;
 pub use lexer::{ Keyword, Lexer, Token };
 
-use super::types::DataType;
+use super::schema::{ReferentialAction, StringLength};
+use super::types::{self, decimal, Collation, DataType, RoundingMode};
 use crate::error::{ Error, Result };
 
 use lazy_static::lazy_static;
@@ -17,7 +18,7 @@ pub struct Parser<'a> {
 
 impl<'a> Parser<'a> {
     // 创建一个词法解析器
-    pub fn new(query: &str) -> Parser {
+    pub fn new(query: &str) -> Parser<'_> {
         Parser {
             lexer: Lexer::new(query).peekable(),
         }
@@ -58,9 +59,7 @@ impl<'a> Parser<'a> {
     /// 获取下一个标识符，如果没有找到则报错。
     fn next_ident(&mut self) -> Result<String> {
         match self.next()? {
-            Token::Ident(ident) => {
-                return Ok(ident);
-            }
+            Token::Ident(ident) => Ok(ident),
             token => Err(Error::Parse(format!("Expected identifier, got {}", token))),
         }
     }
@@ -77,17 +76,17 @@ impl<'a> Parser<'a> {
 
     ///如果下一个操作符满足类型和优先级，则获取它。
     fn next_if_operator<O: Operator>(&mut self, min_prec: u8) -> Result<Option<O>> {
-        if
-            let Some(operator) = self
-                .peek()
-                .unwrap_or(None)
-                .and_then(|token| O::from(&token))
-                .filter(|op| op.prec() >= min_prec)
-        {
-            self.next()?;
-            Ok(Some(operator.augment(self)?))
-        } else {
-            Ok(None)
+        let operator = self
+            .peek()
+            .unwrap_or(None)
+            .and_then(|token| O::from(&token))
+            .filter(|op| op.prec() >= min_prec);
+        match operator {
+            Some(operator) if operator.accepts_next(self.peek_second()?.as_ref()) => {
+                self.next()?;
+                Ok(Some(operator.augment(self)?))
+            }
+            _ => Ok(None),
         }
     }
 
@@ -108,6 +107,13 @@ impl<'a> Parser<'a> {
         self.lexer.peek().cloned().transpose()
     }
 
+    /// Peeks the lexer token following the next one, if any, without consuming either.
+    fn peek_second(&mut self) -> Result<Option<Token>> {
+        let mut lexer = self.lexer.clone();
+        lexer.next();
+        lexer.next().transpose()
+    }
+
     // 解析出一个parse_statement
     fn parse_statement(&mut self) -> Result<ast::Statement> {
         match self.peek()? {
@@ -117,10 +123,13 @@ impl<'a> Parser<'a> {
 
             Some(Token::Keyword(Keyword::Create)) => self.parse_ddl(),
             Some(Token::Keyword(Keyword::Drop)) => self.parse_ddl(),
+            Some(Token::Keyword(Keyword::Alter)) => self.parse_ddl(),
 
             Some(Token::Keyword(Keyword::Delete)) => self.parse_statement_delete(),
             Some(Token::Keyword(Keyword::Insert)) => self.parse_statement_insert(),
             Some(Token::Keyword(Keyword::Select)) => self.parse_statement_select(),
+            Some(Token::Keyword(Keyword::With)) => self.parse_statement_select(),
+            Some(Token::OpenParen) => self.parse_statement_select(),
             Some(Token::Keyword(Keyword::Update)) => self.parse_statement_update(),
 
             Some(Token::Keyword(Keyword::Explain)) => self.parse_statement_explain(),
@@ -138,12 +147,25 @@ impl<'a> Parser<'a> {
                 match self.next()? {
                     // 关键词是 table
                     Token::Keyword(Keyword::Table) => self.parse_ddl_create_table(),
+                    Token::Keyword(Keyword::Index) => self.parse_ddl_create_index(false),
+                    Token::Keyword(Keyword::Unique) => {
+                        self.next_expect(Some(Keyword::Index.into()))?;
+                        self.parse_ddl_create_index(true)
+                    }
+                    Token::Keyword(Keyword::Sequence) => self.parse_ddl_create_sequence(),
                     // 解析失败
                     token => Err(Error::Parse(format!("Unexpected token {}", token))),
                 }
             Token::Keyword(Keyword::Drop) =>
                 match self.next()? {
                     Token::Keyword(Keyword::Table) => self.parse_ddl_drop_table(),
+                    Token::Keyword(Keyword::Index) => self.parse_ddl_drop_index(),
+                    Token::Keyword(Keyword::Sequence) => self.parse_ddl_drop_sequence(),
+                    token => Err(Error::Parse(format!("Unexpected token {}", token))),
+                }
+            Token::Keyword(Keyword::Alter) =>
+                match self.next()? {
+                    Token::Keyword(Keyword::Table) => self.parse_ddl_alter_table(),
                     token => Err(Error::Parse(format!("Unexpected token {}", token))),
                 }
             token => Err(Error::Parse(format!("Unexpected token {}", token))),
@@ -154,16 +176,222 @@ impl<'a> Parser<'a> {
     /// already been consumed.
     fn parse_ddl_create_table(&mut self) -> Result<ast::Statement> {
         let name = self.next_ident()?;
+        self.next_expect(Some(Token::OpenParen))?;
+        let mut columns = Vec::new();
+        let mut primary_key = None;
+        let mut foreign_keys = Vec::new();
+        let mut checks = Vec::new();
+        loop {
+            match self.peek()? {
+                Some(Token::Keyword(Keyword::Constraint)) => {
+                    self.next()?;
+                    let name = Some(self.next_ident()?);
+                    self.next_expect(Some(Keyword::Check.into()))?;
+                    checks.push(ast::Check { name, expression: self.parse_ddl_check()? });
+                }
+                Some(Token::Keyword(Keyword::Check)) => {
+                    self.next()?;
+                    checks.push(ast::Check { name: None, expression: self.parse_ddl_check()? });
+                }
+                Some(Token::Keyword(Keyword::Primary)) => {
+                    if primary_key.is_some() {
+                        return Err(Error::Value(format!(
+                            "Multiple primary keys in table {}",
+                            name
+                        )));
+                    }
+                    self.next()?;
+                    self.next_expect(Some(Keyword::Key.into()))?;
+                    primary_key = Some(self.parse_ddl_column_list()?);
+                }
+                Some(Token::Keyword(Keyword::Foreign)) => {
+                    self.next()?;
+                    self.next_expect(Some(Keyword::Key.into()))?;
+                    let columns = self.parse_ddl_column_list()?;
+                    self.next_expect(Some(Keyword::References.into()))?;
+                    let references = self.next_ident()?;
+                    let (on_delete, on_update) = self.parse_ddl_referential_actions()?;
+                    foreign_keys.push(ast::ForeignKey {
+                        columns,
+                        references,
+                        on_delete,
+                        on_update,
+                    });
+                }
+                _ => columns.push(self.parse_ddl_columnspec()?),
+            }
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        self.next_expect(Some(Token::CloseParen))?;
+
+        // A table-level primary key is also applied to the columns, and can't be combined with
+        // an inline one. Composite keys must be given as a table-level primary key.
+        let inline = columns.iter().filter(|c| c.primary_key).count();
+        if primary_key.is_none() && inline > 1 {
+            return Err(Error::Value(format!("Multiple primary keys in table {}", name)));
+        }
+        if let Some(primary_key) = &primary_key {
+            if let Some(column) = columns.iter().find(|c| c.primary_key) {
+                return Err(Error::Value(format!(
+                    "Column {} can't be an inline primary key when a table primary key is given",
+                    column.name
+                )));
+            }
+            for (i, name) in primary_key.iter().enumerate() {
+                if primary_key[..i].contains(name) {
+                    return Err(Error::Value(format!("Duplicate primary key column {}", name)));
+                }
+                columns
+                    .iter_mut()
+                    .find(|c| &c.name == name)
+                    .ok_or_else(|| Error::Value(format!("Primary key column {} not found", name)))?
+                    .primary_key = true;
+            }
+        }
+        let primary_key = primary_key.unwrap_or_default();
+        Ok(ast::Statement::CreateTable { name, columns, primary_key, foreign_keys, checks })
+    }
+
+    /// Parses an ALTER TABLE DDL statement. The ALTER TABLE prefix has
+    /// already been consumed.
+    fn parse_ddl_alter_table(&mut self) -> Result<ast::Statement> {
+        let table = self.next_ident()?;
+        let alteration = match self.next()? {
+            Token::Keyword(Keyword::Add) => {
+                self.next_if_token(Keyword::Column.into());
+                ast::Alteration::AddColumn(self.parse_ddl_columnspec()?)
+            }
+            Token::Keyword(Keyword::Drop) => {
+                self.next_if_token(Keyword::Column.into());
+                ast::Alteration::DropColumn(self.next_ident()?)
+            }
+            Token::Keyword(Keyword::Rename) => {
+                if self.next_if_token(Keyword::To.into()).is_some() {
+                    ast::Alteration::RenameTable(self.next_ident()?)
+                } else {
+                    self.next_if_token(Keyword::Column.into());
+                    let from = self.next_ident()?;
+                    self.next_expect(Some(Keyword::To.into()))?;
+                    ast::Alteration::RenameColumn { from, to: self.next_ident()? }
+                }
+            }
+            Token::Keyword(Keyword::Alter) => {
+                self.next_if_token(Keyword::Column.into());
+                let column = self.next_ident()?;
+                match self.next()? {
+                    Token::Keyword(Keyword::Set) => match self.next()? {
+                        Token::Keyword(Keyword::Default) => ast::Alteration::SetDefault {
+                            column,
+                            default: Some(self.parse_expression(0)?),
+                        },
+                        Token::Keyword(Keyword::Not) => {
+                            self.next_expect(Some(Keyword::Null.into()))?;
+                            ast::Alteration::SetNullable { column, nullable: false }
+                        }
+                        token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+                    },
+                    Token::Keyword(Keyword::Drop) => match self.next()? {
+                        Token::Keyword(Keyword::Default) => {
+                            ast::Alteration::SetDefault { column, default: None }
+                        }
+                        Token::Keyword(Keyword::Not) => {
+                            self.next_expect(Some(Keyword::Null.into()))?;
+                            ast::Alteration::SetNullable { column, nullable: true }
+                        }
+                        token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+                    },
+                    token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+                }
+            }
+            token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+        };
+        Ok(ast::Statement::AlterTable { table, alteration })
+    }
+
+    /// Parses a CREATE [UNIQUE] INDEX DDL statement. The CREATE [UNIQUE] INDEX prefix has
+    /// already been consumed. Indexed expressions, e.g. JSON paths, must be parenthesized.
+    fn parse_ddl_create_index(&mut self, unique: bool) -> Result<ast::Statement> {
+        let name = self.next_ident()?;
+        self.next_expect(Some(Keyword::On.into()))?;
+        let table = self.next_ident()?;
+        self.next_expect(Some(Token::OpenParen))?;
+        let mut columns = Vec::new();
+        loop {
+            if self.next_if_token(Token::OpenParen).is_some() {
+                columns.push(self.parse_expression(0)?);
+                self.next_expect(Some(Token::CloseParen))?;
+            } else {
+                columns.push(ast::Expression::Field(None, self.next_ident()?));
+            }
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        self.next_expect(Some(Token::CloseParen))?;
+        Ok(ast::Statement::CreateIndex { name, table, columns, unique })
+    }
+
+    /// Parses a DROP INDEX DDL statement. The DROP INDEX prefix has
+    /// already been consumed.
+    fn parse_ddl_drop_index(&mut self) -> Result<ast::Statement> {
+        Ok(ast::Statement::DropIndex(self.next_ident()?))
+    }
+
+    /// Parses a CREATE SEQUENCE DDL statement. The CREATE SEQUENCE prefix has
+    /// already been consumed.
+    fn parse_ddl_create_sequence(&mut self) -> Result<ast::Statement> {
+        let name = self.next_ident()?;
+        let (mut start, mut increment) = (None, None);
+        while let Some(Token::Keyword(keyword)) = self.next_if_keyword() {
+            let (option, noise) = match keyword {
+                Keyword::Start => (&mut start, Keyword::With),
+                Keyword::Increment => (&mut increment, Keyword::By),
+                keyword => return Err(Error::Parse(format!("Unexpected keyword {}", keyword))),
+            };
+            if option.is_some() {
+                return Err(Error::Parse(format!("Duplicate sequence option {}", keyword)));
+            }
+            self.next_if_token(noise.into());
+            *option = Some(self.parse_ddl_integer()?);
+        }
+        Ok(ast::Statement::CreateSequence { name, start, increment })
+    }
+
+    /// Parses a DROP SEQUENCE DDL statement. The DROP SEQUENCE prefix has
+    /// already been consumed.
+    fn parse_ddl_drop_sequence(&mut self) -> Result<ast::Statement> {
+        Ok(ast::Statement::DropSequence(self.next_ident()?))
+    }
+
+    /// Parses an optionally signed integer literal
+    fn parse_ddl_integer(&mut self) -> Result<i64> {
+        let negative = match self.peek()? {
+            Some(Token::Minus) | Some(Token::Plus) => self.next()? == Token::Minus,
+            _ => false,
+        };
+        match self.next()? {
+            Token::Number(n) => {
+                let n = if negative { format!("-{}", n) } else { n };
+                n.parse().map_err(|_| Error::Parse(format!("Invalid integer {}", n)))
+            }
+            token => Err(Error::Parse(format!("Expected integer, found {}", token))),
+        }
+    }
+
+    /// Parses a parenthesized, comma-separated list of column names
+    fn parse_ddl_column_list(&mut self) -> Result<Vec<String>> {
         self.next_expect(Some(Token::OpenParen))?;
         let mut columns = Vec::new();
         loop {
-            columns.push(self.parse_ddl_columnspec()?);
+            columns.push(self.next_ident()?);
             if self.next_if_token(Token::Comma).is_none() {
                 break;
             }
         }
         self.next_expect(Some(Token::CloseParen))?;
-        Ok(ast::Statement::CreateTable { name: name, columns: columns })
+        Ok(columns)
     }
 
     /// Parses a DROP TABLE DDL statement. The DROP TABLE prefix has
@@ -172,31 +400,114 @@ impl<'a> Parser<'a> {
         Ok(ast::Statement::DropTable(self.next_ident()?))
     }
 
+    /// Parses a datatype
+    fn parse_datatype(&mut self) -> Result<DataType> {
+        Ok(match self.next()? {
+            Token::Keyword(Keyword::Binary) => DataType::Binary,
+            Token::Keyword(Keyword::Blob) => DataType::Binary,
+            Token::Keyword(Keyword::Bool) => DataType::Boolean,
+            Token::Keyword(Keyword::Boolean) => DataType::Boolean,
+            Token::Keyword(Keyword::Bytea) => DataType::Binary,
+            Token::Keyword(Keyword::Char) => DataType::String,
+            Token::Keyword(Keyword::Double) => DataType::Float,
+            Token::Keyword(Keyword::Float) => DataType::Float,
+            Token::Keyword(Keyword::Int) => DataType::Integer,
+            Token::Keyword(Keyword::Integer) => DataType::Integer,
+            Token::Keyword(Keyword::Json) => DataType::Json,
+            Token::Keyword(Keyword::String) => DataType::String,
+            Token::Keyword(Keyword::Text) => DataType::String,
+            Token::Keyword(Keyword::Varchar) => DataType::String,
+            Token::Keyword(Keyword::Date) => DataType::Date,
+            Token::Keyword(Keyword::Time) => DataType::Time,
+            Token::Keyword(Keyword::Timestamp) => DataType::Timestamp,
+            Token::Keyword(Keyword::Interval) => DataType::Interval,
+            Token::Keyword(Keyword::Decimal | Keyword::Numeric) => self.parse_decimal_datatype()?,
+            token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+        })
+    }
+
+    /// Parses the optional precision and scale of a DECIMAL(precision, scale) datatype. Like the
+    /// SQL standard, the scale defaults to 0, while the precision defaults to the maximum.
+    fn parse_decimal_datatype(&mut self) -> Result<DataType> {
+        let (mut precision, mut scale) = (decimal::MAX_PRECISION as i64, 0);
+        if self.next_if_token(Token::OpenParen).is_some() {
+            precision = self.parse_ddl_integer()?;
+            if self.next_if_token(Token::Comma).is_some() {
+                scale = self.parse_ddl_integer()?;
+            }
+            self.next_expect(Some(Token::CloseParen))?;
+        }
+        if !(1..=decimal::MAX_PRECISION as i64).contains(&precision) {
+            return Err(Error::Value(format!(
+                "DECIMAL precision must be between 1 and {}, got {}",
+                decimal::MAX_PRECISION,
+                precision
+            )));
+        }
+        if !(0..=precision).contains(&scale) {
+            return Err(Error::Value(format!(
+                "DECIMAL scale must be between 0 and the precision {}, got {}",
+                precision, scale
+            )));
+        }
+        Ok(DataType::Decimal(precision as u8, scale as u8))
+    }
+
+    /// Parses a column datatype, along with the length of CHAR(n) and VARCHAR(n). Like in
+    /// standard SQL, CHAR is CHAR(1), while VARCHAR is unbounded like TEXT.
+    fn parse_ddl_datatype(&mut self) -> Result<(DataType, Option<StringLength>)> {
+        let (length, default): (fn(usize) -> StringLength, _) = match self.peek()? {
+            Some(Token::Keyword(Keyword::Char)) => {
+                (StringLength::Char, Some(StringLength::Char(1)))
+            }
+            Some(Token::Keyword(Keyword::Varchar)) => (StringLength::Varchar, None),
+            _ => return Ok((self.parse_datatype()?, None)),
+        };
+        let datatype = self.parse_datatype()?;
+        if self.next_if_token(Token::OpenParen).is_none() {
+            return Ok((datatype, default));
+        }
+        let n = self.parse_ddl_integer()?;
+        self.next_expect(Some(Token::CloseParen))?;
+        if n < 1 {
+            return Err(Error::Value(format!("String length must be positive, got {}", n)));
+        }
+        Ok((datatype, Some(length(n as usize))))
+    }
+
+    /// Parses a collation name, e.g. for COLLATE. BINARY is also a datatype keyword.
+    fn parse_collation(&mut self) -> Result<Collation> {
+        match self.next()? {
+            Token::Ident(name) | Token::String(name) => name.parse(),
+            Token::Keyword(Keyword::Binary) => Ok(Collation::Binary),
+            token => Err(Error::Parse(format!("Expected collation, found {}", token))),
+        }
+    }
+
     /// Parses a column specification
     fn parse_ddl_columnspec(&mut self) -> Result<ast::Column> {
+        let name = self.next_ident()?;
+        let autoincrement = self.next_if_token(Keyword::Serial.into()).is_some();
+        let (datatype, length) = match autoincrement {
+            true => (DataType::Integer, None),
+            false => self.parse_ddl_datatype()?,
+        };
         let mut column = ast::Column {
-            name: self.next_ident()?,
-            datatype: match self.next()? {
-                Token::Keyword(Keyword::Bool) => DataType::Boolean,
-                Token::Keyword(Keyword::Boolean) => DataType::Boolean,
-                Token::Keyword(Keyword::Char) => DataType::String,
-                Token::Keyword(Keyword::Double) => DataType::Float,
-                Token::Keyword(Keyword::Float) => DataType::Float,
-                Token::Keyword(Keyword::Int) => DataType::Integer,
-                Token::Keyword(Keyword::Integer) => DataType::Integer,
-                Token::Keyword(Keyword::String) => DataType::String,
-                Token::Keyword(Keyword::Text) => DataType::String,
-                Token::Keyword(Keyword::Varchar) => DataType::String,
-                token => {
-                    return Err(Error::Parse(format!("Unexpected token {}", token)));
-                }
-            },
+            name,
+            datatype,
             primary_key: false,
             nullable: None,
             default: None,
             unique: false,
             index: false,
+            autoincrement,
             references: None,
+            on_delete: ReferentialAction::Restrict,
+            on_update: ReferentialAction::Restrict,
+            checks: Vec::new(),
+            rounding: RoundingMode::default(),
+            length,
+            collation: Collation::default(),
         };
 
         while let Some(Token::Keyword(keyword)) = self.next_if_keyword() {
@@ -241,17 +552,98 @@ impl<'a> Parser<'a> {
                 Keyword::Index => {
                     column.index = true;
                 }
+                Keyword::Autoincrement => {
+                    column.autoincrement = true;
+                }
+                Keyword::Check => {
+                    column.checks.push(self.parse_ddl_check()?);
+                }
+                Keyword::Collate => {
+                    if column.datatype != DataType::String {
+                        return Err(Error::Value(format!(
+                            "COLLATE is only valid for string columns, not {} column {}",
+                            column.datatype, column.name
+                        )));
+                    }
+                    column.collation = self.parse_collation()?;
+                }
+                Keyword::Rounding => {
+                    if !matches!(column.datatype, DataType::Decimal(..)) {
+                        return Err(Error::Value(format!(
+                            "ROUNDING is only valid for DECIMAL columns, not {} column {}",
+                            column.datatype, column.name
+                        )));
+                    }
+                    column.rounding = self.next_ident()?.parse()?;
+                }
                 Keyword::References => {
                     column.references = Some(self.next_ident()?);
+                    let (on_delete, on_update) = self.parse_ddl_referential_actions()?;
+                    column.on_delete = on_delete;
+                    column.on_update = on_update;
                 }
                 keyword => {
                     return Err(Error::Parse(format!("Unexpected keyword {}", keyword)));
                 }
             }
         }
+        // Auto-incrementing columns take their default from an implicit sequence.
+        if column.autoincrement {
+            if column.datatype != DataType::Integer {
+                return Err(Error::Value(format!(
+                    "Auto-incrementing column {} must be INTEGER",
+                    column.name
+                )));
+            }
+            if column.default.is_some() {
+                return Err(Error::Value(format!(
+                    "Auto-incrementing column {} can't have a default value",
+                    column.name
+                )));
+            }
+        }
         Ok(column)
     }
 
+    /// Parses the parenthesized expression of a CHECK constraint. The CHECK keyword has
+    /// already been consumed.
+    fn parse_ddl_check(&mut self) -> Result<ast::Expression> {
+        self.next_expect(Some(Token::OpenParen))?;
+        let expression = self.parse_expression(0)?;
+        self.next_expect(Some(Token::CloseParen))?;
+        Ok(expression)
+    }
+
+    /// Parses any ON DELETE and ON UPDATE referential actions following a REFERENCES clause,
+    /// returning the (on_delete, on_update) actions. Actions default to RESTRICT.
+    fn parse_ddl_referential_actions(&mut self) -> Result<(ReferentialAction, ReferentialAction)> {
+        let (mut on_delete, mut on_update) = (None, None);
+        while self.next_if_token(Keyword::On.into()).is_some() {
+            let target = match self.next()? {
+                Token::Keyword(Keyword::Delete) => &mut on_delete,
+                Token::Keyword(Keyword::Update) => &mut on_update,
+                token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+            };
+            if target.is_some() {
+                return Err(Error::Parse("Duplicate referential action".into()));
+            }
+            *target = Some(match self.next()? {
+                Token::Keyword(Keyword::Cascade) => ReferentialAction::Cascade,
+                Token::Keyword(Keyword::Restrict) => ReferentialAction::Restrict,
+                Token::Keyword(Keyword::Set) => match self.next()? {
+                    Token::Keyword(Keyword::Null) => ReferentialAction::SetNull,
+                    Token::Keyword(Keyword::Default) => ReferentialAction::SetDefault,
+                    token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+                },
+                token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+            });
+        }
+        Ok((
+            on_delete.unwrap_or(ReferentialAction::Restrict),
+            on_update.unwrap_or(ReferentialAction::Restrict),
+        ))
+    }
+
     /// Parses a delete statement
     fn parse_statement_delete(&mut self) -> Result<ast::Statement> {
         self.next_expect(Some(Keyword::Delete.into()))?;
@@ -320,25 +712,109 @@ impl<'a> Parser<'a> {
         Ok(ast::Statement::Insert { table, columns, values })
     }
 
-    /// Parses a select statement
+    /// Parses a select statement, which may be a compound query of several selects combined
+    /// with set operators. A leading WITH clause and trailing ORDER BY, LIMIT and OFFSET clauses
+    /// apply to the query as a whole.
     fn parse_statement_select(&mut self) -> Result<ast::Statement> {
+        let with = self.parse_clause_with()?;
+        let mut query = self.parse_select_compound(0)?;
+        let order = self.parse_clause_order()?;
+        let limit = if self.next_if_token(Keyword::Limit.into()).is_some() {
+            Some(self.parse_expression(0)?)
+        } else {
+            None
+        };
+        let offset = if self.next_if_token(Keyword::Offset.into()).is_some() {
+            Some(self.parse_expression(0)?)
+        } else {
+            None
+        };
+        match &mut query {
+            ast::Statement::Select {
+                with: query_with,
+                order: query_order,
+                limit: query_limit,
+                offset: query_offset,
+                ..
+            }
+            | ast::Statement::SetOperation {
+                with: query_with,
+                order: query_order,
+                limit: query_limit,
+                offset: query_offset,
+                ..
+            } => {
+                // A parenthesized query may have its own clauses, which can't be combined with
+                // outer ones.
+                if (!with.is_empty() && !query_with.is_empty())
+                    || (!order.is_empty() && !query_order.is_empty())
+                    || (limit.is_some() && query_limit.is_some())
+                    || (offset.is_some() && query_offset.is_some())
+                {
+                    return Err(Error::Parse("Duplicate clauses for parenthesized query".into()));
+                }
+                query_with.extend(with);
+                query_order.extend(order);
+                *query_limit = query_limit.take().or(limit);
+                *query_offset = query_offset.take().or(offset);
+            }
+            query => return Err(Error::Internal(format!("Unexpected query {:?}", query))),
+        }
+        Ok(query)
+    }
+
+    /// Parses the body of a compound select, i.e. selects combined by set operators, using
+    /// precedence climbing. INTERSECT binds tighter than UNION and EXCEPT, and all are
+    /// left-associative.
+    fn parse_select_compound(&mut self, min_prec: u8) -> Result<ast::Statement> {
+        let mut lhs = self.parse_select_operand()?;
+        loop {
+            let (operator, prec) = match self.peek()? {
+                Some(Token::Keyword(Keyword::Except)) => (ast::SetOperator::Except, 1),
+                Some(Token::Keyword(Keyword::Intersect)) => (ast::SetOperator::Intersect, 2),
+                Some(Token::Keyword(Keyword::Union)) => (ast::SetOperator::Union, 1),
+                _ => break,
+            };
+            if prec < min_prec {
+                break;
+            }
+            self.next()?;
+            let all = self.next_if_token(Keyword::All.into()).is_some();
+            let rhs = self.parse_select_compound(prec + 1)?;
+            lhs = ast::Statement::SetOperation {
+                with: Vec::new(),
+                operator,
+                all,
+                left: Box::new(lhs),
+                right: Box::new(rhs),
+                order: Vec::new(),
+                offset: None,
+                limit: None,
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// Parses an operand of a compound select: either a parenthesized query, or a simple select
+    /// without ORDER BY, LIMIT or OFFSET clauses.
+    fn parse_select_operand(&mut self) -> Result<ast::Statement> {
+        if self.next_if_token(Token::OpenParen).is_some() {
+            let query = self.parse_statement_select()?;
+            self.next_expect(Some(Token::CloseParen))?;
+            return Ok(query);
+        }
+        let (distinct, select) = self.parse_clause_select()?;
         Ok(ast::Statement::Select {
-            select: self.parse_clause_select()?,
+            with: Vec::new(),
+            distinct,
+            select,
             from: self.parse_clause_from()?,
             r#where: self.parse_clause_where()?,
             group_by: self.parse_clause_group_by()?,
             having: self.parse_clause_having()?,
-            order: self.parse_clause_order()?,
-            limit: if self.next_if_token(Keyword::Limit.into()).is_some() {
-                Some(self.parse_expression(0)?)
-            } else {
-                None
-            },
-            offset: if self.next_if_token(Keyword::Offset.into()).is_some() {
-                Some(self.parse_expression(0)?)
-            } else {
-                None
-            },
+            order: Vec::new(),
+            offset: None,
+            limit: None,
         })
     }
 
@@ -409,6 +885,63 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses a WITH clause of common table expressions
+    fn parse_clause_with(&mut self) -> Result<Vec<ast::CommonTableExpression>> {
+        let mut ctes: Vec<ast::CommonTableExpression> = Vec::new();
+        if self.next_if_token(Keyword::With.into()).is_none() {
+            return Ok(ctes);
+        }
+        let recursive = self.next_if_token(Keyword::Recursive.into()).is_some();
+        loop {
+            let name = self.next_ident()?;
+            if ctes.iter().any(|cte| cte.name == name) {
+                return Err(Error::Value(format!("Duplicate common table expression {}", name)));
+            }
+            let columns = match self.peek()? {
+                Some(Token::OpenParen) => Some(self.parse_ddl_column_list()?),
+                _ => None,
+            };
+            self.next_expect(Some(Keyword::As.into()))?;
+            self.next_expect(Some(Token::OpenParen))?;
+            let mut query = self.parse_statement_select()?;
+            let (mut recursive_term, mut union_all) = (None, false);
+            // The recursive term is the right-hand side of a top-level UNION [ALL], if it refers
+            // to the common table expression itself. Otherwise, the query isn't recursive.
+            if recursive {
+                query = match query {
+                    ast::Statement::SetOperation {
+                        with,
+                        operator: ast::SetOperator::Union,
+                        all,
+                        left,
+                        right,
+                        order,
+                        offset: None,
+                        limit: None,
+                    } if with.is_empty() && order.is_empty() && right.references_table(&name) => {
+                        recursive_term = Some(right);
+                        union_all = all;
+                        *left
+                    }
+                    query => query,
+                };
+            }
+            let query = Box::new(query);
+            self.next_expect(Some(Token::CloseParen))?;
+            ctes.push(ast::CommonTableExpression {
+                name,
+                columns,
+                query,
+                recursive: recursive_term,
+                union_all,
+            });
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        Ok(ctes)
+    }
+
     /// Parses a from clause
     fn parse_clause_from(&mut self) -> Result<Vec<ast::FromItem>> {
         let mut from = Vec::new();
@@ -417,18 +950,22 @@ impl<'a> Parser<'a> {
         }
         loop {
             let mut item = self.parse_clause_from_item()?;
-            while let Some(jointype) = self.parse_clause_from_jointype()? {
+            while let Some((jointype, natural)) = self.parse_clause_from_jointype()? {
                 let left = Box::new(item);
                 let right = Box::new(self.parse_clause_from_item()?);
-                let predicate = match &jointype {
-                    ast::JoinType::Cross => None,
+                let constraint = match &jointype {
+                    _ if natural => ast::JoinConstraint::Natural,
+                    ast::JoinType::Cross => ast::JoinConstraint::None,
+                    _ if self.next_if_token(Keyword::Using.into()).is_some() => {
+                        ast::JoinConstraint::Using(self.parse_ddl_column_list()?)
+                    }
                     _ => {
                         self.next_expect(Some(Keyword::On.into()))?;
-                        Some(self.parse_expression(0)?)
+                        ast::JoinConstraint::On(self.parse_expression(0)?)
                     }
                 };
                 let r#type = jointype;
-                item = ast::FromItem::Join { left, right, r#type, predicate };
+                item = ast::FromItem::Join { left, right, r#type, constraint };
             }
             from.push(item);
             if self.next_if_token(Token::Comma).is_none() {
@@ -440,12 +977,37 @@ impl<'a> Parser<'a> {
 
     /// Parses a from clause item
     fn parse_clause_from_item(&mut self) -> Result<ast::FromItem> {
-        self.parse_clause_from_table()
+        if self.next_if_token(Token::OpenParen).is_some() {
+            self.parse_clause_from_subquery()
+        } else {
+            self.parse_clause_from_table()
+        }
+    }
+
+    // Parses a from clause subquery, which must be given an alias. The opening parenthesis has
+    // already been consumed.
+    fn parse_clause_from_subquery(&mut self) -> Result<ast::FromItem> {
+        let query = Box::new(self.parse_subquery_body()?);
+        self.next_if_token(Keyword::As.into());
+        match self.next_if(|t| matches!(t, Token::Ident(_))) {
+            Some(Token::Ident(alias)) => Ok(ast::FromItem::Subquery { query, alias }),
+            _ => Err(Error::Parse("Subquery in FROM must have an alias".into())),
+        }
     }
 
-    // Parses a from clause table
+    // Parses a from clause table, or a table function call such as json_each(doc)
     fn parse_clause_from_table(&mut self) -> Result<ast::FromItem> {
         let name = self.next_ident()?;
+        let mut args = None;
+        if self.next_if_token(Token::OpenParen).is_some() {
+            let args = args.insert(Vec::new());
+            while self.next_if_token(Token::CloseParen).is_none() {
+                if !args.is_empty() {
+                    self.next_expect(Some(Token::Comma))?;
+                }
+                args.push(self.parse_expression(0)?);
+            }
+        }
         let alias = if self.next_if_token(Keyword::As.into()).is_some() {
             Some(self.next_ident()?)
         } else if let Some(Token::Ident(_)) = self.peek()? {
@@ -453,11 +1015,28 @@ impl<'a> Parser<'a> {
         } else {
             None
         };
-        Ok(ast::FromItem::Table { name, alias })
+        Ok(match args {
+            Some(args) => ast::FromItem::Function { name, args, alias },
+            None => ast::FromItem::Table { name, alias },
+        })
+    }
+
+    // Parses a from clause join type, along with whether it is a NATURAL join
+    fn parse_clause_from_jointype(&mut self) -> Result<Option<(ast::JoinType, bool)>> {
+        if self.next_if_token(Keyword::Natural.into()).is_some() {
+            return match self.parse_clause_from_jointype_inner()? {
+                Some(ast::JoinType::Cross) => {
+                    Err(Error::Parse("Can't use NATURAL CROSS JOIN".into()))
+                }
+                Some(jointype) => Ok(Some((jointype, true))),
+                None => Err(Error::Parse("Expected JOIN after NATURAL".into())),
+            };
+        }
+        Ok(self.parse_clause_from_jointype_inner()?.map(|jointype| (jointype, false)))
     }
 
-    // Parses a from clause join type
-    fn parse_clause_from_jointype(&mut self) -> Result<Option<ast::JoinType>> {
+    // Parses a from clause join type, after any NATURAL keyword
+    fn parse_clause_from_jointype_inner(&mut self) -> Result<Option<ast::JoinType>> {
         if self.next_if_token(Keyword::Cross.into()).is_some() {
             self.next_expect(Some(Keyword::Join.into()))?;
             Ok(Some(ast::JoinType::Cross))
@@ -474,24 +1053,74 @@ impl<'a> Parser<'a> {
             self.next_if_token(Keyword::Outer.into());
             self.next_expect(Some(Keyword::Join.into()))?;
             Ok(Some(ast::JoinType::Right))
+        } else if self.next_if_token(Keyword::Full.into()).is_some() {
+            self.next_if_token(Keyword::Outer.into());
+            self.next_expect(Some(Keyword::Join.into()))?;
+            Ok(Some(ast::JoinType::Full))
         } else {
             Ok(None)
         }
     }
 
     /// Parses a group by clause
-    fn parse_clause_group_by(&mut self) -> Result<Vec<ast::Expression>> {
-        let mut exprs = Vec::new();
+    fn parse_clause_group_by(&mut self) -> Result<Vec<ast::GroupingElement>> {
+        let mut elements = Vec::new();
         if self.next_if_token(Keyword::Group.into()).is_none() {
-            return Ok(exprs);
+            return Ok(elements);
         }
         self.next_expect(Some(Keyword::By.into()))?;
         loop {
-            exprs.push(self.parse_expression(0)?);
+            elements.push(self.parse_clause_group_by_element()?);
             if self.next_if_token(Token::Comma).is_none() {
                 break;
             }
         }
+        Ok(elements)
+    }
+
+    /// Parses a GROUP BY element. GROUPING is not a keyword, since it is also a function name.
+    fn parse_clause_group_by_element(&mut self) -> Result<ast::GroupingElement> {
+        if self.next_if_token(Keyword::Rollup.into()).is_some() {
+            return Ok(ast::GroupingElement::Rollup(self.parse_grouping_set()?));
+        }
+        if self.next_if_token(Keyword::Cube.into()).is_some() {
+            return Ok(ast::GroupingElement::Cube(self.parse_grouping_set()?));
+        }
+        if let (Some(Token::Ident(ident)), Some(Token::Keyword(Keyword::Sets))) =
+            (self.peek()?, self.peek_second()?)
+        {
+            if ident == "grouping" {
+                self.next()?;
+                self.next()?;
+                self.next_expect(Some(Token::OpenParen))?;
+                let mut sets = Vec::new();
+                loop {
+                    if self.peek()? == Some(Token::OpenParen) {
+                        sets.push(self.parse_grouping_set()?);
+                    } else {
+                        sets.push(vec![self.parse_expression(0)?]);
+                    }
+                    if self.next_if_token(Token::Comma).is_none() {
+                        break;
+                    }
+                }
+                self.next_expect(Some(Token::CloseParen))?;
+                return Ok(ast::GroupingElement::GroupingSets(sets));
+            }
+        }
+        Ok(ast::GroupingElement::Expression(self.parse_expression(0)?))
+    }
+
+    /// Parses a parenthesized, possibly empty, list of grouping expressions
+    fn parse_grouping_set(&mut self) -> Result<Vec<ast::Expression>> {
+        self.next_expect(Some(Token::OpenParen))?;
+        let mut exprs = Vec::new();
+        while self.next_if_token(Token::CloseParen).is_none() {
+            if !exprs.is_empty() {
+                self.next_expect(Some(Token::Comma))?;
+            }
+            exprs.push(self.parse_expression(0)?);
+        }
         Ok(exprs)
     }
 
@@ -509,6 +1138,11 @@ impl<'a> Parser<'a> {
             return Ok(Vec::new());
         }
         self.next_expect(Some(Keyword::By.into()))?;
+        self.parse_order_list()
+    }
+
+    /// Parses a comma-separated list of ORDER BY expressions with optional directions
+    fn parse_order_list(&mut self) -> Result<Vec<(ast::Expression, ast::Order)>> {
         let mut orders = Vec::new();
         loop {
             orders.push((
@@ -528,12 +1162,34 @@ impl<'a> Parser<'a> {
         Ok(orders)
     }
 
-    /// Parses a select clause
-    fn parse_clause_select(&mut self) -> Result<Vec<(ast::Expression, Option<String>)>> {
+    /// Parses a select clause, along with any DISTINCT modifier
+    #[allow(clippy::type_complexity)]
+    fn parse_clause_select(
+        &mut self,
+    ) -> Result<(Option<ast::Distinct>, Vec<(ast::Expression, Option<String>)>)> {
         let mut select = Vec::new();
         if self.next_if_token(Keyword::Select.into()).is_none() {
-            return Ok(select);
+            return Ok((None, select));
         }
+        let distinct = if self.next_if_token(Keyword::Distinct.into()).is_some() {
+            if self.next_if_token(Keyword::On.into()).is_some() {
+                self.next_expect(Some(Token::OpenParen))?;
+                let mut on = Vec::new();
+                loop {
+                    on.push(self.parse_expression(0)?);
+                    if self.next_if_token(Token::Comma).is_none() {
+                        break;
+                    }
+                }
+                self.next_expect(Some(Token::CloseParen))?;
+                Some(ast::Distinct::On(on))
+            } else {
+                Some(ast::Distinct::Rows)
+            }
+        } else {
+            self.next_if_token(Keyword::All.into());
+            None
+        };
         loop {
             if self.next_if_token(Token::Asterisk).is_some() && select.is_empty() {
                 break;
@@ -552,7 +1208,7 @@ impl<'a> Parser<'a> {
                 break;
             }
         }
-        Ok(select)
+        Ok((distinct, select))
     }
 
     /// Parses a WHERE clause
@@ -571,33 +1227,139 @@ impl<'a> Parser<'a> {
         } else {
             self.parse_expression_atom()?
         };
-        while let Some(postfix) = self.next_if_operator::<PostfixOperator>(min_prec)? {
-            lhs = postfix.build(lhs);
-        }
-        while let Some(infix) = self.next_if_operator::<InfixOperator>(min_prec)? {
-            lhs = infix.build(lhs, self.parse_expression(infix.prec() + infix.assoc())?);
+        // Postfix operators may follow infix operations, e.g. a + b IN (SELECT ...).
+        loop {
+            if let Some(postfix) = self.next_if_operator::<PostfixOperator>(min_prec)? {
+                lhs = postfix.build(lhs);
+            } else if let Some(infix) = self.next_if_operator::<InfixOperator>(min_prec)? {
+                lhs = infix.build(lhs, self.parse_expression(infix.prec() + infix.assoc())?);
+            } else {
+                break;
+            }
         }
         Ok(lhs)
     }
 
+    /// Parses the SELECT statement of a parenthesized subquery, along with the closing
+    /// parenthesis. The opening parenthesis has already been consumed.
+    fn parse_subquery_body(&mut self) -> Result<ast::Statement> {
+        match self.peek()? {
+            Some(Token::Keyword(Keyword::Select)) | Some(Token::Keyword(Keyword::With)) => {}
+            _ => return Err(Error::Parse("Expected SELECT statement in subquery".into())),
+        }
+        let query = self.parse_statement_select()?;
+        self.next_expect(Some(Token::CloseParen))?;
+        Ok(query)
+    }
+
+    /// Parses a parenthesized window specification, following OVER
+    fn parse_window(&mut self) -> Result<ast::Window> {
+        self.next_expect(Some(Token::OpenParen))?;
+        let mut partition_by = Vec::new();
+        if self.next_if_token(Keyword::Partition.into()).is_some() {
+            self.next_expect(Some(Keyword::By.into()))?;
+            loop {
+                partition_by.push(self.parse_expression(0)?);
+                if self.next_if_token(Token::Comma).is_none() {
+                    break;
+                }
+            }
+        }
+        let order_by = self.parse_clause_order()?;
+        let units = match self.next_if_keyword() {
+            Some(Token::Keyword(Keyword::Rows)) => Some(ast::FrameUnits::Rows),
+            Some(Token::Keyword(Keyword::Range)) => Some(ast::FrameUnits::Range),
+            Some(token) => return Err(Error::Parse(format!("Unexpected token {}", token))),
+            None => None,
+        };
+        let frame = match units {
+            Some(units) => {
+                let (start, end) = if self.next_if_token(Keyword::Between.into()).is_some() {
+                    let start = self.parse_window_frame_bound()?;
+                    self.next_expect(Some(Keyword::And.into()))?;
+                    (start, self.parse_window_frame_bound()?)
+                } else {
+                    (self.parse_window_frame_bound()?, ast::FrameBound::CurrentRow)
+                };
+                if start == ast::FrameBound::UnboundedFollowing {
+                    return Err(Error::Parse("Frame can't start at UNBOUNDED FOLLOWING".into()));
+                }
+                if end == ast::FrameBound::UnboundedPreceding {
+                    return Err(Error::Parse("Frame can't end at UNBOUNDED PRECEDING".into()));
+                }
+                Some(ast::WindowFrame { units, start, end })
+            }
+            None => None,
+        };
+        self.next_expect(Some(Token::CloseParen))?;
+        Ok(ast::Window { partition_by, order_by, frame })
+    }
+
+    /// Parses a window frame bound
+    fn parse_window_frame_bound(&mut self) -> Result<ast::FrameBound> {
+        if self.next_if_token(Keyword::Unbounded.into()).is_some() {
+            return match self.next()? {
+                Token::Keyword(Keyword::Preceding) => Ok(ast::FrameBound::UnboundedPreceding),
+                Token::Keyword(Keyword::Following) => Ok(ast::FrameBound::UnboundedFollowing),
+                token => Err(Error::Parse(format!("Unexpected token {}", token))),
+            };
+        }
+        if self.next_if_token(Keyword::Current.into()).is_some() {
+            self.next_expect(Some(Keyword::Row.into()))?;
+            return Ok(ast::FrameBound::CurrentRow);
+        }
+        let offset = Box::new(self.parse_expression(0)?);
+        match self.next()? {
+            Token::Keyword(Keyword::Preceding) => Ok(ast::FrameBound::Preceding(offset)),
+            Token::Keyword(Keyword::Following) => Ok(ast::FrameBound::Following(offset)),
+            token => Err(Error::Parse(format!("Unexpected token {}", token))),
+        }
+    }
+
     /// Parses an expression atom
     fn parse_expression_atom(&mut self) -> Result<ast::Expression> {
         Ok(match self.next()? {
             Token::Ident(i) => {
                 if self.next_if_token(Token::OpenParen).is_some() {
+                    // EXTRACT(field FROM value) is built as extract('field', value).
+                    if i == "extract"
+                        && matches!(self.peek()?, Some(Token::Ident(_)))
+                        && self.peek_second()? == Some(Keyword::From.into())
+                    {
+                        let field = self.next_ident()?;
+                        self.next_expect(Some(Keyword::From.into()))?;
+                        let value = self.parse_expression(0)?;
+                        let args = vec![ast::Literal::String(field).into(), value];
+                        self.next_expect(Some(Token::CloseParen))?;
+                        return Ok(ast::Expression::Function { name: i, args, distinct: false });
+                    }
+                    let distinct = self.next_if_token(Keyword::Distinct.into()).is_some();
                     let mut args = Vec::new();
-                    while self.next_if_token(Token::CloseParen).is_none() {
-                        if !args.is_empty() {
-                            self.next_expect(Some(Token::Comma))?;
-                        }
-                        if i == "count" && self.next_if_token(Token::Asterisk).is_some() {
-                            // FIXME Ugly hack to handle COUNT(*)
-                            args.push(ast::Expression::Literal(ast::Literal::Boolean(true)));
-                        } else {
+                    if !distinct && self.next_if_token(Token::Asterisk).is_some() {
+                        args.push(ast::Expression::All);
+                        self.next_expect(Some(Token::CloseParen))?;
+                    } else {
+                        while self.next_if_token(Token::CloseParen).is_none() {
+                            if !args.is_empty() {
+                                self.next_expect(Some(Token::Comma))?;
+                            }
                             args.push(self.parse_expression(0)?);
                         }
                     }
-                    ast::Expression::Function(i, args)
+                    if distinct && args.is_empty() {
+                        return Err(Error::Parse("Expected expression after DISTINCT".into()));
+                    }
+                    if self.next_if_token(Keyword::Over.into()).is_some() {
+                        if distinct {
+                            return Err(Error::Parse(
+                                "DISTINCT is not supported for window functions".into(),
+                            ));
+                        }
+                        let over = self.parse_window()?;
+                        ast::Expression::WindowFunction { name: i, args, over }
+                    } else {
+                        ast::Expression::Function { name: i, args, distinct }
+                    }
                 } else {
                     let mut relation = None;
                     let mut field = i;
@@ -609,21 +1371,82 @@ impl<'a> Parser<'a> {
                 }
             }
             Token::Number(n) => {
-                if n.chars().all(|c| c.is_digit(10)) {
+                if n.chars().all(|c| c.is_ascii_digit()) {
                     ast::Literal::Integer(n.parse().unwrap()).into()
                 } else {
                     ast::Literal::Float(n.parse().unwrap()).into()
                 }
             }
             Token::OpenParen => {
+                if let Some(Token::Keyword(Keyword::Select | Keyword::With)) = self.peek()? {
+                    ast::Expression::Subquery(Box::new(self.parse_subquery_body()?))
+                } else {
+                    let expr = self.parse_expression(0)?;
+                    self.next_expect(Some(Token::CloseParen))?;
+                    expr
+                }
+            }
+            Token::Keyword(Keyword::Exists) => {
+                self.next_expect(Some(Token::OpenParen))?;
+                ast::Expression::Exists(Box::new(self.parse_subquery_body()?))
+            }
+            Token::Keyword(Keyword::Case) => {
+                let mut operand = None;
+                if self.peek()? != Some(Keyword::When.into()) {
+                    operand = Some(Box::new(self.parse_expression(0)?));
+                }
+                let mut branches = Vec::new();
+                while self.next_if_token(Keyword::When.into()).is_some() {
+                    let when = self.parse_expression(0)?;
+                    self.next_expect(Some(Keyword::Then.into()))?;
+                    branches.push((when, self.parse_expression(0)?));
+                }
+                if branches.is_empty() {
+                    return Err(Error::Parse("Expected WHEN in CASE expression".into()));
+                }
+                let mut r#else = None;
+                if self.next_if_token(Keyword::Else.into()).is_some() {
+                    r#else = Some(Box::new(self.parse_expression(0)?));
+                }
+                self.next_expect(Some(Keyword::End.into()))?;
+                ast::Expression::Case { operand, branches, r#else }
+            }
+            Token::Keyword(Keyword::Cast) => {
+                self.next_expect(Some(Token::OpenParen))?;
                 let expr = self.parse_expression(0)?;
+                self.next_expect(Some(Keyword::As.into()))?;
+                let datatype = self.parse_datatype()?;
                 self.next_expect(Some(Token::CloseParen))?;
-                expr
+                ast::Expression::Cast(Box::new(expr), datatype)
             }
             Token::String(s) => ast::Literal::String(s).into(),
+            Token::Hex(s) => ast::Literal::Binary(types::decode_hex(&s)?).into(),
+            // Typed literals, e.g. DATE '2024-01-01', are casts of string literals.
+            Token::Keyword(
+                keyword @ (Keyword::Date | Keyword::Time | Keyword::Timestamp | Keyword::Interval),
+            ) => {
+                let datatype = match keyword {
+                    Keyword::Date => DataType::Date,
+                    Keyword::Time => DataType::Time,
+                    Keyword::Timestamp => DataType::Timestamp,
+                    _ => DataType::Interval,
+                };
+                match self.next()? {
+                    Token::String(s) => {
+                        ast::Expression::Cast(Box::new(ast::Literal::String(s).into()), datatype)
+                    }
+                    token => {
+                        return Err(Error::Parse(format!(
+                            "Expected string literal after {}, found {}",
+                            keyword.to_str(),
+                            token
+                        )))
+                    }
+                }
+            }
             Token::Keyword(Keyword::False) => ast::Literal::Boolean(false).into(),
-            Token::Keyword(Keyword::Infinity) => ast::Literal::Float(std::f64::INFINITY).into(),
-            Token::Keyword(Keyword::NaN) => ast::Literal::Float(std::f64::NAN).into(),
+            Token::Keyword(Keyword::Infinity) => ast::Literal::Float(f64::INFINITY).into(),
+            Token::Keyword(Keyword::NaN) => ast::Literal::Float(f64::NAN).into(),
             Token::Keyword(Keyword::Null) => ast::Literal::Null.into(),
             Token::Keyword(Keyword::True) => ast::Literal::Boolean(true).into(),
             t => {
@@ -643,6 +1466,11 @@ trait Operator: Sized {
     fn assoc(&self) -> u8;
     /// Returns the operator's precedence
     fn prec(&self) -> u8;
+    /// Checks the token following the operator token, to tell multi-keyword operators such as
+    /// NOT IN apart from unrelated syntax such as a NOT NULL column constraint.
+    fn accepts_next(&self, _next: Option<&Token>) -> bool {
+        true
+    }
 }
 
 const ASSOC_LEFT: u8 = 1;
@@ -675,7 +1503,7 @@ impl Operator for PrefixOperator {
         }
     }
 
-    fn augment(self, parser: &mut Parser) -> Result<Self> {
+    fn augment(self, _parser: &mut Parser) -> Result<Self> {
         Ok(self)
     }
 
@@ -684,7 +1512,11 @@ impl Operator for PrefixOperator {
     }
 
     fn prec(&self) -> u8 {
-        9
+        match self {
+            // Binds looser than comparisons, IN and BETWEEN, e.g. NOT a = b is NOT (a = b).
+            Self::Not => 3,
+            Self::Minus | Self::Plus => 9,
+        }
     }
 }
 
@@ -697,13 +1529,19 @@ enum InfixOperator {
     Exponentiate,
     GreaterThan,
     GreaterThanOrEqual,
+    JsonGet,
+    JsonGetText,
     LessThan,
     LessThanOrEqual,
+    ILike,
+    IRegexMatch,
     Like,
     Modulo,
     Multiply,
     NotEqual,
     Or,
+    RegexMatch,
+    SimilarTo,
     Subtract,
 }
 
@@ -719,13 +1557,19 @@ impl InfixOperator {
                 Self::Exponentiate => ast::Operation::Exponentiate(lhs, rhs),
                 Self::GreaterThan => ast::Operation::GreaterThan(lhs, rhs),
                 Self::GreaterThanOrEqual => ast::Operation::GreaterThanOrEqual(lhs, rhs),
+                Self::JsonGet => ast::Operation::JsonGet(lhs, rhs),
+                Self::JsonGetText => ast::Operation::JsonGetText(lhs, rhs),
                 Self::LessThan => ast::Operation::LessThan(lhs, rhs),
                 Self::LessThanOrEqual => ast::Operation::LessThanOrEqual(lhs, rhs),
+                Self::ILike => ast::Operation::ILike(lhs, rhs),
+                Self::IRegexMatch => ast::Operation::IRegexMatch(lhs, rhs),
                 Self::Like => ast::Operation::Like(lhs, rhs),
                 Self::Modulo => ast::Operation::Modulo(lhs, rhs),
                 Self::Multiply => ast::Operation::Multiply(lhs, rhs),
                 Self::NotEqual => ast::Operation::NotEqual(lhs, rhs),
                 Self::Or => ast::Operation::Or(lhs, rhs),
+                Self::RegexMatch => ast::Operation::RegexMatch(lhs, rhs),
+                Self::SimilarTo => ast::Operation::SimilarTo(lhs, rhs),
                 Self::Subtract => ast::Operation::Subtract(lhs, rhs),
             }
         ).into()
@@ -735,22 +1579,28 @@ impl InfixOperator {
 impl Operator for InfixOperator {
     fn from(token: &Token) -> Option<Self> {
         Some(match token {
+            Token::Arrow => Self::JsonGet,
             Token::Asterisk => Self::Multiply,
             Token::Caret => Self::Exponentiate,
             Token::Equal => Self::Equal,
             Token::GreaterThan => Self::GreaterThan,
             Token::GreaterThanOrEqual => Self::GreaterThanOrEqual,
             Token::Keyword(Keyword::And) => Self::And,
+            Token::Keyword(Keyword::ILike) => Self::ILike,
             Token::Keyword(Keyword::Like) => Self::Like,
             Token::Keyword(Keyword::Or) => Self::Or,
+            Token::Keyword(Keyword::Similar) => Self::SimilarTo,
             Token::LessOrGreaterThan => Self::NotEqual,
             Token::LessThan => Self::LessThan,
             Token::LessThanOrEqual => Self::LessThanOrEqual,
+            Token::LongArrow => Self::JsonGetText,
             Token::Minus => Self::Subtract,
             Token::NotEqual => Self::NotEqual,
             Token::Percent => Self::Modulo,
             Token::Plus => Self::Add,
             Token::Slash => Self::Divide,
+            Token::Tilde => Self::RegexMatch,
+            Token::TildeAsterisk => Self::IRegexMatch,
             _ => {
                 return None;
             }
@@ -758,6 +1608,9 @@ impl Operator for InfixOperator {
     }
 
     fn augment(self, parser: &mut Parser) -> Result<Self> {
+        if let Self::SimilarTo = self {
+            parser.next_expect(Some(Keyword::To.into()))?;
+        }
         Ok(self)
     }
 
@@ -769,7 +1622,25 @@ impl Operator for InfixOperator {
     }
 
     fn prec(&self) -> u8 {
-        todo!()
+        match self {
+            Self::Or => 1,
+            Self::And => 2,
+            Self::Equal
+            | Self::NotEqual
+            | Self::ILike
+            | Self::IRegexMatch
+            | Self::Like
+            | Self::RegexMatch
+            | Self::SimilarTo => 3,
+            Self::GreaterThan
+            | Self::GreaterThanOrEqual
+            | Self::LessThan
+            | Self::LessThanOrEqual => 4,
+            Self::Add | Self::Subtract => 5,
+            Self::Multiply | Self::Divide | Self::Modulo => 6,
+            Self::Exponentiate => 7,
+            Self::JsonGet | Self::JsonGetText => 8,
+        }
     }
 }
 
@@ -779,10 +1650,31 @@ enum PostfixOperator {
     #[allow(dead_code)] IsNull {
         not: bool,
     },
+    /// The subquery or list is parsed by augment()
+    In {
+        not: bool,
+        rhs: Option<InRhs>,
+    },
+    /// The bounds are parsed by augment(). NOT is initially parsed as In, since the following
+    /// keyword isn't known yet, and turned into Between by augment() where appropriate.
+    Between {
+        not: bool,
+        bounds: Option<Box<(ast::Expression, ast::Expression)>>,
+    },
+    /// The datatype of a :: cast is parsed by augment()
+    Cast(Option<DataType>),
+    /// The collation is parsed by augment()
+    Collate(Option<Collation>),
+}
+
+/// The right-hand side of an IN operator
+enum InRhs {
+    List(Vec<ast::Expression>),
+    Subquery(Box<ast::Statement>),
 }
 
 impl PostfixOperator {
-    fn build(&self, lhs: ast::Expression) -> ast::Expression {
+    fn build(self, lhs: ast::Expression) -> ast::Expression {
         let lhs = Box::new(lhs);
         (
             match self {
@@ -792,6 +1684,30 @@ impl PostfixOperator {
                         false => ast::Operation::IsNull(lhs),
                     }
                 Self::Factorial => ast::Operation::Factorial(lhs),
+                Self::In { not, rhs } => {
+                    let op = match rhs.expect("IN operand not parsed") {
+                        InRhs::List(list) => ast::Operation::InList(lhs, list),
+                        InRhs::Subquery(query) => ast::Operation::InSubquery(lhs, query),
+                    };
+                    match not {
+                        true => ast::Operation::Not(Box::new(op.into())),
+                        false => op,
+                    }
+                }
+                Self::Between { not, bounds } => {
+                    let (low, high) = *bounds.expect("BETWEEN bounds not parsed");
+                    let op = ast::Operation::Between(lhs, Box::new(low), Box::new(high));
+                    match not {
+                        true => ast::Operation::Not(Box::new(op.into())),
+                        false => op,
+                    }
+                }
+                Self::Cast(datatype) => {
+                    return ast::Expression::Cast(lhs, datatype.expect("Cast type not parsed"))
+                }
+                Self::Collate(collation) => {
+                    return ast::Expression::Collate(lhs, collation.expect("Collation not parsed"))
+                }
             }
         ).into()
     }
@@ -802,12 +1718,26 @@ impl Operator for PostfixOperator {
         match token {
             Token::Exclamation => Some(Self::Factorial),
             Token::Keyword(Keyword::Is) => Some(Self::IsNull { not: false }),
+            Token::Keyword(Keyword::Between) => Some(Self::Between { not: false, bounds: None }),
+            Token::Keyword(Keyword::In) => Some(Self::In { not: false, rhs: None }),
+            Token::Keyword(Keyword::Not) => Some(Self::In { not: true, rhs: None }),
+            Token::DoubleColon => Some(Self::Cast(None)),
+            Token::Keyword(Keyword::Collate) => Some(Self::Collate(None)),
             _ => None,
         }
     }
 
+    fn accepts_next(&self, next: Option<&Token>) -> bool {
+        match self {
+            Self::In { not: true, .. } => matches!(
+                next,
+                Some(Token::Keyword(Keyword::In)) | Some(Token::Keyword(Keyword::Between))
+            ),
+            _ => true,
+        }
+    }
+
     fn augment(mut self, parser: &mut Parser) -> Result<Self> {
-        #[allow(clippy::single_match)]
         match &mut self {
             Self::IsNull { ref mut not } => {
                 if parser.next_if_token(Keyword::Not.into()).is_some() {
@@ -815,6 +1745,38 @@ impl Operator for PostfixOperator {
                 }
                 parser.next_expect(Some(Keyword::Null.into()))?;
             }
+            Self::In { not: true, .. }
+                if parser.next_if_token(Keyword::Between.into()).is_some() =>
+            {
+                return Self::Between { not: true, bounds: None }.augment(parser);
+            }
+            Self::In { not, rhs } => {
+                if *not {
+                    parser.next_expect(Some(Keyword::In.into()))?;
+                }
+                parser.next_expect(Some(Token::OpenParen))?;
+                if let Some(Token::Keyword(Keyword::Select | Keyword::With)) = parser.peek()? {
+                    *rhs = Some(InRhs::Subquery(Box::new(parser.parse_subquery_body()?)));
+                } else {
+                    let mut list = Vec::new();
+                    loop {
+                        list.push(parser.parse_expression(0)?);
+                        if parser.next_if_token(Token::Comma).is_none() {
+                            break;
+                        }
+                    }
+                    parser.next_expect(Some(Token::CloseParen))?;
+                    *rhs = Some(InRhs::List(list));
+                }
+            }
+            // The bounds bind tighter than AND, such that the AND is the BETWEEN separator.
+            Self::Between { bounds, .. } => {
+                let low = parser.parse_expression(5)?;
+                parser.next_expect(Some(Keyword::And.into()))?;
+                *bounds = Some(Box::new((low, parser.parse_expression(5)?)));
+            }
+            Self::Cast(datatype) => *datatype = Some(parser.parse_datatype()?),
+            Self::Collate(collation) => *collation = Some(parser.parse_collation()?),
             _ => {}
         }
         Ok(self)
@@ -825,7 +1787,13 @@ impl Operator for PostfixOperator {
     }
 
     fn prec(&self) -> u8 {
-        8
+        match self {
+            // Binds tighter than comparisons, but looser than arithmetic.
+            Self::In { .. } | Self::Between { .. } => 5,
+            // Binds tighter than prefix operators, e.g. -1::STRING is -(1::STRING).
+            Self::Cast(_) | Self::Collate(_) => 10,
+            _ => 8,
+        }
     }
 }
 
@@ -842,3 +1810,195 @@ pub(super) fn format_ident(ident: &str) -> String {
         format!("\"{}\"", ident.replace("\"", "\"\""))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &str) -> Result<ast::Statement> {
+        Parser::new(query).parse()
+    }
+
+    #[test]
+    fn create_table_primary_key() -> Result<()> {
+        // Table-level primary keys keep their declared order.
+        match parse("CREATE TABLE t (a INTEGER, b INTEGER, c INTEGER, PRIMARY KEY (c, a))")? {
+            ast::Statement::CreateTable { columns, primary_key, .. } => {
+                assert_eq!(vec!["c".to_string(), "a".to_string()], primary_key);
+                assert_eq!(
+                    vec![true, false, true],
+                    columns.iter().map(|c| c.primary_key).collect::<Vec<_>>()
+                );
+            }
+            statement => panic!("Unexpected statement {:?}", statement),
+        }
+
+        // Composite keys can't be given inline, or combined with inline keys.
+        assert!(parse("CREATE TABLE t (a INTEGER PRIMARY KEY, b INTEGER PRIMARY KEY)").is_err());
+        assert!(
+            parse("CREATE TABLE t (a INTEGER PRIMARY KEY, b INTEGER, PRIMARY KEY (b))").is_err()
+        );
+        assert!(parse("CREATE TABLE t (a INTEGER, PRIMARY KEY (a, a))").is_err());
+        assert!(parse("CREATE TABLE t (a INTEGER, PRIMARY KEY (b))").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn with_recursive() -> Result<()> {
+        let ctes = |query: &str| -> Result<Vec<ast::CommonTableExpression>> {
+            match parse(query)? {
+                ast::Statement::Select { with, .. } => Ok(with),
+                statement => panic!("Unexpected statement {:?}", statement),
+            }
+        };
+
+        // The recursive term is the last UNION operand, which refers to the CTE.
+        let with = ctes(
+            "WITH RECURSIVE r (n) AS (SELECT 1 UNION SELECT 2 UNION ALL SELECT n + 1 FROM r)
+             SELECT * FROM r",
+        )?;
+        assert!(matches!(*with[0].query, ast::Statement::SetOperation { .. }));
+        assert!(with[0].recursive.as_ref().is_some_and(|r| r.references_table("r")));
+        assert!(with[0].union_all);
+
+        // A UNION that doesn't refer to the CTE isn't split, nor are CTEs that aren't recursive.
+        let with = ctes("WITH RECURSIVE r AS (SELECT 1 UNION SELECT 2) SELECT * FROM r")?;
+        assert!(with[0].recursive.is_none());
+        let with = ctes("WITH r AS (SELECT 1 UNION SELECT * FROM r) SELECT * FROM r")?;
+        assert!(with[0].recursive.is_none());
+
+        // References in subqueries count.
+        let with = ctes(
+            "WITH RECURSIVE r (n) AS (SELECT 1 UNION SELECT n FROM t
+             WHERE n IN (SELECT n + 1 FROM r)) SELECT * FROM r",
+        )?;
+        assert!(with[0].recursive.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn case_in_between_cast() -> Result<()> {
+        use ast::Operation::*;
+        let expr = |sql: &str| -> Result<ast::Expression> {
+            match parse(&format!("SELECT {}", sql))? {
+                ast::Statement::Select { mut select, .. } => Ok(select.remove(0).0),
+                statement => panic!("Unexpected statement {:?}", statement),
+            }
+        };
+        let field = |name: &str| Box::new(ast::Expression::Field(None, name.into()));
+        let int = |i: i64| ast::Expression::from(ast::Literal::Integer(i));
+
+        // The BETWEEN bounds bind tighter than AND, and NOT negates BETWEEN and IN.
+        assert_eq!(
+            ast::Expression::from(And(
+                Box::new(Between(field("a"), Box::new(int(1)), Box::new(int(2))).into()),
+                field("b"),
+            )),
+            expr("a BETWEEN 1 AND 2 AND b")?
+        );
+        let low = Box::new(Add(Box::new(int(1)), Box::new(int(1))).into());
+        assert_eq!(
+            ast::Expression::from(Not(Box::new(Between(field("a"), low, Box::new(int(3))).into()))),
+            expr("a NOT BETWEEN 1 + 1 AND 3")?
+        );
+        assert_eq!(
+            ast::Expression::from(Not(Box::new(InList(field("a"), vec![int(1), int(2)]).into()))),
+            expr("a NOT IN (1, 2)")?
+        );
+        assert_eq!(expr("a NOT IN (1, 2)")?, expr("NOT a IN (1, 2)")?);
+        assert_eq!(expr("a NOT BETWEEN 1 + 1 AND 3")?, expr("NOT a BETWEEN 1 + 1 AND 3")?);
+        assert_eq!(
+            ast::Expression::from(And(
+                Box::new(Not(Box::new(Equal(field("a"), Box::new(int(1))).into())).into()),
+                field("b"),
+            )),
+            expr("NOT a = 1 AND b")?
+        );
+        assert!(matches!(expr("a IN (SELECT 1)")?, ast::Expression::Operation(InSubquery(..))));
+        assert!(expr("a IN ()").is_err());
+
+        // CAST and :: are equivalent, and :: binds tighter than arithmetic.
+        assert_eq!(expr("CAST(a AS INTEGER)")?, expr("a::INTEGER")?);
+        assert_eq!(
+            ast::Expression::from(Negate(Box::new(ast::Expression::Cast(
+                field("a"),
+                DataType::String
+            )))),
+            expr("-a::TEXT")?
+        );
+        assert!(expr("CAST(a AS UNKNOWN)").is_err());
+
+        // CASE needs at least one WHEN, and the operand and ELSE are optional.
+        assert_eq!(
+            ast::Expression::Case {
+                operand: Some(field("a")),
+                branches: vec![(int(1), int(2))],
+                r#else: None,
+            },
+            expr("CASE a WHEN 1 THEN 2 END")?
+        );
+        assert_eq!(
+            ast::Expression::Case {
+                operand: None,
+                branches: vec![(*field("a"), int(1)), (*field("b"), int(2))],
+                r#else: Some(Box::new(int(3))),
+            },
+            expr("CASE WHEN a THEN 1 WHEN b THEN 2 ELSE 3 END")?
+        );
+        assert!(expr("CASE a ELSE 1 END").is_err());
+        assert!(expr("CASE WHEN a THEN 1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn hex_literal() -> Result<()> {
+        let literal = |sql: &str| -> Result<ast::Expression> {
+            match parse(&format!("SELECT {}", sql))? {
+                ast::Statement::Select { mut select, .. } => Ok(select.remove(0).0),
+                statement => panic!("Unexpected statement {:?}", statement),
+            }
+        };
+        assert_eq!(ast::Expression::from(ast::Literal::Binary(vec![10, 255])), literal("X'0aFF'")?);
+        assert_eq!(ast::Expression::from(ast::Literal::Binary(Vec::new())), literal("x''")?);
+        assert!(literal("X'0'").is_err());
+        assert!(literal("X'zz'").is_err());
+
+        // X is only a hex prefix when immediately followed by a string.
+        assert_eq!(ast::Expression::Field(None, "x".into()), literal("x")?);
+        assert!(literal("X '00'").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn json_operators() -> Result<()> {
+        use ast::Operation::*;
+        let expr = |sql: &str| -> Result<ast::Expression> {
+            match parse(&format!("SELECT {}", sql))? {
+                ast::Statement::Select { mut select, .. } => Ok(select.remove(0).0),
+                statement => panic!("Unexpected statement {:?}", statement),
+            }
+        };
+        let field = |name: &str| Box::new(ast::Expression::Field(None, name.into()));
+        let lit = |l: ast::Literal| Box::new(ast::Expression::from(l));
+
+        // The operators are left-associative, and bind tighter than comparisons and arithmetic.
+        let get = JsonGet(field("doc"), lit(ast::Literal::String("a".into())));
+        assert_eq!(
+            ast::Expression::from(JsonGetText(Box::new(get.into()), lit(ast::Literal::Integer(0)))),
+            expr("doc -> 'a' ->> 0")?
+        );
+        assert_eq!(expr("doc -> 'a' ->> 0")?, expr("doc->'a'->>0")?);
+        let get_text = JsonGetText(field("doc"), lit(ast::Literal::String("n".into())));
+        assert_eq!(
+            ast::Expression::from(Equal(Box::new(get_text.into()), lit(ast::Literal::Integer(1)))),
+            expr("doc ->> 'n' = 1")?
+        );
+        assert!(matches!(expr("1 + doc -> 'n'")?, ast::Expression::Operation(Add(..))));
+
+        // A minus is only an arrow when immediately followed by >.
+        assert!(matches!(expr("a - -1")?, ast::Expression::Operation(Subtract(..))));
+        assert!(expr("doc - > 'a'").is_err());
+        assert!(expr("doc ->").is_err());
+        Ok(())
+    }
+}
@@ -1,19 +1,52 @@
 mod optimizer;
 mod planner;
 
-use crate::error::Result;
+use super::engine::Transaction;
+use super::execution::{Executor, ResultSet};
+use super::parser::ast;
+use super::schema::{Alteration, Catalog, Index, Sequence, Table};
+use super::types::{Collation, DataType, Expression, TableFunction, Value};
+use optimizer::Optimizer;
+pub use planner::Planner;
+
+use crate::error::{Error, Result};
 
 use serde_derive::{Deserialize, Serialize};
 use std::fmt::{self, Display};
+use std::mem::replace;
 
-
+/// The default maximum number of iterations when evaluating a recursive common table expression
+pub const DEFAULT_RECURSION_LIMIT: u64 = 1000;
 
 /// A plan node
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Node {
+    /// Computes aggregates for each group of rows. The source emits each aggregate's argument
+    /// as its first columns, followed by group_by grouping columns. With grouping sets, given
+    /// as grouping column indexes, rows are grouped by each set in turn, with NULLs for the
+    /// other grouping columns, and a grouping ID column is appended (see grouping_expression()).
+    /// Grouping columns are compared using their collations, given by position.
     Aggregation {
         source: Box<Node>,
         aggregates: Vec<Aggregate>,
+        group_by: usize,
+        grouping_sets: Option<Vec<Vec<usize>>>,
+        collations: Vec<Collation>,
+    },
+    AlterTable {
+        table: String,
+        alteration: Alteration,
+    },
+    CreateIndex {
+        schema: Index,
+    },
+    /// Emits the rows of a common table expression materialized by Materialize or RecursiveUnion
+    CteScan {
+        name: String,
+        alias: Option<String>,
+    },
+    CreateSequence {
+        schema: Sequence,
     },
     CreateTable {
         schema: Table,
@@ -22,6 +55,18 @@ pub enum Node {
         table: String,
         source: Box<Node>,
     },
+    /// Removes duplicate rows, keeping the first one. If on is non-empty, rows are instead
+    /// deduplicated by the values of its expressions, as for SELECT DISTINCT ON.
+    Distinct {
+        source: Box<Node>,
+        on: Vec<Expression>,
+    },
+    DropIndex {
+        name: String,
+    },
+    DropSequence {
+        name: String,
+    },
     DropTable {
         table: String,
     },
@@ -34,13 +79,15 @@ pub enum Node {
         left_field: (usize, Option<(Option<String>, String)>),
         right: Box<Node>,
         right_field: (usize, Option<(Option<String>, String)>),
-        outer: bool,
+        r#type: JoinType,
     },
     IndexLookup {
         table: String,
         alias: Option<String>,
-        column: String,
-        values: Vec<Value>,
+        /// The indexed columns, identifying the index to use.
+        columns: Vec<String>,
+        /// Index keys to look up, as tuples of the indexed column values.
+        values: Vec<Vec<Value>>,
     },
     Insert {
         table: String,
@@ -50,24 +97,32 @@ pub enum Node {
     KeyLookup {
         table: String,
         alias: Option<String>,
-        keys: Vec<Value>,
+        /// Primary keys to look up, as tuples of the primary key column values.
+        keys: Vec<Vec<Value>>,
     },
     Limit {
         source: Box<Node>,
         limit: u64,
     },
+    /// Executes each common table expression once, in order, and buffers its rows under its name
+    /// for CteScan nodes in later common table expressions and the source.
+    Materialize {
+        ctes: Vec<(String, Node)>,
+        source: Box<Node>,
+    },
     NestedLoopJoin {
         left: Box<Node>,
         left_size: usize,
         right: Box<Node>,
         predicate: Option<Expression>,
-        outer: bool,
+        r#type: JoinType,
     },
     Nothing,
     Offset {
         source: Box<Node>,
         offset: u64,
     },
+    /// Sorts the source rows. Strings are sorted using the collation of their order expression.
     Order {
         source: Box<Node>,
         orders: Vec<(Expression, Direction)>,
@@ -76,24 +131,664 @@ pub enum Node {
         source: Box<Node>,
         expressions: Vec<(Expression, Option<String>)>,
     },
+    /// Evaluates a recursive common table expression by iteration until a fixpoint. The anchor is
+    /// evaluated first, and the recursive term is then evaluated repeatedly with CteScan of name
+    /// emitting the previous iteration's new rows, until an iteration yields no new rows. Unless
+    /// union_all is set, rows already emitted are discarded. Errors if the fixpoint is not reached
+    /// within max_iterations.
+    RecursiveUnion {
+        name: String,
+        anchor: Box<Node>,
+        recursive: Box<Node>,
+        union_all: bool,
+        max_iterations: u64,
+    },
     Scan {
         table: String,
         alias: Option<String>,
         filter: Option<Expression>,
     },
+    /// Emits the left rows for which some right row matches the predicate (or none does, for
+    /// an anti-join). The predicate is evaluated in the scope of the right row, with the left row
+    /// as the enclosing scope, i.e. like the correlated subquery it was decorrelated from. A
+    /// null-aware anti-join also drops left rows where the predicate is NULL, as for NOT IN.
+    SemiJoin {
+        left: Box<Node>,
+        right: Box<Node>,
+        predicate: Option<Expression>,
+        anti: bool,
+        null_aware: bool,
+    },
+    /// Combines the rows of two queries. Unless all is set, the result is deduplicated.
+    SetOperation {
+        operator: SetOperator,
+        all: bool,
+        left: Box<Node>,
+        right: Box<Node>,
+    },
+    /// Emits the rows returned by a table function, e.g. json_each(), for the evaluated arguments
+    TableFunction {
+        function: TableFunction,
+        args: Vec<Expression>,
+        alias: Option<String>,
+    },
     Update {
         table: String,
         source: Box<Node>,
         expressions: Vec<(usize, Option<String>, Expression)>,
     },
+    /// Computes window functions over the source rows, appending a column with each function's
+    /// value to every row, like Aggregation does for aggregates but without collapsing rows.
+    Window {
+        source: Box<Node>,
+        functions: Vec<WindowFunction>,
+    },
+}
+
+impl Node {
+    /// Recursively transforms the node tree by applying a closure before and after descending
+    /// into the node's sources.
+    pub fn transform<B, A>(mut self, before: &B, after: &A) -> Result<Self>
+    where
+        B: Fn(Self) -> Result<Self>,
+        A: Fn(Self) -> Result<Self>,
+    {
+        self = before(self)?;
+        match &mut self {
+            Self::Aggregation { source, .. }
+            | Self::Delete { source, .. }
+            | Self::Distinct { source, .. }
+            | Self::Filter { source, .. }
+            | Self::Limit { source, .. }
+            | Self::Offset { source, .. }
+            | Self::Order { source, .. }
+            | Self::Projection { source, .. }
+            | Self::Update { source, .. }
+            | Self::Window { source, .. } => {
+                **source = replace(&mut **source, Self::Nothing).transform(before, after)?;
+            }
+
+            Self::HashJoin { left, right, .. }
+            | Self::NestedLoopJoin { left, right, .. }
+            | Self::RecursiveUnion { anchor: left, recursive: right, .. }
+            | Self::SemiJoin { left, right, .. }
+            | Self::SetOperation { left, right, .. } => {
+                **left = replace(&mut **left, Self::Nothing).transform(before, after)?;
+                **right = replace(&mut **right, Self::Nothing).transform(before, after)?;
+            }
+
+            Self::Materialize { ctes, source } => {
+                for (_, cte) in ctes.iter_mut() {
+                    *cte = replace(cte, Self::Nothing).transform(before, after)?;
+                }
+                **source = replace(&mut **source, Self::Nothing).transform(before, after)?;
+            }
+
+            Self::AlterTable { .. }
+            | Self::CreateIndex { .. }
+            | Self::CreateSequence { .. }
+            | Self::CreateTable { .. }
+            | Self::CteScan { .. }
+            | Self::DropIndex { .. }
+            | Self::DropSequence { .. }
+            | Self::DropTable { .. }
+            | Self::IndexLookup { .. }
+            | Self::Insert { .. }
+            | Self::KeyLookup { .. }
+            | Self::Nothing
+            | Self::Scan { .. }
+            | Self::TableFunction { .. } => {}
+        }
+        after(self)
+    }
+
+    /// Transforms the node's own expressions (not those of its sources) by applying closures
+    /// before and after descending into each expression.
+    pub fn transform_expressions<B, A>(mut self, before: &B, after: &A) -> Result<Self>
+    where
+        B: Fn(Expression) -> Result<Expression>,
+        A: Fn(Expression) -> Result<Expression>,
+    {
+        let transform = |expr: &mut Expression| -> Result<()> {
+            *expr = replace(expr, Expression::Constant(Value::Null)).transform(before, after)?;
+            Ok(())
+        };
+        match &mut self {
+            Self::Distinct { on, .. } => on.iter_mut().try_for_each(transform)?,
+            Self::Filter { predicate, .. } => transform(predicate)?,
+            Self::Insert { expressions, .. } => {
+                expressions.iter_mut().flatten().try_for_each(transform)?
+            }
+            Self::NestedLoopJoin { predicate: Some(predicate), .. }
+            | Self::SemiJoin { predicate: Some(predicate), .. } => transform(predicate)?,
+            Self::Order { orders, .. } => orders.iter_mut().try_for_each(|(e, _)| transform(e))?,
+            Self::Projection { expressions, .. } => {
+                expressions.iter_mut().try_for_each(|(e, _)| transform(e))?
+            }
+            Self::Scan { filter: Some(filter), .. } => transform(filter)?,
+            Self::TableFunction { args, .. } => args.iter_mut().try_for_each(transform)?,
+            Self::Update { expressions, .. } => {
+                expressions.iter_mut().try_for_each(|(_, _, e)| transform(e))?
+            }
+            Self::Window { functions, .. } => {
+                functions.iter_mut().flat_map(|f| f.expressions_mut()).try_for_each(transform)?
+            }
+            _ => {}
+        }
+        Ok(self)
+    }
+
+    /// Returns the node's sources
+    pub fn sources(&self) -> Vec<&Node> {
+        match self {
+            Self::Aggregation { source, .. }
+            | Self::Delete { source, .. }
+            | Self::Distinct { source, .. }
+            | Self::Filter { source, .. }
+            | Self::Limit { source, .. }
+            | Self::Offset { source, .. }
+            | Self::Order { source, .. }
+            | Self::Projection { source, .. }
+            | Self::Update { source, .. }
+            | Self::Window { source, .. } => vec![source],
+            Self::HashJoin { left, right, .. }
+            | Self::NestedLoopJoin { left, right, .. }
+            | Self::RecursiveUnion { anchor: left, recursive: right, .. }
+            | Self::SemiJoin { left, right, .. }
+            | Self::SetOperation { left, right, .. } => vec![left, right],
+            Self::Materialize { ctes, source } => {
+                ctes.iter().map(|(_, cte)| cte).chain(std::iter::once(&**source)).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Binds the row of an enclosing query at the given number of scopes out, replacing its
+    /// field references with constants. Correlated subqueries are bound to each outer row with
+    /// depth 0 before being executed.
+    pub fn bind_outer(self, depth: usize, row: &[Value]) -> Result<Self> {
+        self.transform(
+            &|n| match n {
+                // Semi-join predicates have the left row as an additional enclosing scope.
+                Self::SemiJoin { left, right, predicate, anti, null_aware } => Ok(Self::SemiJoin {
+                    left,
+                    right,
+                    predicate: predicate.map(|p| p.bind_outer(depth + 1, row)).transpose()?,
+                    anti,
+                    null_aware,
+                }),
+                n => n.transform_expressions(&|e| e.bind_outer(depth, row), &|e| Ok(e)),
+            },
+            &|n| Ok(n),
+        )
+    }
+
+    /// Returns true if the node tree references the row of an enclosing query at the given
+    /// number of scopes out or further, i.e. if it is correlated. This includes references made
+    /// by the subqueries it uses, which are looked up in the plan's subqueries.
+    pub fn references_outer(&self, depth: usize, subqueries: &[Node]) -> bool {
+        let refs = |e: &Expression| references_outer(e, depth, subqueries);
+        let references = match self {
+            Self::Distinct { on, .. } => on.iter().any(refs),
+            Self::Filter { predicate, .. } => refs(predicate),
+            Self::Insert { expressions, .. } => expressions.iter().flatten().any(refs),
+            Self::NestedLoopJoin { predicate: Some(predicate), .. } => refs(predicate),
+            Self::Order { orders, .. } => orders.iter().any(|(e, _)| refs(e)),
+            Self::Projection { expressions, .. } => expressions.iter().any(|(e, _)| refs(e)),
+            Self::Scan { filter: Some(filter), .. } => refs(filter),
+            Self::TableFunction { args, .. } => args.iter().any(refs),
+            Self::SemiJoin { predicate: Some(predicate), .. } => {
+                references_outer(predicate, depth + 1, subqueries)
+            }
+            Self::Update { expressions, .. } => expressions.iter().any(|(_, _, e)| refs(e)),
+            Self::Window { functions, .. } => functions.iter().any(|f| f.expressions().any(refs)),
+            _ => false,
+        };
+        references || self.sources().into_iter().any(|s| s.references_outer(depth, subqueries))
+    }
+}
+
+/// Returns true if an expression references the row of an enclosing query at the given number
+/// of scopes out or further, either directly or through the subqueries it uses.
+fn references_outer(expr: &Expression, depth: usize, subqueries: &[Node]) -> bool {
+    expr.references_outer(depth)
+        || expr
+            .subqueries()
+            .into_iter()
+            .any(|id| subqueries.get(id).is_some_and(|s| s.references_outer(depth + 1, subqueries)))
+}
+
+/// A field label, as an optional table and column name
+type Label = Option<(Option<String>, String)>;
+
+/// Projection expressions with optional output labels
+type Projection = Vec<(Expression, Option<String>)>;
+
+/// A join type, determining which unmatched rows are emitted padded with NULLs. Cross joins
+/// are inner joins without a predicate.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum JoinType {
+    /// Only matched rows
+    Inner,
+    /// Matched rows and unmatched left rows
+    Left,
+    /// Matched rows and unmatched right rows
+    Right,
+    /// Matched rows and unmatched rows from both sides
+    Full,
+}
+
+impl JoinType {
+    /// Whether unmatched left rows are emitted
+    pub fn emits_left(&self) -> bool {
+        matches!(self, Self::Left | Self::Full)
+    }
+
+    /// Whether unmatched right rows are emitted
+    pub fn emits_right(&self) -> bool {
+        matches!(self, Self::Right | Self::Full)
+    }
+
+    /// Builds the join predicate and output projection for JOIN ... USING (columns), given the
+    /// field labels of the left and right sources. The predicate is the conjunction of equality
+    /// between the named columns, or None if there are none. The projection outputs each named
+    /// column once, followed by the remaining left and right columns. The named columns are taken
+    /// from the side that is always emitted, or coalesced from both sides for full joins.
+    pub fn build_using(
+        &self,
+        left: &[Label],
+        right: &[Label],
+        columns: &[String],
+    ) -> Result<(Option<Expression>, Projection)> {
+        let lookup = |labels: &[Label], column: &str| {
+            let mut matches = labels
+                .iter()
+                .enumerate()
+                .filter(|(_, l)| matches!(l, Some((_, name)) if name == column))
+                .map(|(i, l)| (i, l.clone()));
+            match (matches.next(), matches.next()) {
+                (Some(m), None) => Ok(m),
+                (Some(_), Some(_)) => {
+                    Err(Error::Value(format!("Ambiguous column {} in USING", column)))
+                }
+                (None, _) => Err(Error::Value(format!("Unknown column {} in USING", column))),
+            }
+        };
+
+        let mut predicate: Option<Expression> = None;
+        let mut projection = Vec::new();
+        let (mut left_used, mut right_used) = (Vec::new(), Vec::new());
+        for column in columns {
+            if left_used.iter().chain(&right_used).any(|(_, c)| c == column) {
+                return Err(Error::Value(format!("Duplicate column {} in USING", column)));
+            }
+            let (l, l_label) = lookup(left, column)?;
+            let (r, r_label) = lookup(right, column)?;
+            let (lhs, rhs) =
+                (Expression::Field(l, l_label), Expression::Field(left.len() + r, r_label));
+            let equal = Expression::Equal(Box::new(lhs.clone()), Box::new(rhs.clone()));
+            predicate = Some(match predicate {
+                Some(p) => Expression::And(Box::new(p), Box::new(equal)),
+                None => equal,
+            });
+            projection.push((
+                match self {
+                    Self::Inner | Self::Left => lhs,
+                    Self::Right => rhs,
+                    Self::Full => Expression::Coalesce(vec![lhs, rhs]),
+                },
+                Some(column.clone()),
+            ));
+            left_used.push((l, column.clone()));
+            right_used.push((r, column.clone()));
+        }
+
+        let remaining = |labels: &[Label], used: &[(usize, String)]| {
+            labels
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !used.iter().any(|(u, _)| u == i))
+                .map(|(i, l)| (i, l.clone()))
+                .collect::<Vec<_>>()
+        };
+        for (i, label) in remaining(left, &left_used) {
+            projection.push((Expression::Field(i, label), None));
+        }
+        for (i, label) in remaining(right, &right_used) {
+            projection.push((Expression::Field(left.len() + i, label), None));
+        }
+        Ok((predicate, projection))
+    }
+
+    /// Returns the columns of a NATURAL join, i.e. the column names found on both sides, in
+    /// left-side order.
+    pub fn natural_columns(left: &[Label], right: &[Label]) -> Vec<String> {
+        let mut columns: Vec<String> = Vec::new();
+        for (_, name) in left.iter().flatten() {
+            if !columns.contains(name) && right.iter().flatten().any(|(_, n)| n == name) {
+                columns.push(name.clone());
+            }
+        }
+        columns
+    }
+}
+
+/// A set operator
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SetOperator {
+    /// Rows of the left query that are not in the right query
+    Except,
+    /// Rows that are in both queries
+    Intersect,
+    /// Rows that are in either query
+    Union,
+}
+
+impl Display for SetOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Except => "EXCEPT",
+            Self::Intersect => "INTERSECT",
+            Self::Union => "UNION",
+        })
+    }
+}
+
+/// An aggregate function call. If distinct is set, duplicate argument values are only
+/// aggregated once, as for COUNT(DISTINCT expr), where strings are compared using the collation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    pub distinct: bool,
+    pub collation: Collation,
 }
 
-/// A query plan
-#[derive(Debug)]
-pub struct Plan(pub Node);
+/// Builds an expression for grouping(columns), given the grouping ID field emitted by an
+/// Aggregation node with grouping sets, and the grouping column indexes of the arguments. The
+/// grouping ID has bit group_by - 1 - i set if grouping column i is not in the row's grouping
+/// set, and grouping() returns the corresponding bits of its arguments, the first one being
+/// the most significant.
+pub fn grouping_expression(id_field: usize, group_by: usize, columns: &[usize]) -> Expression {
+    let constant = |i: i64| Box::new(Expression::Constant(Value::Integer(i)));
+    columns
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(bit, column)| {
+            let id = Box::new(Expression::Field(id_field, None));
+            let shifted = Expression::Divide(id, constant(1 << (group_by - 1 - column)));
+            let flag = Expression::Modulo(Box::new(shifted), constant(2));
+            Expression::Multiply(Box::new(flag), constant(1 << bit))
+        })
+        .reduce(|lhs, rhs| Expression::Add(Box::new(lhs), Box::new(rhs)))
+        .unwrap_or(Expression::Constant(Value::Integer(0)))
+}
 
+/// Aggregate functions. Any parameters are constants given as additional function arguments
+/// after the aggregated expression, e.g. the separator in string_agg(expr, ', ').
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AggregateFunction {
+    Average,
+    /// bool_and(expr): true if all values are true
+    BoolAnd,
+    /// bool_or(expr): true if any value is true
+    BoolOr,
+    Count,
+    Max,
+    Min,
+    /// percentile_cont(expr, fraction): the continuous percentile, interpolating between the
+    /// nearest values
+    PercentileCont(f64),
+    /// The sample standard deviation
+    StdDev,
+    /// The population standard deviation
+    StdDevPop,
+    /// string_agg(expr, separator): values concatenated as strings
+    StringAgg(String),
+    Sum,
+    /// The sample variance
+    Variance,
+    /// The population variance
+    VariancePop,
+}
 
+impl AggregateFunction {
+    /// Looks up an aggregate function by name, along with its constant parameters. Returns
+    /// None if there is no such aggregate function.
+    pub fn lookup(name: &str, params: &[Value]) -> Result<Option<Self>> {
+        let function = match (name, params) {
+            ("avg", []) => Self::Average,
+            ("bool_and", []) | ("every", []) => Self::BoolAnd,
+            ("bool_or", []) => Self::BoolOr,
+            ("count", []) => Self::Count,
+            ("max", []) => Self::Max,
+            ("min", []) => Self::Min,
+            ("percentile_cont", [Value::Float(f)]) if (0.0..=1.0).contains(f) => {
+                Self::PercentileCont(*f)
+            }
+            ("percentile_cont", [Value::Integer(i)]) if (0..=1).contains(i) => {
+                Self::PercentileCont(*i as f64)
+            }
+            ("percentile_cont", _) => {
+                return Err(Error::Value("percentile_cont takes a fraction between 0 and 1".into()))
+            }
+            ("stddev", []) | ("stddev_samp", []) => Self::StdDev,
+            ("stddev_pop", []) => Self::StdDevPop,
+            ("string_agg", [Value::String(separator)]) => Self::StringAgg(separator.clone()),
+            ("string_agg", _) => {
+                return Err(Error::Value("string_agg takes a string separator".into()))
+            }
+            ("group_concat", []) => Self::StringAgg(",".into()),
+            ("group_concat", [Value::String(separator)]) => Self::StringAgg(separator.clone()),
+            ("sum", []) => Self::Sum,
+            ("variance", []) | ("var_samp", []) => Self::Variance,
+            ("var_pop", []) => Self::VariancePop,
+            (
+                "avg" | "bool_and" | "every" | "bool_or" | "count" | "max" | "min" | "stddev"
+                | "stddev_samp" | "stddev_pop" | "group_concat" | "sum" | "variance" | "var_samp"
+                | "var_pop",
+                _,
+            ) => return Err(Error::Value(format!("Invalid arguments for aggregate {}", name))),
+            _ => return Ok(None),
+        };
+        Ok(Some(function))
+    }
 
+    /// Returns the result datatype, given the argument datatype if known
+    pub fn return_datatype(&self, arg: Option<DataType>) -> Option<DataType> {
+        match self {
+            Self::BoolAnd | Self::BoolOr => Some(DataType::Boolean),
+            Self::Count => Some(DataType::Integer),
+            Self::Max | Self::Min => arg,
+            Self::StringAgg(_) => Some(DataType::String),
+            // Sums start from the integer 0, and are promoted like additions.
+            Self::Sum => DataType::promote(&DataType::Integer, &arg?),
+            Self::Average => match arg? {
+                DataType::Decimal(p, s) => Some(DataType::Decimal(p, s)),
+                _ => Some(DataType::Float),
+            },
+            Self::PercentileCont(_)
+            | Self::StdDev
+            | Self::StdDevPop
+            | Self::Variance
+            | Self::VariancePop => Some(DataType::Float),
+        }
+    }
+}
 
+/// A sort direction
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Direction {
+    Ascending,
+    Descending,
+}
 
+impl From<ast::Order> for Direction {
+    fn from(order: ast::Order) -> Self {
+        match order {
+            ast::Order::Ascending => Self::Ascending,
+            ast::Order::Descending => Self::Descending,
+        }
+    }
+}
+
+/// A window function, computed over the partition of rows with equal partition_by values,
+/// sorted by order_by
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WindowFunction {
+    pub kind: WindowKind,
+    pub args: Vec<Expression>,
+    pub partition_by: Vec<Expression>,
+    pub order_by: Vec<(Expression, Direction)>,
+    pub frame: WindowFrame,
+}
+
+impl WindowFunction {
+    /// Returns the function's expressions
+    pub fn expressions(&self) -> impl Iterator<Item = &Expression> {
+        self.args.iter().chain(&self.partition_by).chain(self.order_by.iter().map(|(e, _)| e))
+    }
+
+    /// Returns the function's expressions as mutable references
+    pub fn expressions_mut(&mut self) -> impl Iterator<Item = &mut Expression> {
+        self.args
+            .iter_mut()
+            .chain(self.partition_by.iter_mut())
+            .chain(self.order_by.iter_mut().map(|(e, _)| e))
+    }
+}
 
+/// Window function kinds. Ranking and offset functions ignore the frame, while value and
+/// aggregate functions operate on the frame.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WindowKind {
+    // Ranking functions
+    RowNumber,
+    Rank,
+    DenseRank,
+    PercentRank,
+    CumeDist,
+    /// ntile(buckets)
+    Ntile,
+
+    // Offset functions, taking (expr, offset = 1, default = NULL)
+    Lag,
+    Lead,
+
+    // Value functions
+    FirstValue,
+    LastValue,
+    /// nth_value(expr, n)
+    NthValue,
+
+    /// An aggregate function, computed over the frame
+    Aggregate(AggregateFunction),
+}
+
+impl WindowKind {
+    /// Looks up a window function by name. Aggregate functions are looked up via
+    /// AggregateFunction::lookup() instead, and wrapped in Aggregate.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "row_number" => Self::RowNumber,
+            "rank" => Self::Rank,
+            "dense_rank" => Self::DenseRank,
+            "percent_rank" => Self::PercentRank,
+            "cume_dist" => Self::CumeDist,
+            "ntile" => Self::Ntile,
+            "lag" => Self::Lag,
+            "lead" => Self::Lead,
+            "first_value" => Self::FirstValue,
+            "last_value" => Self::LastValue,
+            "nth_value" => Self::NthValue,
+            _ => return None,
+        })
+    }
+
+    /// Returns the allowed number of arguments, as (min, max)
+    pub fn arity(&self) -> (usize, usize) {
+        match self {
+            Self::RowNumber | Self::Rank | Self::DenseRank | Self::PercentRank | Self::CumeDist => {
+                (0, 0)
+            }
+            Self::Lag | Self::Lead => (1, 3),
+            Self::NthValue => (2, 2),
+            _ => (1, 1),
+        }
+    }
+}
+
+/// A window frame. Offsets are constants, and must be non-negative. RANGE offsets require a
+/// single numeric ORDER BY expression.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WindowFrame {
+    pub units: FrameUnits,
+    pub start: FrameBound,
+    pub end: FrameBound,
+}
+
+impl Default for WindowFrame {
+    /// The SQL default frame, RANGE BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW. Without ORDER
+    /// BY, all rows are peers of the current row, so this is the entire partition.
+    fn default() -> Self {
+        Self {
+            units: FrameUnits::Range,
+            start: FrameBound::UnboundedPreceding,
+            end: FrameBound::CurrentRow,
+        }
+    }
+}
+
+/// Window frame units
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FrameUnits {
+    Rows,
+    Range,
+}
+
+/// A window frame bound
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FrameBound {
+    UnboundedPreceding,
+    Preceding(Value),
+    CurrentRow,
+    Following(Value),
+    UnboundedFollowing,
+}
+
+/// A query plan. Subquery expressions refer to the plans of their subqueries by index into
+/// subqueries, where nested subqueries come before the subqueries using them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plan {
+    pub root: Node,
+    pub subqueries: Vec<Node>,
+}
+
+impl Plan {
+    /// Builds a plan for an AST statement.
+    pub fn build<C: Catalog>(statement: ast::Statement, catalog: &mut C) -> Result<Self> {
+        Planner::new(catalog).build(statement)
+    }
+
+    /// Executes the plan, consuming it.
+    pub fn execute<T: Transaction>(self, txn: &mut T) -> Result<ResultSet> {
+        Executor::new(txn, &self.subqueries).execute(self.root)
+    }
+
+    /// Optimizes the plan, consuming it. Subqueries are optimized first, in order, such that
+    /// nested subqueries are optimized before the queries using them are decorrelated.
+    pub fn optimize<C: Catalog + ?Sized>(self, catalog: &C) -> Result<Self> {
+        let optimize = |mut node: Node, subqueries: &[Node]| -> Result<Node> {
+            node = optimizer::SubqueryDecorrelator::new(subqueries).optimize(node)?;
+            node = optimizer::ConstantFolder.optimize(node)?;
+            optimizer::IndexLookup::new(catalog).optimize(node)
+        };
+        let mut subqueries = Vec::with_capacity(self.subqueries.len());
+        for subquery in self.subqueries {
+            let subquery = optimize(subquery, &subqueries)?;
+            subqueries.push(subquery);
+        }
+        let root = optimize(self.root, &subqueries)?;
+        Ok(Self { root, subqueries })
+    }
+}
@@ -0,0 +1,514 @@
+use super::super::schema::{Catalog, Table};
+use super::super::types::{Collation, DataType, Expression, Value};
+use super::{references_outer, Node};
+use crate::error::Result;
+
+/// A plan optimizer
+pub trait Optimizer {
+    fn optimize(&self, node: Node) -> Result<Node>;
+}
+
+/// Decorrelates EXISTS and IN subqueries in filter predicates into semi-joins and anti-joins, such
+/// that the subquery is executed once instead of once per row. Subqueries that can't be
+/// decorrelated are left in place, and are executed per row.
+pub struct SubqueryDecorrelator<'a> {
+    /// The plan's subqueries, looked up by the subquery expressions
+    subqueries: &'a [Node],
+}
+
+impl<'a> SubqueryDecorrelator<'a> {
+    pub fn new(subqueries: &'a [Node]) -> Self {
+        Self { subqueries }
+    }
+}
+
+impl<'a> Optimizer for SubqueryDecorrelator<'a> {
+    fn optimize(&self, node: Node) -> Result<Node> {
+        node.transform(&|n| Ok(n), &|n| match n {
+            Node::Filter { source, predicate } => {
+                let mut source = *source;
+                let mut remaining = Vec::new();
+                for conjunct in predicate.into_cnf_vec() {
+                    let (new_source, conjunct) = self.decorrelate(source, conjunct);
+                    source = new_source;
+                    remaining.extend(conjunct);
+                }
+                Ok(match Expression::from_cnf_vec(remaining) {
+                    Some(predicate) => Node::Filter { source: Box::new(source), predicate },
+                    None => source,
+                })
+            }
+            n => Ok(n),
+        })
+    }
+}
+
+impl<'a> SubqueryDecorrelator<'a> {
+    /// Attempts to decorrelate a predicate conjunct into a semi-join or anti-join with the source.
+    /// Returns the new source, along with the conjunct if it was left as is.
+    fn decorrelate(&self, source: Node, conjunct: Expression) -> (Node, Option<Expression>) {
+        let (anti, expr) = match &conjunct {
+            Expression::Not(expr) => (true, &**expr),
+            expr => (false, expr),
+        };
+        let join = match expr {
+            Expression::Exists(id) => {
+                self.subqueries.get(*id).and_then(|s| self.decorrelate_exists(s))
+            }
+            Expression::InSubquery(lhs, id) => {
+                self.subqueries.get(*id).and_then(|s| self.decorrelate_in(lhs, s))
+            }
+            _ => None,
+        };
+        match join {
+            Some((right, predicate)) => {
+                // NOT IN is NULL rather than true if any comparison is NULL.
+                let null_aware = anti && matches!(expr, Expression::InSubquery(_, _));
+                let left = Box::new(source);
+                (Node::SemiJoin { left, right: Box::new(right), predicate, anti, null_aware }, None)
+            }
+            None => (source, Some(conjunct)),
+        }
+    }
+
+    /// Decorrelates an EXISTS subquery, ignoring its projection. The correlated conjuncts of its
+    /// filter become the join predicate, and the remaining subquery must be uncorrelated.
+    fn decorrelate_exists(&self, subquery: &Node) -> Option<(Node, Option<Expression>)> {
+        let mut right = subquery.clone();
+        while let Node::Projection { source, .. } = right {
+            right = *source;
+        }
+        let mut predicate = None;
+        if let Node::Filter { source, predicate: filter } = right {
+            let (correlated, uncorrelated): (Vec<_>, Vec<_>) = filter
+                .into_cnf_vec()
+                .into_iter()
+                .partition(|e| references_outer(e, 0, self.subqueries));
+            predicate = Expression::from_cnf_vec(correlated);
+            right = match Expression::from_cnf_vec(uncorrelated) {
+                Some(filter) => Node::Filter { source, predicate: filter },
+                None => *source,
+            };
+        }
+        if right.references_outer(0, self.subqueries) {
+            return None;
+        }
+        Some((right, predicate))
+    }
+
+    /// Decorrelates an uncorrelated IN subquery with a single output column, by joining on
+    /// equality between the left-hand expression and the subquery column.
+    fn decorrelate_in(
+        &self,
+        lhs: &Expression,
+        subquery: &Node,
+    ) -> Option<(Node, Option<Expression>)> {
+        use Expression::*;
+        match subquery {
+            Node::Projection { expressions, .. } if expressions.len() == 1 => {}
+            _ => return None,
+        }
+        if subquery.references_outer(0, self.subqueries) {
+            return None;
+        }
+        // The left-hand expression moves into the join predicate's scope, where the source row
+        // is the enclosing scope. Nested subqueries would need rescoping too, so are skipped.
+        if lhs.contains(&|e| matches!(e, Exists(_) | InSubquery(_, _) | Subquery(_))) {
+            return None;
+        }
+        let lhs = lhs
+            .clone()
+            .transform(
+                &|e| match e {
+                    Field(i, label) => Ok(OuterField(0, i, label)),
+                    OuterField(d, i, label) => Ok(OuterField(d + 1, i, label)),
+                    e => Ok(e),
+                },
+                &|e| Ok(e),
+            )
+            .ok()?;
+        let predicate = Equal(Box::new(lhs), Box::new(Field(0, None)));
+        Some((subquery.clone(), Some(predicate)))
+    }
+}
+
+/// Folds constant expressions into constant values, by evaluating them once during planning
+/// instead of once per row. Expressions that reference rows, sequences, subqueries or
+/// non-deterministic functions are left as is, as are expressions that fail to evaluate, such
+/// that any error is returned during execution instead. Constant patterns of the remaining
+/// expressions are compiled, see Expression::compile_patterns().
+pub struct ConstantFolder;
+
+impl Optimizer for ConstantFolder {
+    fn optimize(&self, node: Node) -> Result<Node> {
+        node.transform(&|n| n.transform_expressions(&|e| Ok(e), &|e| Ok(Self::fold(e))), &|n| Ok(n))
+    }
+}
+
+impl ConstantFolder {
+    /// Folds an expression into a constant if possible. Since the expression is transformed
+    /// bottom-up, any constant children have already been folded. COLLATE is kept, since the
+    /// collation of a constant affects the comparisons it's used in.
+    fn fold(expr: Expression) -> Expression {
+        if matches!(
+            expr,
+            Expression::Constant(_) | Expression::Pattern(_) | Expression::Collate(_, _)
+        ) {
+            return expr;
+        }
+        if !Self::is_constant(&expr) {
+            return expr.compile_patterns();
+        }
+        match expr.evaluate(None) {
+            Ok(value) => Expression::Constant(value),
+            Err(_) => expr,
+        }
+    }
+
+    /// Returns true if the expression always evaluates to the same value
+    fn is_constant(expr: &Expression) -> bool {
+        !expr.contains(&|e| match e {
+            Expression::Field(_, _)
+            | Expression::OuterField(_, _, _)
+            | Expression::CurrVal(_)
+            | Expression::NextVal(_)
+            | Expression::Exists(_)
+            | Expression::InSubquery(_, _)
+            | Expression::Subquery(_) => true,
+            Expression::Function(function, _) => !function.is_deterministic().unwrap_or(false),
+            _ => false,
+        })
+    }
+}
+
+/// Uses primary keys and indexes for scans whose filter looks up specific keys, i.e. equality or
+/// IN list lookups of every primary key or index column, which are turned into key and index
+/// lookups. Multi-column keys are looked up either by a single predicate giving all columns, e.g.
+/// (a = 1 AND b = 2) OR (a = 3 AND b = 4), or by separate lookups of each column. The filter is
+/// kept on top of the lookup, so it need only return a superset of the matching rows.
+pub struct IndexLookup<'a, C: Catalog + ?Sized> {
+    catalog: &'a C,
+}
+
+impl<'a, C: Catalog + ?Sized> IndexLookup<'a, C> {
+    pub fn new(catalog: &'a C) -> Self {
+        Self { catalog }
+    }
+}
+
+impl<'a, C: Catalog + ?Sized> Optimizer for IndexLookup<'a, C> {
+    fn optimize(&self, node: Node) -> Result<Node> {
+        node.transform(&|n| Ok(n), &|n| match n {
+            Node::Scan { table, alias, filter: Some(filter) } => {
+                let schema = self.catalog.must_read_table(&table)?;
+                Ok(match self.lookup(&schema, alias.clone(), &filter)? {
+                    Some(lookup) => Node::Filter { source: Box::new(lookup), predicate: filter },
+                    None => Node::Scan { table, alias, filter: Some(filter) },
+                })
+            }
+            n => Ok(n),
+        })
+    }
+}
+
+impl<'a, C: Catalog + ?Sized> IndexLookup<'a, C> {
+    /// Builds a key or index lookup for a scan filter, if possible. Primary keys are preferred,
+    /// followed by unique indexes and then indexes with more columns.
+    fn lookup(
+        &self,
+        table: &Table,
+        alias: Option<String>,
+        filter: &Expression,
+    ) -> Result<Option<Node>> {
+        let filter = Self::normalize(table, filter);
+        let conjuncts = filter.clone().into_cnf_vec();
+        let pk = table
+            .get_primary_key()?
+            .into_iter()
+            .map(|c| table.get_column_index(&c.name))
+            .collect::<Result<Vec<_>>>()?;
+        let datatypes: Vec<_> =
+            pk.iter().map(|i| Some(table.columns[*i].datatype.clone())).collect();
+        // Keys are looked up as is, so string keys must be compared bytewise.
+        if pk.iter().all(|i| table.columns[*i].collation == Collation::Binary) {
+            if let Some(keys) = Self::lookup_keys(&conjuncts, &pk) {
+                if Self::typed(&datatypes, &keys) {
+                    return Ok(Some(Node::KeyLookup { table: table.name.clone(), alias, keys }));
+                }
+            }
+        }
+
+        let mut indexes = self.catalog.table_indexes(table)?;
+        indexes.sort_by_key(|i| (!i.unique, std::cmp::Reverse(i.columns.len())));
+        for index in indexes {
+            let keys = index
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(i, c)| match index.expression(i) {
+                    Some(expression) => Ok(Self::strip_labels(expression.clone())),
+                    None => Ok(Expression::Field(table.get_column_index(c)?, None)),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            // Index keys are replaced by placeholder fields following the row's fields.
+            let len = table.columns.len();
+            let conjuncts = filter
+                .clone()
+                .transform(
+                    &|e| match keys.iter().position(|k| k == &e) {
+                        Some(i) => Ok(Expression::Field(len + i, None)),
+                        None => Ok(e),
+                    },
+                    &Ok,
+                )?
+                .into_cnf_vec();
+            let fields = (len..len + keys.len()).collect::<Vec<_>>();
+            let values = match Self::lookup_keys(&conjuncts, &fields) {
+                Some(values) => values,
+                None => continue,
+            };
+            let datatypes: Vec<_> = keys
+                .iter()
+                .map(|k| match k {
+                    Expression::Field(i, _) => Some(table.columns[*i].datatype.clone()),
+                    k => k.static_datatype(&[]),
+                })
+                .collect();
+            if !Self::typed(&datatypes, &values) {
+                continue;
+            }
+            // Index entries are keyed by collation keys, see Index::get_row_key().
+            let values = values
+                .into_iter()
+                .map(|tuple| {
+                    tuple
+                        .into_iter()
+                        .zip(&keys)
+                        .map(|(value, key)| match key {
+                            Expression::Field(i, _) => table.columns[*i].collation.key(value),
+                            key => key.collation().unwrap_or_default().key(value),
+                        })
+                        .collect()
+                })
+                .collect();
+            return Ok(Some(Node::IndexLookup {
+                table: table.name.clone(),
+                alias,
+                columns: index.columns,
+                values,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Returns true if the looked up key values have the given key datatypes, such that they
+    /// have the same key encoding. NULL values are allowed.
+    fn typed(datatypes: &[Option<DataType>], keys: &[Vec<Value>]) -> bool {
+        keys.iter().all(|key| {
+            datatypes.iter().zip(key).all(|(datatype, value)| match (value.datatype(), datatype) {
+                (None, _) => true,
+                (Some(v), Some(d)) => v.matches(d),
+                (Some(_), None) => false,
+            })
+        })
+    }
+
+    /// Normalizes a filter for key lookups, by removing field labels and unwrapping column
+    /// references from their column collation
+    fn normalize(table: &Table, filter: &Expression) -> Expression {
+        Self::strip_labels(filter.clone())
+            .transform(&Ok, &|e| match e {
+                Expression::Collate(expr, collation) => match *expr {
+                    Expression::Field(i, None)
+                        if table.columns.get(i).map(|c| c.collation) == Some(collation) =>
+                    {
+                        Ok(Expression::Field(i, None))
+                    }
+                    expr => Ok(Expression::Collate(Box::new(expr), collation)),
+                },
+                e => Ok(e),
+            })
+            .unwrap_or_else(|_| filter.clone())
+    }
+
+    /// Removes field labels from an expression
+    fn strip_labels(expr: Expression) -> Expression {
+        let strip = |e| match e {
+            Expression::Field(i, _) => Ok(Expression::Field(i, None)),
+            e => Ok(e),
+        };
+        expr.clone().transform(&strip, &Ok).unwrap_or(expr)
+    }
+
+    /// Returns the key values looked up by the conjuncts of a filter, as tuples of the given
+    /// field values, or None if the fields aren't all looked up.
+    fn lookup_keys(conjuncts: &[Expression], fields: &[usize]) -> Option<Vec<Vec<Value>>> {
+        if let Some(keys) = conjuncts.iter().find_map(|c| c.as_lookup_tuple(fields)) {
+            return Some(keys);
+        }
+        let mut keys = vec![Vec::new()];
+        for field in fields {
+            let values = conjuncts.iter().find_map(|c| c.as_lookup(*field))?;
+            keys = keys
+                .into_iter()
+                .flat_map(|key: Vec<Value>| {
+                    values.iter().map(move |v| key.iter().cloned().chain([v.clone()]).collect())
+                })
+                .collect();
+        }
+        Some(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::engine::{Engine, KV};
+    use super::super::super::schema::{Column, Index};
+    use super::super::super::types::{register_function, unregister_function, Function};
+    use super::*;
+    use crate::storage::kv;
+
+    fn field(i: usize) -> Box<Expression> {
+        Box::new(Expression::Field(i, None))
+    }
+
+    fn constant(i: i64) -> Box<Expression> {
+        Box::new(Expression::Constant(Value::Integer(i)))
+    }
+
+    fn scan(filter: Expression) -> Node {
+        Node::Scan { table: "t".into(), alias: None, filter: Some(filter) }
+    }
+
+    #[test]
+    fn constant_folder() -> Result<()> {
+        register_function("fold_double", vec![DataType::Integer], DataType::Integer, true, |a| {
+            Ok(Value::Integer(a[0].clone().integer()? * 2))
+        })?;
+        register_function("fold_random", Vec::new(), DataType::Integer, false, |_| {
+            Ok(Value::Integer(4))
+        })?;
+        let fold = |expr: Expression| -> Result<Expression> {
+            let node = ConstantFolder.optimize(scan(expr))?;
+            match node {
+                Node::Scan { filter: Some(filter), .. } => Ok(filter),
+                node => panic!("Unexpected node {:?}", node),
+            }
+        };
+        let call = |name: &str, args: Vec<Expression>| -> Result<Expression> {
+            Ok(Function::build(name, args)?.expect("function not found"))
+        };
+
+        // Constant subexpressions and deterministic functions with constant arguments are folded.
+        assert_eq!(
+            Expression::Equal(field(0), constant(6)),
+            fold(Expression::Equal(
+                field(0),
+                Box::new(call("fold_double", vec![Expression::Add(constant(1), constant(2))])?),
+            ))?
+        );
+
+        // Non-deterministic functions, fields and expressions that fail are left as is.
+        for expr in [
+            Expression::Equal(field(0), Box::new(call("fold_random", Vec::new())?)),
+            Expression::Equal(constant(1), Box::new(call("now", Vec::new())?)),
+            Expression::Equal(field(0), Box::new(call("fold_double", vec![*field(0)])?)),
+            Expression::Equal(field(0), Box::new(Expression::Divide(constant(1), constant(0)))),
+        ] {
+            assert_eq!(expr, fold(expr.clone())?);
+        }
+
+        // Unregistered functions fail when evaluated, and are no longer folded.
+        let double = call("fold_double", vec![*constant(1)])?;
+        assert!(unregister_function("fold_double")?);
+        assert!(double.evaluate(None).is_err());
+        assert_eq!(double, fold(double.clone())?);
+        Ok(())
+    }
+
+    #[test]
+    fn constant_folder_collation() -> Result<()> {
+        let string = |s: &str| Box::new(Expression::Constant(s.into()));
+        let nocase = |expr| Box::new(Expression::Collate(expr, Collation::NoCase));
+        let fold = |expr| -> Result<Option<Expression>> {
+            match ConstantFolder.optimize(scan(expr))? {
+                Node::Scan { filter, .. } => Ok(filter),
+                node => panic!("Unexpected node {:?}", node),
+            }
+        };
+
+        // Collated constants keep their collation, since it affects comparisons with them, but
+        // are folded along with the comparison if it's constant.
+        let expr = Expression::Equal(field(0), nocase(string("A")));
+        assert_eq!(Some(expr.clone()), fold(expr)?);
+        assert_eq!(
+            Some(Expression::Constant(Value::Boolean(true))),
+            fold(Expression::Equal(nocase(string("a")), string("A")))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn index_lookup() -> Result<()> {
+        let mut txn = KV::new(kv::MVCC::new(Box::new(kv::Memory::new()))).begin()?;
+        let columns = vec![
+            Column { primary_key: true, unique: true, ..Column::new("id", DataType::Integer) },
+            Column::new("a", DataType::Integer),
+            Column::new("b", DataType::Integer),
+        ];
+        txn.create_table(Table::new("t".into(), columns)?)?;
+        txn.create_index(Index {
+            name: "t_ab".into(),
+            table: "t".into(),
+            columns: vec!["a".into(), "b".into()],
+            expressions: Vec::new(),
+            unique: false,
+        })?;
+        let optimizer = IndexLookup::new(&txn);
+
+        // Primary key lookups are preferred.
+        let filter = Expression::And(
+            Box::new(Expression::Equal(field(0), constant(1))),
+            Box::new(Expression::Equal(field(1), constant(2))),
+        );
+        assert_eq!(
+            Node::Filter {
+                source: Box::new(Node::KeyLookup {
+                    table: "t".into(),
+                    alias: None,
+                    keys: vec![vec![Value::Integer(1)]],
+                }),
+                predicate: filter.clone(),
+            },
+            optimizer.optimize(scan(filter))?
+        );
+
+        // Multi-column indexes are used when all columns are looked up.
+        let filter = Expression::And(
+            Box::new(Expression::InList(field(1), vec![*constant(1), *constant(2)])),
+            Box::new(Expression::Equal(constant(3), field(2))),
+        );
+        assert_eq!(
+            Node::Filter {
+                source: Box::new(Node::IndexLookup {
+                    table: "t".into(),
+                    alias: None,
+                    columns: vec!["a".into(), "b".into()],
+                    values: vec![
+                        vec![Value::Integer(1), Value::Integer(3)],
+                        vec![Value::Integer(2), Value::Integer(3)],
+                    ],
+                }),
+                predicate: filter.clone(),
+            },
+            optimizer.optimize(scan(filter))?
+        );
+
+        // Partial lookups and lookups of values with a different datatype can't use the index.
+        let filter = Expression::Equal(field(1), constant(1));
+        assert_eq!(scan(filter.clone()), optimizer.optimize(scan(filter))?);
+        let filter = Expression::Equal(field(0), Box::new(Expression::Constant(Value::Float(1.0))));
+        assert_eq!(scan(filter.clone()), optimizer.optimize(scan(filter))?);
+        Ok(())
+    }
+}
@@ -1,22 +1,1569 @@
-use super::super::schema::{Catalog, Column, Table};
-use crate::error::Result;
+use super::super::parser::ast;
+use super::super::schema::{self, Catalog, Index, Sequence, Table};
+use super::super::types::{
+    decimal, Collation, DataType, Expression, Function, TableFunction, Value,
+};
+use super::{
+    grouping_expression, Aggregate, AggregateFunction, Direction, FrameBound, FrameUnits, JoinType,
+    Label, Node, Plan, Projection, SetOperator, WindowFrame, WindowFunction, WindowKind,
+    DEFAULT_RECURSION_LIMIT,
+};
+use crate::error::{Error, Result};
 
-// 定义一个 plan 结构体
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::mem::take;
+
+/// A query planner, which builds plans for AST statements, resolving names via the catalog
 pub struct Planner<'a, C: Catalog> {
     catalog: &'a mut C,
+    recursion_limit: u64,
+    /// The plans of the subqueries built so far, referred to by subquery expressions by index
+    subqueries: Vec<Node>,
+    /// The scopes of the queries enclosing the query being built, innermost last, for
+    /// resolving outer field references in correlated subqueries
+    outer: Vec<Scope>,
+    /// The common table expressions in scope, innermost last
+    ctes: Vec<(String, CteColumns)>,
 }
 
+/// The columns of a common table expression, as column names and datatypes
+type CteColumns = Vec<(Option<String>, Option<DataType>)>;
+
 impl<'a, C: Catalog> Planner<'a, C> {
     /// Creates a new planner.
     pub fn new(catalog: &'a mut C) -> Self {
-        Self { catalog: catalog }
+        Self {
+            catalog,
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
+            subqueries: Vec::new(),
+            outer: Vec::new(),
+            ctes: Vec::new(),
+        }
     }
 
+    /// Sets the maximum number of iterations of recursive common table expressions.
+    pub fn with_recursion_limit(mut self, recursion_limit: u64) -> Self {
+        self.recursion_limit = recursion_limit;
+        self
+    }
 
     /// Builds a plan for an AST statement.
     pub fn build(&mut self, statement: ast::Statement) -> Result<Plan> {
-        Ok(
-            Plan(self.build_statement(statement)?)
-        )
+        let root = self.build_statement(statement)?;
+        Ok(Plan { root, subqueries: take(&mut self.subqueries) })
+    }
+
+    /// Builds a plan node for a statement
+    fn build_statement(&mut self, statement: ast::Statement) -> Result<Node> {
+        Ok(match statement {
+            // Transaction control and explain statements are handled by the session.
+            ast::Statement::Begin { .. }
+            | ast::Statement::Commit
+            | ast::Statement::Rollback
+            | ast::Statement::Explain(_) => {
+                return Err(Error::Internal(format!("Unexpected statement {:?}", statement)))
+            }
+
+            // DDL statements (schema changes)
+            ast::Statement::CreateTable { name, columns, primary_key, foreign_keys, checks } => {
+                Node::CreateTable {
+                    schema: self.build_table(name, columns, primary_key, foreign_keys, checks)?,
+                }
+            }
+            ast::Statement::DropTable(table) => Node::DropTable { table },
+            ast::Statement::AlterTable { table, alteration } => {
+                let alteration = self.build_alteration(&table, alteration)?;
+                Node::AlterTable { table, alteration }
+            }
+            ast::Statement::CreateIndex { name, table, columns, unique } => {
+                Node::CreateIndex { schema: self.build_index(name, table, columns, unique)? }
+            }
+            ast::Statement::DropIndex(name) => Node::DropIndex { name },
+            ast::Statement::CreateSequence { name, start, increment } => Node::CreateSequence {
+                schema: Sequence {
+                    name,
+                    start: start.unwrap_or(1),
+                    increment: increment.unwrap_or(1),
+                },
+            },
+            ast::Statement::DropSequence(name) => Node::DropSequence { name },
+
+            // DML statements (mutations)
+            ast::Statement::Delete { table, r#where } => {
+                let source = self.build_scan(&table, r#where)?;
+                Node::Delete { table, source: Box::new(source) }
+            }
+            ast::Statement::Insert { table, columns, values } => {
+                let scope = Scope::constant();
+                let mut expressions = Vec::with_capacity(values.len());
+                for exprs in values {
+                    expressions.push(
+                        exprs
+                            .into_iter()
+                            .map(|e| self.build_expression(&scope, e))
+                            .collect::<Result<_>>()?,
+                    );
+                }
+                Node::Insert { table, columns: columns.unwrap_or_default(), expressions }
+            }
+            ast::Statement::Update { table, set, r#where } => {
+                let schema = self.catalog.must_read_table(&table)?;
+                let scope = Scope::from_table(&schema, &table)?;
+                let source = self.build_scan(&table, r#where)?;
+                let mut expressions = Vec::with_capacity(set.len());
+                for (column, expr) in set {
+                    let index = schema.get_column_index(&column)?;
+                    expressions.push((index, Some(column), self.build_expression(&scope, expr)?));
+                }
+                Node::Update { table, source: Box::new(source), expressions }
+            }
+
+            // Queries
+            statement @ (ast::Statement::Select { .. } | ast::Statement::SetOperation { .. }) => {
+                self.build_query(statement)?.0
+            }
+        })
+    }
+
+    /// Builds a table schema for CREATE TABLE. Unnamed CHECK constraints are named after the
+    /// table, and the column for inline constraints, like in PostgreSQL.
+    fn build_table(
+        &mut self,
+        name: String,
+        columns: Vec<ast::Column>,
+        primary_key: Vec<String>,
+        foreign_keys: Vec<ast::ForeignKey>,
+        checks: Vec<ast::Check>,
+    ) -> Result<Table> {
+        // Columns of a composite primary key are only unique in combination.
+        let single_pk = primary_key.len() <= 1;
+        let mut all_checks = Vec::new();
+        let mut schema_columns = Vec::with_capacity(columns.len());
+        for mut column in columns {
+            let base = format!("{}_{}_check", name, column.name);
+            all_checks
+                .extend(take(&mut column.checks).into_iter().map(|e| (None, base.clone(), e)));
+            schema_columns.push(self.build_column(&name, column, single_pk)?);
+        }
+        let base = format!("{}_check", name);
+        all_checks.extend(checks.into_iter().map(|c| (c.name, base.clone(), c.expression)));
+
+        let mut table = Table::new(name.clone(), schema_columns)?;
+        table.primary_key = primary_key;
+        table.foreign_keys = foreign_keys
+            .into_iter()
+            .map(|fk| schema::ForeignKey {
+                columns: fk.columns,
+                references: fk.references,
+                on_delete: fk.on_delete,
+                on_update: fk.on_update,
+            })
+            .collect();
+
+        let scope = Scope::from_table(&table, &name)?;
+        let mut names: HashSet<String> =
+            all_checks.iter().filter_map(|(name, _, _)| name.clone()).collect();
+        for (name, base, expr) in all_checks {
+            let name = match name {
+                Some(name) => name,
+                None => {
+                    let mut name = base.clone();
+                    for i in 1.. {
+                        if !names.contains(&name) {
+                            break;
+                        }
+                        name = format!("{}{}", base, i);
+                    }
+                    names.insert(name.clone());
+                    name
+                }
+            };
+            let expression = self.build_schema_expression(&scope, expr)?;
+            table.checks.push(schema::Check { name, expression });
+        }
+        Ok(table)
+    }
+
+    /// Builds a column schema. Nullable columns default to NULL, and AUTOINCREMENT columns to
+    /// the next value of their column sequence.
+    fn build_column(
+        &mut self,
+        table: &str,
+        column: ast::Column,
+        single_pk: bool,
+    ) -> Result<schema::Column> {
+        if !column.checks.is_empty() {
+            return Err(Error::Value(format!(
+                "Can't add CHECK constraint on column {} here",
+                column.name
+            )));
+        }
+        let nullable = column.nullable.unwrap_or(!column.primary_key);
+        let default = match (column.default, column.autoincrement) {
+            (Some(_), true) => {
+                return Err(Error::Value(format!(
+                    "Column {} can't have both a default value and AUTOINCREMENT",
+                    column.name
+                )))
+            }
+            (None, true) => {
+                Some(Expression::NextVal(Sequence::column_sequence_name(table, &column.name)))
+            }
+            (Some(expr), false) => Some(self.build_schema_expression(&Scope::constant(), expr)?),
+            (None, false) if nullable => Some(Expression::Constant(Value::Null)),
+            (None, false) => None,
+        };
+        Ok(schema::Column {
+            name: column.name,
+            datatype: column.datatype,
+            primary_key: column.primary_key,
+            nullable,
+            default,
+            unique: column.unique || (column.primary_key && single_pk),
+            references: column.references,
+            on_delete: column.on_delete,
+            on_update: column.on_update,
+            index: column.index,
+            rounding: column.rounding,
+            length: column.length,
+            collation: column.collation,
+        })
+    }
+
+    /// Builds a table alteration for ALTER TABLE
+    fn build_alteration(
+        &mut self,
+        table: &str,
+        alteration: ast::Alteration,
+    ) -> Result<schema::Alteration> {
+        Ok(match alteration {
+            ast::Alteration::AddColumn(column) => {
+                schema::Alteration::AddColumn(self.build_column(table, column, false)?)
+            }
+            ast::Alteration::DropColumn(name) => schema::Alteration::DropColumn(name),
+            ast::Alteration::RenameColumn { from, to } => {
+                schema::Alteration::RenameColumn { from, to }
+            }
+            ast::Alteration::RenameTable(name) => schema::Alteration::RenameTable(name),
+            ast::Alteration::SetDefault { column, default } => schema::Alteration::SetDefault {
+                column,
+                default: default
+                    .map(|e| self.build_schema_expression(&Scope::constant(), e))
+                    .transpose()?,
+            },
+            ast::Alteration::SetNullable { column, nullable } => {
+                schema::Alteration::SetNullable { column, nullable }
+            }
+        })
+    }
+
+    /// Builds an index schema for CREATE INDEX. Plain columns are indexed by name, and other
+    /// expressions are named by their text.
+    fn build_index(
+        &mut self,
+        name: String,
+        table: String,
+        columns: Vec<ast::Expression>,
+        unique: bool,
+    ) -> Result<Index> {
+        let schema = self.catalog.must_read_table(&table)?;
+        let scope = Scope::from_table(&schema, &table)?;
+        let mut names = Vec::with_capacity(columns.len());
+        let mut expressions = Vec::with_capacity(columns.len());
+        for column in columns {
+            match column {
+                ast::Expression::Field(None, column) => {
+                    names.push(column);
+                    expressions.push(None);
+                }
+                ast::Expression::Field(Some(t), column) if t == table => {
+                    names.push(column);
+                    expressions.push(None);
+                }
+                expr => {
+                    let expr = self.build_schema_expression(&scope, expr)?;
+                    names.push(expr.to_string());
+                    expressions.push(Some(expr));
+                }
+            }
+        }
+        if expressions.iter().all(Option::is_none) {
+            expressions.clear();
+        }
+        Ok(Index { name, table, columns: names, expressions, unique })
+    }
+
+    /// Builds an expression stored in a schema, e.g. a default or check, which can't use
+    /// subqueries since their plans aren't stored
+    fn build_schema_expression(
+        &mut self,
+        scope: &Scope,
+        expr: ast::Expression,
+    ) -> Result<Expression> {
+        let expr = self.build_expression(scope, expr)?;
+        if !expr.subqueries().is_empty() {
+            return Err(Error::Value(format!("Can't use subqueries in {}", expr)));
+        }
+        Ok(expr)
+    }
+
+    /// Builds a scan of a table's rows for DELETE and UPDATE, with an optional filter
+    fn build_scan(&mut self, table: &str, filter: Option<ast::Expression>) -> Result<Node> {
+        let schema = self.catalog.must_read_table(table)?;
+        let scope = Scope::from_table(&schema, table)?;
+        let filter = filter.map(|e| self.build_expression(&scope, e)).transpose()?;
+        Ok(Self::filter(Node::Scan { table: table.to_string(), alias: None, filter: None }, filter))
+    }
+
+    /// Filters a node's rows by a predicate, pushing it into a scan if possible. Predicates with
+    /// subqueries or sequences are kept in a filter node, since they are resolved by the executor.
+    fn filter(source: Node, predicate: Option<Expression>) -> Node {
+        match (source, predicate) {
+            (source, None) => source,
+            (Node::Scan { table, alias, filter: None }, Some(predicate))
+                if predicate.is_resolved() =>
+            {
+                Node::Scan { table, alias, filter: Some(predicate) }
+            }
+            (source, Some(predicate)) => Node::Filter { source: Box::new(source), predicate },
+        }
+    }
+
+    /// Builds a query, i.e. a SELECT or a set operation, returning its node and the scope of its
+    /// result columns. Common table expressions are only in scope within the query.
+    fn build_query(&mut self, statement: ast::Statement) -> Result<(Node, Scope)> {
+        let ctes = self.ctes.len();
+        let result = self.build_query_body(statement);
+        self.ctes.truncate(ctes);
+        result
+    }
+
+    /// Builds a query's common table expressions and body, see build_query()
+    fn build_query_body(&mut self, statement: ast::Statement) -> Result<(Node, Scope)> {
+        let (with, (node, scope)) = match statement {
+            ast::Statement::Select {
+                with,
+                distinct,
+                select,
+                from,
+                r#where,
+                group_by,
+                having,
+                order,
+                offset,
+                limit,
+            } => {
+                let with = self.build_ctes(with)?;
+                let select = Select { distinct, select, from, r#where, group_by, having, order };
+                (with, self.build_select(select, offset, limit)?)
+            }
+            ast::Statement::SetOperation {
+                with,
+                operator,
+                all,
+                left,
+                right,
+                order,
+                offset,
+                limit,
+            } => {
+                let with = self.build_ctes(with)?;
+                let (left, left_scope) = self.build_query(*left)?;
+                let (right, right_scope) = self.build_query(*right)?;
+                let operator = match operator {
+                    ast::SetOperator::Except => SetOperator::Except,
+                    ast::SetOperator::Intersect => SetOperator::Intersect,
+                    ast::SetOperator::Union => SetOperator::Union,
+                };
+                let (left, right, datatypes) =
+                    Self::build_set_columns(&operator, (left, &left_scope), (right, &right_scope))?;
+                let node = Node::SetOperation {
+                    operator,
+                    all,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                };
+                // The result columns have the left query's names, without table qualifiers.
+                let mut scope = Scope::default();
+                for ((label, collation), datatype) in left_scope.columns.into_iter().zip(datatypes)
+                {
+                    scope.add_column(label.map(|(_, name)| (None, name)), collation, datatype);
+                }
+                let mut orders = Vec::with_capacity(order.len());
+                for (expr, direction) in order {
+                    let expr = match expr {
+                        ast::Expression::Literal(ast::Literal::Integer(i)) => {
+                            scope.position(i, "ORDER BY")?
+                        }
+                        expr => self.build_expression(&scope, expr)?,
+                    };
+                    orders.push((expr, direction.into()));
+                }
+                let mut node = node;
+                if !orders.is_empty() {
+                    node = Node::Order { source: Box::new(node), orders };
+                }
+                (with, (self.build_limits(node, offset, limit)?, scope))
+            }
+            statement => return Err(Error::Internal(format!("Unexpected query {:?}", statement))),
+        };
+        if with.is_empty() {
+            return Ok((node, scope));
+        }
+        Ok((Node::Materialize { ctes: with, source: Box::new(node) }, scope))
+    }
+
+    /// Checks that the queries combined by a set operation have the same number of columns, and
+    /// that their column datatypes match where known. Columns of different numeric datatypes are
+    /// cast to a common datatype, like the operands of arithmetic operations are promoted.
+    /// Returns the queries along with the result column datatypes.
+    fn build_set_columns(
+        operator: &SetOperator,
+        (left, left_scope): (Node, &Scope),
+        (right, right_scope): (Node, &Scope),
+    ) -> Result<(Node, Node, Vec<Option<DataType>>)> {
+        if left_scope.columns.len() != right_scope.columns.len() {
+            return Err(Error::Value(format!(
+                "Queries combined by {} must have the same number of columns, got {} and {}",
+                operator,
+                left_scope.columns.len(),
+                right_scope.columns.len()
+            )));
+        }
+        let mut datatypes = Vec::with_capacity(left_scope.datatypes.len());
+        let (mut left_casts, mut right_casts) = (false, false);
+        for (i, (lhs, rhs)) in left_scope.datatypes.iter().zip(&right_scope.datatypes).enumerate() {
+            datatypes.push(match (lhs, rhs) {
+                (Some(lhs), Some(rhs)) if lhs.matches(rhs) => Some(lhs.clone()),
+                (Some(lhs), Some(rhs)) => match DataType::promote(lhs, rhs) {
+                    Some(datatype) => {
+                        left_casts |= !lhs.matches(&datatype);
+                        right_casts |= !rhs.matches(&datatype);
+                        Some(datatype)
+                    }
+                    None => {
+                        return Err(Error::Value(format!(
+                            "Column {} of queries combined by {} has incompatible datatypes {} \
+                             and {}",
+                            i + 1,
+                            operator,
+                            lhs,
+                            rhs
+                        )))
+                    }
+                },
+                (datatype, None) | (None, datatype) => datatype.clone(),
+            });
+        }
+        let cast = |node: Node, scope: &Scope| -> Node {
+            let expressions = scope
+                .columns
+                .iter()
+                .zip(&scope.datatypes)
+                .zip(&datatypes)
+                .enumerate()
+                .map(|(i, (((label, _), from), to))| {
+                    let field = Expression::Field(i, label.clone());
+                    // Integers are cast to decimals exactly, regardless of the other decimals'
+                    // precision and scale.
+                    let to = match (from, to) {
+                        (Some(from), Some(to)) if from.matches(to) => return (field, None),
+                        (Some(DataType::Integer), Some(DataType::Decimal(..))) => {
+                            DataType::Decimal(decimal::MAX_PRECISION, 0)
+                        }
+                        (Some(_), Some(to)) => to.clone(),
+                        _ => return (field, None),
+                    };
+                    (Expression::Cast(Box::new(field), to), None)
+                })
+                .collect();
+            Node::Projection { source: Box::new(node), expressions }
+        };
+        let left = if left_casts { cast(left, left_scope) } else { left };
+        let right = if right_casts { cast(right, right_scope) } else { right };
+        Ok((left, right, datatypes))
+    }
+
+    /// Builds the common table expressions of a WITH clause in order, bringing each one into
+    /// scope for the following ones and the query. A recursive term can refer to its own
+    /// common table expression, which then has the columns of the anchor.
+    fn build_ctes(&mut self, with: Vec<ast::CommonTableExpression>) -> Result<Vec<(String, Node)>> {
+        let mut ctes: Vec<(String, Node)> = Vec::with_capacity(with.len());
+        for cte in with {
+            if ctes.iter().any(|(name, _)| name == &cte.name) {
+                return Err(Error::Value(format!(
+                    "Duplicate common table expression {}",
+                    cte.name
+                )));
+            }
+            let (anchor, scope) = self.build_query(*cte.query)?;
+            let columns: Vec<Option<String>> = match cte.columns {
+                Some(columns) if columns.len() != scope.columns.len() => {
+                    return Err(Error::Value(format!(
+                        "Common table expression {} has {} columns, but {} column names given",
+                        cte.name,
+                        scope.columns.len(),
+                        columns.len()
+                    )))
+                }
+                Some(columns) => columns.into_iter().map(Some).collect(),
+                None => scope.columns.iter().map(|(l, _)| l.clone().map(|(_, n)| n)).collect(),
+            };
+            let columns = columns.into_iter().zip(scope.datatypes.iter().cloned()).collect();
+            let width = scope.columns.len();
+            self.ctes.push((cte.name.clone(), columns));
+            let node = match cte.recursive {
+                Some(recursive) => {
+                    let (recursive, scope) = self.build_query(*recursive)?;
+                    if scope.columns.len() != width {
+                        return Err(Error::Value(format!(
+                            "Recursive term of {} must have {} columns, got {}",
+                            cte.name,
+                            width,
+                            scope.columns.len()
+                        )));
+                    }
+                    Node::RecursiveUnion {
+                        name: cte.name.clone(),
+                        anchor: Box::new(anchor),
+                        recursive: Box::new(recursive),
+                        union_all: cte.union_all,
+                        max_iterations: self.recursion_limit,
+                    }
+                }
+                None => anchor,
+            };
+            ctes.push((cte.name, node));
+        }
+        Ok(ctes)
+    }
+
+    /// Builds a SELECT query. The plan is built in SQL evaluation order: FROM, WHERE, GROUP BY
+    /// and aggregates, HAVING, window functions, the projection, ORDER BY, DISTINCT, OFFSET and
+    /// LIMIT. ORDER BY and DISTINCT ON expressions that aren't in the select list are computed
+    /// by the projection as hidden columns, which are removed at the end.
+    fn build_select(
+        &mut self,
+        select: Select,
+        offset: Option<ast::Expression>,
+        limit: Option<ast::Expression>,
+    ) -> Result<(Node, Scope)> {
+        let Select { distinct, select, from, r#where, group_by, having, order } = select;
+
+        // FROM and WHERE.
+        let (mut node, mut scope) = self.build_from(from)?;
+        if let Some(expr) = r#where {
+            let predicate = self.build_expression(&scope, expr)?;
+            node = Self::filter(node, Some(predicate));
+        }
+
+        // GROUP BY, aggregates and HAVING.
+        let exprs =
+            || select.iter().map(|(e, _)| e).chain(&having).chain(order.iter().map(|(e, _)| e));
+        let aggregates = Self::collect_calls(exprs(), |e| match e {
+            ast::Expression::Function { name, .. } => Self::is_aggregate(name),
+            _ => false,
+        })?;
+        let groupings = Self::collect_calls(
+            exprs(),
+            |e| matches!(e, ast::Expression::Function { name, .. } if name == "grouping"),
+        )?;
+        if !group_by.is_empty() || !aggregates.is_empty() || having.is_some() {
+            let (n, s) = self.build_aggregation(node, scope, &select, group_by, aggregates)?;
+            (node, scope) = (n, s);
+            self.add_groupings(&mut scope, groupings)?;
+        }
+        if let Some(expr) = having {
+            let predicate = self.build_expression(&scope, expr)?;
+            node = Node::Filter { source: Box::new(node), predicate };
+        }
+
+        // Window functions.
+        let width = scope.columns.len();
+        let windows = Self::collect_calls(
+            select.iter().map(|(e, _)| e).chain(order.iter().map(|(e, _)| e)),
+            |e| matches!(e, ast::Expression::WindowFunction { .. }),
+        )?;
+        if !windows.is_empty() {
+            let mut functions = Vec::with_capacity(windows.len());
+            for (i, call) in windows.into_iter().enumerate() {
+                functions.push(self.build_window_function(&scope, call.clone())?);
+                scope.add_column(None, Collation::default(), None);
+                scope.substitutions.push((call, Expression::Field(width + i, None)));
+            }
+            node = Node::Window { source: Box::new(node), functions };
+        }
+
+        // Projection, with hidden columns for ORDER BY and DISTINCT ON.
+        let mut expressions: Projection = Vec::new();
+        if select.is_empty() {
+            for (i, (label, collation)) in scope.columns[..width].iter().enumerate() {
+                expressions.push((field(i, label.clone(), *collation), None));
+            }
+        }
+        for (expr, label) in select {
+            expressions.push((self.build_expression(&scope, expr)?, label));
+        }
+        let visible = expressions.len();
+        let projected = scope.project(&expressions);
+        let mut orders = Vec::with_capacity(order.len());
+        for (expr, direction) in order {
+            let expr = self.build_output(&scope, &projected, &mut expressions, expr)?;
+            orders.push((expr, direction.into()));
+        }
+        if matches!(distinct, Some(ast::Distinct::Rows)) && expressions.len() > visible {
+            return Err(Error::Value(
+                "ORDER BY expressions must be in the select list for SELECT DISTINCT".into(),
+            ));
+        }
+        let mut on = Vec::new();
+        if let Some(ast::Distinct::On(exprs)) = &distinct {
+            for expr in exprs.iter().cloned() {
+                on.push(self.build_output(&scope, &projected, &mut expressions, expr)?);
+            }
+        } else if distinct.is_some()
+            && projected.columns.iter().any(|(_, c)| *c != Collation::default())
+        {
+            // Rows with collated columns are deduplicated by all columns, using the collations.
+            for (i, (label, collation)) in projected.columns.iter().enumerate() {
+                on.push(field(i, label.clone(), *collation));
+            }
+        }
+        let hidden = expressions.len() > visible;
+        node = Node::Projection { source: Box::new(node), expressions };
+        if !orders.is_empty() {
+            node = Node::Order { source: Box::new(node), orders };
+        }
+        if distinct.is_some() {
+            node = Node::Distinct { source: Box::new(node), on };
+        }
+        node = self.build_limits(node, offset, limit)?;
+        if hidden {
+            let expressions = projected
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(i, (label, _))| (Expression::Field(i, label.clone()), None))
+                .collect();
+            node = Node::Projection { source: Box::new(node), expressions };
+        }
+        Ok((node, projected))
+    }
+
+    /// Builds an ORDER BY or DISTINCT ON expression as a field of the projection. Select list
+    /// columns are referred to by name or position, and other expressions are matched against
+    /// the select list or else appended to the projection as hidden columns.
+    fn build_output(
+        &mut self,
+        scope: &Scope,
+        projected: &Scope,
+        expressions: &mut Projection,
+        expr: ast::Expression,
+    ) -> Result<Expression> {
+        match &expr {
+            ast::Expression::Field(table, name) => {
+                if let Ok(Some((i, label, collation))) = projected.resolve(table.as_deref(), name) {
+                    return Ok(field(i, label, collation));
+                }
+            }
+            ast::Expression::Literal(ast::Literal::Integer(i)) => {
+                return projected.position(*i, "ORDER BY");
+            }
+            _ => {}
+        }
+        let expr = self.build_expression(scope, expr)?;
+        let index = match expressions.iter().position(|(e, _)| e == &expr) {
+            Some(index) => index,
+            None => {
+                expressions.push((expr, None));
+                expressions.len() - 1
+            }
+        };
+        let collation = expressions[index].0.collation().unwrap_or_default();
+        Ok(field(index, None, collation))
+    }
+
+    /// Adds OFFSET and LIMIT nodes, which take constant non-negative integers
+    fn build_limits(
+        &mut self,
+        mut node: Node,
+        offset: Option<ast::Expression>,
+        limit: Option<ast::Expression>,
+    ) -> Result<Node> {
+        let mut count = |expr, clause| match self.build_constant(expr)? {
+            Value::Integer(i) if i >= 0 => Ok(i as u64),
+            value => Err(Error::Value(format!("Invalid {} {}", clause, value))),
+        };
+        if let Some(expr) = offset {
+            node = Node::Offset { source: Box::new(node), offset: count(expr, "offset")? };
+        }
+        if let Some(expr) = limit {
+            node = Node::Limit { source: Box::new(node), limit: count(expr, "limit")? };
+        }
+        Ok(node)
+    }
+
+    /// Builds the FROM clause. Multiple items are cross joined, and without items a single
+    /// empty row is emitted.
+    fn build_from(&mut self, from: Vec<ast::FromItem>) -> Result<(Node, Scope)> {
+        let mut items = from.into_iter();
+        let (mut node, mut scope) = match items.next() {
+            Some(item) => self.build_from_item(item)?,
+            None => return Ok((Node::Nothing, Scope::default())),
+        };
+        for item in items {
+            let (right, right_scope) = self.build_from_item(item)?;
+            let left_size = scope.columns.len();
+            scope.merge(right_scope)?;
+            node = Node::NestedLoopJoin {
+                left: Box::new(node),
+                left_size,
+                right: Box::new(right),
+                predicate: None,
+                r#type: JoinType::Inner,
+            };
+        }
+        Ok((node, scope))
+    }
+
+    /// Builds a FROM item
+    fn build_from_item(&mut self, item: ast::FromItem) -> Result<(Node, Scope)> {
+        Ok(match item {
+            ast::FromItem::Table { name, alias } => {
+                let table = alias.clone().unwrap_or_else(|| name.clone());
+                if let Some((_, columns)) = self.ctes.iter().rev().find(|(n, _)| n == &name) {
+                    let mut scope = Scope::default();
+                    scope.add_table(&table)?;
+                    for (column, datatype) in columns {
+                        let label = column.clone().map(|c| (Some(table.clone()), c));
+                        scope.add_column(label, Collation::default(), datatype.clone());
+                    }
+                    (Node::CteScan { name, alias }, scope)
+                } else {
+                    let schema = self.catalog.must_read_table(&name)?;
+                    let scope = Scope::from_table(&schema, &table)?;
+                    (Node::Scan { table: name, alias, filter: None }, scope)
+                }
+            }
+            ast::FromItem::Join { left, right, r#type, constraint } => {
+                self.build_join(*left, *right, r#type, constraint)?
+            }
+            // Derived tables are part of the query, not subqueries, so they can refer to the
+            // same enclosing queries as the query itself.
+            ast::FromItem::Subquery { query, alias } => {
+                let (node, scope) = self.build_query(*query)?;
+                let mut aliased = Scope::default();
+                aliased.add_table(&alias)?;
+                for ((label, collation), datatype) in scope.columns.into_iter().zip(scope.datatypes)
+                {
+                    let label = label.map(|(_, n)| (Some(alias.clone()), n));
+                    aliased.add_column(label, collation, datatype);
+                }
+                (node, aliased)
+            }
+            ast::FromItem::Function { name, args, alias } => {
+                let function = TableFunction::from_name(&name)?;
+                let args = args
+                    .into_iter()
+                    .map(|e| self.build_expression(&Scope::default(), e))
+                    .collect::<Result<_>>()?;
+                let table = alias.clone().unwrap_or_else(|| function.to_string());
+                let mut scope = Scope::default();
+                scope.add_table(&table)?;
+                for column in function.columns() {
+                    scope.add_column(
+                        Some((Some(table.clone()), column.to_string())),
+                        Collation::default(),
+                        None,
+                    );
+                }
+                (Node::TableFunction { function, args, alias }, scope)
+            }
+        })
+    }
+
+    /// Builds a join. USING and NATURAL joins output the join columns once, followed by the
+    /// remaining columns, and the join columns can be referred to via either table.
+    fn build_join(
+        &mut self,
+        left: ast::FromItem,
+        right: ast::FromItem,
+        r#type: ast::JoinType,
+        constraint: ast::JoinConstraint,
+    ) -> Result<(Node, Scope)> {
+        let (left, left_scope) = self.build_from_item(left)?;
+        let (right, right_scope) = self.build_from_item(right)?;
+        let r#type = match r#type {
+            ast::JoinType::Cross | ast::JoinType::Inner => JoinType::Inner,
+            ast::JoinType::Left => JoinType::Left,
+            ast::JoinType::Right => JoinType::Right,
+            ast::JoinType::Full => JoinType::Full,
+        };
+        let left_size = left_scope.columns.len();
+        let (left_labels, right_labels) = (left_scope.labels(), right_scope.labels());
+        let mut scope = left_scope;
+        scope.merge(right_scope)?;
+
+        let mut using = None;
+        let predicate = match constraint {
+            ast::JoinConstraint::None => None,
+            ast::JoinConstraint::On(expr) => Some(self.build_expression(&scope, expr)?),
+            ast::JoinConstraint::Using(columns) => {
+                let (predicate, projection) =
+                    r#type.build_using(&left_labels, &right_labels, &columns)?;
+                using = Some((columns, projection));
+                predicate
+            }
+            ast::JoinConstraint::Natural => {
+                let columns = JoinType::natural_columns(&left_labels, &right_labels);
+                let (predicate, projection) =
+                    r#type.build_using(&left_labels, &right_labels, &columns)?;
+                using = Some((columns, projection));
+                predicate
+            }
+        };
+
+        // Subqueries are resolved by the executor, so inner join predicates using them are
+        // applied by a filter instead. Outer joins can't be rewritten this way.
+        let (predicate, filter) = match predicate {
+            Some(predicate) if !predicate.is_resolved() => match r#type {
+                JoinType::Inner => (None, Some(predicate)),
+                _ => {
+                    return Err(Error::Value(
+                        "Can't use subqueries or sequences in outer join predicates".into(),
+                    ))
+                }
+            },
+            predicate => (predicate, None),
+        };
+        let mut node = Node::NestedLoopJoin {
+            left: Box::new(left),
+            left_size,
+            right: Box::new(right),
+            predicate,
+            r#type,
+        };
+        if let Some(predicate) = filter {
+            node = Node::Filter { source: Box::new(node), predicate };
+        }
+        if let Some((columns, projection)) = using {
+            let mut projected = scope.project(&projection);
+            for (table, name) in scope.qualified.keys() {
+                if let Some(i) = columns.iter().position(|c| c == name) {
+                    projected.qualified.insert((table.clone(), name.clone()), i);
+                }
+            }
+            node = Node::Projection { source: Box::new(node), expressions: projection };
+            scope = projected;
+        }
+        Ok((node, scope))
+    }
+
+    /// Builds an aggregation, returning its node and scope. The aggregate arguments and grouping
+    /// expressions are computed by a projection of the source rows, and the aggregation scope
+    /// substitutes the aggregate calls and grouping expressions with the aggregation's fields.
+    fn build_aggregation(
+        &mut self,
+        source: Node,
+        scope: Scope,
+        select: &[(ast::Expression, Option<String>)],
+        group_by: Vec<ast::GroupingElement>,
+        calls: Vec<ast::Expression>,
+    ) -> Result<(Node, Scope)> {
+        // Grouping expressions are deduplicated across grouping sets.
+        let mut groups: Vec<ast::Expression> = Vec::new();
+        let mut sets = Vec::new();
+        for set in ast::GroupingElement::expand(&group_by) {
+            let mut indexes = Vec::with_capacity(set.len());
+            for expr in set {
+                let expr = Self::resolve_group_label(&scope, select, expr)?;
+                let index = groups.iter().position(|g| g == &expr).unwrap_or_else(|| {
+                    groups.push(expr);
+                    groups.len() - 1
+                });
+                if !indexes.contains(&index) {
+                    indexes.push(index);
+                }
+            }
+            sets.push(indexes);
+        }
+        let grouping_sets = match sets.as_slice() {
+            [set] if set.len() == groups.len() => None,
+            _ => Some(sets),
+        };
+
+        let mut expressions = Vec::with_capacity(calls.len() + groups.len());
+        let mut aggregates = Vec::with_capacity(calls.len());
+        for call in &calls {
+            let (aggregate, arg) = self.build_aggregate(&scope, call.clone())?;
+            aggregates.push(aggregate);
+            expressions.push((arg, None));
+        }
+        let mut collations = Vec::with_capacity(groups.len());
+        for group in &groups {
+            let expr = self.build_expression(&scope, group.clone())?;
+            collations.push(expr.collation().unwrap_or_default());
+            expressions.push((expr, None));
+        }
+        let datatypes: Vec<_> =
+            expressions.iter().map(|(e, _)| e.static_datatype(&scope.datatypes)).collect();
+        let labels: Vec<Label> = expressions[calls.len()..]
+            .iter()
+            .map(|(e, _)| match e {
+                Expression::Field(_, label) => label.clone(),
+                Expression::Collate(e, _) => match &**e {
+                    Expression::Field(_, label) => label.clone(),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+
+        let mut aggregated = Scope { tables: scope.tables.clone(), ..Scope::default() };
+        let width = calls.len();
+        for (i, (call, aggregate)) in calls.into_iter().zip(&aggregates).enumerate() {
+            let datatype = aggregate.function.return_datatype(datatypes[i].clone());
+            aggregated.add_column(None, Collation::default(), datatype);
+            aggregated.substitutions.push((call, Expression::Field(i, None)));
+        }
+        for (i, ((group, label), collation)) in
+            groups.into_iter().zip(labels).zip(&collations).enumerate()
+        {
+            aggregated.add_column(label.clone(), *collation, datatypes[width + i].clone());
+            aggregated.substitutions.push((group, field(width + i, label, *collation)));
+        }
+        if grouping_sets.is_some() {
+            aggregated.add_column(None, Collation::default(), Some(DataType::Integer));
+        }
+        aggregated.aggregated = Some(Box::new(scope));
+
+        let node = Node::Aggregation {
+            source: Box::new(Node::Projection { source: Box::new(source), expressions }),
+            aggregates,
+            group_by: collations.len(),
+            grouping_sets,
+            collations,
+        };
+        Ok((node, aggregated))
+    }
+
+    /// Adds substitutions for grouping() calls to an aggregation scope. The arguments must be
+    /// grouping expressions. Without grouping sets, all grouping columns are always grouped.
+    fn add_groupings(&mut self, scope: &mut Scope, calls: Vec<ast::Expression>) -> Result<()> {
+        let group_by = scope.substitutions.iter().filter(|(e, _)| !Self::is_call(e)).count();
+        let aggregates = scope.substitutions.len() - group_by;
+        let grouping_sets = scope.columns.len() > aggregates + group_by;
+        for call in calls {
+            let args = match &call {
+                ast::Expression::Function { args, .. } if !args.is_empty() => args,
+                _ => return Err(Error::Value("grouping() takes at least 1 argument".into())),
+            };
+            let mut columns = Vec::with_capacity(args.len());
+            for arg in args {
+                let column = scope.substitutions[aggregates..]
+                    .iter()
+                    .position(|(e, _)| e == arg)
+                    .ok_or_else(|| {
+                    Error::Value("Arguments of grouping() must be GROUP BY expressions".into())
+                })?;
+                columns.push(column);
+            }
+            let expr = match grouping_sets {
+                true => grouping_expression(aggregates + group_by, group_by, &columns),
+                false => Expression::Constant(Value::Integer(0)),
+            };
+            scope.substitutions.push((call, expr));
+        }
+        Ok(())
+    }
+
+    /// Resolves a GROUP BY expression that refers to a select list column by label or
+    /// position, unless it's a column name in scope.
+    fn resolve_group_label(
+        scope: &Scope,
+        select: &[(ast::Expression, Option<String>)],
+        expr: ast::Expression,
+    ) -> Result<ast::Expression> {
+        match &expr {
+            ast::Expression::Field(None, name) if scope.resolve(None, name)?.is_none() => {
+                let mut labeled = select.iter().filter(|(_, l)| l.as_deref() == Some(name));
+                if let (Some((expr, _)), None) = (labeled.next(), labeled.next()) {
+                    return Ok(expr.clone());
+                }
+            }
+            ast::Expression::Literal(ast::Literal::Integer(i)) => {
+                return match select.get((*i as usize).wrapping_sub(1)) {
+                    Some((expr, _)) if *i > 0 => Ok(expr.clone()),
+                    _ => {
+                        Err(Error::Value(format!("GROUP BY position {} is not in select list", i)))
+                    }
+                };
+            }
+            _ => {}
+        }
+        Ok(expr)
+    }
+
+    /// Builds an aggregate function call, returning the aggregate and its argument expression.
+    /// Additional arguments are constant parameters, and count(*) counts a constant.
+    fn build_aggregate(
+        &mut self,
+        scope: &Scope,
+        call: ast::Expression,
+    ) -> Result<(Aggregate, Expression)> {
+        let (name, mut args, distinct) = match call {
+            ast::Expression::Function { name, args, distinct } => (name, args, distinct),
+            call => return Err(Error::Internal(format!("Invalid aggregate call {:?}", call))),
+        };
+        if args.is_empty() {
+            return Err(Error::Value(format!("Aggregate function {} takes an argument", name)));
+        }
+        let arg = args.remove(0);
+        let params =
+            args.into_iter().map(|e| self.build_constant(e)).collect::<Result<Vec<_>>>()?;
+        let function = AggregateFunction::lookup(&name, &params)?
+            .ok_or_else(|| Error::Value(format!("Unknown aggregate function {}", name)))?;
+        let arg = self.build_aggregate_arg(scope, &name, arg, distinct)?;
+        let collation = arg.collation().unwrap_or_default();
+        Ok((Aggregate { function, distinct, collation }, arg))
+    }
+
+    /// Builds the argument of an aggregate function, where * is only allowed for count(*).
+    fn build_aggregate_arg(
+        &mut self,
+        scope: &Scope,
+        name: &str,
+        arg: ast::Expression,
+        distinct: bool,
+    ) -> Result<Expression> {
+        match arg {
+            ast::Expression::All if name == "count" && !distinct => {
+                Ok(Expression::Constant(Value::Boolean(true)))
+            }
+            ast::Expression::All => Err(Error::Value(format!("Can't use * with {}", name))),
+            arg => self.build_expression(scope, arg),
+        }
+    }
+
+    /// Builds a window function call. Aggregate functions take their parameters as additional
+    /// arguments, like for build_aggregate().
+    fn build_window_function(
+        &mut self,
+        scope: &Scope,
+        call: ast::Expression,
+    ) -> Result<WindowFunction> {
+        let (name, mut args, over) = match call {
+            ast::Expression::WindowFunction { name, args, over } => (name, args, over),
+            call => return Err(Error::Internal(format!("Invalid window call {:?}", call))),
+        };
+        let (kind, args) = match WindowKind::from_name(&name) {
+            Some(kind) => (
+                kind,
+                args.into_iter().map(|e| self.build_expression(scope, e)).collect::<Result<_>>()?,
+            ),
+            None if Self::is_aggregate(&name) => {
+                if args.is_empty() {
+                    return Err(Error::Value(format!(
+                        "Window function {} takes an argument",
+                        name
+                    )));
+                }
+                let arg = args.remove(0);
+                let params =
+                    args.into_iter().map(|e| self.build_constant(e)).collect::<Result<Vec<_>>>()?;
+                let function = AggregateFunction::lookup(&name, &params)?
+                    .ok_or_else(|| Error::Value(format!("Unknown window function {}", name)))?;
+                let arg = self.build_aggregate_arg(scope, &name, arg, false)?;
+                (WindowKind::Aggregate(function), vec![arg])
+            }
+            None => return Err(Error::Value(format!("Unknown window function {}", name))),
+        };
+        let partition_by = over
+            .partition_by
+            .into_iter()
+            .map(|e| self.build_expression(scope, e))
+            .collect::<Result<_>>()?;
+        let mut order_by = Vec::with_capacity(over.order_by.len());
+        for (expr, direction) in over.order_by {
+            order_by.push((self.build_expression(scope, expr)?, Direction::from(direction)));
+        }
+        let frame = match over.frame {
+            Some(frame) => WindowFrame {
+                units: match frame.units {
+                    ast::FrameUnits::Rows => FrameUnits::Rows,
+                    ast::FrameUnits::Range => FrameUnits::Range,
+                },
+                start: self.build_frame_bound(frame.start)?,
+                end: self.build_frame_bound(frame.end)?,
+            },
+            None => WindowFrame::default(),
+        };
+        let function = WindowFunction { kind, args, partition_by, order_by, frame };
+        if !function.expressions().all(|e| e.is_resolved()) {
+            return Err(Error::Value(format!(
+                "Can't use subqueries or sequences in window function {}",
+                name
+            )));
+        }
+        Ok(function)
+    }
+
+    /// Builds a window frame bound, with a constant offset
+    fn build_frame_bound(&mut self, bound: ast::FrameBound) -> Result<FrameBound> {
+        Ok(match bound {
+            ast::FrameBound::UnboundedPreceding => FrameBound::UnboundedPreceding,
+            ast::FrameBound::Preceding(expr) => FrameBound::Preceding(self.build_constant(*expr)?),
+            ast::FrameBound::CurrentRow => FrameBound::CurrentRow,
+            ast::FrameBound::Following(expr) => FrameBound::Following(self.build_constant(*expr)?),
+            ast::FrameBound::UnboundedFollowing => FrameBound::UnboundedFollowing,
+        })
+    }
+
+    /// Builds and evaluates a constant expression
+    fn build_constant(&mut self, expr: ast::Expression) -> Result<Value> {
+        let expr = self.build_expression(&Scope::constant(), expr)?;
+        if !expr.is_resolved() {
+            return Err(Error::Value(format!("Expression {} must be constant", expr)));
+        }
+        expr.evaluate(None)
+    }
+
+    /// Collects the distinct calls matching a predicate from query expressions, e.g. aggregate
+    /// function calls. Calls can't be nested in matching calls, and calls in subqueries belong
+    /// to the subquery.
+    fn collect_calls<'e, F>(
+        exprs: impl Iterator<Item = &'e ast::Expression>,
+        matches: F,
+    ) -> Result<Vec<ast::Expression>>
+    where
+        F: Fn(&ast::Expression) -> bool,
+    {
+        let calls = RefCell::new(Vec::<ast::Expression>::new());
+        for expr in exprs {
+            expr.walk(&|e| {
+                if matches(e) && !calls.borrow().contains(e) {
+                    calls.borrow_mut().push(e.clone());
+                }
+                true
+            });
+        }
+        let calls = calls.into_inner();
+        for call in &calls {
+            let nested = match call {
+                ast::Expression::Function { args, .. } => args.iter().any(|a| a.contains(&matches)),
+                ast::Expression::WindowFunction { args, over, .. } => args
+                    .iter()
+                    .chain(&over.partition_by)
+                    .chain(over.order_by.iter().map(|(e, _)| e))
+                    .any(|a| a.contains(&matches)),
+                _ => false,
+            };
+            if nested {
+                return Err(Error::Value(format!("Can't nest calls in {:?}", call)));
+            }
+        }
+        Ok(calls)
+    }
+
+    /// Returns true if the name is an aggregate function. Scalar functions take precedence.
+    fn is_aggregate(name: &str) -> bool {
+        name != "coalesce"
+            && !matches!(Function::from_name(name), Ok(Some(_)))
+            && !matches!(AggregateFunction::lookup(name, &[]), Ok(None))
+    }
+
+    /// Returns true if the expression is an aggregate or grouping() call
+    fn is_call(expr: &ast::Expression) -> bool {
+        matches!(expr, ast::Expression::Function { name, .. }
+            if name == "grouping" || Self::is_aggregate(name))
+    }
+
+    /// Builds a subquery, returning its index. The subquery is built with the current scope
+    /// as its innermost enclosing scope. IN and scalar subqueries must return a single column.
+    fn build_subquery(
+        &mut self,
+        scope: &Scope,
+        query: ast::Statement,
+        single_column: bool,
+    ) -> Result<usize> {
+        self.outer.push(scope.clone());
+        let result = self.build_query(query);
+        self.outer.pop();
+        let (node, scope) = result?;
+        if single_column && scope.columns.len() != 1 {
+            return Err(Error::Value(format!(
+                "Subquery must return a single column, got {}",
+                scope.columns.len()
+            )));
+        }
+        self.subqueries.push(node);
+        Ok(self.subqueries.len() - 1)
+    }
+
+    /// Resolves a column name in the scope, or else in the enclosing scopes as an outer field
+    fn build_field(&self, scope: &Scope, table: Option<&str>, name: &str) -> Result<Expression> {
+        if let Some((i, label, collation)) = scope.resolve(table, name)? {
+            return Ok(field(i, label, collation));
+        }
+        for (depth, outer) in self.outer.iter().rev().enumerate() {
+            if let Some((i, label, collation)) = outer.resolve(table, name)? {
+                let field = Expression::OuterField(depth, i, label);
+                return Ok(match collation {
+                    Collation::Binary => field,
+                    collation => Expression::Collate(Box::new(field), collation),
+                });
+            }
+        }
+        Err(Error::Value(match table {
+            Some(table) => format!("Unknown field {}.{}", table, name),
+            None => format!("Unknown field {}", name),
+        }))
+    }
+
+    /// Builds an expression from an AST expression. Expressions computed by earlier plan nodes,
+    /// e.g. aggregates, are substituted by their fields.
+    fn build_expression(&mut self, scope: &Scope, expr: ast::Expression) -> Result<Expression> {
+        use Expression::*;
+        if let Some((_, field)) = scope.substitutions.iter().find(|(e, _)| e == &expr) {
+            return Ok(field.clone());
+        }
+        let mut build = |expr: Box<ast::Expression>| -> Result<Box<Expression>> {
+            Ok(Box::new(self.build_expression(scope, *expr)?))
+        };
+        Ok(match expr {
+            ast::Expression::Literal(literal) => Constant(match literal {
+                ast::Literal::Null => Value::Null,
+                ast::Literal::Boolean(b) => Value::Boolean(b),
+                ast::Literal::Integer(i) => Value::Integer(i),
+                ast::Literal::Float(f) => Value::Float(f),
+                ast::Literal::String(s) => Value::String(s),
+                ast::Literal::Binary(b) => Value::Binary(b),
+            }),
+            ast::Expression::Field(table, name) => {
+                self.build_field(scope, table.as_deref(), &name)?
+            }
+            ast::Expression::Column(i) => {
+                let (label, collation) = scope
+                    .columns
+                    .get(i)
+                    .cloned()
+                    .ok_or_else(|| Error::Internal(format!("Unknown column #{}", i)))?;
+                field(i, label, collation)
+            }
+            ast::Expression::All => return Err(Error::Value("Can't use * here".into())),
+            ast::Expression::Function { name, args, distinct } => {
+                self.build_function(scope, name, args, distinct)?
+            }
+            ast::Expression::WindowFunction { name, .. } => {
+                return Err(Error::Value(format!("Can't use window function {} here", name)))
+            }
+            ast::Expression::Case { operand, branches, r#else } => {
+                let operand = operand.map(&mut build).transpose()?;
+                let r#else = r#else.map(&mut build).transpose()?;
+                let branches = branches
+                    .into_iter()
+                    .map(|(w, t)| {
+                        Ok((self.build_expression(scope, w)?, self.build_expression(scope, t)?))
+                    })
+                    .collect::<Result<_>>()?;
+                Case { operand, branches, r#else }
+            }
+            ast::Expression::Cast(expr, datatype) => Cast(build(expr)?, datatype),
+            ast::Expression::Collate(expr, collation) => Collate(build(expr)?, collation),
+            ast::Expression::Operation(op) => self.build_operation(scope, op)?,
+            ast::Expression::Subquery(query) => Subquery(self.build_subquery(scope, *query, true)?),
+            ast::Expression::Exists(query) => Exists(self.build_subquery(scope, *query, false)?),
+        })
+    }
+
+    /// Builds a function call. Sequence functions take the sequence name as a string literal.
+    fn build_function(
+        &mut self,
+        scope: &Scope,
+        name: String,
+        args: Vec<ast::Expression>,
+        distinct: bool,
+    ) -> Result<Expression> {
+        if name == "nextval" || name == "currval" {
+            let sequence = match args.as_slice() {
+                [ast::Expression::Literal(ast::Literal::String(sequence))] => sequence.clone(),
+                _ => return Err(Error::Value(format!("{} takes a sequence name", name))),
+            };
+            self.catalog.must_read_sequence(&sequence)?;
+            return Ok(match name.as_str() {
+                "nextval" => Expression::NextVal(sequence),
+                _ => Expression::CurrVal(sequence),
+            });
+        }
+        if name == "grouping" {
+            return Err(Error::Value("grouping() requires GROUP BY".into()));
+        }
+        if Self::is_aggregate(&name) {
+            return Err(Error::Value(format!("Can't use aggregate function {} here", name)));
+        }
+        if distinct {
+            return Err(Error::Value(format!("Can't use DISTINCT with function {}", name)));
+        }
+        let args =
+            args.into_iter().map(|e| self.build_expression(scope, e)).collect::<Result<_>>()?;
+        Function::build(&name, args)?
+            .ok_or_else(|| Error::Value(format!("Unknown function {}", name)))
+    }
+
+    /// Builds an operation. NEQ is built as a composite operation.
+    fn build_operation(&mut self, scope: &Scope, op: ast::Operation) -> Result<Expression> {
+        use ast::Operation as Op;
+        use Expression::*;
+        let mut build = |expr: Box<ast::Expression>| -> Result<Box<Expression>> {
+            Ok(Box::new(self.build_expression(scope, *expr)?))
+        };
+        Ok(match op {
+            // Logical operators
+            Op::And(lhs, rhs) => And(build(lhs)?, build(rhs)?),
+            Op::Not(expr) => Not(build(expr)?),
+            Op::Or(lhs, rhs) => Or(build(lhs)?, build(rhs)?),
+
+            // Comparison operators
+            Op::Between(expr, low, high) => Between(build(expr)?, build(low)?, build(high)?),
+            Op::Equal(lhs, rhs) => Equal(build(lhs)?, build(rhs)?),
+            Op::GreaterThan(lhs, rhs) => GreaterThan(build(lhs)?, build(rhs)?),
+            Op::GreaterThanOrEqual(lhs, rhs) => GreaterThanOrEqual(build(lhs)?, build(rhs)?),
+            Op::InList(expr, list) => InList(
+                build(expr)?,
+                list.into_iter().map(|e| self.build_expression(scope, e)).collect::<Result<_>>()?,
+            ),
+            Op::IsNull(expr) => IsNull(build(expr)?),
+            Op::LessThan(lhs, rhs) => LessThan(build(lhs)?, build(rhs)?),
+            Op::LessThanOrEqual(lhs, rhs) => LessThanOrEqual(build(lhs)?, build(rhs)?),
+            Op::NotEqual(lhs, rhs) => Not(Equal(build(lhs)?, build(rhs)?).into()),
+
+            // Mathematical operators
+            Op::Add(lhs, rhs) => Add(build(lhs)?, build(rhs)?),
+            Op::Assert(expr) => Assert(build(expr)?),
+            Op::Divide(lhs, rhs) => Divide(build(lhs)?, build(rhs)?),
+            Op::Exponentiate(lhs, rhs) => Exponentiate(build(lhs)?, build(rhs)?),
+            Op::Factorial(expr) => Factorial(build(expr)?),
+            Op::Modulo(lhs, rhs) => Modulo(build(lhs)?, build(rhs)?),
+            Op::Multiply(lhs, rhs) => Multiply(build(lhs)?, build(rhs)?),
+            Op::Negate(expr) => Negate(build(expr)?),
+            Op::Subtract(lhs, rhs) => Subtract(build(lhs)?, build(rhs)?),
+
+            // String operators
+            Op::ILike(lhs, rhs) => ILike(build(lhs)?, build(rhs)?),
+            Op::IRegexMatch(lhs, rhs) => IRegexMatch(build(lhs)?, build(rhs)?),
+            Op::Like(lhs, rhs) => Like(build(lhs)?, build(rhs)?),
+            Op::RegexMatch(lhs, rhs) => RegexMatch(build(lhs)?, build(rhs)?),
+            Op::SimilarTo(lhs, rhs) => SimilarTo(build(lhs)?, build(rhs)?),
+
+            // JSON operators
+            Op::JsonGet(lhs, rhs) => JsonGet(build(lhs)?, build(rhs)?),
+            Op::JsonGetText(lhs, rhs) => JsonGetText(build(lhs)?, build(rhs)?),
+
+            // Subquery operators
+            Op::InSubquery(expr, query) => {
+                InSubquery(build(expr)?, self.build_subquery(scope, *query, true)?)
+            }
+        })
+    }
+}
+
+/// The clauses of a SELECT query, other than WITH, OFFSET and LIMIT
+struct Select {
+    distinct: Option<ast::Distinct>,
+    select: Vec<(ast::Expression, Option<String>)>,
+    from: Vec<ast::FromItem>,
+    r#where: Option<ast::Expression>,
+    group_by: Vec<ast::GroupingElement>,
+    having: Option<ast::Expression>,
+    order: Vec<(ast::Expression, ast::Order)>,
+}
+
+/// A scope for resolving names in expressions, i.e. the columns of the rows that the
+/// expressions are evaluated for
+#[derive(Clone, Debug, Default)]
+struct Scope {
+    /// If true, expressions must be constant, e.g. for INSERT values
+    constant: bool,
+    /// The table names (or aliases) in scope
+    tables: HashSet<String>,
+    /// The column labels and collations, by field index
+    columns: Vec<(Label, Collation)>,
+    /// The column datatypes, where known during planning, by field index
+    datatypes: Vec<Option<DataType>>,
+    /// Qualified column names, by table and column name
+    qualified: HashMap<(String, String), usize>,
+    /// Unqualified column names
+    unqualified: HashMap<String, usize>,
+    /// Unqualified column names that are ambiguous, i.e. used by several columns
+    ambiguous: HashSet<String>,
+    /// Expressions computed by earlier plan nodes, e.g. aggregates, and their fields
+    substitutions: Vec<(ast::Expression, Expression)>,
+    /// For aggregated rows, the scope of the source rows, to reject ungrouped columns
+    aggregated: Option<Box<Scope>>,
+}
+
+impl Scope {
+    /// Creates a scope for constant expressions
+    fn constant() -> Self {
+        Self { constant: true, ..Self::default() }
+    }
+
+    /// Creates a scope for a table's columns, qualified by the given table name or alias
+    fn from_table(table: &Table, name: &str) -> Result<Self> {
+        let mut scope = Self::default();
+        scope.add_table(name)?;
+        for column in &table.columns {
+            let label = Some((Some(name.to_string()), column.name.clone()));
+            scope.add_column(label, column.collation, Some(column.datatype.clone()));
+        }
+        Ok(scope)
+    }
+
+    /// Adds a table name to the scope
+    fn add_table(&mut self, name: &str) -> Result<()> {
+        if !self.tables.insert(name.to_string()) {
+            return Err(Error::Value(format!("Duplicate table name {}", name)));
+        }
+        Ok(())
+    }
+
+    /// Adds a column to the scope
+    fn add_column(&mut self, label: Label, collation: Collation, datatype: Option<DataType>) {
+        let index = self.columns.len();
+        if let Some((table, name)) = &label {
+            if let Some(table) = table {
+                self.qualified.insert((table.clone(), name.clone()), index);
+            }
+            if self.unqualified.contains_key(name) {
+                self.ambiguous.insert(name.clone());
+            } else {
+                self.unqualified.insert(name.clone(), index);
+            }
+        }
+        self.columns.push((label, collation));
+        self.datatypes.push(datatype);
+    }
+
+    /// Merges another scope into this one, appending its columns, e.g. for joins
+    fn merge(&mut self, scope: Scope) -> Result<()> {
+        for table in &scope.tables {
+            self.add_table(table)?;
+        }
+        let offset = self.columns.len();
+        for ((label, collation), datatype) in scope.columns.into_iter().zip(scope.datatypes) {
+            self.add_column(label, collation, datatype);
+        }
+        for (name, index) in scope.qualified {
+            self.qualified.insert(name, offset + index);
+        }
+        self.ambiguous.extend(scope.ambiguous);
+        Ok(())
+    }
+
+    /// Returns the column labels
+    fn labels(&self) -> Vec<Label> {
+        self.columns.iter().map(|(label, _)| label.clone()).collect()
+    }
+
+    /// Resolves a column name to its field index, label and collation. Returns None if the
+    /// column isn't in scope, such that it can be resolved in an enclosing scope.
+    fn resolve(
+        &self,
+        table: Option<&str>,
+        name: &str,
+    ) -> Result<Option<(usize, Label, Collation)>> {
+        let field = match table {
+            Some(table) => format!("{}.{}", table, name),
+            None => name.to_string(),
+        };
+        if self.constant {
+            return Err(Error::Value(format!(
+                "Expression must be constant, found field {}",
+                field
+            )));
+        }
+        let index = match table {
+            Some(table) => self.qualified.get(&(table.to_string(), name.to_string())).copied(),
+            None if self.ambiguous.contains(name) => {
+                return Err(Error::Value(format!("Ambiguous field {}", field)))
+            }
+            None => self.unqualified.get(name).copied(),
+        };
+        let index = match index {
+            Some(index) => index,
+            None => {
+                if let Some(source) = &self.aggregated {
+                    if source.resolve(table, name)?.is_some() {
+                        return Err(Error::Value(format!(
+                            "Field {} must be used in an aggregate function or GROUP BY",
+                            field
+                        )));
+                    }
+                }
+                if table.is_some_and(|t| self.tables.contains(t)) {
+                    return Err(Error::Value(format!("Unknown field {}", field)));
+                }
+                return Ok(None);
+            }
+        };
+        let (label, collation) = self.columns[index].clone();
+        Ok(Some((index, label, collation)))
+    }
+
+    /// Returns a field for a 1-based column position, e.g. in ORDER BY
+    fn position(&self, position: i64, clause: &str) -> Result<Expression> {
+        match self.columns.get((position as usize).wrapping_sub(1)) {
+            Some((label, collation)) if position > 0 => {
+                Ok(field(position as usize - 1, label.clone(), *collation))
+            }
+            _ => {
+                Err(Error::Value(format!("{} position {} is not in select list", clause, position)))
+            }
+        }
+    }
+
+    /// Creates a scope for the output of a projection. Columns are named by their label or
+    /// the field they project, and projected fields can still be referred to by their
+    /// qualified names.
+    fn project(&self, expressions: &[(Expression, Option<String>)]) -> Self {
+        let mut scope = Self { tables: self.tables.clone(), ..Self::default() };
+        for (i, (expr, label)) in expressions.iter().enumerate() {
+            let fields = Self::fields(expr);
+            let label = match label {
+                Some(label) => Some((None, label.clone())),
+                None => match fields.as_slice() {
+                    [field] => self.columns.get(*field).and_then(|(l, _)| l.clone()),
+                    _ => None,
+                },
+            };
+            let datatype = expr.static_datatype(&self.datatypes);
+            scope.add_column(label, expr.collation().unwrap_or_default(), datatype);
+            for (name, index) in &self.qualified {
+                if fields.contains(index) {
+                    scope.qualified.insert(name.clone(), i);
+                }
+            }
+        }
+        scope
+    }
+
+    /// Returns the fields passed through by a projection expression
+    fn fields(expr: &Expression) -> Vec<usize> {
+        match expr {
+            Expression::Field(i, _) => vec![*i],
+            Expression::Collate(expr, _) => Self::fields(expr),
+            Expression::Coalesce(exprs) => exprs.iter().flat_map(Self::fields).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Builds a field reference, wrapping it in its collation if non-default
+fn field(index: usize, label: Label, collation: Collation) -> Expression {
+    let field = Expression::Field(index, label);
+    match collation {
+        Collation::Binary => field,
+        collation => Expression::Collate(Box::new(field), collation),
     }
 }
@@ -1,6 +1,6 @@
 use super::engine::Transaction;
 use super::parser::format_ident;
-use super::types::{DataType, Value};
+use super::types::{json, Collation, DataType, Decimal, Expression, RoundingMode, Row, Value};
 use crate::error::{Error, Result};
 
 use serde_derive::{Deserialize, Serialize};
@@ -23,52 +23,123 @@ pub trait Catalog {
 
     fn detele_table(&mut self, table: &str) -> Result<()>;
 
-    fn read_table(&mut self, table: &str) -> Result<Option<Table>>;
+    fn read_table(&self, table: &str) -> Result<Option<Table>>;
 
     /// Iterates over all tables
     fn scan_tables(&self) -> Result<Tables>;
 
+    /// Alters a table, see Table::alter(). Existing rows are rewritten and validated against the
+    /// new schema, indexes are updated for renamed columns and tables, and references from
+    /// other tables are updated for renamed tables.
+    fn alter_table(&mut self, table: &str, alteration: Alteration) -> Result<()>;
+
+    /// Creates a new index, and backfills it with the table's existing rows. Errors if the index
+    /// is unique and the existing rows contain duplicate values.
+    fn create_index(&mut self, index: Index) -> Result<()>;
+
+    /// Deletes an index, along with all of its entries
+    fn delete_index(&mut self, name: &str) -> Result<()>;
+
+    /// Iterates over all indexes created with CREATE INDEX
+    fn scan_indexes(&self) -> Result<Indexes>;
+
+    /// Creates a new sequence
+    fn create_sequence(&mut self, sequence: Sequence) -> Result<()>;
+
+    /// Deletes a sequence, along with its current value
+    fn delete_sequence(&mut self, name: &str) -> Result<()>;
+
+    /// Reads a sequence, if it exists
+    fn read_sequence(&self, name: &str) -> Result<Option<Sequence>>;
+
+    /// Reads a sequence, and errors if it does not exist
+    fn must_read_sequence(&self, name: &str) -> Result<Sequence> {
+        self.read_sequence(name)?
+            .ok_or_else(|| Error::Value(format!("Sequence {} does not exist", name)))
+    }
+
+    /// Reads an index, if it exists, including implicit column indexes
+    fn get_index(&self, name: &str) -> Result<Option<Index>> {
+        if let Some(index) = self.scan_indexes()?.find(|i| i.name == name) {
+            return Ok(Some(index));
+        }
+        Ok(self.scan_tables()?.flat_map(|t| t.implicit_indexes()).find(|i| i.name == name))
+    }
+
+    /// Reads an index, and errors if it does not exist
+    fn must_get_index(&self, name: &str) -> Result<Index> {
+        self.get_index(name)?.ok_or_else(|| Error::Value(format!("Index {} does not exist", name)))
+    }
+
+    /// Returns all indexes of a table: the implicit column indexes, see Table::implicit_indexes(),
+    /// followed by indexes created with CREATE INDEX. The primary key is unique by definition and
+    /// not indexed separately.
+    fn table_indexes(&self, table: &Table) -> Result<Vec<Index>> {
+        Ok(table
+            .implicit_indexes()
+            .into_iter()
+            .chain(self.scan_indexes()?.filter(|i| i.table == table.name))
+            .collect())
+    }
+
     /// Reads a table, and errors if it does not exist
     fn must_read_table(&self, table: &str) -> Result<Table> {
         self.read_table(table)?
             .ok_or_else(|| Error::Value(format!("Table {} does not exist", table)))
     }
 
-    /// Returns all references to a table, as table,column pairs.
-    fn table_references(&self, table: &str, with_self: bool) -> Result<Vec<(String, Vec<String>)>> {
+    /// Returns all references to a table, as the referencing tables and their foreign keys.
+    /// Multi-column foreign keys are returned as a single foreign key spanning several columns.
+    fn table_references(
+        &self,
+        table: &str,
+        with_self: bool,
+    ) -> Result<Vec<(String, Vec<ForeignKey>)>> {
         Ok(self
             .scan_tables()?
             .filter(|t| with_self || t.name != table)
             .map(|t| {
-                (
-                    t.name,
-                    t.columns
-                        .iter()
-                        .filter(|c| c.references.as_deref() == Some(table))
-                        .map(|c| c.name.clone())
-                        .collect::<Vec<_>>(),
-                )
+                let references = t
+                    .get_foreign_keys()
+                    .into_iter()
+                    .filter(|fk| fk.references == table)
+                    .collect::<Vec<_>>();
+                (t.name, references)
             })
-            .filter(|(_, cs)| !cs.is_empty())
+            .filter(|(_, fks)| !fks.is_empty())
             .collect())
     }
 }
 
 /// A table scan iterator
 pub type Tables = Box<dyn DoubleEndedIterator<Item = Table> + Send>;
+/// An index scan iterator
+pub type Indexes = Box<dyn DoubleEndedIterator<Item = Index> + Send>;
 /// A table schema
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Table {
     pub name: String,
     pub columns: Vec<Column>,
+    /// The primary key columns in key order, as given by a table-level PRIMARY KEY. If empty,
+    /// the primary key is given by Column::primary_key, in column order.
+    #[serde(default)]
+    pub primary_key: Vec<String>,
+    /// Table-level foreign keys, which may span multiple columns. Single-column foreign keys
+    /// are given inline via Column::references instead.
+    pub foreign_keys: Vec<ForeignKey>,
+    /// CHECK constraints, both table-level and inline column constraints
+    pub checks: Vec<Check>,
 }
 
 impl Table {
     /// Creates a new table schema
     pub fn new(name: String, columns: Vec<Column>) -> Result<Self> {
         Ok(Self {
-            name: name,
-            columns: columns,
+            name,
+            columns,
+            primary_key: Vec::new(),
+            foreign_keys: Vec::new(),
+            checks: Vec::new(),
         })
     }
 
@@ -84,35 +155,224 @@ impl Table {
         })
     }
 
+    /// Fetches a mutable column by name
+    fn get_column_mut(&mut self, name: &str) -> Result<&mut Column> {
+        let table = &self.name;
+        self.columns
+            .iter_mut()
+            .find(|c| c.name == name)
+            .ok_or_else(|| Error::Value(format!("Column {} not found in table {}", name, table)))
+    }
+
     /// Fetches a column index by name
     /// 第几个位置
     pub fn get_column_index(&self, name: &str) -> Result<usize> {
         self.columns
             .iter()
-            .position(|c| c.name = name.to_owned())
+            .position(|c| c.name == name)
             .ok_or_else(|| {
                 Error::Value(format!("Column {} not found in table {}", name, self.name))
             })
     }
 
-    /// Returns the primary key column of the table
-    pub fn get_primary_key(&self) -> Result<&Column> {
+    /// Returns the primary key columns of the table, in key order. A composite primary key
+    /// has more than one column.
+    pub fn get_primary_key(&self) -> Result<Vec<&Column>> {
+        let pk = match self.primary_key.is_empty() {
+            true => self.columns.iter().filter(|c| c.primary_key).collect::<Vec<_>>(),
+            false => self.primary_key.iter().map(|n| self.get_column(n)).collect::<Result<_>>()?,
+        };
+        if pk.is_empty() {
+            return Err(Error::Value(format!("Primary key not found in table {}", self.name)));
+        }
+        Ok(pk)
+    }
+
+    /// Returns the primary key value of a row, as a tuple of the primary key column values in
+    /// key order
+    pub fn get_row_key(&self, row: &[Value]) -> Result<Vec<Value>> {
+        self.get_primary_key()?
+            .into_iter()
+            .map(|c| {
+                row.get(self.get_column_index(&c.name)?)
+                    .cloned()
+                    .ok_or_else(|| Error::Value("Primary key value not found for row".into()))
+            })
+            .collect()
+    }
+
+    /// Returns all foreign keys of the table, both inline column references and table-level
+    /// foreign keys.
+    pub fn get_foreign_keys(&self) -> Vec<ForeignKey> {
         self.columns
             .iter()
-            .find(|c: &&Column| c.primary_primary)
-            .ok_or_else(|| Error::Value(format!("Primary key not found in table {}", self.name)))
+            .filter_map(|c| {
+                c.references.as_ref().map(|r| ForeignKey {
+                    columns: vec![c.name.clone()],
+                    references: r.clone(),
+                    on_delete: c.on_delete.clone(),
+                    on_update: c.on_update.clone(),
+                })
+            })
+            .chain(self.foreign_keys.iter().cloned())
+            .collect()
     }
 
-    /// Returns the primary key value of a row
-    pub fn get_row_key(&self, row: &[Value]) -> Result<Value> {
-        row.get(
-            self.columns
-                .iter()
-                .position(|c| c.primary_key)
-                .ok_or_else(|| Error::Value("Primary key not found".into()))?,
-        )
-        .cloned()
-        .ok_or_else(|| Error::Value("Primary key value not found for row".into()))
+    /// Returns the implicit single-column indexes of columns declared with INDEX or UNIQUE. These
+    /// are named by Index::implicit_name(), and are created and deleted along with the columns.
+    pub fn implicit_indexes(&self) -> Vec<Index> {
+        self.columns
+            .iter()
+            .filter(|c| c.index || (c.unique && !c.primary_key))
+            .map(|c| Index {
+                name: Index::implicit_name(&self.name, &c.name),
+                table: self.name.clone(),
+                columns: vec![c.name.clone()],
+                expressions: Vec::new(),
+                unique: c.unique,
+            })
+            .collect()
+    }
+
+    /// Returns the implicit sequences of the table's auto-incrementing columns, i.e. columns
+    /// defaulting to nextval() of their column sequence. These are created and deleted along
+    /// with the table.
+    pub fn get_column_sequences(&self) -> Vec<Sequence> {
+        self.columns
+            .iter()
+            .map(|c| Sequence::new(&Sequence::column_sequence_name(&self.name, &c.name)))
+            .zip(&self.columns)
+            .filter(|(s, c)| c.default == Some(Expression::NextVal(s.name.clone())))
+            .map(|(s, _)| s)
+            .collect()
+    }
+
+    /// Returns a new table schema with the alteration applied, after validating it. Existing rows
+    /// must be rewritten with Alteration::transform_row() and validated against the new schema,
+    /// and for renamed tables the references in other tables fixed with rename_references().
+    pub fn alter(&self, alteration: &Alteration, txn: &mut dyn Transaction) -> Result<Table> {
+        let mut table = self.clone();
+        match alteration {
+            Alteration::AddColumn(column) => {
+                if table.get_column(&column.name).is_ok() {
+                    return Err(Error::Value(format!(
+                        "Column {} already exists in table {}",
+                        column.name, table.name
+                    )));
+                }
+                if column.primary_key {
+                    return Err(Error::Value(format!(
+                        "Can't add primary key column {} to table {}",
+                        column.name, table.name
+                    )));
+                }
+                table.columns.push(column.clone());
+            }
+            Alteration::DropColumn(name) => {
+                if table.get_column(name)?.primary_key {
+                    return Err(Error::Value(format!("Can't drop primary key column {}", name)));
+                }
+                if let Some(fk) = table.foreign_keys.iter().find(|fk| fk.columns.contains(name)) {
+                    return Err(Error::Value(format!(
+                        "Can't drop column {}, it is part of foreign key ({})",
+                        name,
+                        fk.columns.join(", ")
+                    )));
+                }
+                let index = table.get_column_index(name)?;
+                if let Some(i) = txn.scan_indexes()?.find(|i| {
+                    i.table == table.name
+                        && (i
+                            .columns
+                            .iter()
+                            .enumerate()
+                            .any(|(k, c)| i.expression(k).is_none() && c == name)
+                            || i.expressions.iter().flatten().any(|e| {
+                                e.contains(&|e| matches!(e, Expression::Field(f, _) if *f == index))
+                            }))
+                }) {
+                    return Err(Error::Value(format!(
+                        "Can't drop column {}, it is used by index {}",
+                        name, i.name
+                    )));
+                }
+                if let Some(check) = table.checks.iter().find(|c| c.references_column(index)) {
+                    return Err(Error::Value(format!(
+                        "Can't drop column {}, it is used by check constraint {}",
+                        name, check.name
+                    )));
+                }
+                for check in table.checks.iter_mut() {
+                    check.drop_column(index)?;
+                }
+                table.columns.retain(|c| &c.name != name);
+            }
+            Alteration::RenameColumn { from, to } => {
+                if table.get_column(to).is_ok() {
+                    return Err(Error::Value(format!(
+                        "Column {} already exists in table {}",
+                        to, table.name
+                    )));
+                }
+                table.get_column_mut(from)?.name = to.clone();
+                for column in table.primary_key.iter_mut().filter(|c| *c == from) {
+                    *column = to.clone();
+                }
+                for check in table.checks.iter_mut() {
+                    check.rename_column(from, to)?;
+                }
+                for column in table.foreign_keys.iter_mut().flat_map(|fk| fk.columns.iter_mut()) {
+                    if column == from {
+                        *column = to.clone();
+                    }
+                }
+            }
+            Alteration::RenameTable(name) => {
+                if txn.read_table(name)?.is_some() {
+                    return Err(Error::Value(format!("Table {} already exists", name)));
+                }
+                table.rename_references(&self.name, name);
+                table.name = name.clone();
+            }
+            Alteration::SetDefault { column, default } => {
+                let column = table.get_column_mut(column)?;
+                // Nullable columns always have a default, which is NULL unless given.
+                column.default = match default {
+                    Some(default) => Some(default.clone()),
+                    None if column.nullable => Some(Expression::Constant(Value::Null)),
+                    None => None,
+                };
+            }
+            Alteration::SetNullable { column, nullable } => {
+                let column = table.get_column_mut(column)?;
+                column.nullable = *nullable;
+                match &column.default {
+                    None if column.nullable => {
+                        column.default = Some(Expression::Constant(Value::Null))
+                    }
+                    Some(Expression::Constant(Value::Null)) if !column.nullable => {
+                        column.default = None
+                    }
+                    _ => {}
+                }
+            }
+        }
+        table.validate(txn)?;
+        Ok(table)
+    }
+
+    /// Updates references to a renamed table, both inline column references and foreign keys
+    pub fn rename_references(&mut self, from: &str, to: &str) {
+        for column in self.columns.iter_mut() {
+            if column.references.as_deref() == Some(from) {
+                column.references = Some(to.to_string());
+            }
+        }
+        for foreign_key in self.foreign_keys.iter_mut() {
+            if foreign_key.references == from {
+                foreign_key.references = to.to_string();
+            }
+        }
     }
 
     /// Validates the table schema
@@ -120,27 +380,72 @@ impl Table {
         if self.columns.is_empty() {
             return Err(Error::Value(format!("Table {} has no columns", self.name)));
         }
-        match self.columns.iter().filter(|c| c.primary_key).count() {
-            1 => {}
-            0 => {
-                return Err(Error::Value(format!(
-                    "No primary key in table {}",
-                    self.name
-                )))
+        if !self.columns.iter().any(|c| c.primary_key) {
+            return Err(Error::Value(format!("No primary key in table {}", self.name)));
+        }
+        if !self.primary_key.is_empty() {
+            for (i, name) in self.primary_key.iter().enumerate() {
+                if self.primary_key[..i].contains(name) || !self.get_column(name)?.primary_key {
+                    return Err(Error::Value(format!(
+                        "Invalid primary key column {} in table {}",
+                        name, self.name
+                    )));
+                }
             }
-            _ => {
+            if let Some(column) =
+                self.columns.iter().find(|c| c.primary_key && !self.primary_key.contains(&c.name))
+            {
                 return Err(Error::Value(format!(
-                    "Multiple primary keys in table {}",
-                    self.name
-                )))
+                    "Column {} is missing from the primary key of table {}",
+                    column.name, self.name
+                )));
             }
-        };
+        } else if self.columns.iter().filter(|c| c.primary_key).count() > 1 {
+            return Err(Error::Value(format!("Multiple primary keys in table {}", self.name)));
+        }
         for column in &self.columns {
             column.validate(self, txn)?;
         }
+        for foreign_key in &self.foreign_keys {
+            foreign_key.validate(self, txn)?;
+        }
+        for foreign_key in self.get_foreign_keys() {
+            foreign_key.validate_actions(self)?;
+        }
+        for index in self.implicit_indexes() {
+            let clash = txn.scan_indexes()?.find(|i| i.name == index.name).or(txn
+                .scan_tables()?
+                .filter(|t| t.name != self.name)
+                .flat_map(|t| t.implicit_indexes())
+                .find(|i| i.name == index.name));
+            if let Some(clash) = clash {
+                return Err(Error::Value(format!(
+                    "Index {} for column {} already exists on table {}",
+                    index.name, index.columns[0], clash.table
+                )));
+            }
+        }
+        for (i, check) in self.checks.iter().enumerate() {
+            if self.checks[..i].iter().any(|c| c.name == check.name) {
+                return Err(Error::Value(format!(
+                    "Duplicate check constraint {} in table {}",
+                    check.name, self.name
+                )));
+            }
+            check.validate(self)?;
+        }
         Ok(())
     }
 
+    /// Coerces a row to the column datatypes, see Column::coerce_value(). This is done before
+    /// validating and writing rows.
+    pub fn coerce_row(&self, row: Row) -> Result<Row> {
+        if row.len() != self.columns.len() {
+            return Err(Error::Value(format!("Invalid row size for table {}", self.name)));
+        }
+        self.columns.iter().zip(row).map(|(column, value)| column.coerce_value(value)).collect()
+    }
+
     /// Validates a row
     pub fn validate_row(&self, row: &[Value], txn: &mut dyn Transaction) -> Result<()> {
         if row.len() != self.columns.len() {
@@ -153,22 +458,433 @@ impl Table {
         for (column, value) in self.columns.iter().zip(row.iter()) {
             column.validate_value(self, &pk, value, txn)?;
         }
+        for foreign_key in &self.foreign_keys {
+            foreign_key.validate_row(self, &pk, row, txn)?;
+        }
+        for check in &self.checks {
+            check.validate_row(row)?;
+        }
+        // Unique columns are checked by Column::validate_value(), other unique indexes here.
+        for index in txn.table_indexes(self)? {
+            if let ([column], None) = (index.columns.as_slice(), index.expression(0)) {
+                if self.get_column(column)?.unique {
+                    continue;
+                }
+            }
+            index.validate_row(self, &pk, row, txn)?;
+        }
         Ok(())
     }
 }
 
 impl Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Composite primary keys are given as a table constraint rather than inline.
+        let pk = self.get_primary_key().unwrap_or_default();
+        let composite = pk.len() > 1;
+        let mut lines = self
+            .columns
+            .iter()
+            .map(|c| format!("  {}", c.format(!composite)))
+            .collect::<Vec<String>>();
+        if composite {
+            lines.push(format!(
+                "  PRIMARY KEY ({})",
+                pk.iter().map(|c| format_ident(&c.name)).collect::<Vec<_>>().join(", ")
+            ));
+        }
+        lines.extend(self.foreign_keys.iter().map(|fk| format!("  {}", fk)));
+        lines.extend(self.checks.iter().map(|c| format!("  {}", c)));
+        write!(f, "CREATE TABLE {} (\n{}\n)", format_ident(&self.name), lines.join(",\n"))
+    }
+}
+
+/// A CHECK constraint, which must not evaluate to false for any row in the table. The expression
+/// refers to the row's columns by position.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Check {
+    /// Constraint name
+    pub name: String,
+    /// The boolean expression to check
+    pub expression: Expression,
+}
+
+impl Check {
+    /// Validates the check constraint schema
+    pub fn validate(&self, table: &Table) -> Result<()> {
+        for index in self.column_indexes() {
+            if index >= table.columns.len() {
+                return Err(Error::Value(format!(
+                    "Check constraint {} references unknown column #{}",
+                    self.name, index
+                )));
+            }
+        }
+        if self
+            .expression
+            .contains(&|e| matches!(e, Expression::CurrVal(_) | Expression::NextVal(_)))
+        {
+            return Err(Error::Value(format!(
+                "Check constraint {} can't use sequences",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Validates a row against the constraint. Like in standard SQL, a NULL result passes.
+    pub fn validate_row(&self, row: &[Value]) -> Result<()> {
+        match self.expression.evaluate(Some(&row.to_vec()))? {
+            Value::Boolean(true) | Value::Null => Ok(()),
+            Value::Boolean(false) => Err(Error::Value(format!(
+                "Row violates check constraint {} ({})",
+                self.name, self.expression
+            ))),
+            value => Err(Error::Value(format!(
+                "Check constraint {} returned {}, expected boolean",
+                self.name, value
+            ))),
+        }
+    }
+
+    /// Returns the column indexes referenced by the constraint
+    fn column_indexes(&self) -> Vec<usize> {
+        let indexes = std::cell::RefCell::new(Vec::new());
+        self.expression.walk(&|e| {
+            if let Expression::Field(i, _) = e {
+                indexes.borrow_mut().push(*i);
+            }
+            true
+        });
+        indexes.into_inner()
+    }
+
+    /// Returns true if the constraint references the given column index
+    fn references_column(&self, index: usize) -> bool {
+        self.expression.contains(&|e| matches!(e, Expression::Field(i, _) if *i == index))
+    }
+
+    /// Shifts column references past a dropped column, which must not itself be referenced
+    fn drop_column(&mut self, index: usize) -> Result<()> {
+        self.expression = self.expression.clone().transform(
+            &|e| match e {
+                Expression::Field(i, label) if i > index => Ok(Expression::Field(i - 1, label)),
+                e => Ok(e),
+            },
+            &Ok,
+        )?;
+        Ok(())
+    }
+
+    /// Updates the labels of column references for a renamed column
+    fn rename_column(&mut self, from: &str, to: &str) -> Result<()> {
+        self.expression = self.expression.clone().transform(
+            &|e| match e {
+                Expression::Field(i, Some((table, name))) if name == from => {
+                    Ok(Expression::Field(i, Some((table, to.to_string()))))
+                }
+                e => Ok(e),
+            },
+            &Ok,
+        )?;
+        Ok(())
+    }
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CONSTRAINT {} CHECK ({})", format_ident(&self.name), self.expression)
+    }
+}
+
+/// A table schema alteration, as given by ALTER TABLE
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Alteration {
+    /// Adds a column, using the column default for existing rows
+    AddColumn(Column),
+    /// Drops a column
+    DropColumn(String),
+    /// Renames a column
+    RenameColumn { from: String, to: String },
+    /// Renames the table
+    RenameTable(String),
+    /// Sets or drops (with None) the default value of a column
+    SetDefault { column: String, default: Option<Expression> },
+    /// Sets or drops the NOT NULL constraint of a column
+    SetNullable { column: String, nullable: bool },
+}
+
+impl Alteration {
+    /// Rewrites an existing row of the unaltered table for the altered table schema
+    pub fn transform_row<T: Transaction + ?Sized>(
+        &self,
+        table: &Table,
+        mut row: Row,
+        txn: &mut T,
+    ) -> Result<Row> {
+        match self {
+            Self::AddColumn(column) => row.push(column.evaluate_default(txn)?),
+            Self::DropColumn(name) => {
+                row.remove(table.get_column_index(name)?);
+            }
+            Self::RenameColumn { .. }
+            | Self::RenameTable(_)
+            | Self::SetDefault { .. }
+            | Self::SetNullable { .. } => {}
+        }
+        Ok(row)
+    }
+
+    /// Rewrites an index created with CREATE INDEX on the unaltered table for the altered table
+    /// schema, following renamed columns and tables and shifting the column references of indexed
+    /// expressions past dropped columns. The index entries must be rebuilt.
+    pub fn transform_index(&self, table: &Table, mut index: Index) -> Result<Index> {
+        match self {
+            Self::DropColumn(name) => {
+                let dropped = table.get_column_index(name)?;
+                index.transform_expressions(&|e| match e {
+                    Expression::Field(i, label) if i > dropped => {
+                        Ok(Expression::Field(i - 1, label))
+                    }
+                    e => Ok(e),
+                })?;
+            }
+            Self::RenameColumn { from, to } => {
+                for i in 0..index.columns.len() {
+                    if index.expression(i).is_none() && &index.columns[i] == from {
+                        index.columns[i] = to.clone();
+                    }
+                }
+                index.transform_expressions(&|e| match e {
+                    Expression::Field(i, Some((table, name))) if &name == from => {
+                        Ok(Expression::Field(i, Some((table, to.clone()))))
+                    }
+                    e => Ok(e),
+                })?;
+            }
+            Self::RenameTable(name) => {
+                index.table = name.clone();
+                index.transform_expressions(&|e| match e {
+                    Expression::Field(i, Some((Some(t), column))) if t == table.name => {
+                        Ok(Expression::Field(i, Some((Some(name.clone()), column))))
+                    }
+                    e => Ok(e),
+                })?;
+            }
+            Self::AddColumn(_) | Self::SetDefault { .. } | Self::SetNullable { .. } => {}
+        }
+        Ok(index)
+    }
+}
+
+impl Display for Alteration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AddColumn(column) => write!(f, "ADD COLUMN {}", column),
+            Self::DropColumn(name) => write!(f, "DROP COLUMN {}", format_ident(name)),
+            Self::RenameColumn { from, to } => {
+                write!(f, "RENAME COLUMN {} TO {}", format_ident(from), format_ident(to))
+            }
+            Self::RenameTable(name) => write!(f, "RENAME TO {}", format_ident(name)),
+            Self::SetDefault { column, default: Some(default) } => {
+                write!(f, "ALTER COLUMN {} SET DEFAULT {}", format_ident(column), default)
+            }
+            Self::SetDefault { column, default: None } => {
+                write!(f, "ALTER COLUMN {} DROP DEFAULT", format_ident(column))
+            }
+            Self::SetNullable { column, nullable: true } => {
+                write!(f, "ALTER COLUMN {} DROP NOT NULL", format_ident(column))
+            }
+            Self::SetNullable { column, nullable: false } => {
+                write!(f, "ALTER COLUMN {} SET NOT NULL", format_ident(column))
+            }
+        }
+    }
+}
+
+/// A table-level foreign key, referencing the (possibly composite) primary key of a table
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ForeignKey {
+    /// The referencing columns, in the order of the referenced primary key columns
+    pub columns: Vec<String>,
+    /// The table whose primary key is referenced
+    pub references: String,
+    /// The action to take when the referenced row is deleted
+    pub on_delete: ReferentialAction,
+    /// The action to take when the primary key of the referenced row is updated
+    pub on_update: ReferentialAction,
+}
+
+impl ForeignKey {
+    /// Validates that the referential actions can be applied to the referencing columns
+    pub fn validate_actions(&self, table: &Table) -> Result<()> {
+        for action in &[&self.on_delete, &self.on_update] {
+            for name in &self.columns {
+                let column = table.get_column(name)?;
+                match action {
+                    ReferentialAction::SetNull if !column.nullable => {
+                        return Err(Error::Value(format!(
+                            "Can't use SET NULL for non-nullable column {}",
+                            column.name
+                        )))
+                    }
+                    ReferentialAction::SetDefault if column.default.is_none() => {
+                        return Err(Error::Value(format!(
+                            "Can't use SET DEFAULT for column {} without a default value",
+                            column.name
+                        )))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the foreign key schema
+    pub fn validate(&self, table: &Table, txn: &mut dyn Transaction) -> Result<()> {
+        let target = if self.references == table.name {
+            table.clone()
+        } else if let Some(target) = txn.read_table(&self.references)? {
+            target
+        } else {
+            return Err(Error::Value(format!(
+                "Table {} referenced by foreign key ({}) does not exist",
+                self.references,
+                self.columns.join(", ")
+            )));
+        };
+        let target_pk = target.get_primary_key()?;
+        if self.columns.len() != target_pk.len() {
+            return Err(Error::Value(format!(
+                "Foreign key ({}) has {} columns, but the primary key of table {} has {}",
+                self.columns.join(", "),
+                self.columns.len(),
+                target.name,
+                target_pk.len()
+            )));
+        }
+        for (name, target_column) in self.columns.iter().zip(target_pk) {
+            let column = table.get_column(name)?;
+            if column.datatype != target_column.datatype {
+                return Err(Error::Value(format!(
+                    "Can't reference {} primary key column {} of table {} from {} column {}",
+                    target_column.datatype,
+                    target_column.name,
+                    target.name,
+                    column.datatype,
+                    column.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the foreign key values of a row. Rows with a NULL value in any of the
+    /// referencing columns are not checked, like MATCH SIMPLE in Postgres.
+    pub fn validate_row(
+        &self,
+        table: &Table,
+        pk: &[Value],
+        row: &[Value],
+        txn: &mut dyn Transaction,
+    ) -> Result<()> {
+        let key = self
+            .columns
+            .iter()
+            .map(|c| Ok(row.get(table.get_column_index(c)?).cloned().unwrap_or(Value::Null)))
+            .collect::<Result<Vec<_>>>()?;
+        if key.iter().any(|v| v == &Value::Null) {
+            return Ok(());
+        }
+        if self.references == table.name && key == pk {
+            return Ok(());
+        }
+        if txn.read(&self.references, &key)?.is_none() {
+            return Err(Error::Value(format!(
+                "Referenced primary key ({}) in table {} does not exist",
+                key.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "),
+                self.references,
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Display for ForeignKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "CREATE TABLE {} (\n{}\n)",
-            format_ident(&self.name),
-            self.columns
-                .iter()
-                .map(|c| format!("  {}", c))
-                .collect::<Vec<String>>()
-                .join(",\n")
-        )
+            "FOREIGN KEY ({}) REFERENCES {}",
+            self.columns.iter().map(|c| format_ident(c)).collect::<Vec<_>>().join(", "),
+            format_ident(&self.references)
+        )?;
+        if self.on_delete != ReferentialAction::Restrict {
+            write!(f, " ON DELETE {}", self.on_delete)?;
+        }
+        if self.on_update != ReferentialAction::Restrict {
+            write!(f, " ON UPDATE {}", self.on_update)?;
+        }
+        Ok(())
+    }
+}
+
+/// An action to take on referencing rows when a referenced row is deleted, or when its primary
+/// key is updated
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum ReferentialAction {
+    /// Errors if the row is referenced
+    Restrict,
+    /// Deletes the referencing rows, or updates their foreign key to the new primary key
+    Cascade,
+    /// Sets the referencing columns to NULL
+    SetNull,
+    /// Sets the referencing columns to their default values
+    SetDefault,
+}
+
+impl Display for ReferentialAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Restrict => "RESTRICT",
+            Self::Cascade => "CASCADE",
+            Self::SetNull => "SET NULL",
+            Self::SetDefault => "SET DEFAULT",
+        })
+    }
+}
+
+/// The declared length of a string column, in characters
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum StringLength {
+    /// CHAR(n). Like in Postgres, trailing spaces are insignificant, so values are stored
+    /// without them, which also makes comparisons, grouping and keys ignore them.
+    Char(usize),
+    /// VARCHAR(n)
+    Varchar(usize),
+}
+
+impl StringLength {
+    /// Returns the maximum number of characters
+    pub fn max(&self) -> usize {
+        match self {
+            Self::Char(n) | Self::Varchar(n) => *n,
+        }
+    }
+
+    /// Returns true if the string fits the length. Strings can't have more characters than
+    /// bytes, so only long strings have their characters counted.
+    pub fn allows(&self, s: &str) -> bool {
+        s.len() <= self.max() || s.chars().count() <= self.max()
+    }
+}
+
+impl Display for StringLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Char(n) => write!(f, "CHAR({})", n),
+            Self::Varchar(n) => write!(f, "VARCHAR({})", n),
+        }
     }
 }
 
@@ -183,17 +899,49 @@ pub struct Column {
     pub primary_key: bool,
     /// Whether the column allows null values
     pub nullable: bool,
-    /// The default value of the column
-    pub default: Option<Value>,
+    /// The default value of the column, as an expression evaluated for each inserted row
+    pub default: Option<Expression>,
     /// Whether the column should only take unique values
     pub unique: bool,
     /// The table which is referenced by this foreign key
     pub references: Option<String>,
+    /// The action to take when the referenced row is deleted
+    pub on_delete: ReferentialAction,
+    /// The action to take when the primary key of the referenced row is updated
+    pub on_update: ReferentialAction,
     /// Whether the column should be indexed
     pub index: bool,
+    /// How to round DECIMAL values to the column scale
+    #[serde(default)]
+    pub rounding: RoundingMode,
+    /// The maximum length of STRING values, for CHAR(n) and VARCHAR(n) columns
+    #[serde(default)]
+    pub length: Option<StringLength>,
+    /// How to compare STRING values, e.g. for comparisons, sorting, grouping and index keys
+    #[serde(default)]
+    pub collation: Collation,
 }
 
 impl Column {
+    /// Creates a non-nullable column of the given datatype, without a default or constraints
+    pub fn new(name: &str, datatype: DataType) -> Self {
+        Self {
+            name: name.into(),
+            datatype,
+            primary_key: false,
+            nullable: false,
+            default: None,
+            unique: false,
+            references: None,
+            on_delete: ReferentialAction::Restrict,
+            on_update: ReferentialAction::Restrict,
+            index: false,
+            rounding: RoundingMode::default(),
+            length: None,
+            collation: Collation::default(),
+        }
+    }
+
     /// Validates the column schema
     pub fn validate(&self, table: &Table, txn: &mut dyn Transaction) -> Result<()> {
         // Validate primary key
@@ -203,17 +951,49 @@ impl Column {
                 self.name
             )));
         }
-        if self.primary_key && !self.unique {
+        // Columns of a composite primary key are only unique in combination.
+        if self.primary_key && !self.unique && table.get_primary_key()?.len() == 1 {
             return Err(Error::Value(format!(
                 "Primary key {} must be unique",
                 self.name
             )));
         }
 
-        // Validate default value
-        if let Some(default) = &self.default {
-            if let Some(datatype) = default.datatype() {
-                if datatype != self.datatype {
+        // Validate string length
+        match self.length {
+            Some(length) if self.datatype != DataType::String => {
+                return Err(Error::Value(format!(
+                    "Can't use {} for {} column {}",
+                    length, self.datatype, self.name
+                )));
+            }
+            Some(length) if length.max() == 0 => {
+                return Err(Error::Value(format!(
+                    "{} column {} must have a positive length",
+                    length, self.name
+                )));
+            }
+            _ => {}
+        }
+        if self.collation != Collation::default() && self.datatype != DataType::String {
+            return Err(Error::Value(format!(
+                "Can't use collation {} for {} column {}",
+                self.collation, self.datatype, self.name
+            )));
+        }
+        // Row keys are the raw primary key values, so they can't be collated.
+        if self.collation != Collation::default() && self.primary_key {
+            return Err(Error::Value(format!(
+                "Can't use collation {} for primary key column {}",
+                self.collation, self.name
+            )));
+        }
+
+        // Validate default value. Constant defaults are checked here, while other expressions
+        // are evaluated and validated for each inserted row.
+        if let Some(Expression::Constant(default)) = &self.default {
+            if let Some(datatype) = self.coerce_value(default.clone())?.datatype() {
+                if !datatype.matches(&self.datatype) {
                     return Err(Error::Value(format!(
                         "Default value for column {} has datatype {}, must be {}",
                         self.name, datatype, self.datatype
@@ -225,6 +1005,13 @@ impl Column {
                     self.name
                 )));
             }
+        } else if let Some(default) = &self.default {
+            if default.contains(&|e| matches!(e, Expression::Field(_, _))) {
+                return Err(Error::Value(format!(
+                    "Default value for column {} can't reference columns",
+                    self.name
+                )));
+            }
         } else if self.nullable {
             return Err(Error::Value(format!(
                 "Nullable column {} must have a default value",
@@ -244,10 +1031,17 @@ impl Column {
                     reference, self.name
                 )));
             };
-            if self.datatype != target.get_primary_key()?.datatype {
+            let target_pk = target.get_primary_key()?;
+            if target_pk.len() != 1 {
+                return Err(Error::Value(format!(
+                    "Can't reference composite primary key of table {} from column {}",
+                    target.name, self.name
+                )));
+            }
+            if self.datatype != target_pk[0].datatype {
                 return Err(Error::Value(format!(
                     "Can't reference {} primary key of table {} from {} column {}",
-                    target.get_primary_key()?.datatype,
+                    target_pk[0].datatype,
                     target.name,
                     self.datatype,
                     self.name
@@ -262,7 +1056,7 @@ impl Column {
     pub fn validate_value(
         &self,
         table: &Table,
-        pk: &Value,
+        pk: &[Value],
         value: &Value,
         txn: &mut dyn Transaction,
     ) -> Result<()> {
@@ -273,7 +1067,7 @@ impl Column {
                 "NULL value not allowed for column {}",
                 self.name
             ))),
-            Some(ref datatype) if datatype != &self.datatype => Err(Error::Value(format!(
+            Some(ref datatype) if !datatype.matches(&self.datatype) => Err(Error::Value(format!(
                 "Invalid datatype {} for {} column {}",
                 datatype, self.datatype, self.name
             ))),
@@ -281,10 +1075,29 @@ impl Column {
         }?;
 
         // Validate value
-        match value {
-            Value::String(s) if s.len() > 1024 => Err(Error::Value(
-                "Strings cannot be more than 1024 bytes".into(),
+        match (value, &self.datatype) {
+            (Value::String(s), _) if s.len() > txn.limits().max_string_size => Err(Error::Value(
+                format!("Strings cannot be more than {} bytes", txn.limits().max_string_size),
             )),
+            (Value::String(s), _) if self.length.is_some_and(|l| !l.allows(s)) => {
+                Err(Error::Value(format!(
+                    "String of {} characters exceeds {} column {}",
+                    s.chars().count(),
+                    self.length.map(|l| l.to_string()).unwrap_or_default(),
+                    self.name
+                )))
+            }
+            (Value::Binary(b), _) if b.len() > txn.limits().max_binary_size => Err(Error::Value(
+                format!("Binary values cannot be more than {} bytes", txn.limits().max_binary_size),
+            )),
+            (Value::Decimal(d), DataType::Decimal(precision, scale))
+                if d.scale() > *scale || d.integer_digits() > precision - scale =>
+            {
+                Err(Error::Value(format!(
+                    "Decimal {} exceeds {} column {}",
+                    d, self.datatype, self.name
+                )))
+            }
             _ => Ok(()),
         }?;
 
@@ -293,8 +1106,8 @@ impl Column {
             match value {
                 Value::Null => Ok(()),
                 Value::Float(f) if f.is_nan() => Ok(()),
-                v if target == &table.name && v == pk => Ok(()),
-                v if txn.read(target, v)?.is_none() => Err(Error::Value(format!(
+                v if target == &table.name && std::slice::from_ref(v) == pk => Ok(()),
+                v if txn.read(target, std::slice::from_ref(v))?.is_none() => Err(Error::Value(format!(
                     "Referenced primary key {} in table {} does not exist",
                     v, target,
                 ))),
@@ -302,19 +1115,18 @@ impl Column {
             }?;
         }
 
-        // Validate uniqueness constraints
+        // Validate uniqueness constraints, by probing the column's implicit unique index. Two
+        // concurrent transactions writing the same value will both write the same index entry,
+        // and the latter will fail with a serialization conflict.
         if self.unique && !self.primary_key && value != &Value::Null {
-            let index = table.get_column_index(&self.name)?;
-            let mut scan = txn.scan(&table.name, None)?;
-            while let Some(row) = scan.next().transpose()? {
-                if row.get(index).unwrap_or(&Value::Null) == value
-                    && &table.get_row_key(&row)? != pk
-                {
-                    return Err(Error::Value(format!(
-                        "Unique value {} already exists for column {}",
-                        value, self.name
-                    )));
-                }
+            let columns = [self.name.clone()];
+            let key = [self.collation.key(value.clone())];
+            let pks = txn.read_index(&table.name, &columns, &key)?;
+            if pks.iter().any(|other| other != pk) {
+                return Err(Error::Value(format!(
+                    "Unique value {} already exists for column {}",
+                    value, self.name
+                )));
             }
         }
 
@@ -322,14 +1134,64 @@ impl Column {
     }
 }
 
-impl Display for Column {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl Column {
+    /// Coerces a value to the column datatype before validation and storage. Integers, floats
+    /// and decimals written to DECIMAL columns are rounded to the column scale using the column's
+    /// rounding mode, strings written to JSON columns are validated and normalized, and strings
+    /// written to CHAR(n) columns have trailing spaces removed, while other values are returned
+    /// as is.
+    pub fn coerce_value(&self, value: Value) -> Result<Value> {
+        let scale = match self.datatype {
+            DataType::Decimal(_, scale) => scale,
+            DataType::String => {
+                return Ok(match (value, self.length) {
+                    (Value::String(mut s), Some(StringLength::Char(_))) => {
+                        s.truncate(s.trim_end_matches(' ').len());
+                        Value::String(s)
+                    }
+                    (value, _) => value,
+                })
+            }
+            DataType::Json => {
+                return Ok(match value {
+                    Value::String(s) => Value::Json(json::normalize(&s)?),
+                    value => value,
+                })
+            }
+            _ => return Ok(value),
+        };
+        Ok(match value {
+            Value::Integer(i) => Value::Decimal(Decimal::from(i).rescale(scale, self.rounding)?),
+            Value::Float(f) => Value::Decimal(Decimal::from_f64(f)?.rescale(scale, self.rounding)?),
+            Value::Decimal(d) => Value::Decimal(d.rescale(scale, self.rounding)?),
+            value => value,
+        })
+    }
+
+    /// Evaluates the default value of the column for a new row, resolving any sequence
+    /// operations in the transaction and coercing the value to the column datatype. Columns
+    /// without a default evaluate to NULL.
+    pub fn evaluate_default<T: Transaction + ?Sized>(&self, txn: &mut T) -> Result<Value> {
+        match &self.default {
+            Some(default) => {
+                self.coerce_value(txn.resolve_sequences(default.clone())?.evaluate(None)?)
+            }
+            None => Ok(Value::Null),
+        }
+    }
+
+    /// Formats the column specification as SQL. Members of a composite primary key are
+    /// formatted without an inline PRIMARY KEY, since that is given as a table constraint.
+    fn format(&self, inline_primary_key: bool) -> String {
         let mut sql = format_ident(&self.name);
-        sql += &format!(" {}", self.datatype);
-        if self.primary_key {
+        match self.length {
+            Some(length) => sql += &format!(" {}", length),
+            None => sql += &format!(" {}", self.datatype),
+        }
+        if self.primary_key && inline_primary_key {
             sql += " PRIMARY KEY";
         }
-        if !self.nullable && !self.primary_key {
+        if !(self.nullable || self.primary_key && inline_primary_key) {
             sql += " NOT NULL";
         }
         if let Some(default) = &self.default {
@@ -340,10 +1202,353 @@ impl Display for Column {
         }
         if let Some(reference) = &self.references {
             sql += &format!(" REFERENCES {}", reference);
+            if self.on_delete != ReferentialAction::Restrict {
+                sql += &format!(" ON DELETE {}", self.on_delete);
+            }
+            if self.on_update != ReferentialAction::Restrict {
+                sql += &format!(" ON UPDATE {}", self.on_update);
+            }
         }
         if self.index {
             sql += " INDEX";
         }
-        write!(f, "{}", sql)
+        if self.rounding != RoundingMode::default() {
+            sql += &format!(" ROUNDING {}", self.rounding);
+        }
+        if self.collation != Collation::default() {
+            sql += &format!(" COLLATE {}", self.collation);
+        }
+        sql
+    }
+}
+
+impl Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(true))
+    }
+}
+
+/// A secondary index over one or more columns or expressions of a table. Index entries are keyed
+/// by the tuple of indexed values, so an index is identified in storage by its table and columns,
+/// while the name is used to refer to it in DDL statements.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Index {
+    /// Index name
+    pub name: String,
+    /// The indexed table
+    pub table: String,
+    /// The indexed columns, in index key order. Indexed expressions are named by their text.
+    pub columns: Vec<String>,
+    /// The indexed expressions, e.g. JSON path lookups, by position in columns. None for plain
+    /// columns, and empty if the index only has plain columns.
+    #[serde(default)]
+    pub expressions: Vec<Option<Expression>>,
+    /// Whether the indexed column values must be unique
+    pub unique: bool,
+}
+
+impl Index {
+    /// Returns the name of the implicit index of a column declared with INDEX or UNIQUE. Other
+    /// indexes can't use these names.
+    pub fn implicit_name(table: &str, column: &str) -> String {
+        format!("{}_{}_idx", table, column)
+    }
+
+    /// Validates the index schema
+    pub fn validate(&self, txn: &mut dyn Transaction) -> Result<()> {
+        if txn.get_index(&self.name)?.is_some() {
+            return Err(Error::Value(format!("Index {} already exists", self.name)));
+        }
+        let table = txn.must_read_table(&self.table)?;
+        if let Some(column) =
+            table.columns.iter().find(|c| Self::implicit_name(&table.name, &c.name) == self.name)
+        {
+            return Err(Error::Value(format!(
+                "Index name {} is reserved for column {}",
+                self.name, column.name
+            )));
+        }
+        if self.columns.is_empty() {
+            return Err(Error::Value(format!("Index {} has no columns", self.name)));
+        }
+        for (i, column) in self.columns.iter().enumerate() {
+            match self.expression(i) {
+                Some(expression) => self.validate_expression(&table, expression)?,
+                None => {
+                    table.get_column(column)?;
+                }
+            }
+            if self.columns[..i].contains(column) {
+                return Err(Error::Value(format!(
+                    "Duplicate column {} in index {}",
+                    column, self.name
+                )));
+            }
+        }
+        if let Some(existing) =
+            txn.table_indexes(&table)?.into_iter().find(|i| i.columns == self.columns)
+        {
+            return Err(Error::Value(format!(
+                "Index {} already exists on table {} columns ({})",
+                existing.name,
+                self.table,
+                self.columns.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Validates a row against a unique index, by probing the index for the row's index key. Keys
+    /// containing NULL are never considered duplicates.
+    pub fn validate_row(
+        &self,
+        table: &Table,
+        pk: &[Value],
+        row: &[Value],
+        txn: &mut dyn Transaction,
+    ) -> Result<()> {
+        if !self.unique {
+            return Ok(());
+        }
+        let key = self.get_row_key(table, row)?;
+        if key.iter().any(|v| v == &Value::Null) {
+            return Ok(());
+        }
+        if txn.read_index(&table.name, &self.columns, &key)?.iter().any(|other| other != pk) {
+            return Err(Error::Value(format!(
+                "Unique value ({}) already exists for index {}",
+                key.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "),
+                self.name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the index key of a row, as a tuple of the indexed column and expression values.
+    /// Strings are keyed by their collation key, see Collation::key().
+    pub fn get_row_key(&self, table: &Table, row: &[Value]) -> Result<Vec<Value>> {
+        self.columns
+            .iter()
+            .enumerate()
+            .map(|(i, c)| match self.expression(i) {
+                Some(expression) => Ok(expression
+                    .collation()
+                    .unwrap_or_default()
+                    .key(expression.evaluate(Some(&row.to_vec()))?)),
+                None => {
+                    let column = table.get_column(c)?;
+                    let value = row.get(table.get_column_index(c)?).cloned();
+                    Ok(column.collation.key(value.unwrap_or(Value::Null)))
+                }
+            })
+            .collect()
+    }
+
+    /// Transforms the indexed expressions, and renames their key columns after their new text
+    fn transform_expressions<F: Fn(Expression) -> Result<Expression>>(
+        &mut self,
+        transform: &F,
+    ) -> Result<()> {
+        for (column, expression) in self.columns.iter_mut().zip(self.expressions.iter_mut()) {
+            if let Some(expression) = expression {
+                *expression = expression.clone().transform(transform, &Ok)?;
+                *column = expression.to_string();
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the indexed expression at the given key position, if any
+    pub fn expression(&self, i: usize) -> Option<&Expression> {
+        self.expressions.get(i).and_then(|e| e.as_ref())
+    }
+
+    /// Validates an indexed expression, which must only reference the table's columns and must
+    /// be deterministic
+    fn validate_expression(&self, table: &Table, expression: &Expression) -> Result<()> {
+        let len = table.columns.len();
+        if expression.contains(&|e| matches!(e, Expression::Field(i, _) if *i >= len)) {
+            return Err(Error::Value(format!(
+                "Index {} expression {} references unknown column",
+                self.name, expression
+            )));
+        }
+        if expression.contains(&|e| matches!(e, Expression::CurrVal(_) | Expression::NextVal(_))) {
+            return Err(Error::Value(format!(
+                "Index {} expression {} can't use sequences",
+                self.name, expression
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Display for Index {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "CREATE {}INDEX {} ON {} ({})",
+            if self.unique { "UNIQUE " } else { "" },
+            format_ident(&self.name),
+            format_ident(&self.table),
+            self.columns
+                .iter()
+                .enumerate()
+                .map(|(i, c)| match self.expression(i) {
+                    Some(_) => format!("({})", c),
+                    None => format_ident(c),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+/// A sequence, which generates integers for e.g. auto-incrementing primary keys. The current value
+/// is stored transactionally alongside the schema, so concurrent transactions fetching values
+/// from the same sequence will conflict rather than hand out duplicates.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Sequence {
+    /// Sequence name
+    pub name: String,
+    /// The first value of the sequence
+    pub start: i64,
+    /// The increment between consecutive values, which may be negative
+    pub increment: i64,
+}
+
+impl Sequence {
+    /// Creates a new sequence starting at 1 with increment 1
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), start: 1, increment: 1 }
+    }
+
+    /// Returns the name of the implicit sequence backing an AUTOINCREMENT or SERIAL column
+    pub fn column_sequence_name(table: &str, column: &str) -> String {
+        format!("{}_{}_seq", table, column)
+    }
+
+    /// Validates the sequence schema
+    pub fn validate(&self, txn: &mut dyn Transaction) -> Result<()> {
+        if txn.read_sequence(&self.name)?.is_some() {
+            return Err(Error::Value(format!("Sequence {} already exists", self.name)));
+        }
+        if self.increment == 0 {
+            return Err(Error::Value(format!("Sequence {} can't have increment 0", self.name)));
+        }
+        Ok(())
+    }
+
+    /// Returns the value following the given previous value, or the start value if the sequence
+    /// has not been used yet
+    pub fn next(&self, previous: Option<i64>) -> Result<i64> {
+        match previous {
+            Some(previous) => previous
+                .checked_add(self.increment)
+                .ok_or_else(|| Error::Value(format!("Sequence {} is exhausted", self.name))),
+            None => Ok(self.start),
+        }
+    }
+}
+
+impl Display for Sequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "CREATE SEQUENCE {} START WITH {} INCREMENT BY {}",
+            format_ident(&self.name),
+            self.start,
+            self.increment
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::engine::{Engine, KV};
+    use super::*;
+    use crate::storage::kv;
+
+    fn setup() -> Result<impl Transaction> {
+        KV::new(kv::MVCC::new(Box::new(kv::Memory::new()))).begin()
+    }
+
+    #[test]
+    fn composite_primary_key() -> Result<()> {
+        let mut txn = setup()?;
+        let mut table = Table::new(
+            "t".into(),
+            vec![
+                Column { primary_key: true, ..Column::new("a", DataType::Integer) },
+                Column::new("b", DataType::Integer),
+                Column { primary_key: true, ..Column::new("c", DataType::Integer) },
+            ],
+        )?;
+        table.primary_key = vec!["c".into(), "a".into()];
+        table.validate(&mut txn)?;
+
+        // The key is in declared order, both for row keys and when formatted.
+        let row = vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)];
+        assert_eq!(vec![Value::Integer(3), Value::Integer(1)], table.get_row_key(&row)?);
+        assert!(table.to_string().contains("PRIMARY KEY (c, a)"));
+
+        // Key columns must match the primary key flags.
+        let mut invalid = table.clone();
+        invalid.primary_key = vec!["c".into()];
+        assert!(invalid.validate(&mut txn).is_err());
+        invalid.primary_key = vec!["c".into(), "b".into()];
+        assert!(invalid.validate(&mut txn).is_err());
+
+        // Multiple primary key columns need an explicit key order.
+        invalid.primary_key = Vec::new();
+        assert!(invalid.validate(&mut txn).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn check() -> Result<()> {
+        let mut txn = setup()?;
+        let field = |i: usize, name: &str| Expression::Field(i, Some((None, name.into())));
+        let mut table = Table::new(
+            "t".into(),
+            vec![
+                Column { primary_key: true, unique: true, ..Column::new("a", DataType::Integer) },
+                Column {
+                    nullable: true,
+                    default: Some(Expression::Constant(Value::Null)),
+                    ..Column::new("b", DataType::Integer)
+                },
+            ],
+        )?;
+        table.primary_key = vec!["a".into()];
+        table.checks.push(Check {
+            name: "a_lt_b".into(),
+            expression: Expression::LessThan(field(0, "a").into(), field(1, "b").into()),
+        });
+        table.validate(&mut txn)?;
+        assert!(table.to_string().contains("CONSTRAINT a_lt_b CHECK (a < b)"), "{}", table);
+
+        // NULL results pass, and violations name the constraint.
+        table.validate_row(&[Value::Integer(1), Value::Integer(2)], &mut txn)?;
+        table.validate_row(&[Value::Integer(1), Value::Null], &mut txn)?;
+        match table.validate_row(&[Value::Integer(2), Value::Integer(1)], &mut txn) {
+            Err(Error::Value(message)) => assert!(message.contains("a_lt_b"), "{}", message),
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        // Checks must return booleans, and reference existing columns.
+        let mut invalid = table.clone();
+        invalid.checks[0].expression = field(0, "a");
+        assert!(invalid.validate_row(&[Value::Integer(1), Value::Null], &mut txn).is_err());
+        invalid.checks[0].expression = field(2, "c");
+        assert!(invalid.validate(&mut txn).is_err());
+
+        // Check names are unique, and referenced columns can't be dropped.
+        let mut invalid = table.clone();
+        invalid.checks.push(table.checks[0].clone());
+        assert!(invalid.validate(&mut txn).is_err());
+        txn.create_table(table)?;
+        assert!(txn.alter_table("t", Alteration::DropColumn("b".into())).is_err());
+        Ok(())
     }
 }
@@ -0,0 +1,165 @@
+//! String collations, which determine how strings are compared, ordered and grouped. Collations
+//! only affect strings, other values are always compared as is.
+
+use super::Value;
+use crate::error::{Error, Result};
+
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// A string collation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Collation {
+    /// Compares strings bytewise, i.e. by Unicode code point
+    #[default]
+    Binary,
+    /// Compares strings case-insensitively, ignoring the case of ASCII letters only
+    NoCase,
+    /// Compares strings case-insensitively using Unicode case mappings, e.g. 'É' equals 'é'
+    Unicode,
+}
+
+impl Collation {
+    /// Compares two strings
+    pub fn compare(&self, lhs: &str, rhs: &str) -> Ordering {
+        match self {
+            Self::Binary => lhs.cmp(rhs),
+            Self::NoCase => lhs
+                .bytes()
+                .map(|b| b.to_ascii_lowercase())
+                .cmp(rhs.bytes().map(|b| b.to_ascii_lowercase())),
+            Self::Unicode => lhs
+                .chars()
+                .flat_map(char::to_lowercase)
+                .cmp(rhs.chars().flat_map(char::to_lowercase)),
+        }
+    }
+
+    /// Compares two values, using the collation for strings. Returns None if the values can't
+    /// be compared.
+    pub fn compare_values(&self, lhs: &Value, rhs: &Value) -> Option<Ordering> {
+        match (lhs, rhs) {
+            (Value::String(lhs), Value::String(rhs)) => Some(self.compare(lhs, rhs)),
+            (lhs, rhs) => lhs.partial_cmp(rhs),
+        }
+    }
+
+    /// Returns the collation key of a value, such that values are equal under the collation if
+    /// and only if their keys are equal, and keys order like the collation. Used for e.g.
+    /// grouping and index keys.
+    pub fn key(&self, value: Value) -> Value {
+        match (self, value) {
+            (Self::NoCase, Value::String(s)) => Value::String(s.to_ascii_lowercase()),
+            (Self::Unicode, Value::String(s)) => {
+                Value::String(s.chars().flat_map(char::to_lowercase).collect())
+            }
+            (_, value) => value,
+        }
+    }
+
+    /// Returns true if the collation ignores case, e.g. for LIKE
+    pub fn is_case_insensitive(&self) -> bool {
+        *self != Self::Binary
+    }
+
+    /// Returns the collation of a comparison between operands with the given collations, where
+    /// None is the default collation. Errors if the operands have different collations.
+    pub fn combine(lhs: Option<Self>, rhs: Option<Self>) -> Result<Option<Self>> {
+        match (lhs, rhs) {
+            (Some(lhs), Some(rhs)) if lhs != rhs => {
+                Err(Error::Value(format!("Conflicting collations {} and {}", lhs, rhs)))
+            }
+            (lhs, rhs) => Ok(lhs.or(rhs)),
+        }
+    }
+}
+
+impl Display for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Binary => "BINARY",
+            Self::NoCase => "NOCASE",
+            Self::Unicode => "UNICODE",
+        })
+    }
+}
+
+impl FromStr for Collation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_uppercase().as_str() {
+            "BINARY" => Ok(Self::Binary),
+            "NOCASE" => Ok(Self::NoCase),
+            "UNICODE" => Ok(Self::Unicode),
+            _ => Err(Error::Value(format!("Unknown collation {}", s))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare() {
+        use Collation::*;
+        assert_eq!(Ordering::Less, Binary.compare("B", "a"));
+        assert_eq!(Ordering::Greater, NoCase.compare("B", "a"));
+        assert_eq!(Ordering::Equal, NoCase.compare("abC", "ABc"));
+        assert_eq!(Ordering::Less, NoCase.compare("ab", "ABc"));
+
+        // NOCASE only folds ASCII letters, while UNICODE folds all letters.
+        assert_ne!(Ordering::Equal, NoCase.compare("É", "é"));
+        assert_eq!(Ordering::Equal, Unicode.compare("Éa", "éA"));
+
+        // Other values are compared as is, and incomparable values give None.
+        assert_eq!(Some(Ordering::Equal), NoCase.compare_values(&"A".into(), &"a".into()));
+        assert_eq!(
+            Some(Ordering::Less),
+            NoCase.compare_values(&Value::Integer(1), &Value::Float(1.5))
+        );
+        assert_eq!(None, NoCase.compare_values(&Value::Integer(1), &"a".into()));
+    }
+
+    #[test]
+    fn key() {
+        // Keys are equal if and only if the values compare equal, and order the same way.
+        let strings = ["a", "A", "b", "B", "É", "é", "ab", ""];
+        for collation in [Collation::Binary, Collation::NoCase, Collation::Unicode] {
+            for lhs in strings {
+                for rhs in strings {
+                    let (lkey, rkey) = (collation.key(lhs.into()), collation.key(rhs.into()));
+                    assert_eq!(
+                        collation.compare(lhs, rhs),
+                        lkey.partial_cmp(&rkey).unwrap(),
+                        "{} {} {}",
+                        collation,
+                        lhs,
+                        rhs
+                    );
+                }
+            }
+        }
+        assert_eq!(Value::Integer(1), Collation::NoCase.key(Value::Integer(1)));
+        assert_eq!(Value::Null, Collation::Unicode.key(Value::Null));
+    }
+
+    #[test]
+    fn combine() -> Result<()> {
+        use Collation::*;
+        assert_eq!(None, Collation::combine(None, None)?);
+        assert_eq!(Some(NoCase), Collation::combine(None, Some(NoCase))?);
+        assert_eq!(Some(NoCase), Collation::combine(Some(NoCase), Some(NoCase))?);
+        assert!(Collation::combine(Some(Binary), Some(NoCase)).is_err());
+
+        // Names are case-insensitive, and displayed in upper case.
+        assert_eq!(Unicode, "unicode".parse()?);
+        assert_eq!("NOCASE", "NoCase".parse::<Collation>()?.to_string());
+        assert!("latin1".parse::<Collation>().is_err());
+        assert!(NoCase.is_case_insensitive() && !Binary.is_case_insensitive());
+        Ok(())
+    }
+}
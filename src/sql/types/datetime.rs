@@ -0,0 +1,586 @@
+//! Date and time support, without time zones, using the proleptic Gregorian calendar.
+//!
+//! Date:      Days since 1970-01-01.
+//! Time:      Microseconds since midnight.
+//! Timestamp: Microseconds since 1970-01-01 00:00:00.
+//! Interval:  Months, days and microseconds, since months and days vary in length.
+
+use super::Value;
+use crate::error::{Error, Result};
+
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const MICROS_PER_SECOND: i64 = 1_000_000;
+pub const MICROS_PER_MINUTE: i64 = 60 * MICROS_PER_SECOND;
+pub const MICROS_PER_HOUR: i64 = 60 * MICROS_PER_MINUTE;
+pub const MICROS_PER_DAY: i64 = 24 * MICROS_PER_HOUR;
+
+/// The number of days per month when comparing intervals, like Postgres
+const DAYS_PER_MONTH: i64 = 30;
+
+/// A time interval. Like in Postgres, intervals are compared by assuming 30 days per month, such
+/// that '1 month' equals '30 days' in SQL comparisons, but the components are kept separate for
+/// date arithmetic, e.g. 2024-01-31 + 1 month is 2024-02-29. Equality and hashing are
+/// consistent with this, so e.g. GROUP BY and DISTINCT also treat them as equal.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Interval {
+    pub months: i32,
+    pub days: i32,
+    pub micros: i64,
+}
+
+impl Interval {
+    /// Returns the interval length in microseconds, assuming 30 days per month
+    pub fn normalized(&self) -> i128 {
+        (self.months as i128 * DAYS_PER_MONTH as i128 + self.days as i128) * MICROS_PER_DAY as i128
+            + self.micros as i128
+    }
+
+    /// Builds an interval from a length in microseconds, filling whole months first and then
+    /// whole days. The result equals any interval with the same normalized length.
+    pub fn from_normalized(length: i128) -> Result<Self> {
+        let month = DAYS_PER_MONTH as i128 * MICROS_PER_DAY as i128;
+        let months = (length / month).clamp(i32::MIN as i128, i32::MAX as i128);
+        let length = length - months * month;
+        let days = (length / MICROS_PER_DAY as i128).clamp(i32::MIN as i128, i32::MAX as i128);
+        let micros = length - days * MICROS_PER_DAY as i128;
+        Ok(Self {
+            months: months as i32,
+            days: days as i32,
+            micros: micros.try_into().map_err(|_| overflow())?,
+        })
+    }
+
+    /// Adds two intervals
+    pub fn checked_add(&self, other: &Self) -> Result<Self> {
+        Ok(Self {
+            months: self.months.checked_add(other.months).ok_or_else(overflow)?,
+            days: self.days.checked_add(other.days).ok_or_else(overflow)?,
+            micros: self.micros.checked_add(other.micros).ok_or_else(overflow)?,
+        })
+    }
+
+    /// Negates the interval
+    pub fn checked_neg(&self) -> Result<Self> {
+        Ok(Self {
+            months: self.months.checked_neg().ok_or_else(overflow)?,
+            days: self.days.checked_neg().ok_or_else(overflow)?,
+            micros: self.micros.checked_neg().ok_or_else(overflow)?,
+        })
+    }
+
+    /// Multiplies the interval by a factor. Fractional months and days cascade down into days
+    /// and microseconds respectively, e.g. 1 month * 0.5 is 15 days.
+    pub fn checked_mul(&self, factor: f64) -> Result<Self> {
+        let months = self.months as f64 * factor;
+        let days = self.days as f64 * factor + months.fract() * DAYS_PER_MONTH as f64;
+        let micros = self.micros as f64 * factor + days.fract() * MICROS_PER_DAY as f64;
+        Ok(Self {
+            months: to_integer(months.trunc())?,
+            days: to_integer(days.trunc())?,
+            micros: to_integer(micros.round())?,
+        })
+    }
+
+    /// Compares two intervals by length, assuming 30 days per month
+    pub fn compare(&self, other: &Self) -> Ordering {
+        self.normalized().cmp(&other.normalized())
+    }
+}
+
+impl PartialEq for Interval {
+    fn eq(&self, other: &Self) -> bool {
+        self.normalized() == other.normalized()
+    }
+}
+
+impl Eq for Interval {}
+
+impl Hash for Interval {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.normalized().hash(state)
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural =
+            |n: i64, unit: &str| format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" });
+        let mut parts = Vec::new();
+        let (years, months) = (self.months / 12, self.months % 12);
+        if years != 0 {
+            parts.push(plural(years as i64, "year"));
+        }
+        if months != 0 {
+            parts.push(plural(months as i64, "mon"));
+        }
+        if self.days != 0 {
+            parts.push(plural(self.days as i64, "day"));
+        }
+        if self.micros != 0 || parts.is_empty() {
+            let sign = if self.micros < 0 { "-" } else { "" };
+            parts.push(format!("{}{}", sign, format_time(self.micros.unsigned_abs() as i64)));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// Returns an integer overflow error
+fn overflow() -> Error {
+    Error::Value("Date/time value out of range".into())
+}
+
+/// Converts a whole float to an integer, erroring if out of range
+fn to_integer<T: TryFrom<i64>>(f: f64) -> Result<T> {
+    if !f.is_finite() || f < i64::MIN as f64 || f >= i64::MAX as f64 {
+        return Err(overflow());
+    }
+    T::try_from(f as i64).map_err(|_| overflow())
+}
+
+/// Returns true if the year is a leap year
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Returns the number of days in the given month (1-12)
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Converts a year, month (1-12) and day (1-31) into days since 1970-01-01.
+/// See: https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Converts days since 1970-01-01 into a year, month (1-12) and day (1-31).
+/// See: https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Parses a date as YYYY-MM-DD
+pub fn parse_date(s: &str) -> Result<i32> {
+    let invalid = || Error::Value(format!("Invalid date {}", s));
+    let parts = s.trim().split('-').map(|p| p.parse::<i64>()).collect::<Vec<_>>();
+    let (year, month, day) = match parts.as_slice() {
+        [Ok(year), Ok(month), Ok(day)] => (*year, *month, *day),
+        _ => return Err(invalid()),
+    };
+    if !(1..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+    {
+        return Err(invalid());
+    }
+    Ok(days_from_civil(year, month, day) as i32)
+}
+
+/// Parses a time as HH:MM[:SS[.ffffff]]
+pub fn parse_time(s: &str) -> Result<i64> {
+    let invalid = || Error::Value(format!("Invalid time {}", s));
+    let parts = s.trim().split(':').collect::<Vec<_>>();
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [hours, minutes] => (*hours, *minutes, "0"),
+        [hours, minutes, seconds] => (*hours, *minutes, *seconds),
+        _ => return Err(invalid()),
+    };
+    let hours = hours.parse::<i64>().map_err(|_| invalid())?;
+    let minutes = minutes.parse::<i64>().map_err(|_| invalid())?;
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    let seconds = seconds.parse::<i64>().map_err(|_| invalid())?;
+    if !(0..24).contains(&hours) || !(0..60).contains(&minutes) || !(0..60).contains(&seconds) {
+        return Err(invalid());
+    }
+    if fraction.len() > 6 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let micros = format!("{:0<6}", fraction).parse::<i64>().map_err(|_| invalid())?;
+    Ok(hours * MICROS_PER_HOUR + minutes * MICROS_PER_MINUTE + seconds * MICROS_PER_SECOND + micros)
+}
+
+/// Parses a timestamp as YYYY-MM-DD[( |T)HH:MM[:SS[.ffffff]]]
+pub fn parse_timestamp(s: &str) -> Result<i64> {
+    let s = s.trim();
+    let (date, time) = match s.split_once([' ', 'T']) {
+        Some((date, time)) => (date, parse_time(time)?),
+        None => (s, 0),
+    };
+    Ok(parse_date(date)? as i64 * MICROS_PER_DAY + time)
+}
+
+/// Parses an interval as a sequence of quantities and units, e.g. '1 year 2 months -3 days',
+/// optionally followed by a time as [-]HH:MM[:SS[.ffffff]]. Quantities may be fractional.
+pub fn parse_interval(s: &str) -> Result<Interval> {
+    let invalid = || Error::Value(format!("Invalid interval {}", s));
+    let mut interval = Interval::default();
+    let mut tokens = s.split_whitespace().peekable();
+    if tokens.peek().is_none() {
+        return Err(invalid());
+    }
+    while let Some(token) = tokens.next() {
+        if token.contains(':') {
+            let micros = match token.strip_prefix('-') {
+                Some(time) => -parse_time(time).map_err(|_| invalid())?,
+                None => parse_time(token).map_err(|_| invalid())?,
+            };
+            interval.micros = interval.micros.checked_add(micros).ok_or_else(overflow)?;
+            continue;
+        }
+        let quantity = token.parse::<f64>().map_err(|_| invalid())?;
+        let unit = tokens.next().ok_or_else(invalid)?.to_lowercase();
+        let unit = match unit.as_str() {
+            "microsecond" | "microseconds" | "us" => Interval { micros: 1, ..Default::default() },
+            "millisecond" | "milliseconds" | "ms" => {
+                Interval { micros: 1000, ..Default::default() }
+            }
+            "second" | "seconds" | "sec" | "secs" | "s" => {
+                Interval { micros: MICROS_PER_SECOND, ..Default::default() }
+            }
+            "minute" | "minutes" | "min" | "mins" | "m" => {
+                Interval { micros: MICROS_PER_MINUTE, ..Default::default() }
+            }
+            "hour" | "hours" | "h" => Interval { micros: MICROS_PER_HOUR, ..Default::default() },
+            "day" | "days" | "d" => Interval { days: 1, ..Default::default() },
+            "week" | "weeks" | "w" => Interval { days: 7, ..Default::default() },
+            "month" | "months" | "mon" | "mons" => Interval { months: 1, ..Default::default() },
+            "year" | "years" | "y" => Interval { months: 12, ..Default::default() },
+            _ => return Err(invalid()),
+        };
+        interval = interval.checked_add(&unit.checked_mul(quantity)?)?;
+    }
+    Ok(interval)
+}
+
+/// Formats a date as YYYY-MM-DD
+pub fn format_date(date: i32) -> String {
+    let (year, month, day) = civil_from_days(date as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Formats a time as HH:MM:SS[.ffffff], omitting trailing zeros of the fraction
+pub fn format_time(time: i64) -> String {
+    let (hours, minutes) = (time / MICROS_PER_HOUR, time % MICROS_PER_HOUR / MICROS_PER_MINUTE);
+    let (seconds, micros) =
+        (time % MICROS_PER_MINUTE / MICROS_PER_SECOND, time % MICROS_PER_SECOND);
+    let mut s = format!("{:02}:{:02}:{:02}", hours, minutes, seconds);
+    if micros != 0 {
+        s += format!(".{:06}", micros).trim_end_matches('0');
+    }
+    s
+}
+
+/// Formats a timestamp as YYYY-MM-DD HH:MM:SS[.ffffff]
+pub fn format_timestamp(timestamp: i64) -> String {
+    format!(
+        "{} {}",
+        format_date(timestamp_date(timestamp)),
+        format_time(timestamp.rem_euclid(MICROS_PER_DAY))
+    )
+}
+
+/// Returns the date of a timestamp
+pub fn timestamp_date(timestamp: i64) -> i32 {
+    timestamp.div_euclid(MICROS_PER_DAY) as i32
+}
+
+/// Returns the timestamp at midnight of a date
+pub fn date_timestamp(date: i32) -> i64 {
+    date as i64 * MICROS_PER_DAY
+}
+
+/// Returns the current timestamp
+pub fn now() -> Result<i64> {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| Error::Internal(format!("System clock is before the Unix epoch: {}", e)))?;
+    i64::try_from(elapsed.as_micros()).map_err(|_| overflow())
+}
+
+/// Checks that a date, as days since 1970-01-01, is within years 1 to 9999
+fn check_date(date: i64) -> Result<i64> {
+    if !(days_from_civil(1, 1, 1)..=days_from_civil(9999, 12, 31)).contains(&date) {
+        return Err(overflow());
+    }
+    Ok(date)
+}
+
+/// Adds a number of days to a date, which must remain within years 1 to 9999
+pub fn add_days(date: i32, days: i64) -> Result<i32> {
+    Ok(check_date((date as i64).checked_add(days).ok_or_else(overflow)?)? as i32)
+}
+
+/// Adds an interval to a timestamp, which must remain within years 1 to 9999. Months are added
+/// first, clamping the day to the end of the resulting month, then days and microseconds.
+pub fn add_interval(timestamp: i64, interval: &Interval) -> Result<i64> {
+    let mut timestamp = timestamp;
+    if interval.months != 0 {
+        let (year, month, day) = civil_from_days(timestamp.div_euclid(MICROS_PER_DAY));
+        let months = year * 12 + month - 1 + interval.months as i64;
+        let (year, month) = (months.div_euclid(12), months.rem_euclid(12) + 1);
+        let day = day.min(days_in_month(year, month));
+        let date = days_from_civil(year, month, day);
+        timestamp = date
+            .checked_mul(MICROS_PER_DAY)
+            .and_then(|t| t.checked_add(timestamp.rem_euclid(MICROS_PER_DAY)))
+            .ok_or_else(overflow)?;
+    }
+    let timestamp = (interval.days as i64)
+        .checked_mul(MICROS_PER_DAY)
+        .and_then(|days| timestamp.checked_add(days))
+        .and_then(|t| t.checked_add(interval.micros))
+        .ok_or_else(overflow)?;
+    check_date(timestamp.div_euclid(MICROS_PER_DAY))?;
+    Ok(timestamp)
+}
+
+/// Adds the days and microseconds of an interval to a time, wrapping around midnight
+pub fn add_time_interval(time: i64, interval: &Interval) -> i64 {
+    (time as i128 + interval.micros as i128).rem_euclid(MICROS_PER_DAY as i128) as i64
+}
+
+/// Returns the interval between two timestamps, as days and microseconds
+pub fn timestamp_difference(lhs: i64, rhs: i64) -> Result<Interval> {
+    let micros = lhs.checked_sub(rhs).ok_or_else(overflow)?;
+    Ok(Interval {
+        months: 0,
+        days: (micros / MICROS_PER_DAY) as i32,
+        micros: micros % MICROS_PER_DAY,
+    })
+}
+
+/// Extracts a field from a date, time, timestamp or interval value, for extract(). Seconds and
+/// epochs are returned as floats, other fields as integers.
+pub fn extract(field: &str, value: &Value) -> Result<Value> {
+    let invalid = || Error::Value(format!("Can't extract {} from {}", field, value));
+    let field = field.to_lowercase();
+    let (date, time) = match value {
+        Value::Date(date) => (Some(*date as i64), None),
+        Value::Time(time) => (None, Some(*time)),
+        Value::Timestamp(ts) => {
+            (Some(ts.div_euclid(MICROS_PER_DAY)), Some(ts.rem_euclid(MICROS_PER_DAY)))
+        }
+        Value::Interval(interval) => {
+            let seconds = interval.micros % MICROS_PER_MINUTE;
+            return Ok(match field.as_str() {
+                "year" => Value::Integer(interval.months as i64 / 12),
+                "month" => Value::Integer(interval.months as i64 % 12),
+                "day" => Value::Integer(interval.days as i64),
+                "hour" => Value::Integer(interval.micros / MICROS_PER_HOUR),
+                "minute" => Value::Integer(interval.micros % MICROS_PER_HOUR / MICROS_PER_MINUTE),
+                "second" => Value::Float(seconds as f64 / MICROS_PER_SECOND as f64),
+                "epoch" => Value::Float(interval.normalized() as f64 / MICROS_PER_SECOND as f64),
+                _ => return Err(invalid()),
+            });
+        }
+        _ => return Err(invalid()),
+    };
+    if let Some(date) = date {
+        let (year, month, day) = civil_from_days(date);
+        match field.as_str() {
+            "year" => return Ok(Value::Integer(year)),
+            "quarter" => return Ok(Value::Integer((month - 1) / 3 + 1)),
+            "month" => return Ok(Value::Integer(month)),
+            "day" => return Ok(Value::Integer(day)),
+            // 1970-01-01 was a Thursday, and Sunday is 0.
+            "dow" => return Ok(Value::Integer((date + 4).rem_euclid(7))),
+            "doy" => return Ok(Value::Integer(date - days_from_civil(year, 1, 1) + 1)),
+            _ => {}
+        }
+    }
+    if let Some(time) = time {
+        match field.as_str() {
+            "hour" => return Ok(Value::Integer(time / MICROS_PER_HOUR)),
+            "minute" => return Ok(Value::Integer(time % MICROS_PER_HOUR / MICROS_PER_MINUTE)),
+            "second" => {
+                let seconds = time % MICROS_PER_MINUTE;
+                return Ok(Value::Float(seconds as f64 / MICROS_PER_SECOND as f64));
+            }
+            _ => {}
+        }
+    }
+    match (field.as_str(), value) {
+        ("epoch", Value::Date(date)) => Ok(Value::Float((*date as i64 * 86400) as f64)),
+        ("epoch", Value::Time(time) | Value::Timestamp(time)) => {
+            Ok(Value::Float(*time as f64 / MICROS_PER_SECOND as f64))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Truncates a timestamp to the given precision, for date_trunc(). Weeks start on Monday.
+pub fn truncate(unit: &str, timestamp: i64) -> Result<i64> {
+    let date = timestamp.div_euclid(MICROS_PER_DAY);
+    let (year, month, _) = civil_from_days(date);
+    let truncate_micros = |unit: i64| timestamp - timestamp.rem_euclid(unit);
+    Ok(match unit.to_lowercase().as_str() {
+        "year" => days_from_civil(year, 1, 1) * MICROS_PER_DAY,
+        "quarter" => days_from_civil(year, (month - 1) / 3 * 3 + 1, 1) * MICROS_PER_DAY,
+        "month" => days_from_civil(year, month, 1) * MICROS_PER_DAY,
+        // 1970-01-01 was a Thursday, so Mondays are 3 days off from multiples of 7.
+        "week" => (date - (date + 3).rem_euclid(7)) * MICROS_PER_DAY,
+        "day" => truncate_micros(MICROS_PER_DAY),
+        "hour" => truncate_micros(MICROS_PER_HOUR),
+        "minute" => truncate_micros(MICROS_PER_MINUTE),
+        "second" => truncate_micros(MICROS_PER_SECOND),
+        unit => return Err(Error::Value(format!("Unknown date_trunc unit {}", unit))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn hash(interval: &Interval) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        interval.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn interval_eq() -> Result<()> {
+        let month = parse_interval("1 month")?;
+        let days = parse_interval("30 days")?;
+        let hours = parse_interval("720 hours")?;
+        assert_eq!((1, 0, 0), (month.months, month.days, month.micros));
+        assert_eq!(Ordering::Equal, month.compare(&days));
+        assert_eq!(month, days);
+        assert_eq!(days, hours);
+        assert_eq!(hash(&month), hash(&days));
+        assert_eq!(hash(&days), hash(&hours));
+
+        let day = parse_interval("1 day")?;
+        assert_ne!(day, parse_interval("23 hours")?);
+        assert_eq!(Ordering::Greater, day.compare(&parse_interval("23 hours")?));
+        assert_eq!(Value::Interval(month), Value::Interval(hours));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_format() -> Result<()> {
+        // Dates round-trip, including leap days and dates before the epoch.
+        for date in ["1970-01-01", "2024-02-29", "2000-02-29", "1969-12-31", "0001-01-01"] {
+            assert_eq!(date, format_date(parse_date(date)?));
+        }
+        assert_eq!(0, parse_date("1970-01-01")?);
+        assert_eq!(-1, parse_date("1969-12-31")?);
+        for date in ["2023-02-29", "1900-02-29", "2024-13-01", "2024-04-31", "10000-01-01", "x"] {
+            assert!(parse_date(date).is_err(), "{}", date);
+        }
+
+        // Times have optional seconds and fractions, with trailing fraction zeros omitted.
+        assert_eq!("13:05:00", format_time(parse_time("13:05")?));
+        assert_eq!("13:05:07.25", format_time(parse_time("13:05:07.250")?));
+        assert_eq!(MICROS_PER_DAY - 1, parse_time("23:59:59.999999")?);
+        for time in ["24:00", "12:60", "12:00:60", "12:00:00.1234567", "12"] {
+            assert!(parse_time(time).is_err(), "{}", time);
+        }
+
+        // Timestamps separate the date and time by a space or T, and default to midnight.
+        assert_eq!(parse_timestamp("2024-03-01 10:00")?, parse_timestamp("2024-03-01T10:00")?);
+        assert_eq!("2024-03-01 00:00:00", format_timestamp(parse_timestamp("2024-03-01")?));
+        assert_eq!("1969-12-31 23:00:00", format_timestamp(-MICROS_PER_HOUR));
+        Ok(())
+    }
+
+    #[test]
+    fn interval() -> Result<()> {
+        let interval = parse_interval("1 year 14 mons -3 days 1.5 hours")?;
+        assert_eq!(
+            (26, -3, 90 * MICROS_PER_MINUTE),
+            (interval.months, interval.days, interval.micros)
+        );
+        assert_eq!("2 years 2 mons -3 days 01:30:00", interval.to_string());
+        assert_eq!("-01:02:00", parse_interval("-01:02")?.to_string());
+        assert_eq!("00:00:00", parse_interval("0 days")?.to_string());
+
+        // Fractional quantities spill into smaller units, which aren't carried into larger ones.
+        assert_eq!("1 mon 15 days", parse_interval("1.5 months")?.to_string());
+        assert_eq!("24:00:00", parse_interval("0.5 days 12 hours")?.to_string());
+        for interval in ["", "1", "1 fortnight", "x days"] {
+            assert!(parse_interval(interval).is_err(), "{}", interval);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn arithmetic() -> Result<()> {
+        let ts = |s| parse_timestamp(s);
+        let add = |s, i| -> Result<String> {
+            Ok(format_timestamp(add_interval(ts(s)?, &parse_interval(i)?)?))
+        };
+
+        // Months are added first, clamping the day to the end of the month.
+        assert_eq!("2024-02-29 10:00:00", add("2024-01-31 10:00", "1 month")?);
+        assert_eq!("2023-02-28 00:00:00", add("2024-02-29", "-1 year")?);
+        assert_eq!("2024-03-01 01:00:00", add("2024-01-31", "1 month 1 day 1 hour")?);
+        assert_eq!("2024-02-28 23:00:00", add("2024-02-29", "-1 hour")?);
+        assert_eq!("9999-12-31 23:59:59", add("9999-12-31", "23:59:59")?);
+        assert!(add("9999-12-31", "1 year").is_err());
+        assert!(add("9999-12-31", "24 hours").is_err());
+        assert!(add("0001-01-01", "-1 month").is_err());
+        assert!(add("0001-01-01", "-00:00:01").is_err());
+
+        assert_eq!(parse_date("2024-03-01")?, add_days(parse_date("2024-02-28")?, 2)?);
+        assert!(add_days(parse_date("9999-12-31")?, 1).is_err());
+        assert!(add_days(parse_date("0001-01-01")?, -1).is_err());
+        let late = 23 * MICROS_PER_HOUR;
+        assert_eq!(MICROS_PER_HOUR, add_time_interval(late, &parse_interval("2 hours")?));
+
+        let difference = timestamp_difference(ts("2024-03-02 01:00")?, ts("2024-03-01")?)?;
+        assert_eq!("1 day 01:00:00", difference.to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn extract_truncate() -> Result<()> {
+        let ts = Value::Timestamp(parse_timestamp("2024-08-15 13:45:30.5")?);
+        let fields = ["year", "quarter", "month", "day", "dow", "doy", "hour", "minute"];
+        let values = fields.iter().map(|f| extract(f, &ts)).collect::<Result<Vec<_>>>()?;
+        let expect = [2024, 3, 8, 15, 4, 228, 13, 45].map(Value::Integer);
+        assert_eq!(expect.to_vec(), values);
+        assert_eq!(Value::Float(30.5), extract("SECOND", &ts)?);
+        assert_eq!(Value::Float(86400.0), extract("epoch", &Value::Date(1))?);
+        assert!(extract("hour", &Value::Date(1)).is_err());
+        assert!(extract("day", &Value::Time(0)).is_err());
+        let interval = Value::Interval(parse_interval("1 year 3 months 2 days 04:05:06")?);
+        assert_eq!(Value::Integer(3), extract("month", &interval)?);
+        assert_eq!(Value::Integer(5), extract("minute", &interval)?);
+
+        // Weeks start on Monday.
+        let truncate = |unit| -> Result<String> {
+            Ok(format_timestamp(truncate(unit, parse_timestamp("2024-08-15 13:45:30.5")?)?))
+        };
+        assert_eq!("2024-07-01 00:00:00", truncate("quarter")?);
+        assert_eq!("2024-08-12 00:00:00", truncate("week")?);
+        assert_eq!("2024-08-15 13:45:30", truncate("second")?);
+        assert!(truncate("decade").is_err());
+        Ok(())
+    }
+}
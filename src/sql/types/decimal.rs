@@ -0,0 +1,583 @@
+//! Exact decimal numbers, stored as a fixed-point integer mantissa and a decimal scale (the number
+//! of fractional digits), with at most 38 significant digits.
+//!
+//! Results keep the larger operand scale for addition, subtraction and remainders, and the sum of
+//! the operand scales for multiplication. Division keeps at least 16 fractional digits. Results
+//! with more than 38 fractional digits are rounded half up.
+
+use crate::error::{Error, Result};
+
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+/// The maximum number of significant digits, and thus also the maximum scale
+pub const MAX_PRECISION: u8 = 38;
+
+/// The minimum number of fractional digits of division results
+const DIVISION_SCALE: u8 = 16;
+
+/// The largest mantissa, i.e. 38 nines
+const MAX_MANTISSA: i128 = 10i128.pow(MAX_PRECISION as u32) - 1;
+
+/// How to round values to a smaller scale
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RoundingMode {
+    /// Rounds ties away from zero, e.g. 2.5 to 3 and -2.5 to -3
+    #[default]
+    HalfUp,
+    /// Rounds ties to the nearest even digit, e.g. 2.5 to 2 and 3.5 to 4
+    HalfEven,
+    /// Discards the excess digits, e.g. 2.9 to 2 and -2.9 to -2
+    Truncate,
+}
+
+impl Display for RoundingMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::HalfUp => "HALF_UP",
+            Self::HalfEven => "HALF_EVEN",
+            Self::Truncate => "TRUNCATE",
+        })
+    }
+}
+
+impl FromStr for RoundingMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_uppercase().as_str() {
+            "HALF_UP" => Ok(Self::HalfUp),
+            "HALF_EVEN" => Ok(Self::HalfEven),
+            "TRUNCATE" => Ok(Self::Truncate),
+            _ => Err(Error::Value(format!("Unknown rounding mode {}", s))),
+        }
+    }
+}
+
+/// An exact decimal number: mantissa * 10^-scale. Decimals are compared and hashed by numeric
+/// value, such that 1.0 equals 1.00, but the scale is kept for display.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u8,
+}
+
+impl Decimal {
+    /// Creates a decimal from a mantissa and scale, erroring if out of range
+    pub fn new(mantissa: i128, scale: u8) -> Result<Self> {
+        if scale > MAX_PRECISION {
+            return Err(Error::Value(format!("Decimal scale {} exceeds {}", scale, MAX_PRECISION)));
+        }
+        Self { mantissa, scale }.checked()
+    }
+
+    /// Returns the mantissa
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    /// Returns the scale, i.e. the number of fractional digits
+    pub fn scale(&self) -> u8 {
+        self.scale
+    }
+
+    /// Returns the precision, i.e. the total number of digits including the fractional digits
+    pub fn precision(&self) -> u8 {
+        let mut digits = 1;
+        let mut mantissa = self.mantissa.unsigned_abs();
+        while mantissa >= 10 {
+            mantissa /= 10;
+            digits += 1;
+        }
+        digits.max(self.scale)
+    }
+
+    /// Returns the number of integer digits, ignoring a zero integer part
+    pub fn integer_digits(&self) -> u8 {
+        if self.mantissa.unsigned_abs() < pow10(self.scale) as u128 {
+            return 0;
+        }
+        self.precision() - self.scale
+    }
+
+    /// Returns true if the decimal is zero
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    /// Returns the value at the given scale, rounding if the scale is reduced
+    pub fn rescale(&self, scale: u8, mode: RoundingMode) -> Result<Self> {
+        if scale > MAX_PRECISION {
+            return Err(Error::Value(format!("Decimal scale {} exceeds {}", scale, MAX_PRECISION)));
+        }
+        match scale.cmp(&self.scale) {
+            Ordering::Equal => Ok(*self),
+            Ordering::Greater => Self {
+                mantissa: self
+                    .mantissa
+                    .checked_mul(pow10(scale - self.scale))
+                    .ok_or_else(|| overflow(self))?,
+                scale,
+            }
+            .checked(),
+            Ordering::Less => {
+                Self { mantissa: divide(self.mantissa, pow10(self.scale - scale), mode), scale }
+                    .checked()
+            }
+        }
+    }
+
+    /// Rounds the decimal to the given scale, erroring if it then exceeds the given precision
+    pub fn fit(&self, precision: u8, scale: u8, mode: RoundingMode) -> Result<Self> {
+        let decimal = self.rescale(scale, mode)?;
+        if decimal.integer_digits() > precision.saturating_sub(scale) {
+            return Err(Error::Value(format!(
+                "Decimal {} exceeds DECIMAL({},{})",
+                self, precision, scale
+            )));
+        }
+        Ok(decimal)
+    }
+
+    /// Rounds the decimal half up to the given number of fractional digits, which can be
+    /// negative to round to tens, hundreds and so on.
+    pub fn round(&self, digits: i64) -> Result<Self> {
+        if digits >= self.scale as i64 {
+            return Ok(*self);
+        }
+        if digits >= 0 {
+            return self.rescale(digits as u8, RoundingMode::HalfUp);
+        }
+        // If the value has fewer integer digits than the rounding position, it rounds to zero.
+        let shift = self.scale as i64 - digits;
+        if shift > MAX_PRECISION as i64 {
+            return Ok(Self::default());
+        }
+        let mantissa = divide(self.mantissa, pow10(shift as u8), RoundingMode::HalfUp);
+        Self {
+            mantissa: mantissa.checked_mul(pow10(-digits as u8)).ok_or_else(|| overflow(self))?,
+            scale: 0,
+        }
+        .checked()
+    }
+
+    /// Returns the largest integer less than or equal to the decimal
+    pub fn floor(&self) -> Self {
+        Self { mantissa: self.split().0, scale: 0 }
+    }
+
+    /// Returns the smallest integer greater than or equal to the decimal
+    pub fn ceil(&self) -> Result<Self> {
+        match self.split() {
+            (integer, 0) => Ok(Self { mantissa: integer, scale: 0 }),
+            (integer, _) => Self { mantissa: integer + 1, scale: 0 }.checked(),
+        }
+    }
+
+    /// Converts the decimal to an integer, rounding any fractional digits
+    pub fn to_i64(&self, mode: RoundingMode) -> Result<i64> {
+        let mantissa = divide(self.mantissa, pow10(self.scale), mode);
+        i64::try_from(mantissa).map_err(|_| Error::Value(format!("Integer {} out of range", self)))
+    }
+
+    /// Converts the decimal to the nearest float
+    pub fn to_f64(&self) -> f64 {
+        // Parsing the decimal string representation rounds correctly, unlike dividing the
+        // mantissa by a power of 10.
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// Converts a float to a decimal, using the shortest representation that round-trips
+    pub fn from_f64(f: f64) -> Result<Self> {
+        if !f.is_finite() {
+            return Err(Error::Value(format!("Can't convert {} to DECIMAL", f)));
+        }
+        f.to_string().parse()
+    }
+
+    /// Negates the decimal
+    pub fn checked_neg(&self) -> Result<Self> {
+        Ok(Self { mantissa: -self.mantissa, scale: self.scale })
+    }
+
+    /// Returns the absolute value of the decimal
+    pub fn abs(&self) -> Self {
+        Self { mantissa: self.mantissa.abs(), scale: self.scale }
+    }
+
+    /// Adds two decimals
+    pub fn checked_add(&self, other: &Self) -> Result<Self> {
+        let (lhs, rhs) = self.align(other)?;
+        Self { mantissa: lhs.mantissa + rhs.mantissa, scale: lhs.scale }.checked()
+    }
+
+    /// Subtracts two decimals
+    pub fn checked_sub(&self, other: &Self) -> Result<Self> {
+        let (lhs, rhs) = self.align(other)?;
+        Self { mantissa: lhs.mantissa - rhs.mantissa, scale: lhs.scale }.checked()
+    }
+
+    /// Multiplies two decimals
+    pub fn checked_mul(&self, other: &Self) -> Result<Self> {
+        let mantissa = self
+            .mantissa
+            .checked_mul(other.mantissa)
+            .ok_or_else(|| Error::Value(format!("Decimal {} * {} out of range", self, other)))?;
+        let scale = self.scale + other.scale;
+        if scale > MAX_PRECISION {
+            let mantissa = divide(mantissa, pow10(scale - MAX_PRECISION), RoundingMode::HalfUp);
+            return Self { mantissa, scale: MAX_PRECISION }.checked();
+        }
+        Self { mantissa, scale }.checked()
+    }
+
+    /// Divides two decimals, rounding half up
+    pub fn checked_div(&self, other: &Self) -> Result<Self> {
+        if other.is_zero() {
+            return Err(Error::Value("Can't divide by zero".into()));
+        }
+        // Use the largest scale up to the minimum division scale that doesn't overflow, such
+        // that e.g. 10^30 / 3 is computed with 8 fractional digits.
+        let target = self.scale.max(other.scale).max(DIVISION_SCALE);
+        for scale in (0..=target).rev() {
+            // The quotient's scale is self.scale - other.scale, so shift the dividend (or the
+            // divisor) by the difference to the target scale.
+            let shift = scale as i32 + other.scale as i32 - self.scale as i32;
+            let (dividend, divisor) = match shift {
+                0.. => (
+                    10i128.checked_pow(shift as u32).and_then(|p| self.mantissa.checked_mul(p)),
+                    Some(other.mantissa),
+                ),
+                _ => (
+                    Some(self.mantissa),
+                    10i128.checked_pow((-shift) as u32).and_then(|p| other.mantissa.checked_mul(p)),
+                ),
+            };
+            let (Some(dividend), Some(divisor)) = (dividend, divisor) else { continue };
+            let quotient = divide(dividend, divisor, RoundingMode::HalfUp);
+            if let Ok(result) = (Self { mantissa: quotient, scale }).checked() {
+                return Ok(result);
+            }
+        }
+        Err(Error::Value(format!("Decimal {} / {} out of range", self, other)))
+    }
+
+    /// Returns the remainder of dividing two decimals, with the sign of the dividend
+    pub fn checked_rem(&self, other: &Self) -> Result<Self> {
+        if other.is_zero() {
+            return Err(Error::Value("Can't divide by zero".into()));
+        }
+        let (lhs, rhs) = self.align(other)?;
+        Self { mantissa: lhs.mantissa % rhs.mantissa, scale: lhs.scale }.checked()
+    }
+
+    /// Raises the decimal to a non-negative integer power
+    pub fn checked_pow(&self, exponent: u32) -> Result<Self> {
+        let mut result = Self::from(1);
+        for _ in 0..exponent {
+            result = result.checked_mul(self)?;
+        }
+        Ok(result)
+    }
+
+    /// Splits the decimal into its integer part, rounded towards negative infinity, and its
+    /// non-negative fractional part scaled to 38 digits. This is used for comparisons and
+    /// order-preserving key encoding, since it is independent of the scale.
+    pub fn split(&self) -> (i128, u128) {
+        let scale = pow10(self.scale);
+        let integer = self.mantissa.div_euclid(scale);
+        let fraction = self.mantissa.rem_euclid(scale) * pow10(MAX_PRECISION - self.scale);
+        (integer, fraction as u128)
+    }
+
+    /// Joins an integer and fractional part as returned by split(), using the smallest scale
+    /// that represents the value exactly.
+    pub fn join(integer: i128, fraction: u128) -> Result<Self> {
+        let mut scale = MAX_PRECISION;
+        let mut fraction = i128::try_from(fraction)?;
+        while scale > 0 && fraction % 10 == 0 {
+            fraction /= 10;
+            scale -= 1;
+        }
+        if fraction >= pow10(scale) {
+            return Err(Error::Internal(format!("Invalid decimal fraction {}", fraction)));
+        }
+        let mantissa = integer
+            .checked_mul(pow10(scale))
+            .and_then(|m| m.checked_add(fraction))
+            .ok_or_else(|| Error::Internal(format!("Invalid decimal integer {}", integer)))?;
+        Self { mantissa, scale }.checked()
+    }
+
+    /// Returns the decimal with the trailing fractional zeros removed
+    pub fn normalize(&self) -> Self {
+        let mut normalized = *self;
+        while normalized.scale > 0 && normalized.mantissa % 10 == 0 {
+            normalized.mantissa /= 10;
+            normalized.scale -= 1;
+        }
+        normalized
+    }
+
+    /// Returns both decimals at the larger of their scales
+    fn align(&self, other: &Self) -> Result<(Self, Self)> {
+        let scale = self.scale.max(other.scale);
+        Ok((
+            self.rescale(scale, RoundingMode::Truncate)?,
+            other.rescale(scale, RoundingMode::Truncate)?,
+        ))
+    }
+
+    /// Checks that the mantissa is within range
+    fn checked(self) -> Result<Self> {
+        if self.mantissa.abs() > MAX_MANTISSA {
+            return Err(overflow(&self));
+        }
+        Ok(self)
+    }
+}
+
+/// Returns 10^exp as an i128, for exp <= 38
+fn pow10(exp: u8) -> i128 {
+    10i128.pow(exp as u32)
+}
+
+/// Divides two integers, rounding the quotient with the given rounding mode
+fn divide(dividend: i128, divisor: i128, mode: RoundingMode) -> i128 {
+    let quotient = dividend / divisor;
+    let remainder = (dividend % divisor).unsigned_abs();
+    let half = divisor.unsigned_abs() - remainder;
+    let away = match mode {
+        RoundingMode::Truncate => false,
+        RoundingMode::HalfUp => remainder >= half,
+        RoundingMode::HalfEven => remainder > half || remainder == half && quotient % 2 != 0,
+    };
+    match away {
+        true if (dividend < 0) != (divisor < 0) => quotient - 1,
+        true => quotient + 1,
+        false => quotient,
+    }
+}
+
+fn overflow(decimal: &Decimal) -> Error {
+    Error::Value(format!("Decimal {} out of range", decimal))
+}
+
+impl From<i64> for Decimal {
+    fn from(i: i64) -> Self {
+        Self { mantissa: i as i128, scale: 0 }
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.split() == other.split()
+    }
+}
+
+impl Eq for Decimal {}
+
+impl Hash for Decimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.split().hash(state)
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.split().cmp(&other.split())
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, integer, fraction)
+    }
+}
+
+/// Parses a decimal such as -12.340 or 1.5e3, keeping trailing fractional zeros. Values with more
+/// than 38 fractional digits are rounded half up.
+impl FromStr for Decimal {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Value(format!("Invalid decimal {}", s));
+        let trimmed = s.trim();
+        let (negative, unsigned) = match trimmed.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+        let (number, exponent) = match unsigned.split_once(['e', 'E']) {
+            Some((number, exponent)) => (number, exponent.parse::<i32>().map_err(|_| invalid())?),
+            None => (unsigned, 0),
+        };
+        let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if integer.is_empty() && fraction.is_empty()
+            || !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+
+        let out_of_range = || Error::Value(format!("Decimal {} out of range", s));
+        let mut mantissa: i128 = 0;
+        for c in integer.trim_start_matches('0').chars().chain(fraction.chars()) {
+            mantissa = mantissa
+                .checked_mul(10)
+                .and_then(|m| m.checked_add(c.to_digit(10).unwrap_or(0) as i128))
+                .ok_or_else(out_of_range)?;
+        }
+        if negative {
+            mantissa = -mantissa;
+        }
+        // Negative scales beyond the precision can't be represented, even for zero.
+        let mut scale = i64::try_from(fraction.len())
+            .ok()
+            .and_then(|len| len.checked_sub(exponent as i64))
+            .filter(|&scale| scale >= -(MAX_PRECISION as i64))
+            .ok_or_else(out_of_range)?;
+        while scale < 0 {
+            mantissa = mantissa.checked_mul(10).ok_or_else(out_of_range)?;
+            scale += 1;
+        }
+        if scale > MAX_PRECISION as i64 {
+            let excess = (scale - MAX_PRECISION as i64).min(MAX_PRECISION as i64 + 1);
+            mantissa = match excess {
+                e if e > MAX_PRECISION as i64 => 0,
+                e => divide(mantissa, pow10(e as u8), RoundingMode::HalfUp),
+            };
+            scale = MAX_PRECISION as i64;
+        }
+        Self { mantissa, scale: scale as u8 }.checked().map_err(|_| out_of_range())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str() -> Result<()> {
+        let parse = |s: &str| s.parse::<Decimal>();
+        assert_eq!((1234, 2), (parse("12.34")?.mantissa(), parse("12.34")?.scale()));
+        assert_eq!((-15, 0), (parse("-1.5e1")?.mantissa(), parse("-1.5e1")?.scale()));
+        assert_eq!((125, 4), (parse("1.25e-2")?.mantissa(), parse("1.25e-2")?.scale()));
+        assert_eq!(parse("0.3")?, parse("3e-1")?);
+
+        // Excess fractional digits are rounded, also for extreme negative exponents.
+        assert_eq!(MAX_PRECISION, parse("1e-40")?.scale());
+        assert!(parse("1e-40")?.is_zero());
+        assert!(parse("0e-2147483648")?.is_zero());
+        assert!(parse("5e-2147483648")?.is_zero());
+
+        // Exponents beyond the precision are out of range, rather than overflowing or looping.
+        for s in ["1e39", "0e39", "0e2147483647", "1e2147483647", "1e9999999999", "x", "1.2.3"] {
+            assert!(matches!(parse(s), Err(Error::Value(_))), "{}", s);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rounding() -> Result<()> {
+        let d = |s: &str| s.parse::<Decimal>().unwrap();
+        let rescale = |s: &str, mode| -> Result<String> { Ok(d(s).rescale(0, mode)?.to_string()) };
+        for (value, half_up, half_even, truncate) in [
+            ("2.5", "3", "2", "2"),
+            ("3.5", "4", "4", "3"),
+            ("-2.5", "-3", "-2", "-2"),
+            ("2.49", "2", "2", "2"),
+            ("-2.9", "-3", "-3", "-2"),
+        ] {
+            assert_eq!(half_up, rescale(value, RoundingMode::HalfUp)?, "{}", value);
+            assert_eq!(half_even, rescale(value, RoundingMode::HalfEven)?, "{}", value);
+            assert_eq!(truncate, rescale(value, RoundingMode::Truncate)?, "{}", value);
+        }
+        assert_eq!("1.500", d("1.5").rescale(3, RoundingMode::HalfUp)?.to_string());
+
+        // Values are rounded to the scale, and must then fit the precision.
+        assert_eq!("123.46", d("123.455").fit(5, 2, RoundingMode::HalfUp)?.to_string());
+        assert_eq!("0.01", d("0.005").fit(2, 2, RoundingMode::HalfUp)?.to_string());
+        assert!(d("999.995").fit(5, 2, RoundingMode::HalfUp).is_err());
+        assert!(d("1").fit(2, 2, RoundingMode::HalfUp).is_err());
+
+        assert_eq!("1200", d("1249.9").round(-2)?.to_string());
+        assert_eq!("-1300", d("-1250").round(-2)?.to_string());
+        assert_eq!("0", d("49").round(-2)?.to_string());
+        assert_eq!("0", d("1").round(-40)?.to_string());
+        assert_eq!("1.25", d("1.25").round(5)?.to_string());
+        assert_eq!("-3", d("-2.1").floor().to_string());
+        assert_eq!("-2", d("-2.1").ceil()?.to_string());
+        assert_eq!(-3, d("-2.5").to_i64(RoundingMode::HalfUp)?);
+        assert!(d("1e20").to_i64(RoundingMode::HalfUp).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn arithmetic() -> Result<()> {
+        let d = |s: &str| s.parse::<Decimal>().unwrap();
+
+        // Results keep the larger scale for addition, subtraction and remainders, and the sum
+        // of scales for multiplication.
+        assert_eq!("3.30", d("1.1").checked_add(&d("2.20"))?.to_string());
+        assert_eq!("-1.10", d("1.1").checked_sub(&d("2.20"))?.to_string());
+        assert_eq!("2.420", d("1.1").checked_mul(&d("2.20"))?.to_string());
+        assert_eq!("-1.5", d("-7.5").checked_rem(&d("2"))?.to_string());
+        assert_eq!("0.3333333333333333", d("1").checked_div(&d("3"))?.to_string());
+        assert_eq!("2.5", d("5").checked_div(&d("2"))?.normalize().to_string());
+        assert_eq!("1.21", d("1.1").checked_pow(2)?.to_string());
+        assert!(d("1").checked_div(&d("0.0")).is_err());
+        assert!(d("1").checked_rem(&d("0")).is_err());
+
+        // Overflows error, while excess fractional digits are rounded.
+        let big = d("99999999999999999999999999999999999999");
+        assert!(big.checked_add(&d("1")).is_err());
+        assert!(big.checked_mul(&d("10")).is_err());
+        assert_eq!(MAX_PRECISION, d("1e-20").checked_mul(&d("1e-20"))?.scale());
+        Ok(())
+    }
+
+    #[test]
+    fn compare() -> Result<()> {
+        let d = |s: &str| s.parse::<Decimal>().unwrap();
+        let hash = |d: Decimal| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            d.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        // Decimals compare by value regardless of scale, which is kept for display.
+        assert_eq!(d("1.0"), d("1.00"));
+        assert_eq!(hash(d("1.0")), hash(d("1.00")));
+        assert_eq!("1.00", d("1.00").to_string());
+        assert!(d("-0.5") < d("0.25"));
+        assert!(d("-1.5") < d("-1.25"));
+        assert_eq!((4, 2), (d("12.34").precision(), d("12.34").integer_digits()));
+        assert_eq!((3, 0), (d("0.001").precision(), d("0.001").integer_digits()));
+
+        // Splitting and joining round-trips, using the smallest exact scale.
+        for s in ["0", "-1.5", "12.340", "-0.001", "99999999999999999999999999999999999999"] {
+            let (integer, fraction) = d(s).split();
+            assert_eq!(d(s).normalize().to_string(), Decimal::join(integer, fraction)?.to_string());
+        }
+        assert_eq!(0.1, d("0.1").to_f64());
+        assert_eq!("0.1", Decimal::from_f64(0.1)?.to_string());
+        assert!(Decimal::from_f64(f64::NAN).is_err());
+        Ok(())
+    }
+}
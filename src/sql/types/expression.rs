@@ -1,8 +1,10 @@
-use super::{Row, Value};
+use super::pattern::{self, Pattern, Syntax};
+use super::{datetime, json, Collation, DataType, Function, Row, Value};
 use crate::error::{Error, Result};
 
-use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::fmt::{self, Display};
 use std::mem::replace;
 
@@ -12,7 +14,31 @@ use std::mem::replace;
 pub enum Expression {
     // Values 值
     Constant(Value),
+    /// A constant pattern, compiled during planning, see compile_patterns(). Evaluates to the
+    /// pattern string.
+    Pattern(Pattern),
     Field(usize, Option<(Option<String>, String)>),
+    /// The first non-NULL value, evaluated lazily from left to right
+    Coalesce(Vec<Expression>),
+    /// A scalar function call
+    Function(Function, Vec<Expression>),
+    /// CASE [operand] WHEN .. THEN .. [ELSE ..] END. With an operand, each WHEN value is
+    /// compared to the operand for equality, otherwise each WHEN value is a boolean condition.
+    /// Returns the THEN value of the first match, or the ELSE value (NULL by default).
+    Case {
+        operand: Option<Box<Expression>>,
+        branches: Vec<(Expression, Expression)>,
+        r#else: Option<Box<Expression>>,
+    },
+    /// CAST(expr AS datatype)
+    Cast(Box<Expression>, DataType),
+    /// expr COLLATE collation. Evaluates to the value of expr, but strings are compared using the
+    /// collation, see collation(). The planner also wraps references to columns with a
+    /// non-default collation in this.
+    Collate(Box<Expression>, Collation),
+    /// A field of an enclosing query's row, for correlated subqueries. The first index is the
+    /// number of scopes out (0 being the immediately enclosing query), the second the field index.
+    OuterField(usize, usize, Option<(Option<String>, String)>),
 
     // Logical operations
     // 逻辑运算
@@ -20,12 +46,19 @@ pub enum Expression {
     Not(Box<Expression>),
     Or(Box<Expression>, Box<Expression>),
 
-    // Comparisons operations (GTE, LTE, and NEQ are composite operations)
+    // Comparisons operations (NEQ is a composite operation)
     //  比较运算符
+    /// expr BETWEEN low AND high, i.e. low <= expr AND expr <= high
+    Between(Box<Expression>, Box<Expression>, Box<Expression>),
     Equal(Box<Expression>, Box<Expression>),
     GreaterThan(Box<Expression>, Box<Expression>),
+    GreaterThanOrEqual(Box<Expression>, Box<Expression>),
+    /// expr IN (list). Like a chain of OR equalities, this is NULL rather than false if any
+    /// comparison is NULL.
+    InList(Box<Expression>, Vec<Expression>),
     IsNull(Box<Expression>),
     LessThan(Box<Expression>, Box<Expression>),
+    LessThanOrEqual(Box<Expression>, Box<Expression>),
 
     // Mathematical operations
     // 算术运算符
@@ -41,7 +74,31 @@ pub enum Expression {
 
     // String operations
     // 字符串运算符
+    /// Case-insensitive LIKE
+    ILike(Box<Expression>, Box<Expression>),
+    /// Case-insensitive regular expression match (~*)
+    IRegexMatch(Box<Expression>, Box<Expression>),
     Like(Box<Expression>, Box<Expression>),
+    /// Regular expression match (~), which matches anywhere in the string
+    RegexMatch(Box<Expression>, Box<Expression>),
+    /// SIMILAR TO, a LIKE pattern with regular expression operators
+    SimilarTo(Box<Expression>, Box<Expression>),
+
+    // JSON operations
+    /// A field or element of a JSON document, as JSON (->)
+    JsonGet(Box<Expression>, Box<Expression>),
+    /// A field or element of a JSON document, as text (->>)
+    JsonGetText(Box<Expression>, Box<Expression>),
+
+    // Sequence operations, which must be resolved by a transaction before evaluation
+    CurrVal(String),
+    NextVal(String),
+
+    // Subquery operations, which must be executed before evaluation. The subquery plans are kept
+    // by the plan, and referred to here by index.
+    Exists(usize),
+    InSubquery(Box<Expression>, usize),
+    Subquery(usize),
 }
 
 impl Expression {
@@ -53,7 +110,50 @@ impl Expression {
         Ok(match self {
             // Constant values 常量表达式
             Self::Constant(c) => c.clone(),
+            Self::Pattern(pattern) => String(pattern.source().to_string()),
             Self::Field(i, _) => row.and_then(|row| row.get(*i).cloned()).unwrap_or(Null),
+            Self::Coalesce(exprs) => {
+                for expr in exprs {
+                    match expr.evaluate(row)? {
+                        Null => {}
+                        value => return Ok(value),
+                    }
+                }
+                Null
+            }
+            Self::Function(function, args) => {
+                let pattern = args.iter().find_map(|e| match e {
+                    Self::Pattern(pattern) => Some(pattern),
+                    _ => None,
+                });
+                let args = args.iter().map(|e| e.evaluate(row)).collect::<Result<Vec<_>>>()?;
+                function.evaluate_with_pattern(args, pattern)?
+            }
+            Self::Case { operand, branches, r#else } => {
+                let value = operand.as_ref().map(|e| e.evaluate(row)).transpose()?;
+                for (when, then) in branches {
+                    let matched = match (operand, &value) {
+                        (Some(operand), Some(value)) => {
+                            let collation = Self::comparison_collation(&[operand, when])?;
+                            Self::equal(value.clone(), when.evaluate(row)?, collation)?
+                        }
+                        _ => when.evaluate(row)?,
+                    };
+                    match matched {
+                        Boolean(true) => return then.evaluate(row),
+                        Boolean(false) | Null => {}
+                        value => {
+                            return Err(Error::Value(format!("CASE condition returned {}", value)))
+                        }
+                    }
+                }
+                match r#else {
+                    Some(expr) => expr.evaluate(row)?,
+                    None => Null,
+                }
+            }
+            Self::Cast(expr, datatype) => expr.evaluate(row)?.cast(datatype)?,
+            Self::Collate(expr, _) => expr.evaluate(row)?,
 
             // Logical operations
             // 逻辑运算
@@ -83,45 +183,59 @@ impl Expression {
 
             // Comparison operations
             // 比较运算
-            #[allow(clippy::float_cmp)] // Up to the user if they want to compare or not
-            Self::Equal(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                (Boolean(lhs), Boolean(rhs)) => Boolean(lhs == rhs),
-                (Integer(lhs), Integer(rhs)) => Boolean(lhs == rhs),
-                (Integer(lhs), Float(rhs)) => Boolean(lhs as f64 == rhs),
-                (Float(lhs), Integer(rhs)) => Boolean(lhs == rhs as f64),
-                (Float(lhs), Float(rhs)) => Boolean(lhs == rhs),
-                (String(lhs), String(rhs)) => Boolean(lhs == rhs),
-                (Null, _) | (_, Null) => Null,
-                (lhs, rhs) => {
-                    return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs)))
+            Self::Between(expr, low, high) => {
+                let collation = Self::comparison_collation(&[expr, low, high])?;
+                let value = expr.evaluate(row)?;
+                let below = Self::less_than(value.clone(), low.evaluate(row)?, collation)?;
+                let above = Self::greater_than(value, high.evaluate(row)?, collation)?;
+                match (below, above) {
+                    (Boolean(true), _) | (_, Boolean(true)) => Boolean(false),
+                    (Boolean(false), Boolean(false)) => Boolean(true),
+                    _ => Null,
                 }
-            },
-            Self::GreaterThan(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                #[allow(clippy::bool_comparison)]
-                (Boolean(lhs), Boolean(rhs)) => Boolean(lhs > rhs),
-                (Integer(lhs), Integer(rhs)) => Boolean(lhs > rhs),
-                (Integer(lhs), Float(rhs)) => Boolean(lhs as f64 > rhs),
-                (Float(lhs), Integer(rhs)) => Boolean(lhs > rhs as f64),
-                (Float(lhs), Float(rhs)) => Boolean(lhs > rhs),
-                (String(lhs), String(rhs)) => Boolean(lhs > rhs),
-                (Null, _) | (_, Null) => Null,
-                (lhs, rhs) => {
-                    return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs)))
+            }
+            Self::Equal(lhs, rhs) => {
+                let collation = Self::comparison_collation(&[lhs, rhs])?;
+                Self::equal(lhs.evaluate(row)?, rhs.evaluate(row)?, collation)?
+            }
+            Self::GreaterThan(lhs, rhs) => {
+                let collation = Self::comparison_collation(&[lhs, rhs])?;
+                Self::greater_than(lhs.evaluate(row)?, rhs.evaluate(row)?, collation)?
+            }
+            // The operands are evaluated once, since they may be volatile, e.g. nextval().
+            Self::GreaterThanOrEqual(lhs, rhs) => {
+                let collation = Self::comparison_collation(&[lhs, rhs])?;
+                let (lhs, rhs) = (lhs.evaluate(row)?, rhs.evaluate(row)?);
+                match Self::greater_than(lhs.clone(), rhs.clone(), collation)? {
+                    Boolean(false) => Self::equal(lhs, rhs, collation)?,
+                    value => value,
                 }
-            },
-            Self::LessThan(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                #[allow(clippy::bool_comparison)]
-                (Boolean(lhs), Boolean(rhs)) => Boolean(lhs < rhs),
-                (Integer(lhs), Integer(rhs)) => Boolean(lhs < rhs),
-                (Integer(lhs), Float(rhs)) => Boolean((lhs as f64) < rhs),
-                (Float(lhs), Integer(rhs)) => Boolean(lhs < rhs as f64),
-                (Float(lhs), Float(rhs)) => Boolean(lhs < rhs),
-                (String(lhs), String(rhs)) => Boolean(lhs < rhs),
-                (Null, _) | (_, Null) => Null,
-                (lhs, rhs) => {
-                    return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs)))
+            }
+            Self::InList(expr, list) => {
+                let value = expr.evaluate(row)?;
+                let mut result = Boolean(false);
+                for item in list {
+                    let collation = Self::comparison_collation(&[expr, item])?;
+                    match Self::equal(value.clone(), item.evaluate(row)?, collation)? {
+                        Boolean(true) => return Ok(Boolean(true)),
+                        Null => result = Null,
+                        _ => {}
+                    }
                 }
-            },
+                result
+            }
+            Self::LessThan(lhs, rhs) => {
+                let collation = Self::comparison_collation(&[lhs, rhs])?;
+                Self::less_than(lhs.evaluate(row)?, rhs.evaluate(row)?, collation)?
+            }
+            Self::LessThanOrEqual(lhs, rhs) => {
+                let collation = Self::comparison_collation(&[lhs, rhs])?;
+                let (lhs, rhs) = (lhs.evaluate(row)?, rhs.evaluate(row)?);
+                match Self::less_than(lhs.clone(), rhs.clone(), collation)? {
+                    Boolean(false) => Self::equal(lhs, rhs, collation)?,
+                    value => value,
+                }
+            }
             Self::IsNull(expr) => match expr.evaluate(row)? {
                 Null => Boolean(true),
                 _ => Boolean(false),
@@ -129,7 +243,7 @@ impl Expression {
 
             // Mathematical operations
             // 算术运算
-            Self::Add(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
+            Self::Add(lhs, rhs) => match Self::evaluate_operands(lhs, rhs, row)? {
                 (Integer(lhs), Integer(rhs)) => Integer(
                     lhs.checked_add(rhs)
                         .ok_or_else(|| Error::Value("Integer overflow".into()))?,
@@ -142,16 +256,35 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Decimal(lhs), Decimal(rhs)) => Decimal(lhs.checked_add(&rhs)?),
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
+                (Date(d), Integer(i)) | (Integer(i), Date(d)) => Date(datetime::add_days(d, i)?),
+                (Date(d), Time(t)) | (Time(t), Date(d)) => {
+                    Timestamp(datetime::date_timestamp(d) + t)
+                }
+                (Date(d), Interval(i)) | (Interval(i), Date(d)) => {
+                    Timestamp(datetime::add_interval(datetime::date_timestamp(d), &i)?)
+                }
+                (Timestamp(ts), Interval(i)) | (Interval(i), Timestamp(ts)) => {
+                    Timestamp(datetime::add_interval(ts, &i)?)
+                }
+                (Time(t), Interval(i)) | (Interval(i), Time(t)) => {
+                    Time(datetime::add_time_interval(t, &i))
+                }
+                (Interval(lhs), Interval(rhs)) => Interval(lhs.checked_add(&rhs)?),
+                (Date(_) | Time(_) | Timestamp(_) | Interval(_), Null)
+                | (Null, Date(_) | Time(_) | Timestamp(_) | Interval(_)) => Null,
                 (lhs, rhs) => return Err(Error::Value(format!("Can't add {} and {}", lhs, rhs))),
             },
             Self::Assert(expr) => match expr.evaluate(row)? {
                 Float(f) => Float(f),
                 Integer(i) => Integer(i),
+                Decimal(d) => Decimal(d),
                 Null => Null,
                 expr => return Err(Error::Value(format!("Can't take the positive of {}", expr))),
             },
-            Self::Divide(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                (Integer(_), Integer(rhs)) if rhs == 0 => {
+            Self::Divide(lhs, rhs) => match Self::evaluate_operands(lhs, rhs, row)? {
+                (Integer(_), Integer(0)) => {
                     return Err(Error::Value("Can't divide by zero".into()))
                 }
                 (Integer(lhs), Integer(rhs)) => Integer(lhs / rhs),
@@ -163,11 +296,23 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Decimal(lhs), Decimal(rhs)) => Decimal(lhs.checked_div(&rhs)?),
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
+                (Interval(_), Integer(0)) => {
+                    return Err(Error::Value("Can't divide by zero".into()))
+                }
+                (Interval(i), Integer(rhs)) => Interval(i.checked_mul(1.0 / rhs as f64)?),
+                (Interval(i), Float(rhs)) => Interval(i.checked_mul(1.0 / rhs)?),
+                (Interval(_), Decimal(rhs)) if rhs.is_zero() => {
+                    return Err(Error::Value("Can't divide by zero".into()))
+                }
+                (Interval(i), Decimal(rhs)) => Interval(i.checked_mul(1.0 / rhs.to_f64())?),
+                (Interval(_), Null) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't divide {} and {}", lhs, rhs)))
                 }
             },
-            Self::Exponentiate(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
+            Self::Exponentiate(lhs, rhs) => match Self::evaluate_operands(lhs, rhs, row)? {
                 (Integer(lhs), Integer(rhs)) if rhs >= 0 => Integer(
                     lhs.checked_pow(rhs as u32)
                         .ok_or_else(|| Error::Value("Integer overflow".into()))?,
@@ -181,6 +326,12 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                // Decimals are only exact for non-negative integer exponents.
+                (Decimal(lhs), Decimal(rhs)) => match u32::try_from(rhs.mantissa()) {
+                    Ok(exponent) if rhs.scale() == 0 => Decimal(lhs.checked_pow(exponent)?),
+                    _ => Float(lhs.to_f64().powf(rhs.to_f64())),
+                },
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!(
                         "Can't exponentiate {} and {}",
@@ -194,13 +345,13 @@ impl Expression {
                         "Can't take factorial of negative number".into(),
                     ))
                 }
-                Integer(i) => Integer((1..=i).fold(1, |a, b| a * b as i64)),
+                Integer(i) => Integer((1..=i).product()),
                 Null => Null,
                 value => return Err(Error::Value(format!("Can't take factorial of {}", value))),
             },
-            Self::Modulo(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
+            Self::Modulo(lhs, rhs) => match Self::evaluate_operands(lhs, rhs, row)? {
                 // This uses remainder semantics, like Postgres.
-                (Integer(_), Integer(rhs)) if rhs == 0 => {
+                (Integer(_), Integer(0)) => {
                     return Err(Error::Value("Can't divide by zero".into()))
                 }
                 (Integer(lhs), Integer(rhs)) => Integer(lhs % rhs),
@@ -212,6 +363,8 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Decimal(lhs), Decimal(rhs)) => Decimal(lhs.checked_rem(&rhs)?),
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!(
                         "Can't take modulo of {} and {}",
@@ -219,7 +372,7 @@ impl Expression {
                     )))
                 }
             },
-            Self::Multiply(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
+            Self::Multiply(lhs, rhs) => match Self::evaluate_operands(lhs, rhs, row)? {
                 (Integer(lhs), Integer(rhs)) => Integer(
                     lhs.checked_mul(rhs)
                         .ok_or_else(|| Error::Value("Integer overflow".into()))?,
@@ -232,6 +385,16 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Decimal(lhs), Decimal(rhs)) => Decimal(lhs.checked_mul(&rhs)?),
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
+                (Interval(i), Integer(n)) | (Integer(n), Interval(i)) => {
+                    Interval(i.checked_mul(n as f64)?)
+                }
+                (Interval(i), Float(f)) | (Float(f), Interval(i)) => Interval(i.checked_mul(f)?),
+                (Interval(i), Decimal(d)) | (Decimal(d), Interval(i)) => {
+                    Interval(i.checked_mul(d.to_f64())?)
+                }
+                (Interval(_), Null) | (Null, Interval(_)) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't multiply {} and {}", lhs, rhs)))
                 }
@@ -239,10 +402,12 @@ impl Expression {
             Self::Negate(expr) => match expr.evaluate(row)? {
                 Integer(i) => Integer(-i),
                 Float(f) => Float(-f),
+                Decimal(d) => Decimal(d.checked_neg()?),
+                Interval(i) => Interval(i.checked_neg()?),
                 Null => Null,
                 value => return Err(Error::Value(format!("Can't negate {}", value))),
             },
-            Self::Subtract(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
+            Self::Subtract(lhs, rhs) => match Self::evaluate_operands(lhs, rhs, row)? {
                 (Integer(lhs), Integer(rhs)) => Integer(
                     lhs.checked_sub(rhs)
                         .ok_or_else(|| Error::Value("Integer overflow".into()))?,
@@ -255,32 +420,320 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Decimal(lhs), Decimal(rhs)) => Decimal(lhs.checked_sub(&rhs)?),
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
+                (Date(d), Integer(i)) => Date(datetime::add_days(
+                    d,
+                    i.checked_neg().ok_or_else(|| Error::Value("Integer overflow".into()))?,
+                )?),
+                (Date(lhs), Date(rhs)) => Integer(lhs as i64 - rhs as i64),
+                (Date(d), Interval(i)) => Timestamp(datetime::add_interval(
+                    datetime::date_timestamp(d),
+                    &i.checked_neg()?,
+                )?),
+                (Timestamp(ts), Interval(i)) => {
+                    Timestamp(datetime::add_interval(ts, &i.checked_neg()?)?)
+                }
+                (Time(t), Interval(i)) => Time(datetime::add_time_interval(t, &i.checked_neg()?)),
+                (Timestamp(lhs), Timestamp(rhs)) => {
+                    Interval(datetime::timestamp_difference(lhs, rhs)?)
+                }
+                (Time(lhs), Time(rhs)) => Interval(datetime::timestamp_difference(lhs, rhs)?),
+                (Interval(lhs), Interval(rhs)) => Interval(lhs.checked_add(&rhs.checked_neg()?)?),
+                (Date(_) | Time(_) | Timestamp(_) | Interval(_), Null)
+                | (Null, Date(_) | Time(_) | Timestamp(_) | Interval(_)) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't subtract {} and {}", lhs, rhs)))
                 }
             },
 
-            // String operations
-            Self::Like(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                (String(lhs), String(rhs)) => Boolean(
-                    Regex::new(&format!(
-                        "^{}$",
-                        regex::escape(&rhs)
-                            .replace("%", ".*")
-                            .replace(".*.*", "%")
-                            .replace("_", ".")
-                            .replace("..", "_")
-                    ))
-                    .unwrap()
-                    .is_match(&lhs),
-                ),
-                (String(_), Null) => Null,
-                (Null, String(_)) => Null,
-                (lhs, rhs) => return Err(Error::Value(format!("Can't LIKE {} and {}", lhs, rhs))),
+            // String operations. Constant patterns are compiled during planning, other patterns
+            // are compiled for every row.
+            Self::ILike(lhs, rhs)
+            | Self::IRegexMatch(lhs, rhs)
+            | Self::Like(lhs, rhs)
+            | Self::RegexMatch(lhs, rhs)
+            | Self::SimilarTo(lhs, rhs) => {
+                let lhs = lhs.evaluate(row)?;
+                if let (String(lhs), Self::Pattern(pattern)) = (&lhs, &**rhs) {
+                    return Ok(Boolean(pattern.is_match(lhs)));
+                }
+                match (lhs, rhs.evaluate(row)?) {
+                    (String(lhs), String(rhs)) => {
+                        let (syntax, case_insensitive) = self.match_syntax()?;
+                        Boolean(Pattern::new(syntax, &rhs, case_insensitive)?.is_match(&lhs))
+                    }
+                    (String(_), Null) => Null,
+                    (Null, String(_)) => Null,
+                    (lhs, rhs) => {
+                        return Err(Error::Value(format!("Can't match {} against {}", lhs, rhs)))
+                    }
+                }
+            }
+
+            // JSON operations. String documents are parsed, like for JSON functions.
+            Self::JsonGet(lhs, rhs) | Self::JsonGetText(lhs, rhs) => {
+                match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
+                    (Null, _) | (_, Null) => Null,
+                    (lhs @ (Json(_) | String(_)), rhs @ (String(_) | Integer(_))) => match self {
+                        Self::JsonGet(_, _) => json::get(&lhs, &rhs)?,
+                        _ => json::get_text(&lhs, &rhs)?,
+                    },
+                    (lhs, rhs) => {
+                        return Err(Error::Value(format!(
+                            "Can't look up {} in JSON document {}",
+                            rhs, lhs
+                        )))
+                    }
+                }
+            }
+
+            // Sequence operations
+            Self::CurrVal(_) | Self::NextVal(_) => {
+                return Err(Error::Internal(format!("Unresolved sequence operation {}", self)))
+            }
+
+            // Subquery operations
+            Self::Exists(_)
+            | Self::InSubquery(_, _)
+            | Self::OuterField(_, _, _)
+            | Self::Subquery(_) => {
+                return Err(Error::Internal(format!("Unresolved subquery operation {}", self)))
+            }
+        })
+    }
+
+    /// Evaluates the operands of a binary arithmetic operation, promoting them with promote()
+    fn evaluate_operands(lhs: &Self, rhs: &Self, row: Option<&Row>) -> Result<(Value, Value)> {
+        Ok(Self::promote(lhs.evaluate(row)?, rhs.evaluate(row)?))
+    }
+
+    /// Promotes the operands of a numeric operation involving a decimal. Integers are promoted to
+    /// exact decimals, while decimals are promoted to floats if the other operand is a float,
+    /// since floats are inexact anyway. Other operands are returned as is.
+    fn promote(lhs: Value, rhs: Value) -> (Value, Value) {
+        use Value::*;
+        match (lhs, rhs) {
+            (Decimal(lhs), Integer(rhs)) => (Decimal(lhs), Decimal(rhs.into())),
+            (Integer(lhs), Decimal(rhs)) => (Decimal(lhs.into()), Decimal(rhs)),
+            (Decimal(lhs), Float(rhs)) => (Float(lhs.to_f64()), Float(rhs)),
+            (Float(lhs), Decimal(rhs)) => (Float(lhs), Float(rhs.to_f64())),
+            (lhs, rhs) => (lhs, rhs),
+        }
+    }
+
+    /// Compares two date/time values. Returns None if either isn't a date/time value, or if they
+    /// can't be compared, e.g. a date and a time.
+    fn compare_temporal(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+        use Value::*;
+        match (lhs, rhs) {
+            (
+                Date(_) | Time(_) | Timestamp(_) | Interval(_),
+                Date(_) | Time(_) | Timestamp(_) | Interval(_),
+            ) => lhs.partial_cmp(rhs),
+            _ => None,
+        }
+    }
+
+    /// Returns the explicit collation of the expression, if any
+    pub fn collation(&self) -> Option<Collation> {
+        match self {
+            Self::Collate(_, collation) => Some(*collation),
+            _ => None,
+        }
+    }
+
+    /// Returns the collation used to compare the given operands, i.e. their explicit collation
+    /// or the default. Errors if operands have different explicit collations.
+    fn comparison_collation(operands: &[&Self]) -> Result<Collation> {
+        let mut collation = None;
+        for operand in operands {
+            collation = Collation::combine(collation, operand.collation())?;
+        }
+        Ok(collation.unwrap_or_default())
+    }
+
+    /// Returns the pattern syntax and case-insensitivity of a pattern matching operator. LIKE and
+    /// SIMILAR TO are case-insensitive under case-insensitive collations, while regular
+    /// expressions ignore collations.
+    fn match_syntax(&self) -> Result<(Syntax, bool)> {
+        let (syntax, case_insensitive, lhs, rhs) = match self {
+            Self::ILike(lhs, rhs) => (Syntax::Like, true, lhs, rhs),
+            Self::IRegexMatch(lhs, rhs) => (Syntax::Regex, true, lhs, rhs),
+            Self::Like(lhs, rhs) => (Syntax::Like, false, lhs, rhs),
+            Self::RegexMatch(lhs, rhs) => (Syntax::Regex, false, lhs, rhs),
+            Self::SimilarTo(lhs, rhs) => (Syntax::SimilarTo, false, lhs, rhs),
+            expr => return Err(Error::Internal(format!("Unexpected pattern match {}", expr))),
+        };
+        let case_insensitive = case_insensitive
+            || (syntax != Syntax::Regex
+                && Self::comparison_collation(&[lhs, rhs])?.is_case_insensitive());
+        Ok((syntax, case_insensitive))
+    }
+
+    /// Compiles a constant pattern given to the expression, i.e. the right-hand side of a pattern
+    /// matching operator or the regular expression of regexp_match() and regexp_replace() with
+    /// constant flags, such that it's compiled once during planning rather than for every row.
+    /// Invalid patterns are left as is, such that the error is returned during execution. Does
+    /// not descend into children.
+    pub fn compile_patterns(mut self) -> Self {
+        let compiled = match &self {
+            Self::ILike(_, rhs)
+            | Self::IRegexMatch(_, rhs)
+            | Self::Like(_, rhs)
+            | Self::RegexMatch(_, rhs)
+            | Self::SimilarTo(_, rhs) => match (&**rhs, self.match_syntax()) {
+                (Self::Constant(Value::String(source)), Ok((syntax, case_insensitive))) => {
+                    Pattern::new(syntax, source, case_insensitive).ok()
+                }
+                _ => None,
             },
+            Self::Function(function @ (Function::RegexpMatch | Function::RegexpReplace), args) => {
+                let global = *function == Function::RegexpReplace;
+                let flags = match args.get(if global { 3 } else { 2 }) {
+                    Some(Self::Constant(Value::String(flags))) => Some(flags.as_str()),
+                    Some(_) => None,
+                    None => Some(""),
+                };
+                match (args.get(1), flags.map(|flags| pattern::parse_flags(flags, global))) {
+                    (Some(Self::Constant(Value::String(source))), Some(Ok((ci, _)))) => {
+                        Pattern::new(Syntax::Regex, source, ci).ok()
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        match (&mut self, compiled) {
+            (Self::Function(_, args), Some(pattern)) => args[1] = Self::Pattern(pattern),
+            (
+                Self::ILike(_, rhs)
+                | Self::IRegexMatch(_, rhs)
+                | Self::Like(_, rhs)
+                | Self::RegexMatch(_, rhs)
+                | Self::SimilarTo(_, rhs),
+                Some(pattern),
+            ) => **rhs = Self::Pattern(pattern),
+            _ => {}
+        }
+        self
+    }
+
+    /// Compares two values for equality, with SQL NULL semantics
+    #[allow(clippy::float_cmp)] // Up to the user if they want to compare or not
+    fn equal(lhs: Value, rhs: Value, collation: Collation) -> Result<Value> {
+        use Value::*;
+        if let Some(ordering) = Self::compare_temporal(&lhs, &rhs) {
+            return Ok(Boolean(ordering == Ordering::Equal));
+        }
+        Ok(match Self::promote(lhs, rhs) {
+            (Boolean(lhs), Boolean(rhs)) => Boolean(lhs == rhs),
+            (Integer(lhs), Integer(rhs)) => Boolean(lhs == rhs),
+            (Integer(lhs), Float(rhs)) => Boolean(lhs as f64 == rhs),
+            (Float(lhs), Integer(rhs)) => Boolean(lhs == rhs as f64),
+            (Float(lhs), Float(rhs)) => Boolean(lhs == rhs),
+            (Decimal(lhs), Decimal(rhs)) => Boolean(lhs == rhs),
+            (String(lhs), String(rhs)) => Boolean(collation.compare(&lhs, &rhs).is_eq()),
+            (Binary(lhs), Binary(rhs)) => Boolean(lhs == rhs),
+            // Documents are normalized, so equal documents have equal text.
+            (Json(lhs), Json(rhs)) => Boolean(lhs == rhs),
+            (Null, _) | (_, Null) => Null,
+            (lhs, rhs) => return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs))),
         })
     }
 
+    /// Compares whether the left value is greater than the right, with SQL NULL semantics
+    fn greater_than(lhs: Value, rhs: Value, collation: Collation) -> Result<Value> {
+        use Value::*;
+        if let Some(ordering) = Self::compare_temporal(&lhs, &rhs) {
+            return Ok(Boolean(ordering == Ordering::Greater));
+        }
+        Ok(match Self::promote(lhs, rhs) {
+            #[allow(clippy::bool_comparison)]
+            (Boolean(lhs), Boolean(rhs)) => Boolean(lhs > rhs),
+            (Integer(lhs), Integer(rhs)) => Boolean(lhs > rhs),
+            (Integer(lhs), Float(rhs)) => Boolean(lhs as f64 > rhs),
+            (Float(lhs), Integer(rhs)) => Boolean(lhs > rhs as f64),
+            (Float(lhs), Float(rhs)) => Boolean(lhs > rhs),
+            (Decimal(lhs), Decimal(rhs)) => Boolean(lhs > rhs),
+            (String(lhs), String(rhs)) => Boolean(collation.compare(&lhs, &rhs).is_gt()),
+            (Binary(lhs), Binary(rhs)) => Boolean(lhs > rhs),
+            (Null, _) | (_, Null) => Null,
+            (lhs, rhs) => return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs))),
+        })
+    }
+
+    /// Compares whether the left value is less than the right, with SQL NULL semantics
+    fn less_than(lhs: Value, rhs: Value, collation: Collation) -> Result<Value> {
+        use Value::*;
+        if let Some(ordering) = Self::compare_temporal(&lhs, &rhs) {
+            return Ok(Boolean(ordering == Ordering::Less));
+        }
+        Ok(match Self::promote(lhs, rhs) {
+            #[allow(clippy::bool_comparison)]
+            (Boolean(lhs), Boolean(rhs)) => Boolean(lhs < rhs),
+            (Integer(lhs), Integer(rhs)) => Boolean(lhs < rhs),
+            (Integer(lhs), Float(rhs)) => Boolean((lhs as f64) < rhs),
+            (Float(lhs), Integer(rhs)) => Boolean(lhs < rhs as f64),
+            (Float(lhs), Float(rhs)) => Boolean(lhs < rhs),
+            (Decimal(lhs), Decimal(rhs)) => Boolean(lhs < rhs),
+            (String(lhs), String(rhs)) => Boolean(collation.compare(&lhs, &rhs).is_lt()),
+            (Binary(lhs), Binary(rhs)) => Boolean(lhs < rhs),
+            (Null, _) | (_, Null) => Null,
+            (lhs, rhs) => return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs))),
+        })
+    }
+
+    /// Binds the row of an enclosing query at the given number of scopes out, by replacing
+    /// references to its fields with constants. Subqueries are bound separately by the executor.
+    pub fn bind_outer(self, depth: usize, row: &[Value]) -> Result<Self> {
+        self.transform(
+            &|e| match e {
+                Self::OuterField(d, i, _) if d == depth => {
+                    Ok(Self::Constant(row.get(i).cloned().unwrap_or(Value::Null)))
+                }
+                e => Ok(e),
+            },
+            &|e| Ok(e),
+        )
+    }
+
+    /// Returns true if the expression references the row of an enclosing query at the given
+    /// number of scopes out or further, not including through subqueries.
+    pub fn references_outer(&self, depth: usize) -> bool {
+        self.contains(&|e| matches!(e, Self::OuterField(d, _, _) if *d >= depth))
+    }
+
+    /// Returns true if the expression has no sequence or subquery operations, which must be
+    /// resolved before it can be evaluated.
+    pub fn is_resolved(&self) -> bool {
+        !self.contains(&|e| {
+            matches!(
+                e,
+                Self::CurrVal(_)
+                    | Self::NextVal(_)
+                    | Self::Exists(_)
+                    | Self::InSubquery(_, _)
+                    | Self::Subquery(_)
+            )
+        })
+    }
+
+    /// Returns the indexes of the subqueries used by the expression.
+    pub fn subqueries(&self) -> Vec<usize> {
+        let ids = RefCell::new(Vec::new());
+        self.walk(&|e| {
+            match e {
+                Self::Exists(id) | Self::InSubquery(_, id) | Self::Subquery(id) => {
+                    ids.borrow_mut().push(*id)
+                }
+                _ => {}
+            }
+            true
+        });
+        ids.into_inner()
+    }
+
     /// Walks the expression tree while calling a closure. Returns true as soon as the closure
     /// returns true. This is the inverse of walk().
     pub fn contains<F: Fn(&Expression) -> bool>(&self, visitor: &F) -> bool {
@@ -310,27 +763,127 @@ impl Expression {
             | Self::Equal(lhs, rhs)
             | Self::Exponentiate(lhs, rhs)
             | Self::GreaterThan(lhs, rhs)
+            | Self::GreaterThanOrEqual(lhs, rhs)
+            | Self::ILike(lhs, rhs)
+            | Self::IRegexMatch(lhs, rhs)
+            | Self::JsonGet(lhs, rhs)
+            | Self::JsonGetText(lhs, rhs)
             | Self::LessThan(lhs, rhs)
+            | Self::LessThanOrEqual(lhs, rhs)
             | Self::Like(lhs, rhs)
             | Self::Modulo(lhs, rhs)
             | Self::Multiply(lhs, rhs)
             | Self::Or(lhs, rhs)
+            | Self::RegexMatch(lhs, rhs)
+            | Self::SimilarTo(lhs, rhs)
             | Self::Subtract(lhs, rhs) => {
                 Self::replace_with(lhs, |e| e.transform(before, after))?;
                 Self::replace_with(rhs, |e| e.transform(before, after))?;
             }
 
+            Self::Between(expr, low, high) => {
+                Self::replace_with(expr, |e| e.transform(before, after))?;
+                Self::replace_with(low, |e| e.transform(before, after))?;
+                Self::replace_with(high, |e| e.transform(before, after))?;
+            }
+
             Self::Assert(expr)
+            | Self::Cast(expr, _)
+            | Self::Collate(expr, _)
             | Self::Factorial(expr)
             | Self::IsNull(expr)
+            | Self::InSubquery(expr, _)
             | Self::Negate(expr)
             | Self::Not(expr) => Self::replace_with(expr, |e| e.transform(before, after))?,
 
-            Self::Constant(_) | Self::Field(_, _) => {}
+            Self::Coalesce(exprs) | Self::Function(_, exprs) => {
+                for expr in exprs {
+                    Self::replace_with(expr, |e| e.transform(before, after))?;
+                }
+            }
+
+            Self::InList(expr, list) => {
+                Self::replace_with(expr, |e| e.transform(before, after))?;
+                for item in list {
+                    Self::replace_with(item, |e| e.transform(before, after))?;
+                }
+            }
+
+            Self::Case { operand, branches, r#else } => {
+                let exprs = operand.iter_mut().map(|e| &mut **e);
+                let exprs = exprs.chain(branches.iter_mut().flat_map(|(w, t)| [w, t]));
+                for expr in exprs.chain(r#else.iter_mut().map(|e| &mut **e)) {
+                    Self::replace_with(expr, |e| e.transform(before, after))?;
+                }
+            }
+
+            // Subquery plans have their own scope, and are not descended into.
+            Self::Constant(_)
+            | Self::CurrVal(_)
+            | Self::Exists(_)
+            | Self::Field(_, _)
+            | Self::NextVal(_)
+            | Self::OuterField(_, _, _)
+            | Self::Pattern(_)
+            | Self::Subquery(_) => {}
         };
         after(self)
     }
 
+    /// Returns the expression's datatype if it can be determined without evaluating it, e.g.
+    /// for constants, given the datatypes of the fields where known. Used to check function
+    /// arguments and set operation columns during planning.
+    pub fn static_datatype(&self, fields: &[Option<DataType>]) -> Option<DataType> {
+        let numeric = |lhs: &Self, rhs: &Self| {
+            DataType::promote(&lhs.static_datatype(fields)?, &rhs.static_datatype(fields)?)
+        };
+        match self {
+            Self::Constant(value) => value.datatype(),
+            Self::Field(i, _) => fields.get(*i).cloned().flatten(),
+            Self::Function(function, args) => function.return_datatype(
+                &args.iter().map(|e| e.static_datatype(fields)).collect::<Vec<_>>(),
+            ),
+            Self::Cast(_, datatype) => Some(datatype.clone()),
+            Self::Collate(expr, _) => expr.static_datatype(fields),
+            Self::Coalesce(exprs) => exprs.iter().find_map(|e| e.static_datatype(fields)),
+            Self::Case { branches, r#else, .. } => branches
+                .iter()
+                .map(|(_, then)| then)
+                .chain(r#else.as_deref())
+                .find_map(|e| e.static_datatype(fields)),
+            Self::And(_, _)
+            | Self::Between(_, _, _)
+            | Self::Equal(_, _)
+            | Self::Exists(_)
+            | Self::GreaterThan(_, _)
+            | Self::GreaterThanOrEqual(_, _)
+            | Self::InList(_, _)
+            | Self::InSubquery(_, _)
+            | Self::IsNull(_)
+            | Self::ILike(_, _)
+            | Self::IRegexMatch(_, _)
+            | Self::LessThan(_, _)
+            | Self::LessThanOrEqual(_, _)
+            | Self::Like(_, _)
+            | Self::Not(_)
+            | Self::Or(_, _)
+            | Self::RegexMatch(_, _)
+            | Self::SimilarTo(_, _) => Some(DataType::Boolean),
+            // Only numeric operations are typed, other operands (e.g. dates) may be valid too.
+            Self::Add(lhs, rhs)
+            | Self::Divide(lhs, rhs)
+            | Self::Modulo(lhs, rhs)
+            | Self::Multiply(lhs, rhs)
+            | Self::Subtract(lhs, rhs) => numeric(lhs, rhs),
+            Self::Assert(expr) | Self::Negate(expr) => numeric(expr, expr),
+            Self::Factorial(_) | Self::CurrVal(_) | Self::NextVal(_) => Some(DataType::Integer),
+            Self::JsonGet(_, _) => Some(DataType::Json),
+            Self::JsonGetText(_, _) | Self::Pattern(_) => Some(DataType::String),
+            // Integer exponents may give floats, depending on their sign.
+            Self::Exponentiate(_, _) | Self::OuterField(_, _, _) | Self::Subquery(_) => None,
+        }
+    }
+
     /// Walks the expression tree, calling a closure for every node. Halts if closure returns false.
     /// 遍历每个表示式 得到结果
     /// 递归运算
@@ -344,20 +897,57 @@ impl Expression {
                 | Self::Equal(lhs, rhs)
                 | Self::Exponentiate(lhs, rhs)
                 | Self::GreaterThan(lhs, rhs)
+                | Self::GreaterThanOrEqual(lhs, rhs)
+                | Self::ILike(lhs, rhs)
+                | Self::IRegexMatch(lhs, rhs)
+                | Self::JsonGet(lhs, rhs)
+                | Self::JsonGetText(lhs, rhs)
                 | Self::LessThan(lhs, rhs)
+                | Self::LessThanOrEqual(lhs, rhs)
                 | Self::Like(lhs, rhs)
                 | Self::Modulo(lhs, rhs)
                 | Self::Multiply(lhs, rhs)
                 | Self::Or(lhs, rhs)
+                | Self::RegexMatch(lhs, rhs)
+                | Self::SimilarTo(lhs, rhs)
                 | Self::Subtract(lhs, rhs) => lhs.walk(visitor) && rhs.walk(visitor),
 
+                Self::Between(expr, low, high) => {
+                    expr.walk(visitor) && low.walk(visitor) && high.walk(visitor)
+                }
+
                 Self::Assert(expr)
+                | Self::Cast(expr, _)
+                | Self::Collate(expr, _)
                 | Self::Factorial(expr)
                 | Self::IsNull(expr)
+                | Self::InSubquery(expr, _)
                 | Self::Negate(expr)
                 | Self::Not(expr) => expr.walk(visitor),
 
-                Self::Constant(_) | Self::Field(_, _) => true,
+                Self::Coalesce(exprs) | Self::Function(_, exprs) => {
+                    exprs.iter().all(|e| e.walk(visitor))
+                }
+
+                Self::InList(expr, list) => {
+                    expr.walk(visitor) && list.iter().all(|e| e.walk(visitor))
+                }
+
+                Self::Case { operand, branches, r#else } => operand
+                    .iter()
+                    .map(|e| &**e)
+                    .chain(branches.iter().flat_map(|(w, t)| [w, t]))
+                    .chain(r#else.as_deref())
+                    .all(|e| e.walk(visitor)),
+
+                Self::Constant(_)
+                | Self::CurrVal(_)
+                | Self::Exists(_)
+                | Self::Field(_, _)
+                | Self::NextVal(_)
+                | Self::OuterField(_, _, _)
+                | Self::Pattern(_)
+                | Self::Subquery(_) => true,
             }
     }
 
@@ -508,7 +1098,7 @@ impl Expression {
         use Expression::*;
         // FIXME This should use a single match level, but since the child expressions are boxed
         // that would require box patterns, which are unstable.
-        match &*self {
+        match self {
             Equal(lhs, rhs) => match (&**lhs, &**rhs) {
                 (Field(i, _), Constant(v)) if i == &field => Some(vec![v.clone()]),
                 (Constant(v), Field(i, _)) if i == &field => Some(vec![v.clone()]),
@@ -518,6 +1108,18 @@ impl Expression {
                 Field(i, _) if i == &field => Some(vec![Value::Null]),
                 _ => None,
             },
+            // NULL list items never match, and are skipped.
+            InList(e, list) => match &**e {
+                Field(i, _) if i == &field => list
+                    .iter()
+                    .filter(|item| item != &&Constant(Value::Null))
+                    .map(|item| match item {
+                        Constant(v) => Some(v.clone()),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => None,
+            },
             Or(lhs, rhs) => match (lhs.as_lookup(field), rhs.as_lookup(field)) {
                 (Some(mut lvalues), Some(mut rvalues)) => {
                     lvalues.append(&mut rvalues);
@@ -529,6 +1131,32 @@ impl Expression {
         }
     }
 
+    // Checks if the expression is a lookup of a tuple of fields, e.g. a composite primary key, and
+    // returns the list of value tuples looked up. The expression must be an OR of ANDs, where each
+    // AND gives exactly one value for every field using = or IS NULL.
+    pub fn as_lookup_tuple(&self, fields: &[usize]) -> Option<Vec<Vec<Value>>> {
+        if let [field] = fields {
+            return self.as_lookup(*field).map(|vs| vs.into_iter().map(|v| vec![v]).collect());
+        }
+        let mut tuples = Vec::new();
+        for conjunction in self.clone().into_dnf_vec() {
+            let mut tuple: Vec<Option<Value>> = vec![None; fields.len()];
+            for expr in conjunction.into_cnf_vec() {
+                let (i, value) = fields.iter().enumerate().find_map(|(i, field)| {
+                    match expr.as_lookup(*field).as_deref() {
+                        Some([value]) => Some((i, value.clone())),
+                        _ => None,
+                    }
+                })?;
+                if tuple[i].replace(value).is_some() {
+                    return None;
+                }
+            }
+            tuples.push(tuple.into_iter().collect::<Option<Vec<_>>>()?);
+        }
+        Some(tuples)
+    }
+
     // Creates an expression from a list of field lookup values.
     pub fn from_lookup(
         field: usize,
@@ -560,17 +1188,53 @@ impl Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
             Self::Constant(v) => v.to_string(),
+            Self::Pattern(pattern) => Value::String(pattern.source().to_string()).to_string(),
             Self::Field(i, None) => format!("#{}", i),
             Self::Field(_, Some((None, name))) => name.to_string(),
             Self::Field(_, Some((Some(table), name))) => format!("{}.{}", table, name),
+            Self::Coalesce(exprs) => format!(
+                "coalesce({})",
+                exprs.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ")
+            ),
+            Self::Function(function, args) => format!(
+                "{}({})",
+                function,
+                args.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ")
+            ),
+            Self::Case { operand, branches, r#else } => {
+                let mut s = "CASE".to_string();
+                if let Some(operand) = operand {
+                    s += &format!(" {}", operand);
+                }
+                for (when, then) in branches {
+                    s += &format!(" WHEN {} THEN {}", when, then);
+                }
+                if let Some(r#else) = r#else {
+                    s += &format!(" ELSE {}", r#else);
+                }
+                s + " END"
+            }
+            Self::Cast(expr, datatype) => format!("CAST({} AS {})", expr, datatype),
+            Self::Collate(expr, collation) => format!("{} COLLATE {}", expr, collation),
+            Self::OuterField(depth, i, None) => format!("outer({})#{}", depth, i),
+            Self::OuterField(_, _, Some((None, name))) => name.to_string(),
+            Self::OuterField(_, _, Some((Some(table), name))) => format!("{}.{}", table, name),
 
             Self::And(lhs, rhs) => format!("{} AND {}", lhs, rhs),
             Self::Or(lhs, rhs) => format!("{} OR {}", lhs, rhs),
             Self::Not(expr) => format!("NOT {}", expr),
 
+            Self::Between(expr, low, high) => format!("{} BETWEEN {} AND {}", expr, low, high),
             Self::Equal(lhs, rhs) => format!("{} = {}", lhs, rhs),
             Self::GreaterThan(lhs, rhs) => format!("{} > {}", lhs, rhs),
+            Self::GreaterThanOrEqual(lhs, rhs) => format!("{} >= {}", lhs, rhs),
             Self::LessThan(lhs, rhs) => format!("{} < {}", lhs, rhs),
+            Self::LessThanOrEqual(lhs, rhs) => format!("{} <= {}", lhs, rhs),
+            Self::InList(expr, list) => format!(
+                "{} IN ({})",
+                expr,
+                list.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ")
+            ),
             Self::IsNull(expr) => format!("{} IS NULL", expr),
 
             Self::Add(lhs, rhs) => format!("{} + {}", lhs, rhs),
@@ -583,8 +1247,151 @@ impl Display for Expression {
             Self::Negate(expr) => format!("-{}", expr),
             Self::Subtract(lhs, rhs) => format!("{} - {}", lhs, rhs),
 
+            Self::ILike(lhs, rhs) => format!("{} ILIKE {}", lhs, rhs),
+            Self::IRegexMatch(lhs, rhs) => format!("{} ~* {}", lhs, rhs),
             Self::Like(lhs, rhs) => format!("{} LIKE {}", lhs, rhs),
+            Self::RegexMatch(lhs, rhs) => format!("{} ~ {}", lhs, rhs),
+            Self::SimilarTo(lhs, rhs) => format!("{} SIMILAR TO {}", lhs, rhs),
+
+            Self::JsonGet(lhs, rhs) => format!("{} -> {}", lhs, rhs),
+            Self::JsonGetText(lhs, rhs) => format!("{} ->> {}", lhs, rhs),
+
+            Self::CurrVal(sequence) => format!("currval('{}')", sequence.replace('\'', "''")),
+            Self::NextVal(sequence) => format!("nextval('{}')", sequence.replace('\'', "''")),
+
+            Self::Exists(id) => format!("EXISTS (subquery #{})", id),
+            Self::InSubquery(expr, id) => format!("{} IN (subquery #{})", expr, id),
+            Self::Subquery(id) => format!("(subquery #{})", id),
         };
         write!(f, "{}", s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Expression::*;
+
+    fn c(value: Value) -> Box<Expression> {
+        Box::new(Constant(value))
+    }
+
+    fn build_case(
+        operand: Option<Expression>,
+        branches: Vec<(Value, Value)>,
+        r#else: Option<Value>,
+    ) -> Expression {
+        Case {
+            operand: operand.map(Box::new),
+            branches: branches.into_iter().map(|(w, t)| (Constant(w), Constant(t))).collect(),
+            r#else: r#else.map(c),
+        }
+    }
+
+    #[test]
+    fn case() -> Result<()> {
+        use Value::{Boolean, Integer, Null};
+        let row = vec![Integer(2), Null];
+        // Simple CASE compares the operand for equality, and picks the first match.
+        let simple = |operand, r#else| {
+            let branches =
+                vec![(Integer(1), Integer(10)), (Integer(2), Integer(20)), (Null, Integer(30))];
+            build_case(Some(operand), branches, r#else).evaluate(Some(&row))
+        };
+        assert_eq!(Integer(20), simple(Field(0, None), None)?);
+        assert_eq!(Integer(20), simple(Constant(Integer(2)), Some(Integer(0)))?);
+
+        // A NULL operand doesn't match anything, not even a NULL WHEN value, and without an
+        // ELSE the result is NULL.
+        assert_eq!(Null, simple(Field(1, None), None)?);
+        assert_eq!(Integer(0), simple(Field(1, None), Some(Integer(0)))?);
+        assert_eq!(Integer(0), simple(Constant(Integer(3)), Some(Integer(0)))?);
+
+        // Searched CASE treats NULL conditions as false, and errors on non-boolean ones.
+        let searched = |conditions: Vec<Value>| {
+            let branches = conditions.into_iter().zip([Integer(1), Integer(2)]).collect();
+            build_case(None, branches, Some(Integer(3))).evaluate(None)
+        };
+        assert_eq!(Integer(1), searched(vec![Boolean(true), Boolean(true)])?);
+        assert_eq!(Integer(2), searched(vec![Null, Boolean(true)])?);
+        assert_eq!(Integer(3), searched(vec![Boolean(false), Null])?);
+        assert!(searched(vec![Integer(1)]).is_err());
+
+        // Branches after the first match aren't evaluated.
+        let error = Divide(c(Integer(1)), c(Integer(0)));
+        let lazy = Case {
+            operand: None,
+            branches: vec![(Constant(Boolean(true)), Constant(Integer(1))), (error.clone(), error)],
+            r#else: None,
+        };
+        assert_eq!(Integer(1), lazy.evaluate(None)?);
+        assert_eq!(Some(DataType::Integer), lazy.static_datatype(&[]));
+        Ok(())
+    }
+
+    #[test]
+    fn in_list() -> Result<()> {
+        use Value::{Boolean, Integer, Null};
+        let in_list = |value, list: Vec<Value>| {
+            InList(c(value), list.into_iter().map(Constant).collect()).evaluate(None)
+        };
+
+        // Like a chain of ORs, a NULL comparison gives NULL rather than false, unless there's
+        // a match.
+        assert_eq!(Boolean(true), in_list(Integer(2), vec![Integer(1), Integer(2)])?);
+        assert_eq!(Boolean(false), in_list(Integer(3), vec![Integer(1), Integer(2)])?);
+        assert_eq!(Boolean(true), in_list(Integer(2), vec![Null, Integer(2)])?);
+        assert_eq!(Null, in_list(Integer(3), vec![Integer(1), Null])?);
+        assert_eq!(Null, in_list(Null, vec![Integer(1)])?);
+
+        // NOT IN is also NULL then, so it doesn't match either.
+        let not_in = Not(Box::new(InList(c(Integer(3)), vec![Constant(Null)])));
+        assert_eq!(Null, not_in.evaluate(None)?);
+
+        // Values are compared across numeric types, but not across incompatible ones.
+        assert_eq!(Boolean(true), in_list(Integer(1), vec![Value::Float(1.0)])?);
+        assert!(in_list(Integer(1), vec![Value::String("1".into())]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn between() -> Result<()> {
+        use Value::{Boolean, Integer, Null, String};
+        let between = |value, low, high| Between(c(value), c(low), c(high)).evaluate(None);
+
+        // Bounds are inclusive.
+        assert_eq!(Boolean(true), between(Integer(1), Integer(1), Integer(3))?);
+        assert_eq!(Boolean(true), between(Integer(3), Integer(1), Integer(3))?);
+        assert_eq!(Boolean(false), between(Integer(4), Integer(1), Integer(3))?);
+        assert_eq!(Boolean(false), between(Integer(2), Integer(3), Integer(1))?);
+        let string = |s: &str| String(s.into());
+        assert_eq!(Boolean(true), between(string("b"), string("a"), string("c"))?);
+
+        // A NULL bound gives NULL, unless the other bound already excludes the value.
+        assert_eq!(Null, between(Null, Integer(1), Integer(3))?);
+        assert_eq!(Null, between(Integer(2), Null, Integer(3))?);
+        assert_eq!(Null, between(Integer(2), Integer(1), Null)?);
+        assert_eq!(Boolean(false), between(Integer(0), Integer(1), Null)?);
+        assert_eq!(Boolean(false), between(Integer(4), Null, Integer(3))?);
+        Ok(())
+    }
+
+    #[test]
+    fn cast() -> Result<()> {
+        use Value::{Boolean, Float, Integer, Null, String};
+        let cast = |value, datatype| Cast(c(value), datatype).evaluate(None);
+
+        assert_eq!(Integer(3), cast(String(" 3 ".into()), DataType::Integer)?);
+        assert_eq!(Integer(3), cast(Float(2.5), DataType::Integer)?);
+        assert_eq!(Float(1.0), cast(Boolean(true).cast(&DataType::Integer)?, DataType::Float)?);
+        assert_eq!(Boolean(false), cast(String("off".into()), DataType::Boolean)?);
+        assert_eq!(String("2.5".into()), cast(Float(2.5), DataType::String)?);
+        assert_eq!(Null, cast(Null, DataType::Integer)?);
+        assert!(cast(String("x".into()), DataType::Integer).is_err());
+        assert!(cast(Float(f64::NAN), DataType::Integer).is_err());
+
+        // The cast datatype is known statically, even for NULL.
+        assert_eq!(Some(DataType::Integer), Cast(c(Null), DataType::Integer).static_datatype(&[]));
+        Ok(())
+    }
+}
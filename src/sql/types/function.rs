@@ -0,0 +1,769 @@
+use super::pattern::{self, Pattern, Syntax};
+use super::{datetime, json, DataType, Expression, Rows, Value};
+use crate::error::{Error, Result};
+
+use lazy_static::lazy_static;
+use serde_derive::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::sync::{Arc, RwLock};
+
+lazy_static! {
+    /// User-defined functions, by name. These are process-wide, like the built-in functions.
+    static ref USER_FUNCTIONS: RwLock<HashMap<String, Arc<UserFunction>>> =
+        RwLock::new(HashMap::new());
+}
+
+/// The implementation of a user-defined function
+pub type UserFunctionImpl = dyn Fn(Vec<Value>) -> Result<Value> + Send + Sync;
+
+/// A user-defined scalar function, implemented by a Rust closure
+pub struct UserFunction {
+    args: Vec<DataType>,
+    returns: DataType,
+    deterministic: bool,
+    function: Box<UserFunctionImpl>,
+}
+
+/// Registers a Rust closure as a scalar SQL function, callable by name wherever expressions are
+/// evaluated. The closure is given argument values of the declared datatypes, and must return
+/// a value of the declared return datatype. Like most built-in functions, it is not called if
+/// any argument is NULL, and NULL is returned instead. Deterministic functions must always
+/// return the same value for the same arguments, and may be evaluated once during planning when
+/// given constant arguments. Errors if the name is already taken.
+pub fn register_function<F>(
+    name: &str,
+    args: Vec<DataType>,
+    returns: DataType,
+    deterministic: bool,
+    function: F,
+) -> Result<()>
+where
+    F: Fn(Vec<Value>) -> Result<Value> + Send + Sync + 'static,
+{
+    let name = name.to_lowercase();
+    if name == "coalesce" || Function::builtin(&name).is_some() {
+        return Err(Error::Value(format!("Function {} is a built-in function", name)));
+    }
+    let mut functions = USER_FUNCTIONS.write()?;
+    if functions.contains_key(&name) {
+        return Err(Error::Value(format!("Function {} already exists", name)));
+    }
+    let function = UserFunction { args, returns, deterministic, function: Box::new(function) };
+    functions.insert(name, Arc::new(function));
+    Ok(())
+}
+
+/// Unregisters a user-defined function, returning false if it did not exist. Plans that call
+/// the function will error when evaluated.
+pub fn unregister_function(name: &str) -> Result<bool> {
+    Ok(USER_FUNCTIONS.write()?.remove(&name.to_lowercase()).is_some())
+}
+
+/// Looks up a user-defined function
+fn user_function(name: &str) -> Result<Arc<UserFunction>> {
+    USER_FUNCTIONS
+        .read()?
+        .get(name)
+        .cloned()
+        .ok_or_else(|| Error::Value(format!("Unknown function {}", name)))
+}
+
+/// A scalar function, either built-in or user-defined. Unless otherwise noted, functions return
+/// NULL if any argument is NULL.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Function {
+    // String functions
+    /// concat(value, ...): the values as concatenated strings, skipping NULLs
+    Concat,
+    /// length(string): the number of characters, or bytes for binary values
+    Length,
+    Lower,
+    /// regexp_match(string, pattern [, flags]): the capture groups of the first match as a JSON
+    /// array, or the entire match if there are no groups. NULL if there is no match.
+    RegexpMatch,
+    /// regexp_replace(string, pattern, replacement [, flags]): replaces the first match, or all
+    /// matches with the g flag. \1 to \9 in the replacement insert capture groups.
+    RegexpReplace,
+    /// replace(string, from, to)
+    Replace,
+    /// substr(string, start [, length]): characters, or bytes for binary values, from 1-based start
+    Substr,
+    /// trim(string [, characters]): strips whitespace, or the given characters, from both ends
+    Trim,
+    Upper,
+
+    // Math functions
+    Abs,
+    Ceil,
+    Floor,
+    Ln,
+    /// round(number [, digits])
+    Round,
+    Sqrt,
+
+    // Comparison functions
+    /// greatest(value, ...): the largest value, skipping NULLs
+    Greatest,
+    /// least(value, ...): the smallest value, skipping NULLs
+    Least,
+    /// nullif(a, b): NULL if a equals b, otherwise a
+    NullIf,
+
+    // Date/time functions
+    /// date_trunc(unit, timestamp): the timestamp truncated to the given unit, e.g. 'month'
+    DateTrunc,
+    /// extract(field FROM value): a field of a date/time value, e.g. year. Built with the
+    /// field name as the first argument, and also callable as extract('year', value).
+    Extract,
+    /// now(): the current timestamp
+    Now,
+
+    // JSON functions
+    /// json_array_length(json [, path]): the number of elements of a JSON array
+    JsonArrayLength,
+    /// json_extract(json, path): the value at a path such as $.a[0], as a SQL value if scalar
+    JsonExtract,
+
+    /// A user-defined function, by name (see register_function())
+    User(String),
+}
+
+impl Function {
+    /// Looks up a function by name, including user-defined functions
+    pub fn from_name(name: &str) -> Result<Option<Self>> {
+        if let Some(function) = Self::builtin(name) {
+            return Ok(Some(function));
+        }
+        Ok(USER_FUNCTIONS.read()?.get(name).map(|_| Self::User(name.to_string())))
+    }
+
+    /// Looks up a built-in function by name
+    fn builtin(name: &str) -> Option<Self> {
+        Some(match name {
+            "abs" => Self::Abs,
+            "ceil" | "ceiling" => Self::Ceil,
+            "concat" => Self::Concat,
+            "date_trunc" => Self::DateTrunc,
+            "extract" => Self::Extract,
+            "floor" => Self::Floor,
+            "greatest" => Self::Greatest,
+            "json_array_length" => Self::JsonArrayLength,
+            "json_extract" => Self::JsonExtract,
+            "least" => Self::Least,
+            "length" | "char_length" => Self::Length,
+            "ln" => Self::Ln,
+            "lower" => Self::Lower,
+            "now" => Self::Now,
+            "nullif" => Self::NullIf,
+            "regexp_match" => Self::RegexpMatch,
+            "regexp_replace" => Self::RegexpReplace,
+            "replace" => Self::Replace,
+            "round" => Self::Round,
+            "sqrt" => Self::Sqrt,
+            "substr" | "substring" => Self::Substr,
+            "trim" => Self::Trim,
+            "upper" => Self::Upper,
+            _ => return None,
+        })
+    }
+
+    /// Builds a function call expression, checking the arity and any known argument types.
+    /// COALESCE is built as Expression::Coalesce, since it evaluates its arguments lazily.
+    /// Returns None if there is no such function.
+    pub fn build(name: &str, args: Vec<Expression>) -> Result<Option<Expression>> {
+        if name == "coalesce" {
+            if args.is_empty() {
+                return Err(Error::Value("coalesce takes at least 1 argument".into()));
+            }
+            return Ok(Some(Expression::Coalesce(args)));
+        }
+        let function = match Self::from_name(name)? {
+            Some(function) => function,
+            None => return Ok(None),
+        };
+        let types = args.iter().map(|e| e.static_datatype(&[])).collect::<Vec<_>>();
+        function.check(&types)?;
+        Ok(Some(Expression::Function(function, args)))
+    }
+
+    /// Returns true if the function always returns the same value for the same arguments, such
+    /// that calls with constant arguments can be evaluated during planning.
+    pub fn is_deterministic(&self) -> Result<bool> {
+        match self {
+            Self::User(name) => Ok(user_function(name)?.deterministic),
+            Self::Now => Ok(false),
+            _ => Ok(true),
+        }
+    }
+
+    /// Returns the allowed number of arguments, as (min, max), with None for no maximum
+    fn arity(&self) -> Result<(usize, Option<usize>)> {
+        Ok(match self {
+            Self::Concat | Self::Greatest | Self::Least => (1, None),
+            Self::Now => (0, Some(0)),
+            Self::Abs
+            | Self::Ceil
+            | Self::Floor
+            | Self::Length
+            | Self::Ln
+            | Self::Lower
+            | Self::Sqrt
+            | Self::Upper => (1, Some(1)),
+            Self::JsonArrayLength | Self::Round | Self::Trim => (1, Some(2)),
+            Self::DateTrunc | Self::Extract | Self::JsonExtract | Self::NullIf => (2, Some(2)),
+            Self::RegexpMatch | Self::Substr => (2, Some(3)),
+            Self::Replace => (3, Some(3)),
+            Self::RegexpReplace => (3, Some(4)),
+            Self::User(name) => {
+                let args = user_function(name)?.args.len();
+                (args, Some(args))
+            }
+        })
+    }
+
+    /// Checks the number of arguments and their datatypes, where known. This is done at plan
+    /// time, and again during evaluation.
+    pub fn check(&self, types: &[Option<DataType>]) -> Result<()> {
+        let (min, max) = self.arity()?;
+        let user = match self {
+            Self::User(name) => Some(user_function(name)?),
+            _ => None,
+        };
+        if types.len() < min || max.is_some_and(|max| types.len() > max) {
+            return Err(Error::Value(format!(
+                "{} takes {} arguments, got {}",
+                self,
+                match max {
+                    Some(max) if max == min => min.to_string(),
+                    Some(max) => format!("{} to {}", min, max),
+                    None => format!("at least {}", min),
+                },
+                types.len()
+            )));
+        }
+        for (i, datatype) in types.iter().enumerate() {
+            let valid = match (self, datatype) {
+                (_, None) => true,
+                (Self::User(_), Some(t)) => user.as_ref().is_some_and(|u| u.args[i].matches(t)),
+                (Self::Concat | Self::Greatest | Self::Least | Self::NullIf, _) => true,
+                (Self::Length, t) => matches!(t, Some(DataType::String | DataType::Binary)),
+                (Self::Substr, t) if i == 0 => {
+                    matches!(t, Some(DataType::String | DataType::Binary))
+                }
+                (
+                    Self::Lower
+                    | Self::RegexpMatch
+                    | Self::RegexpReplace
+                    | Self::Replace
+                    | Self::Trim
+                    | Self::Upper,
+                    t,
+                ) => t == &Some(DataType::String),
+                (Self::DateTrunc | Self::Extract, t) if i == 0 => t == &Some(DataType::String),
+                (Self::JsonArrayLength | Self::JsonExtract, t) if i == 0 => {
+                    matches!(t, Some(DataType::Json | DataType::String))
+                }
+                (Self::JsonArrayLength | Self::JsonExtract, t) => t == &Some(DataType::String),
+                (Self::DateTrunc, t) => matches!(t, Some(DataType::Date | DataType::Timestamp)),
+                (Self::Extract, t) => matches!(
+                    t,
+                    Some(
+                        DataType::Date | DataType::Time | DataType::Timestamp | DataType::Interval
+                    )
+                ),
+                (Self::Substr | Self::Round, t) if i > 0 => t == &Some(DataType::Integer),
+                (Self::Abs | Self::Ceil | Self::Floor | Self::Ln | Self::Round | Self::Sqrt, t) => {
+                    matches!(t, Some(DataType::Integer | DataType::Float | DataType::Decimal(..)))
+                }
+                _ => false,
+            };
+            if !valid {
+                return Err(Error::Value(format!(
+                    "Invalid argument {} for {}: {}",
+                    i + 1,
+                    self,
+                    datatype.as_ref().map_or("NULL".into(), |t| t.to_string())
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the return datatype, given the argument datatypes where known
+    pub fn return_datatype(&self, types: &[Option<DataType>]) -> Option<DataType> {
+        match self {
+            Self::User(name) => user_function(name).ok().map(|f| f.returns.clone()),
+            Self::Concat
+            | Self::Lower
+            | Self::RegexpReplace
+            | Self::Replace
+            | Self::Trim
+            | Self::Upper => Some(DataType::String),
+            Self::RegexpMatch => Some(DataType::Json),
+            Self::Substr => match types.first() {
+                Some(Some(DataType::Binary)) => Some(DataType::Binary),
+                _ => Some(DataType::String),
+            },
+            Self::JsonArrayLength | Self::Length => Some(DataType::Integer),
+            // Depends on the extracted JSON value.
+            Self::JsonExtract => None,
+            Self::DateTrunc | Self::Now => Some(DataType::Timestamp),
+            // Depends on the field: seconds and epochs are floats, other fields integers.
+            Self::Extract => None,
+            Self::Ln | Self::Sqrt => Some(DataType::Float),
+            Self::Abs | Self::Ceil | Self::Floor | Self::Round | Self::NullIf => {
+                types.first().cloned().flatten()
+            }
+            Self::Greatest | Self::Least => types.iter().flatten().next().cloned(),
+        }
+    }
+
+    /// Evaluates the function for the given argument values
+    pub fn evaluate(&self, args: Vec<Value>) -> Result<Value> {
+        self.evaluate_with_pattern(args, None)
+    }
+
+    /// Evaluates the function for the given argument values, using the given compiled regular
+    /// expression for regexp_match() and regexp_replace() if any, see
+    /// Expression::compile_patterns(). Otherwise, the regular expression is compiled here.
+    pub fn evaluate_with_pattern(
+        &self,
+        args: Vec<Value>,
+        pattern: Option<&Pattern>,
+    ) -> Result<Value> {
+        self.check(&args.iter().map(|v| v.datatype()).collect::<Vec<_>>())?;
+        use Value::*;
+        match self {
+            Self::Concat => {
+                return Ok(String(
+                    args.into_iter().filter(|v| v != &Null).map(|v| v.to_string()).collect(),
+                ))
+            }
+            Self::Greatest | Self::Least => {
+                let wanted =
+                    if *self == Self::Greatest { Ordering::Greater } else { Ordering::Less };
+                let mut result = None;
+                for value in args.into_iter().filter(|v| v != &Null) {
+                    result = Some(match result {
+                        Some(current) => {
+                            let ordering = value.partial_cmp(&current).ok_or_else(|| {
+                                Error::Value(format!("Can't compare {} and {}", value, current))
+                            })?;
+                            if ordering == wanted {
+                                value
+                            } else {
+                                current
+                            }
+                        }
+                        None => value,
+                    });
+                }
+                return Ok(result.unwrap_or(Null));
+            }
+            Self::NullIf => {
+                let mut args = args.into_iter();
+                let (a, b) = (args.next().unwrap_or(Null), args.next().unwrap_or(Null));
+                return Ok(if a != Null && a.partial_cmp(&b) == Some(Ordering::Equal) {
+                    Null
+                } else {
+                    a
+                });
+            }
+            _ => {}
+        }
+        if args.contains(&Null) {
+            return Ok(Null);
+        }
+        if let Self::User(name) = self {
+            let function = user_function(name)?;
+            return match (function.function)(args)? {
+                value if value.datatype().is_none_or(|t| t.matches(&function.returns)) => Ok(value),
+                value => Err(Error::Value(format!(
+                    "Function {} returned {}, expected {}",
+                    name, value, function.returns
+                ))),
+            };
+        }
+
+        let compile = |regex: &str, case_insensitive| match pattern {
+            Some(pattern) => Ok(Cow::Borrowed(pattern)),
+            None => Pattern::new(Syntax::Regex, regex, case_insensitive).map(Cow::Owned),
+        };
+        let mut args = args.into_iter();
+        let mut next = || args.next().unwrap_or(Null);
+        Ok(match (self, next()) {
+            (Self::Now, _) => Timestamp(datetime::now()?),
+            (Self::Extract, String(field)) => datetime::extract(&field, &next())?,
+            (Self::DateTrunc, String(unit)) => match next() {
+                Date(date) => Timestamp(datetime::truncate(&unit, datetime::date_timestamp(date))?),
+                Timestamp(timestamp) => Timestamp(datetime::truncate(&unit, timestamp)?),
+                value => return Err(Error::Internal(format!("Unexpected value {}", value))),
+            },
+
+            (Self::JsonArrayLength, value) => match next() {
+                String(path) => match json::extract(&value, &path)? {
+                    Null => Null,
+                    value => json::array_length(&value)?,
+                },
+                _ => json::array_length(&value)?,
+            },
+            (Self::JsonExtract, value) => json::extract(&value, &next().string()?)?,
+
+            (Self::Length, String(s)) => Integer(s.chars().count() as i64),
+            (Self::Length, Binary(b)) => Integer(b.len() as i64),
+            (Self::Lower, String(s)) => String(s.to_lowercase()),
+            (Self::Upper, String(s)) => String(s.to_uppercase()),
+            (Self::Replace, String(s)) => match (next(), next()) {
+                (String(from), String(to)) => String(s.replace(&from, &to)),
+                _ => return Err(Error::Internal("Unexpected replace arguments".into())),
+            },
+            // Flags are optional, so a missing flags argument is NULL.
+            (Self::RegexpMatch, String(s)) => {
+                let (regex, flags) = (next().string()?, next().string().unwrap_or_default());
+                let (case_insensitive, _) = pattern::parse_flags(&flags, false)?;
+                match pattern::captures(&s, &*compile(&regex, case_insensitive)?) {
+                    Some(groups) => Json(json::array(groups)),
+                    None => Null,
+                }
+            }
+            (Self::RegexpReplace, String(s)) => {
+                let (regex, replacement) = (next().string()?, next().string()?);
+                let flags = next().string().unwrap_or_default();
+                let (case_insensitive, global) = pattern::parse_flags(&flags, true)?;
+                let regex = compile(&regex, case_insensitive)?;
+                String(pattern::replace(&s, &regex, &replacement, global))
+            }
+            (Self::Substr, value @ (String(_) | Binary(_))) => {
+                let start = next().integer()?;
+                let end = match next() {
+                    Integer(length) if length < 0 => {
+                        return Err(Error::Value("Negative substring length".into()))
+                    }
+                    Integer(length) => start.saturating_add(length),
+                    _ => i64::MAX,
+                };
+                // Positions are 1-based, and may start before the string.
+                let skip = (start.max(1) - 1) as usize;
+                let take = end.saturating_sub(start.max(1)).max(0) as usize;
+                match value {
+                    String(s) => String(s.chars().skip(skip).take(take).collect()),
+                    Binary(b) => Binary(b.into_iter().skip(skip).take(take).collect()),
+                    value => return Err(Error::Internal(format!("Unexpected value {}", value))),
+                }
+            }
+            (Self::Trim, String(s)) => match next() {
+                String(chars) => String(s.trim_matches(|c| chars.contains(c)).to_string()),
+                _ => String(s.trim().to_string()),
+            },
+
+            (Self::Abs, Integer(i)) => {
+                Integer(i.checked_abs().ok_or_else(|| Error::Value("Integer overflow".into()))?)
+            }
+            (Self::Abs, Float(f)) => Float(f.abs()),
+            (Self::Ceil, Float(f)) => Float(f.ceil()),
+            (Self::Floor, Float(f)) => Float(f.floor()),
+            (Self::Ceil | Self::Floor, Integer(i)) => Integer(i),
+            (Self::Abs, Decimal(d)) => Decimal(d.abs()),
+            (Self::Ceil, Decimal(d)) => Decimal(d.ceil()?),
+            (Self::Floor, Decimal(d)) => Decimal(d.floor()),
+            (Self::Round, value) => {
+                let digits = match next() {
+                    Integer(digits) => digits,
+                    _ => 0,
+                };
+                match value {
+                    Integer(i) if digits >= 0 => Integer(i),
+                    Integer(i) => {
+                        // Rounds half away from zero, in i128 since the factor may exceed i64.
+                        let overflow = || Error::Value("Integer overflow".into());
+                        let exponent = digits.unsigned_abs().min(u32::MAX as u64) as u32;
+                        let i = i as i128;
+                        match 10i128.checked_pow(exponent) {
+                            Some(factor) => {
+                                let (quotient, remainder) = (i / factor, i % factor);
+                                let quotient = match remainder.abs().checked_mul(2) {
+                                    Some(twice) if twice >= factor => quotient + remainder.signum(),
+                                    _ => quotient,
+                                };
+                                let rounded = quotient.checked_mul(factor).ok_or_else(overflow)?;
+                                Integer(rounded.try_into().map_err(|_| overflow())?)
+                            }
+                            None => Integer(0),
+                        }
+                    }
+                    Float(f) => {
+                        // If scaling overflows, the digits are beyond the float's precision and
+                        // there's nothing to round.
+                        let factor = 10f64.powi(digits.clamp(-308, 308) as i32);
+                        match f * factor {
+                            scaled if scaled.is_finite() => Float(scaled.round() / factor),
+                            _ => Float(f),
+                        }
+                    }
+                    Decimal(d) => Decimal(d.round(digits)?),
+                    value => return Err(Error::Internal(format!("Unexpected value {}", value))),
+                }
+            }
+            (Self::Ln, value) => match to_float(value)? {
+                f if f <= 0.0 => {
+                    return Err(Error::Value("Can't take logarithm of non-positive number".into()))
+                }
+                f => Float(f.ln()),
+            },
+            (Self::Sqrt, value) => match to_float(value)? {
+                f if f < 0.0 => {
+                    return Err(Error::Value("Can't take square root of negative number".into()))
+                }
+                f => Float(f.sqrt()),
+            },
+            (function, value) => {
+                return Err(Error::Internal(format!("Unexpected {} argument {}", function, value)))
+            }
+        })
+    }
+}
+
+/// Converts a numeric value to a float
+fn to_float(value: Value) -> Result<f64> {
+    match value {
+        Value::Integer(i) => Ok(i as f64),
+        Value::Float(f) => Ok(f),
+        Value::Decimal(d) => Ok(d.to_f64()),
+        value => Err(Error::Value(format!("Not a number: {}", value))),
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Abs => "abs",
+            Self::Ceil => "ceil",
+            Self::Concat => "concat",
+            Self::DateTrunc => "date_trunc",
+            Self::Extract => "extract",
+            Self::Floor => "floor",
+            Self::Greatest => "greatest",
+            Self::JsonArrayLength => "json_array_length",
+            Self::JsonExtract => "json_extract",
+            Self::Least => "least",
+            Self::Length => "length",
+            Self::Ln => "ln",
+            Self::Lower => "lower",
+            Self::Now => "now",
+            Self::NullIf => "nullif",
+            Self::RegexpMatch => "regexp_match",
+            Self::RegexpReplace => "regexp_replace",
+            Self::Replace => "replace",
+            Self::Round => "round",
+            Self::Sqrt => "sqrt",
+            Self::Substr => "substr",
+            Self::Trim => "trim",
+            Self::Upper => "upper",
+            Self::User(name) => name,
+        })
+    }
+}
+
+/// A table function, which is called in a FROM clause and returns a set of rows. NULL arguments
+/// yield no rows.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TableFunction {
+    /// json_each(json): the top-level object fields or array elements of a document, as rows
+    /// of (key, value) where value is JSON
+    JsonEach,
+    /// regexp_split_to_table(string, pattern [, flags]): the parts of a string between matches
+    RegexpSplitToTable,
+}
+
+impl TableFunction {
+    /// Looks up a table function by name
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "json_each" => Ok(Self::JsonEach),
+            "regexp_split_to_table" => Ok(Self::RegexpSplitToTable),
+            name => Err(Error::Value(format!("Unknown table function {}", name))),
+        }
+    }
+
+    /// Returns the names of the function's result columns
+    pub fn columns(&self) -> Vec<&'static str> {
+        match self {
+            Self::JsonEach => vec!["key", "value"],
+            Self::RegexpSplitToTable => vec!["regexp_split_to_table"],
+        }
+    }
+
+    /// Evaluates the function for the given arguments
+    pub fn evaluate(&self, args: Vec<Value>) -> Result<Rows> {
+        let rows = match (self, args.as_slice()) {
+            (_, args) if args.contains(&Value::Null) => Vec::new(),
+            (Self::JsonEach, [value]) => json::each(value)?,
+            (Self::RegexpSplitToTable, [Value::String(s), Value::String(regex), flags @ ..])
+                if flags.len() <= 1 =>
+            {
+                let flags = match flags {
+                    [Value::String(flags)] => flags.as_str(),
+                    [flags] => return Err(Error::Value(format!("Invalid flags {}", flags))),
+                    _ => "",
+                };
+                let (case_insensitive, _) = pattern::parse_flags(flags, false)?;
+                let regex = Pattern::new(Syntax::Regex, regex, case_insensitive)?;
+                let parts = pattern::split(s, &regex);
+                parts.into_iter().map(|part| vec![Value::String(part)]).collect()
+            }
+            (_, args) => {
+                return Err(Error::Value(format!(
+                    "Invalid arguments for {}: {}",
+                    self,
+                    args.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ")
+                )))
+            }
+        };
+        Ok(Box::new(rows.into_iter().map(Ok)))
+    }
+}
+
+impl Display for TableFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::JsonEach => "json_each",
+            Self::RegexpSplitToTable => "regexp_split_to_table",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds and evaluates a function call with constant arguments
+    fn call(name: &str, args: Vec<Value>) -> Result<Value> {
+        let args = args.into_iter().map(Expression::Constant).collect();
+        match Function::build(name, args)? {
+            Some(expr) => expr.evaluate(None),
+            None => Err(Error::Value(format!("Unknown function {}", name))),
+        }
+    }
+
+    #[test]
+    fn builtin() -> Result<()> {
+        use Value::*;
+        let s = |s: &str| String(s.into());
+
+        assert_eq!(s("ABC"), call("upper", vec![s("abc")])?);
+        assert_eq!(s("abc"), call("lower", vec![s("ABC")])?);
+        assert_eq!(Integer(3), call("length", vec![s("åäö")])?);
+        assert_eq!(Integer(2), call("char_length", vec![Binary(vec![1, 2])])?);
+        assert_eq!(s("bc"), call("substr", vec![s("abcd"), Integer(2), Integer(2)])?);
+        assert_eq!(s("a"), call("substring", vec![s("abcd"), Integer(0), Integer(2)])?);
+        assert_eq!(s("cd"), call("substr", vec![s("abcd"), Integer(3)])?);
+        assert!(call("substr", vec![s("abcd"), Integer(1), Integer(-1)]).is_err());
+        assert_eq!(s("a b"), call("trim", vec![s(" a b  ")])?);
+        assert_eq!(s("a"), call("trim", vec![s("xxayx"), s("xy")])?);
+        assert_eq!(s("a-b-c"), call("replace", vec![s("a b c"), s(" "), s("-")])?);
+        assert_eq!(s("a1TRUE"), call("concat", vec![s("a"), Integer(1), Null, Boolean(true)])?);
+
+        assert_eq!(Integer(3), call("abs", vec![Integer(-3)])?);
+        assert!(call("abs", vec![Integer(i64::MIN)]).is_err());
+        assert_eq!(Float(2.0), call("ceil", vec![Float(1.1)])?);
+        assert_eq!(Float(1.0), call("floor", vec![Float(1.9)])?);
+        assert_eq!(Float(3.0), call("round", vec![Float(2.5)])?);
+        assert_eq!(Float(1.23), call("round", vec![Float(1.234), Integer(2)])?);
+        assert_eq!(Integer(1200), call("round", vec![Integer(1234), Integer(-2)])?);
+        assert_eq!(Integer(-1300), call("round", vec![Integer(-1250), Integer(-2)])?);
+        assert_eq!(
+            Integer(9_223_372_036_854_775_000),
+            call("round", vec![Integer(i64::MAX - 1000), Integer(-3)])?
+        );
+        assert!(call("round", vec![Integer(i64::MAX), Integer(-3)]).is_err());
+        assert!(call("round", vec![Integer(i64::MIN), Integer(-19)]).is_err());
+        assert_eq!(Integer(0), call("round", vec![Integer(i64::MAX), Integer(-20)])?);
+        assert_eq!(Integer(0), call("round", vec![Integer(i64::MIN), Integer(i64::MIN)])?);
+        assert_eq!(Float(2.0), call("round", vec![Float(2.0), Integer(308)])?);
+        assert_eq!(Float(1.5), call("round", vec![Float(1.5), Integer(i64::MAX)])?);
+        assert_eq!(Float(0.0), call("round", vec![Float(1.5), Integer(-308)])?);
+        assert_eq!(Float(3.0), call("sqrt", vec![Integer(9)])?);
+        assert!(call("sqrt", vec![Integer(-1)]).is_err());
+        assert_eq!(Float(0.0), call("ln", vec![Integer(1)])?);
+        assert!(call("ln", vec![Integer(0)]).is_err());
+
+        assert_eq!(Integer(3), call("greatest", vec![Integer(1), Null, Integer(3)])?);
+        assert_eq!(Integer(1), call("least", vec![Integer(1), Null, Integer(3)])?);
+        assert_eq!(Null, call("greatest", vec![Null])?);
+        assert_eq!(Null, call("nullif", vec![Integer(1), Integer(1)])?);
+        assert_eq!(Integer(1), call("nullif", vec![Integer(1), Integer(2)])?);
+        assert_eq!(Integer(1), call("coalesce", vec![Null, Integer(1), Integer(2)])?);
+
+        // Most functions return NULL for NULL arguments.
+        assert_eq!(Null, call("upper", vec![Null])?);
+        assert_eq!(Null, call("substr", vec![s("abc"), Null])?);
+        Ok(())
+    }
+
+    #[test]
+    fn user() -> Result<()> {
+        use Value::*;
+        let types = vec![DataType::String, DataType::Integer];
+        register_function("Test_Repeat", types, DataType::String, true, |args| {
+            match args.as_slice() {
+                [String(s), Integer(n)] => Ok(String(s.repeat(*n as usize))),
+                args => Err(Error::Value(format!("Unexpected arguments {:?}", args))),
+            }
+        })?;
+        register_function("test_invalid", Vec::new(), DataType::Integer, false, |_| {
+            Ok(String("a".into()))
+        })?;
+        let repeat = |args| call("test_repeat", args);
+
+        // Names are case-insensitive, and must not be taken by other functions.
+        assert_eq!(String("abab".into()), repeat(vec![String("ab".into()), Integer(2)])?);
+        let register =
+            |name| register_function(name, Vec::new(), DataType::Integer, true, |_| Ok(Null));
+        assert!(register("test_repeat").is_err());
+        assert!(register("upper").is_err());
+        assert!(register("coalesce").is_err());
+
+        // Arguments are checked against the declared datatypes, NULL arguments give NULL
+        // without calling the function, and return values must have the declared datatype.
+        assert!(repeat(vec![String("ab".into())]).is_err());
+        assert!(repeat(vec![Integer(1), Integer(2)]).is_err());
+        assert_eq!(Null, repeat(vec![Null, Integer(2)])?);
+        assert!(call("test_invalid", Vec::new()).is_err());
+        assert!(!Function::User("test_invalid".into()).is_deterministic()?);
+
+        assert!(unregister_function("TEST_REPEAT")?);
+        assert!(!unregister_function("test_repeat")?);
+        assert!(repeat(vec![String("ab".into()), Integer(2)]).is_err());
+        unregister_function("test_invalid")?;
+        Ok(())
+    }
+
+    #[test]
+    fn check() -> Result<()> {
+        use Value::*;
+        // Arity and known argument types are checked when building the call.
+        assert!(call("upper", vec![]).is_err());
+        assert!(call("upper", vec![String("a".into()), String("b".into())]).is_err());
+        assert!(call("upper", vec![Integer(1)]).is_err());
+        assert!(call("abs", vec![String("a".into())]).is_err());
+        assert!(call("substr", vec![String("a".into()), Float(1.0)]).is_err());
+        assert!(call("greatest", vec![]).is_err());
+        assert!(call("coalesce", vec![]).is_err());
+        assert_eq!(None, Function::build("missing", Vec::new())?);
+
+        let field = Expression::Field(0, None);
+        assert!(Function::build("upper", vec![field.clone()])?.is_some());
+        assert_eq!(
+            Some(DataType::Float),
+            Function::Sqrt.return_datatype(&[Some(DataType::Integer)])
+        );
+        assert_eq!(
+            Some(DataType::Integer),
+            Function::Abs.return_datatype(&[Some(DataType::Integer)])
+        );
+        Ok(())
+    }
+}
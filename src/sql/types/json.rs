@@ -0,0 +1,274 @@
+//! JSON documents. These are stored as normalized JSON text, i.e. compact and with object keys
+//! in sorted order, such that equal documents have equal representations. Documents are parsed
+//! as needed when evaluating JSON operators and functions.
+
+use super::{Row, Value};
+use crate::error::{Error, Result};
+
+use serde_json::Value as Json;
+
+/// Parses a JSON document
+pub fn parse(text: &str) -> Result<Json> {
+    serde_json::from_str(text).map_err(|err| Error::Value(format!("Invalid JSON: {}", err)))
+}
+
+/// Validates and normalizes a JSON document
+pub fn normalize(text: &str) -> Result<String> {
+    Ok(parse(text)?.to_string())
+}
+
+/// Converts a scalar SQL value to a JSON document, e.g. when casting
+pub fn from_value(value: &Value) -> Result<String> {
+    Ok(match value {
+        Value::Boolean(b) => Json::Bool(*b),
+        Value::Integer(i) => Json::from(*i),
+        Value::Float(f) => serde_json::Number::from_f64(*f)
+            .map(Json::Number)
+            .ok_or_else(|| Error::Value(format!("Can't convert {} to JSON", f)))?,
+        Value::String(s) => Json::String(s.clone()),
+        value => return Err(Error::Value(format!("Can't convert {} to JSON", value))),
+    }
+    .to_string())
+}
+
+/// Builds a JSON array of strings, where None is JSON null, e.g. for regexp_match()
+pub fn array(strings: Vec<Option<String>>) -> String {
+    Json::from(strings).to_string()
+}
+
+/// Returns the JSON document of a JSON or string value
+fn document(value: &Value) -> Result<Json> {
+    match value {
+        Value::Json(text) | Value::String(text) => parse(text),
+        value => Err(Error::Value(format!("Not a JSON document: {}", value))),
+    }
+}
+
+/// Looks up an object field by string key, or an array element by integer index, where
+/// negative indexes count from the end of the array. Returns None if there is no such field or
+/// element, or if the document isn't an object or array respectively.
+fn lookup(json: Json, key: &Value) -> Option<Json> {
+    match (json, key) {
+        (Json::Object(mut object), Value::String(key)) => object.remove(key),
+        (Json::Array(mut array), Value::Integer(index)) => {
+            let index = match *index {
+                i if i < 0 => (array.len() as i64).checked_add(i)?,
+                i => i,
+            };
+            let index = usize::try_from(index).ok().filter(|i| *i < array.len())?;
+            Some(array.swap_remove(index))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the field or element of a document as JSON, for the -> operator
+pub fn get(value: &Value, key: &Value) -> Result<Value> {
+    Ok(match lookup(document(value)?, key) {
+        Some(json) => Value::Json(json.to_string()),
+        None => Value::Null,
+    })
+}
+
+/// Returns the field or element of a document as text, for the ->> operator. JSON strings are
+/// returned without quotes, JSON null as NULL, and other values as JSON text.
+pub fn get_text(value: &Value, key: &Value) -> Result<Value> {
+    Ok(match lookup(document(value)?, key) {
+        Some(Json::Null) | None => Value::Null,
+        Some(Json::String(s)) => Value::String(s),
+        Some(json) => Value::String(json.to_string()),
+    })
+}
+
+/// Converts a JSON value to the corresponding SQL value. Objects and arrays remain JSON.
+fn to_value(json: Json) -> Value {
+    match json {
+        Json::Null => Value::Null,
+        Json::Bool(b) => Value::Boolean(b),
+        Json::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Json::String(s) => Value::String(s),
+        json => Value::Json(json.to_string()),
+    }
+}
+
+/// Parses a path such as $.a.b[0] or $."a b"[-1] into a sequence of lookup keys
+fn parse_path(path: &str) -> Result<Vec<Value>> {
+    let invalid = || Error::Value(format!("Invalid JSON path {}", path));
+    let mut chars = path.trim().chars().peekable();
+    if chars.next() != Some('$') {
+        return Err(invalid());
+    }
+    let mut keys = Vec::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' if chars.peek() == Some(&'"') => {
+                chars.next();
+                let key: String = chars.by_ref().take_while(|c| *c != '"').collect();
+                keys.push(Value::String(key));
+            }
+            '.' => {
+                let mut key = String::new();
+                while let Some(c) = chars.next_if(|c| *c != '.' && *c != '[') {
+                    key.push(c);
+                }
+                if key.is_empty() {
+                    return Err(invalid());
+                }
+                keys.push(Value::String(key));
+            }
+            '[' => {
+                let index: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                keys.push(Value::Integer(index.trim().parse().map_err(|_| invalid())?));
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(keys)
+}
+
+/// Extracts the value at a path from a document, for json_extract(). Returns NULL if the path
+/// doesn't exist.
+pub fn extract(value: &Value, path: &str) -> Result<Value> {
+    let mut json = document(value)?;
+    for key in parse_path(path)? {
+        match lookup(json, &key) {
+            Some(next) => json = next,
+            None => return Ok(Value::Null),
+        }
+    }
+    Ok(to_value(json))
+}
+
+/// Returns the length of a JSON array, for json_array_length()
+pub fn array_length(value: &Value) -> Result<Value> {
+    match document(value)? {
+        Json::Array(array) => Ok(Value::Integer(array.len() as i64)),
+        json => Err(Error::Value(format!("Can't get array length of non-array {}", json))),
+    }
+}
+
+/// Expands the top-level elements of a document into (key, value) rows, for json_each(). Object
+/// fields are keyed by name and array elements by index, while values are JSON. Scalars yield a
+/// single row with a NULL key.
+pub fn each(value: &Value) -> Result<Vec<Row>> {
+    Ok(match document(value)? {
+        Json::Object(object) => object
+            .into_iter()
+            .map(|(k, v)| vec![Value::String(k), Value::Json(v.to_string())])
+            .collect(),
+        Json::Array(array) => array
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| vec![Value::Integer(i as i64), Value::Json(v.to_string())])
+            .collect(),
+        json => vec![vec![Value::Null, Value::Json(json.to_string())]],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_convert() -> Result<()> {
+        // Documents are compacted with sorted object keys, so equal documents are equal text.
+        assert_eq!(r#"{"a":[1,2.5],"b":null}"#, normalize(r#" { "b": null, "a": [1, 2.5] } "#)?);
+        assert_eq!(normalize(r#"{"x":1,"y":2}"#)?, normalize(r#"{"y": 2, "x": 1}"#)?);
+        assert!(normalize("{").is_err());
+        assert!(normalize("").is_err());
+
+        assert_eq!("true", from_value(&Value::Boolean(true))?);
+        assert_eq!("-3", from_value(&Value::Integer(-3))?);
+        assert_eq!("1.5", from_value(&Value::Float(1.5))?);
+        assert_eq!(r#""a\"b""#, from_value(&Value::String("a\"b".into()))?);
+        assert!(from_value(&Value::Float(f64::NAN)).is_err());
+        assert!(from_value(&Value::Null).is_err());
+        assert_eq!(r#"["a",null]"#, array(vec![Some("a".into()), None]));
+        Ok(())
+    }
+
+    #[test]
+    fn get() -> Result<()> {
+        let doc = Value::Json(r#"{"a":{"b":1},"s":"x","n":null,"l":[10,"y",false]}"#.into());
+        let key = |k: &str| Value::String(k.into());
+
+        // -> returns JSON, while ->> returns text with strings unquoted and null as NULL.
+        assert_eq!(Value::Json(r#"{"b":1}"#.into()), super::get(&doc, &key("a"))?);
+        assert_eq!(Value::Json(r#""x""#.into()), super::get(&doc, &key("s"))?);
+        assert_eq!(Value::Json("null".into()), super::get(&doc, &key("n"))?);
+        assert_eq!(Value::String("x".into()), get_text(&doc, &key("s"))?);
+        assert_eq!(Value::String(r#"{"b":1}"#.into()), get_text(&doc, &key("a"))?);
+        assert_eq!(Value::Null, get_text(&doc, &key("n"))?);
+
+        // Missing fields, out of range indexes and mismatched key types are NULL.
+        assert_eq!(Value::Null, super::get(&doc, &key("missing"))?);
+        assert_eq!(Value::Null, super::get(&doc, &Value::Integer(0))?);
+        let list = super::get(&doc, &key("l"))?;
+        assert_eq!(Value::Json("10".into()), super::get(&list, &Value::Integer(0))?);
+        assert_eq!(Value::String("false".into()), get_text(&list, &Value::Integer(-1))?);
+        assert_eq!(Value::String("10".into()), get_text(&list, &Value::Integer(-3))?);
+        for index in [3, -4, i64::MIN] {
+            assert_eq!(Value::Null, super::get(&list, &Value::Integer(index))?, "{}", index);
+        }
+        assert_eq!(Value::Null, super::get(&list, &key("0"))?);
+
+        // Strings are parsed as documents, but other values aren't documents.
+        assert_eq!(
+            Value::Json("1".into()),
+            super::get(&Value::String("[1]".into()), &Value::Integer(0))?
+        );
+        assert!(super::get(&Value::String("[1".into()), &Value::Integer(0)).is_err());
+        assert!(super::get(&Value::Integer(1), &Value::Integer(0)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn extract_path() -> Result<()> {
+        let doc = Value::Json(r#"{"a":{"b":[1,2.5,"s",true,null,{"c":[]}]},"a b":{"c":3}}"#.into());
+        let extract = |path: &str| super::extract(&doc, path);
+
+        // Scalars are converted to SQL values, while objects and arrays remain JSON.
+        assert_eq!(Value::Integer(1), extract("$.a.b[0]")?);
+        assert_eq!(Value::Float(2.5), extract("$.a.b[1]")?);
+        assert_eq!(Value::String("s".into()), extract("$.a.b[2]")?);
+        assert_eq!(Value::Boolean(true), extract("$.a.b[-3]")?);
+        assert_eq!(Value::Null, extract("$.a.b[4]")?);
+        assert_eq!(Value::Json("[]".into()), extract("$.a.b[5].c")?);
+        assert_eq!(Value::Integer(3), extract(r#"$."a b".c"#)?);
+        assert_eq!(doc, extract(" $ ")?);
+
+        // Missing paths are NULL, but malformed paths are errors.
+        assert_eq!(Value::Null, extract("$.x.y")?);
+        assert_eq!(Value::Null, extract("$.a.b[9]")?);
+        for path in ["", "a", "$.", "$.a..b", "$[x]", "$a"] {
+            assert!(extract(path).is_err(), "{}", path);
+        }
+
+        assert_eq!(Value::Integer(6), array_length(&extract("$.a.b")?)?);
+        assert_eq!(Value::Integer(0), array_length(&Value::String("[]".into()))?);
+        assert!(array_length(&extract("$.a")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn each() -> Result<()> {
+        let json = |s: &str| Value::Json(s.into());
+        assert_eq!(
+            vec![
+                vec![Value::String("a".into()), json("1")],
+                vec![Value::String("b".into()), json(r#"{"c":null}"#)],
+            ],
+            super::each(&json(r#"{"b":{"c":null},"a":1}"#))?
+        );
+        assert_eq!(
+            vec![vec![Value::Integer(0), json(r#""x""#)], vec![Value::Integer(1), json("[]")]],
+            super::each(&json(r#"["x",[]]"#))?
+        );
+        assert_eq!(vec![vec![Value::Null, json("7")]], super::each(&json("7"))?);
+        assert_eq!(Vec::<Row>::new(), super::each(&json("{}"))?);
+        Ok(())
+    }
+}
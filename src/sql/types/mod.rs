@@ -1,5 +1,15 @@
+pub mod collation;
+pub mod datetime;
+pub mod decimal;
 mod expression;
+mod function;
+pub mod json;
+pub mod pattern;
+pub use collation::Collation;
+pub use datetime::Interval;
+pub use decimal::{Decimal, RoundingMode};
 pub use expression::Expression;
+pub use function::{register_function, unregister_function, Function, TableFunction, UserFunction};
 
 use crate::error::{Error, Result};
 
@@ -15,48 +25,131 @@ pub enum DataType {
     Boolean,
     Integer,
     Float,
+    /// An exact decimal with the given precision (total digits) and scale (fractional digits)
+    Decimal(u8, u8),
     String,
+    Binary,
+    Json,
+    Date,
+    Time,
+    Timestamp,
+    Interval,
+}
+
+impl DataType {
+    /// Returns true if the datatypes are the same, ignoring decimal precision and scale. Values
+    /// of a datatype only have the precision and scale they need, so decimals of any precision
+    /// and scale are considered the same datatype, and are rounded and checked separately.
+    pub fn matches(&self, other: &DataType) -> bool {
+        matches!((self, other), (Self::Decimal(..), Self::Decimal(..))) || self == other
+    }
+
+    /// Returns the datatype of a numeric operation's result, given the operand datatypes, or
+    /// None if either isn't numeric. Operands are promoted like Expression::evaluate() does:
+    /// integers to decimals, and both to floats.
+    pub fn promote(lhs: &DataType, rhs: &DataType) -> Option<DataType> {
+        use DataType::*;
+        match (lhs, rhs) {
+            (Integer, Integer) => Some(Integer),
+            (Float, Integer | Float | Decimal(..)) | (Integer | Decimal(..), Float) => Some(Float),
+            (Decimal(..), Integer) => Some(lhs.clone()),
+            (Integer | Decimal(..), Decimal(..)) => Some(rhs.clone()),
+            _ => None,
+        }
+    }
 }
 
 /// 类型打印
 impl std::fmt::Display for DataType {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-                f.write_str(match self {
+        if let Self::Decimal(precision, scale) = self {
+            return write!(f, "DECIMAL({},{})", precision, scale);
+        }
+        f.write_str(match self {
             Self::Boolean => "BOOLEAN",
             Self::Integer => "INTEGER",
             Self::Float => "FLOAT",
+            Self::Decimal(..) => "DECIMAL",
             Self::String => "STRING",
+            Self::Binary => "BINARY",
+            Self::Json => "JSON",
+            Self::Date => "DATE",
+            Self::Time => "TIME",
+            Self::Timestamp => "TIMESTAMP",
+            Self::Interval => "INTERVAL",
         })
     }
 }
 
 /// A specific value of a data type
 /// 类型对应枚举值
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Value {
     Null,
     Boolean(bool),
     Integer(i64),
     Float(f64),
+    Decimal(Decimal),
     String(String),
+    Binary(Vec<u8>),
+    /// A JSON document, as normalized JSON text (see json::normalize)
+    Json(String),
+    Date(i32),
+    Time(i64),
+    Timestamp(i64),
+    Interval(Interval),
 }
 
 
+/// Values are compared structurally rather than with SQL semantics (see Expression::Equal), such
+/// that Eq and Hash are consistent and values can be deduplicated by hashing: all NaNs are equal,
+/// as are 0.0 and -0.0, while NULL equals NULL.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Null, Value::Null) => true,
+            (Value::Boolean(lhs), Value::Boolean(rhs)) => lhs == rhs,
+            (Value::Integer(lhs), Value::Integer(rhs)) => lhs == rhs,
+            #[allow(clippy::float_cmp)]
+            (Value::Float(lhs), Value::Float(rhs)) => lhs == rhs || lhs.is_nan() && rhs.is_nan(),
+            (Value::Decimal(lhs), Value::Decimal(rhs)) => lhs == rhs,
+            (Value::String(lhs), Value::String(rhs)) => lhs == rhs,
+            (Value::Binary(lhs), Value::Binary(rhs)) => lhs == rhs,
+            (Value::Json(lhs), Value::Json(rhs)) => lhs == rhs,
+            (Value::Date(lhs), Value::Date(rhs)) => lhs == rhs,
+            (Value::Time(lhs), Value::Time(rhs)) => lhs == rhs,
+            (Value::Timestamp(lhs), Value::Timestamp(rhs)) => lhs == rhs,
+            (Value::Interval(lhs), Value::Interval(rhs)) => lhs == rhs,
+            _ => false,
+        }
+    }
+}
+
 /// 实现等于
 impl Eq for Value {}
 
 
 /// 实现hash方法
-#[allow(clippy::derive_hash_xor_eq)]
 impl Hash for Value {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.datatype().hash(state);
+        // Hash the variant rather than the datatype, since equal decimals can have different
+        // precisions and scales.
+        std::mem::discriminant(self).hash(state);
         match self {
-            Value::Null => self.hash(state),
+            Value::Null => {}
             Value::Boolean(v) => v.hash(state),
             Value::Integer(v) => v.hash(state),
+            // Normalize NaNs and -0.0, which are equal to other NaNs and 0.0 respectively.
+            Value::Float(v) if v.is_nan() => f64::NAN.to_be_bytes().hash(state),
+            Value::Float(v) if *v == 0.0 => 0.0f64.to_be_bytes().hash(state),
             Value::Float(v) => v.to_be_bytes().hash(state),
+            Value::Decimal(v) => v.hash(state),
             Value::String(v) => v.hash(state),
+            Value::Binary(v) => v.hash(state),
+            Value::Json(v) => v.hash(state),
+            Value::Date(v) => v.hash(state),
+            Value::Time(v) | Value::Timestamp(v) => v.hash(state),
+            Value::Interval(v) => v.hash(state),
         }
     }
 }
@@ -84,10 +177,99 @@ impl Value {
             Self::Boolean(_) => Some(DataType::Boolean),
             Self::Integer(_) => Some(DataType::Integer),
             Self::Float(_) => Some(DataType::Float),
+            Self::Decimal(d) => Some(DataType::Decimal(d.precision(), d.scale())),
             Self::String(_) => Some(DataType::String),
+            Self::Binary(_) => Some(DataType::Binary),
+            Self::Json(_) => Some(DataType::Json),
+            Self::Date(_) => Some(DataType::Date),
+            Self::Time(_) => Some(DataType::Time),
+            Self::Timestamp(_) => Some(DataType::Timestamp),
+            Self::Interval(_) => Some(DataType::Interval),
         }
     }
 
+    /// Casts the value to the given datatype. NULL casts to NULL for any datatype. Floats and
+    /// decimals are rounded half up to the nearest integer or to the decimal scale, and strings
+    /// are parsed after trimming whitespace.
+    pub fn cast(self, datatype: &DataType) -> Result<Self> {
+        Ok(match (self, datatype) {
+            (Self::Null, _) => Self::Null,
+            (v @ Self::Boolean(_), DataType::Boolean) => v,
+            (Self::Integer(i), DataType::Boolean) => Self::Boolean(i != 0),
+            (Self::Decimal(d), DataType::Boolean) => Self::Boolean(!d.is_zero()),
+            (Self::String(s), DataType::Boolean) => match s.trim().to_lowercase().as_str() {
+                "true" | "t" | "yes" | "y" | "on" | "1" => Self::Boolean(true),
+                "false" | "f" | "no" | "n" | "off" | "0" => Self::Boolean(false),
+                _ => return Err(Error::Value(format!("Can't cast {} to BOOLEAN", s))),
+            },
+            (Self::Boolean(b), DataType::Integer) => Self::Integer(b as i64),
+            (v @ Self::Integer(_), DataType::Integer) => v,
+            (Self::Float(f), DataType::Integer) => {
+                let i = f.round();
+                if i.is_nan() || i < i64::MIN as f64 || i >= i64::MAX as f64 {
+                    return Err(Error::Value(format!("Can't cast {} to INTEGER", f)));
+                }
+                Self::Integer(i as i64)
+            }
+            (Self::Decimal(d), DataType::Integer) => Self::Integer(d.to_i64(RoundingMode::HalfUp)?),
+            (Self::String(s), DataType::Integer) => Self::Integer(
+                s.trim()
+                    .parse()
+                    .map_err(|_| Error::Value(format!("Can't cast {} to INTEGER", s)))?,
+            ),
+            (Self::Integer(i), DataType::Float) => Self::Float(i as f64),
+            (v @ Self::Float(_), DataType::Float) => v,
+            (Self::Decimal(d), DataType::Float) => Self::Float(d.to_f64()),
+            (Self::String(s), DataType::Float) => Self::Float(
+                s.trim().parse().map_err(|_| Error::Value(format!("Can't cast {} to FLOAT", s)))?,
+            ),
+            (Self::Integer(i), DataType::Decimal(p, s)) => {
+                Self::Decimal(Decimal::from(i).fit(*p, *s, RoundingMode::HalfUp)?)
+            }
+            (Self::Float(f), DataType::Decimal(p, s)) => {
+                Self::Decimal(Decimal::from_f64(f)?.fit(*p, *s, RoundingMode::HalfUp)?)
+            }
+            (Self::Decimal(d), DataType::Decimal(p, s)) => {
+                Self::Decimal(d.fit(*p, *s, RoundingMode::HalfUp)?)
+            }
+            (Self::String(v), DataType::Decimal(p, s)) => {
+                Self::Decimal(v.parse::<Decimal>()?.fit(*p, *s, RoundingMode::HalfUp)?)
+            }
+            (Self::String(s), DataType::Date) => Self::Date(datetime::parse_date(&s)?),
+            (v @ Self::Date(_), DataType::Date) => v,
+            (Self::Timestamp(ts), DataType::Date) => Self::Date(datetime::timestamp_date(ts)),
+            (Self::String(s), DataType::Time) => Self::Time(datetime::parse_time(&s)?),
+            (v @ Self::Time(_), DataType::Time) => v,
+            (Self::Timestamp(ts), DataType::Time) => {
+                Self::Time(ts.rem_euclid(datetime::MICROS_PER_DAY))
+            }
+            (Self::String(s), DataType::Timestamp) => {
+                Self::Timestamp(datetime::parse_timestamp(&s)?)
+            }
+            (Self::Date(d), DataType::Timestamp) => Self::Timestamp(datetime::date_timestamp(d)),
+            (v @ Self::Timestamp(_), DataType::Timestamp) => v,
+            (Self::String(s), DataType::Interval) => Self::Interval(datetime::parse_interval(&s)?),
+            (Self::Time(t), DataType::Interval) => {
+                Self::Interval(Interval { micros: t, ..Default::default() })
+            }
+            (v @ Self::Interval(_), DataType::Interval) => v,
+            // Like Postgres, strings starting with \x are parsed as hex, others are taken as UTF-8.
+            (Self::String(s), DataType::Binary) => match s.strip_prefix("\\x") {
+                Some(hex) => Self::Binary(decode_hex(hex)?),
+                None => Self::Binary(s.into_bytes()),
+            },
+            (v @ Self::Binary(_), DataType::Binary) => v,
+            (Self::String(s), DataType::Json) => Self::Json(json::normalize(&s)?),
+            (v @ Self::Json(_), DataType::Json) => v,
+            (v @ (Self::Boolean(_) | Self::Integer(_) | Self::Float(_)), DataType::Json) => {
+                Self::Json(json::from_value(&v)?)
+            }
+            (v @ Self::String(_), DataType::String) => v,
+            (v, DataType::String) => Self::String(v.to_string()),
+            (v, datatype) => return Err(Error::Value(format!("Can't cast {} to {}", v, datatype))),
+        })
+    }
+
     /// Returns the inner boolean, or an error if not a boolean
     pub fn boolean(self) -> Result<bool> {
         match self {
@@ -130,7 +312,14 @@ impl std::fmt::Display for Value {
                 Self::Boolean(_) => "FALSE".to_string(),
                 Self::Integer(i) => i.to_string(),
                 Self::Float(f) => f.to_string(),
+                Self::Decimal(d) => d.to_string(),
                 Self::String(s) => s.clone(),
+                Self::Binary(b) => format!("\\x{}", encode_hex(b)),
+                Self::Json(j) => j.clone(),
+                Self::Date(d) => datetime::format_date(*d),
+                Self::Time(t) => datetime::format_time(*t),
+                Self::Timestamp(ts) => datetime::format_timestamp(*ts),
+                Self::Interval(i) => i.to_string(),
             }
                 .as_ref(),
         )
@@ -148,7 +337,21 @@ impl PartialOrd for Value {
             (Self::Float(a), Self::Integer(b)) => a.partial_cmp(&(*b as f64)),
             (Self::Integer(a), Self::Float(b)) => (*a as f64).partial_cmp(b),
             (Self::Integer(a), Self::Integer(b)) => a.partial_cmp(b),
+            (Self::Decimal(a), Self::Decimal(b)) => a.partial_cmp(b),
+            (Self::Decimal(a), Self::Integer(b)) => a.partial_cmp(&Decimal::from(*b)),
+            (Self::Integer(a), Self::Decimal(b)) => Decimal::from(*a).partial_cmp(b),
+            (Self::Decimal(a), Self::Float(b)) => a.to_f64().partial_cmp(b),
+            (Self::Float(a), Self::Decimal(b)) => a.partial_cmp(&b.to_f64()),
             (Self::String(a), Self::String(b)) => a.partial_cmp(b),
+            (Self::Binary(a), Self::Binary(b)) => a.partial_cmp(b),
+            // JSON documents are ordered by their normalized text, which is arbitrary but stable.
+            (Self::Json(a), Self::Json(b)) => a.partial_cmp(b),
+            (Self::Date(a), Self::Date(b)) => a.partial_cmp(b),
+            (Self::Date(a), Self::Timestamp(b)) => datetime::date_timestamp(*a).partial_cmp(b),
+            (Self::Timestamp(a), Self::Date(b)) => a.partial_cmp(&datetime::date_timestamp(*b)),
+            (Self::Time(a), Self::Time(b)) => a.partial_cmp(b),
+            (Self::Timestamp(a), Self::Timestamp(b)) => a.partial_cmp(b),
+            (Self::Interval(a), Self::Interval(b)) => Some(a.compare(b)),
             (_, _) => None,
         }
     }
@@ -166,6 +369,12 @@ impl From<f64> for Value {
     }
 }
 
+impl From<Decimal> for Value {
+    fn from(v: Decimal) -> Self {
+        Value::Decimal(v)
+    }
+}
+
 impl From<i64> for Value {
     fn from(v: i64) -> Self {
         Value::Integer(v)
@@ -184,6 +393,25 @@ impl From<&str> for Value {
     }
 }
 
+/// Encodes bytes as a lowercase hex string
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes a hex string into bytes, ignoring case
+pub fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(Error::Value(format!("Invalid hex string {}", hex)));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| Error::Value(format!("Invalid hex string {}", hex)))
+        })
+        .collect()
+}
+
 /// A row of values
 pub type Row = Vec<Value>;
 
@@ -198,3 +426,34 @@ pub struct Column {
 
 /// A set of columns
 pub type Columns = Vec<Column>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary() -> Result<()> {
+        assert_eq!("00ff7f", encode_hex(&[0, 255, 127]));
+        assert_eq!(vec![0, 255, 127], decode_hex("00FF7f")?);
+        assert_eq!(Vec::<u8>::new(), decode_hex("")?);
+        for hex in ["0", "0g", "é0"] {
+            assert!(decode_hex(hex).is_err(), "{}", hex);
+        }
+
+        // Like Postgres, strings starting with \x are cast from hex and others from UTF-8, and
+        // binary values are displayed as hex.
+        let binary = |s: &str| Value::String(s.into()).cast(&DataType::Binary);
+        assert_eq!(Value::Binary(vec![10, 255]), binary("\\x0aff")?);
+        assert_eq!(Value::Binary(b"ab".to_vec()), binary("ab")?);
+        assert!(binary("\\x0").is_err());
+        assert_eq!("\\x0aff", Value::Binary(vec![10, 255]).to_string());
+        assert_eq!(Value::String("\\x".into()), Value::Binary(Vec::new()).cast(&DataType::String)?);
+        assert!(Value::Integer(1).cast(&DataType::Binary).is_err());
+
+        // Binary values compare bytewise, with prefixes first.
+        assert!(Value::Binary(vec![1]) < Value::Binary(vec![1, 0]));
+        assert!(Value::Binary(vec![1, 255]) < Value::Binary(vec![2]));
+        assert_eq!(None, Value::Binary(vec![1]).partial_cmp(&Value::String("a".into())));
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+//! Pattern matching with regular expressions, and with LIKE and SIMILAR TO patterns which are
+//! translated to regular expressions. Constant patterns are compiled once when planning, see
+//! Expression::compile_patterns(), since a query typically matches every row against the same
+//! pattern. Other patterns are compiled whenever they are evaluated.
+
+use crate::error::{Error, Result};
+
+use regex::{Regex, RegexBuilder};
+use serde_derive::{Deserialize, Serialize};
+
+/// A pattern syntax
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Syntax {
+    /// A regular expression, which matches anywhere in a string (~)
+    Regex,
+    /// A LIKE pattern, where % matches any characters and _ a single character
+    Like,
+    /// A SIMILAR TO pattern, i.e. a LIKE pattern which also allows the regular expression
+    /// operators | * + ? {m,n} () and bracket expressions
+    SimilarTo,
+}
+
+/// A compiled pattern. Patterns are compared and serialized by their syntax, source and
+/// case-insensitivity, and recompiled when deserialized.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "Source", into = "Source")]
+pub struct Pattern {
+    syntax: Syntax,
+    source: String,
+    case_insensitive: bool,
+    regex: Regex,
+}
+
+/// The serialized form of a pattern
+#[derive(Serialize, Deserialize)]
+struct Source(Syntax, String, bool);
+
+impl Pattern {
+    /// Compiles a pattern. LIKE and SIMILAR TO patterns must match the entire string, and \
+    /// escapes the following character.
+    pub fn new(syntax: Syntax, source: &str, case_insensitive: bool) -> Result<Self> {
+        let translated = match syntax {
+            Syntax::Regex => source.to_string(),
+            Syntax::Like => translate(source, false)?,
+            Syntax::SimilarTo => translate(source, true)?,
+        };
+        let regex = RegexBuilder::new(&translated)
+            .case_insensitive(case_insensitive)
+            .build()
+            .map_err(|err| Error::Value(format!("Invalid pattern {}: {}", source, err)))?;
+        Ok(Self { syntax, source: source.to_string(), case_insensitive, regex })
+    }
+
+    /// Returns the pattern syntax
+    pub fn syntax(&self) -> Syntax {
+        self.syntax
+    }
+
+    /// Returns the pattern as given
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns true if the pattern ignores case
+    pub fn is_case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+
+    /// Returns true if the pattern matches the string
+    pub fn is_match(&self, string: &str) -> bool {
+        self.regex.is_match(string)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        (self.syntax, &self.source, self.case_insensitive)
+            == (other.syntax, &other.source, other.case_insensitive)
+    }
+}
+
+impl From<Pattern> for Source {
+    fn from(pattern: Pattern) -> Self {
+        Self(pattern.syntax, pattern.source, pattern.case_insensitive)
+    }
+}
+
+impl TryFrom<Source> for Pattern {
+    type Error = Error;
+
+    fn try_from(Source(syntax, source, case_insensitive): Source) -> Result<Self> {
+        Self::new(syntax, &source, case_insensitive)
+    }
+}
+
+/// Translates a LIKE or SIMILAR TO pattern into an anchored regular expression. Like in
+/// Postgres, the pattern can't end with a lone escape character.
+fn translate(pattern: &str, similar: bool) -> Result<String> {
+    let mut regex = String::from("^(?s:");
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => regex.push_str(".*"),
+            '_' => regex.push('.'),
+            '\\' => match chars.next() {
+                Some(c) => regex.push_str(&regex::escape(&c.to_string())),
+                None => {
+                    return Err(Error::Value(format!(
+                        "Pattern {} must not end with escape character",
+                        pattern
+                    )))
+                }
+            },
+            '|' | '*' | '+' | '?' | '{' | '}' | '(' | ')' if similar => regex.push(c),
+            // Bracket expressions are copied as is, escaping regex class set operators.
+            '[' if similar => {
+                regex.push('[');
+                for c in chars.by_ref() {
+                    if matches!(c, '[' | '&' | '~' | '\\') {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                    if c == ']' {
+                        break;
+                    }
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push_str(")$");
+    Ok(regex)
+}
+
+/// Parses regular expression function flags: i for case-insensitive, c for case-sensitive
+/// (the default), and g for replacing all matches rather than the first one if allowed.
+/// Returns (case_insensitive, global).
+pub fn parse_flags(flags: &str, allow_global: bool) -> Result<(bool, bool)> {
+    let (mut case_insensitive, mut global) = (false, false);
+    for flag in flags.chars() {
+        match flag {
+            'i' => case_insensitive = true,
+            'c' => case_insensitive = false,
+            'g' if allow_global => global = true,
+            flag => return Err(Error::Value(format!("Invalid regular expression flag {}", flag))),
+        }
+    }
+    Ok((case_insensitive, global))
+}
+
+/// Replaces the first match of a regular expression, or all matches if global, for
+/// regexp_replace(). In the replacement, \1 to \9 insert capture groups and \& the entire match.
+pub fn replace(string: &str, pattern: &Pattern, replacement: &str, global: bool) -> String {
+    let mut expanded = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some(n @ '1'..='9')) => {
+                chars.next();
+                expanded.push_str(&format!("${{{}}}", n));
+            }
+            ('\\', Some('&')) => {
+                chars.next();
+                expanded.push_str("${0}");
+            }
+            ('\\', Some('\\')) => {
+                chars.next();
+                expanded.push('\\');
+            }
+            ('$', _) => expanded.push_str("$$"),
+            (c, _) => expanded.push(c),
+        }
+    }
+    let limit = if global { 0 } else { 1 };
+    pattern.regex.replacen(string, limit, expanded.as_str()).into_owned()
+}
+
+/// Returns the capture groups of the first match of a regular expression, or the entire match
+/// if there are no groups, for regexp_match(). Groups that didn't participate are None.
+pub fn captures(string: &str, pattern: &Pattern) -> Option<Vec<Option<String>>> {
+    pattern.regex.captures(string).map(|captures| {
+        let skip = if captures.len() > 1 { 1 } else { 0 };
+        captures.iter().skip(skip).map(|m| m.map(|m| m.as_str().to_string())).collect()
+    })
+}
+
+/// Splits a string by a regular expression, for regexp_split_to_table()
+pub fn split(string: &str, pattern: &Pattern) -> Vec<String> {
+    pattern.regex.split(string).map(|s| s.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern() -> Result<()> {
+        assert!(Pattern::new(Syntax::Like, "a%", false)?.is_match("abc"));
+        assert!(!Pattern::new(Syntax::Like, "a%", false)?.is_match("ABC"));
+        assert!(Pattern::new(Syntax::Like, "a%", true)?.is_match("ABC"));
+        assert!(Pattern::new(Syntax::Like, "a\\%", false)?.is_match("a%"));
+        assert!(!Pattern::new(Syntax::Like, "a\\%", false)?.is_match("ab"));
+        assert!(Pattern::new(Syntax::SimilarTo, "(a|b)+", false)?.is_match("abba"));
+        assert!(Pattern::new(Syntax::Regex, "b+", false)?.is_match("abba"));
+
+        // A trailing escape character is an error, rather than a literal backslash.
+        assert!(Pattern::new(Syntax::Like, "a\\", false).is_err());
+        assert!(Pattern::new(Syntax::SimilarTo, "a\\", false).is_err());
+        assert!(Pattern::new(Syntax::Like, "a\\\\", false)?.is_match("a\\"));
+
+        // Patterns are serialized by their source, and recompiled when deserialized.
+        let pattern = Pattern::new(Syntax::Like, "a_c", true)?;
+        let decoded: Pattern = bincode::deserialize(&bincode::serialize(&pattern)?)?;
+        assert_eq!(pattern, decoded);
+        assert!(decoded.is_match("ABC"));
+        Ok(())
+    }
+}
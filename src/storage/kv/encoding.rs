@@ -0,0 +1,400 @@
+//! Order-preserving encodings for use in keys.
+//!
+//! bool:    0x00 for false, 0x01 for true.
+//! Vec<u8>: 0x00 is escaped with 0x00 0xff, terminated with 0x00 0x00.
+//! f64:     Big-endian, sign bit flipped if positive, all bits flipped if negative.
+//! i64:     Big-endian, sign bit flipped.
+//! String:  Like Vec<u8>.
+//! u64:     Big-endian.
+//! Value:   A type prefix byte followed by the encoded inner value. Dates, times and timestamps
+//!          are encoded like i64, while intervals are encoded as their length followed by their
+//!          months, days and microseconds. Decimals are encoded as their integer part (rounded
+//!          down) like an i128, followed by their fractional part as a big-endian u128 scaled to
+//!          38 digits, and thus lose trailing fractional zeros. Binary values are encoded like
+//!          Vec<u8>, and JSON documents like String using their normalized text.
+//! [Value]: The encoded values concatenated, i.e. a tuple.
+
+use crate::error::{Error, Result};
+use crate::sql::types::{Decimal, Interval, Value};
+
+use std::convert::TryInto;
+
+/// Encodes a boolean
+pub fn encode_boolean(value: bool) -> u8 {
+    match value {
+        true => 0x01,
+        false => 0x00,
+    }
+}
+
+/// Decodes a boolean from a slice and shrinks the slice
+pub fn take_boolean(bytes: &mut &[u8]) -> Result<bool> {
+    take_byte(bytes).and_then(|b| match b {
+        0x00 => Ok(false),
+        0x01 => Ok(true),
+        b => Err(Error::Internal(format!("Invalid boolean value {:x?}", b))),
+    })
+}
+
+/// Decodes a single byte from a slice and shrinks the slice
+pub fn take_byte(bytes: &mut &[u8]) -> Result<u8> {
+    if bytes.is_empty() {
+        return Err(Error::Internal("Unexpected end of bytes".into()));
+    }
+    let b = bytes[0];
+    *bytes = &bytes[1..];
+    Ok(b)
+}
+
+/// Encodes a byte vector. 0x00 is escaped as 0x00 0xff, and 0x00 0x00 is used as a terminator.
+/// See: https://activesphere.com/blog/2018/08/17/order-preserving-serialization
+pub fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(bytes.len() + 2);
+    for b in bytes {
+        match b {
+            0x00 => encoded.extend_from_slice(&[0x00, 0xff]),
+            b => encoded.push(*b),
+        }
+    }
+    encoded.extend_from_slice(&[0x00, 0x00]);
+    encoded
+}
+
+/// Decodes a byte vector from a slice and shrinks the slice
+pub fn take_bytes(bytes: &mut &[u8]) -> Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    let mut iter = bytes.iter().enumerate();
+    let taken = loop {
+        match iter.next().map(|(_, b)| b) {
+            Some(0x00) => match iter.next() {
+                Some((i, 0x00)) => break i + 1,
+                Some((_, 0xff)) => decoded.push(0x00),
+                Some((_, b)) => {
+                    return Err(Error::Internal(format!("Invalid byte escape {:x?}", b)))
+                }
+                None => return Err(Error::Internal("Unexpected end of bytes".into())),
+            },
+            Some(b) => decoded.push(*b),
+            None => return Err(Error::Internal("Unexpected end of bytes".into())),
+        }
+    };
+    *bytes = &bytes[taken..];
+    Ok(decoded)
+}
+
+/// Encodes an f64. Uses big-endian form, and flips the sign bit for positive numbers (so that
+/// they sort after negative numbers), or all bits for negative numbers (so that larger negative
+/// numbers sort first).
+pub fn encode_f64(n: f64) -> [u8; 8] {
+    let mut bytes = n.to_bits().to_be_bytes();
+    if (bytes[0] >> 7) & 1 == 0 {
+        bytes[0] ^= 1 << 7
+    } else {
+        bytes.iter_mut().for_each(|b| *b = !*b)
+    }
+    bytes
+}
+
+/// Decodes an f64 from a slice and shrinks the slice
+pub fn take_f64(bytes: &mut &[u8]) -> Result<f64> {
+    if bytes.len() < 8 {
+        return Err(Error::Internal(format!("Unable to decode f64 from {} bytes", bytes.len())));
+    }
+    let mut b: [u8; 8] = bytes[0..8].try_into()?;
+    *bytes = &bytes[8..];
+    if (b[0] >> 7) & 1 == 1 {
+        b[0] ^= 1 << 7
+    } else {
+        b.iter_mut().for_each(|b| *b = !*b)
+    }
+    Ok(f64::from_bits(u64::from_be_bytes(b)))
+}
+
+/// Encodes an i64. Uses big-endian form, and flips the sign bit so that negative numbers sort
+/// before positive numbers.
+pub fn encode_i64(n: i64) -> [u8; 8] {
+    let mut bytes = n.to_be_bytes();
+    bytes[0] ^= 1 << 7;
+    bytes
+}
+
+/// Decodes an i64 from a slice and shrinks the slice
+pub fn take_i64(bytes: &mut &[u8]) -> Result<i64> {
+    if bytes.len() < 8 {
+        return Err(Error::Internal(format!("Unable to decode i64 from {} bytes", bytes.len())));
+    }
+    let mut b: [u8; 8] = bytes[0..8].try_into()?;
+    *bytes = &bytes[8..];
+    b[0] ^= 1 << 7;
+    Ok(i64::from_be_bytes(b))
+}
+
+/// Encodes an i128, like an i64
+pub fn encode_i128(n: i128) -> [u8; 16] {
+    let mut bytes = n.to_be_bytes();
+    bytes[0] ^= 1 << 7;
+    bytes
+}
+
+/// Decodes an i128 from a slice and shrinks the slice
+pub fn take_i128(bytes: &mut &[u8]) -> Result<i128> {
+    if bytes.len() < 16 {
+        return Err(Error::Internal(format!("Unable to decode i128 from {} bytes", bytes.len())));
+    }
+    let mut b: [u8; 16] = bytes[0..16].try_into()?;
+    *bytes = &bytes[16..];
+    b[0] ^= 1 << 7;
+    Ok(i128::from_be_bytes(b))
+}
+
+/// Encodes a string. Simply converts to a byte vector and encodes that.
+pub fn encode_string(string: &str) -> Vec<u8> {
+    encode_bytes(string.as_bytes())
+}
+
+/// Decodes a string from a slice and shrinks the slice
+pub fn take_string(bytes: &mut &[u8]) -> Result<String> {
+    Ok(String::from_utf8(take_bytes(bytes)?)?)
+}
+
+/// Encodes a u64 in big-endian form
+pub fn encode_u64(n: u64) -> [u8; 8] {
+    n.to_be_bytes()
+}
+
+/// Decodes a u64 from a slice and shrinks the slice
+pub fn take_u64(bytes: &mut &[u8]) -> Result<u64> {
+    if bytes.len() < 8 {
+        return Err(Error::Internal(format!("Unable to decode u64 from {} bytes", bytes.len())));
+    }
+    let n = u64::from_be_bytes(bytes[0..8].try_into()?);
+    *bytes = &bytes[8..];
+    Ok(n)
+}
+
+/// Encodes a value, using a type prefix byte followed by the inner value
+pub fn encode_value(value: &Value) -> Vec<u8> {
+    match value {
+        Value::Null => vec![0x00],
+        Value::Boolean(b) => vec![0x01, encode_boolean(*b)],
+        Value::Float(f) => [&[0x02][..], &encode_f64(*f)].concat(),
+        Value::Integer(i) => [&[0x03][..], &encode_i64(*i)].concat(),
+        Value::String(s) => [&[0x04][..], &encode_string(s)].concat(),
+        Value::Date(d) => [&[0x05][..], &encode_i64(*d as i64)].concat(),
+        Value::Time(t) => [&[0x06][..], &encode_i64(*t)].concat(),
+        Value::Timestamp(ts) => [&[0x07][..], &encode_i64(*ts)].concat(),
+        // Intervals are keyed by length alone, since e.g. '1 month' equals '30 days'.
+        Value::Interval(i) => [&[0x08][..], &encode_i128(i.normalized())].concat(),
+        Value::Decimal(d) => {
+            let (integer, fraction) = d.split();
+            [&[0x09][..], &encode_i128(integer), &fraction.to_be_bytes()].concat()
+        }
+        Value::Binary(b) => [&[0x0a][..], &encode_bytes(b)].concat(),
+        Value::Json(j) => [&[0x0b][..], &encode_string(j)].concat(),
+    }
+}
+
+/// Decodes a value from a slice and shrinks the slice
+pub fn take_value(bytes: &mut &[u8]) -> Result<Value> {
+    match take_byte(bytes)? {
+        0x00 => Ok(Value::Null),
+        0x01 => Ok(Value::Boolean(take_boolean(bytes)?)),
+        0x02 => Ok(Value::Float(take_f64(bytes)?)),
+        0x03 => Ok(Value::Integer(take_i64(bytes)?)),
+        0x04 => Ok(Value::String(take_string(bytes)?)),
+        0x05 => Ok(Value::Date(take_i64(bytes)?.try_into()?)),
+        0x06 => Ok(Value::Time(take_i64(bytes)?)),
+        0x07 => Ok(Value::Timestamp(take_i64(bytes)?)),
+        0x08 => Ok(Value::Interval(Interval::from_normalized(take_i128(bytes)?)?)),
+        0x09 => {
+            let integer = take_i128(bytes)?;
+            if bytes.len() < 16 {
+                return Err(Error::Internal("Unable to decode decimal fraction".into()));
+            }
+            let fraction = u128::from_be_bytes(bytes[0..16].try_into()?);
+            *bytes = &bytes[16..];
+            Ok(Value::Decimal(Decimal::join(integer, fraction)?))
+        }
+        0x0a => Ok(Value::Binary(take_bytes(bytes)?)),
+        0x0b => Ok(Value::Json(take_string(bytes)?)),
+        b => Err(Error::Internal(format!("Invalid value prefix {:x?}", b))),
+    }
+}
+
+/// Encodes a tuple of values, e.g. a composite key. Since each value encoding is self-delimiting,
+/// the tuple is simply the concatenation of the values, and sorts by each value in turn.
+pub fn encode_values(values: &[Value]) -> Vec<u8> {
+    values.iter().flat_map(encode_value).collect()
+}
+
+/// Decodes a tuple of the given number of values from a slice and shrinks the slice
+pub fn take_values(bytes: &mut &[u8], count: usize) -> Result<Vec<Value>> {
+    (0..count).map(|_| take_value(bytes)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boolean() -> Result<()> {
+        assert!(encode_boolean(false) < encode_boolean(true));
+        let bytes = [encode_boolean(true), encode_boolean(false)];
+        let mut slice = &bytes[..];
+        assert!(take_boolean(&mut slice)?);
+        assert!(!take_boolean(&mut slice)?);
+        assert!(slice.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn bytes() -> Result<()> {
+        let encoded = encode_bytes(&[0x01, 0x00, 0x02]);
+        assert_eq!(vec![0x01, 0x00, 0xff, 0x02, 0x00, 0x00], encoded);
+        let mut slice = &encoded[..];
+        assert_eq!(vec![0x01, 0x00, 0x02], take_bytes(&mut slice)?);
+        assert!(slice.is_empty());
+
+        assert!(encode_bytes(&[0x01]) < encode_bytes(&[0x01, 0x00]));
+        assert!(encode_bytes(&[0x01, 0x00]) < encode_bytes(&[0x01, 0x01]));
+        Ok(())
+    }
+
+    #[test]
+    fn f64() -> Result<()> {
+        let values = [f64::NEG_INFINITY, -2.5, -1.0, -0.0, 0.0, 1.0, 2.5, f64::INFINITY];
+        for pair in values.windows(2) {
+            assert!(encode_f64(pair[0]) < encode_f64(pair[1]));
+        }
+        for value in &values {
+            let encoded = encode_f64(*value);
+            assert_eq!(value.to_bits(), take_f64(&mut &encoded[..])?.to_bits());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn i64() -> Result<()> {
+        let values = [i64::MIN, -1024, -1, 0, 1, 1024, i64::MAX];
+        for pair in values.windows(2) {
+            assert!(encode_i64(pair[0]) < encode_i64(pair[1]));
+        }
+        for value in &values {
+            assert_eq!(*value, take_i64(&mut &encode_i64(*value)[..])?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn u64() -> Result<()> {
+        assert!(encode_u64(1) < encode_u64(256));
+        assert_eq!(256, take_u64(&mut &encode_u64(256)[..])?);
+        Ok(())
+    }
+
+    #[test]
+    fn values() -> Result<()> {
+        let key = vec![Value::Integer(1), Value::String("a\0b".into()), Value::Null];
+        let encoded = encode_values(&key);
+        let mut slice = &encoded[..];
+        assert_eq!(key, take_values(&mut slice, 3)?);
+        assert!(slice.is_empty());
+
+        // Tuples sort by each value in turn.
+        assert!(
+            encode_values(&[Value::Integer(1), Value::String("b".into())])
+                < encode_values(&[Value::Integer(2), Value::String("a".into())])
+        );
+        assert!(
+            encode_values(&[Value::Integer(1), Value::String("a".into())])
+                < encode_values(&[Value::Integer(1), Value::String("b".into())])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn datetime_values() -> Result<()> {
+        let interval = |months, days, micros| Value::Interval(Interval { months, days, micros });
+        let values = [
+            vec![Value::Date(-1), Value::Date(0), Value::Date(19723)],
+            vec![Value::Time(0), Value::Time(1), Value::Time(86_399_999_999)],
+            vec![Value::Timestamp(-1), Value::Timestamp(0), Value::Timestamp(1)],
+            vec![interval(0, -1, 0), interval(0, 0, 1), interval(0, 1, 0), interval(1, 0, 0)],
+        ];
+        for values in values {
+            for pair in values.windows(2) {
+                assert!(encode_value(&pair[0]) < encode_value(&pair[1]));
+            }
+            for value in values {
+                assert_eq!(value, take_value(&mut &encode_value(&value)[..])?);
+            }
+        }
+
+        // Equal-length intervals have equal keys, and decode with whole months and days.
+        assert_eq!(encode_value(&interval(0, 30, 0)), encode_value(&interval(1, 0, 0)));
+        assert_eq!(encode_value(&interval(0, 1, 0)), encode_value(&interval(0, 0, 86_400_000_000)));
+        let decoded = take_value(&mut &encode_value(&interval(0, 31, -1))[..])?;
+        assert!(matches!(
+            decoded,
+            Value::Interval(Interval { months: 1, days: 0, micros: 86_399_999_999 })
+        ));
+        for extreme in [interval(i32::MIN, i32::MIN, i64::MIN), interval(i32::MAX, -1, i64::MAX)] {
+            assert_eq!(extreme, take_value(&mut &encode_value(&extreme)[..])?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn binary_values() -> Result<()> {
+        let values = [
+            Value::Binary(vec![]),
+            Value::Binary(vec![0x00]),
+            Value::Binary(vec![0x00, 0x00]),
+            Value::Binary(vec![0x00, 0xff]),
+            Value::Binary(vec![0x01]),
+            Value::Binary(vec![0xff, 0x00]),
+        ];
+        for pair in values.windows(2) {
+            assert!(encode_value(&pair[0]) < encode_value(&pair[1]));
+        }
+        for value in values {
+            assert_eq!(value, take_value(&mut &encode_value(&value)[..])?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn json_values() -> Result<()> {
+        for text in [r#"null"#, r#"{"a":[1,"x\u0000"]}"#, r#"[{},{"b":null}]"#] {
+            let value = Value::Json(text.to_string());
+            assert_eq!(value, take_value(&mut &encode_value(&value)[..])?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn decimal_values() -> Result<()> {
+        let decimal = |s: &str| -> Result<Value> { Ok(Value::Decimal(s.parse()?)) };
+        let values = [
+            decimal("-99999999999999999999999999999999999999")?,
+            decimal("-1.5")?,
+            decimal("-1.25")?,
+            decimal("-0.00000000000000000000000000000000000001")?,
+            decimal("0")?,
+            decimal("0.1")?,
+            decimal("1")?,
+            decimal("1.0000000000000000000000000000000000001")?,
+            decimal("99999999999999999999999999999999999999")?,
+        ];
+        for pair in values.windows(2) {
+            assert!(encode_value(&pair[0]) < encode_value(&pair[1]));
+        }
+        for value in values {
+            assert_eq!(value, take_value(&mut &encode_value(&value)[..])?);
+        }
+
+        // Equal decimals have equal keys regardless of scale.
+        assert_eq!(encode_value(&decimal("1.50")?), encode_value(&decimal("1.5")?));
+        Ok(())
+    }
+}
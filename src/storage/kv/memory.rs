@@ -0,0 +1,72 @@
+use super::{Range, Scan, Store};
+use crate::error::Result;
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+/// An in-memory key/value store, using the Rust standard library's B-tree
+pub struct Memory {
+    data: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Memory {
+    /// Creates a new in-memory store
+    pub fn new() -> Self {
+        Self { data: BTreeMap::new() }
+    }
+}
+
+impl Display for Memory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "memory")
+    }
+}
+
+impl Store for Memory {
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.data.remove(key);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.data.get(key).cloned())
+    }
+
+    // The scan can't borrow the store, since callers hold it behind a lock, so the range is
+    // copied out.
+    fn scan(&self, range: Range) -> Scan {
+        Box::new(
+            self.data
+                .range(range)
+                .map(|(k, v)| Ok((k.clone(), v.clone())))
+                .collect::<Vec<_>>()
+                .into_iter(),
+        )
+    }
+
+    fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.data.insert(key.to_vec(), value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::TestSuite;
+    use super::*;
+
+    impl TestSuite<Memory> for Memory {
+        fn setup() -> Result<Memory> {
+            Ok(Memory::new())
+        }
+    }
+
+    #[test]
+    fn tests() -> Result<()> {
+        Memory::test()
+    }
+}
@@ -0,0 +1,221 @@
+pub mod encoding;
+mod memory;
+mod mvcc;
+
+pub use memory::Memory;
+pub use mvcc::{Mode, Reader, Transaction, MVCC};
+
+use crate::error::Result;
+
+use std::fmt::Display;
+use std::ops::{Bound, RangeBounds};
+
+/// A key/value store
+pub trait Store: Display + Send + Sync {
+    /// Deletes a key, or does nothing if it does not exist
+    fn delete(&mut self, key: &[u8]) -> Result<()>;
+
+    /// Flushes any buffered data to the underlying storage medium
+    fn flush(&mut self) -> Result<()>;
+
+    /// Gets a value for a key, if it exists
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    /// Iterates over an ordered range of key/value pairs
+    fn scan(&self, range: Range) -> Scan;
+
+    /// Sets a value for a key, replacing the existing value if any
+    fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()>;
+}
+
+/// A scan range
+pub struct Range {
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+}
+
+impl Range {
+    /// Creates a new range from the given Rust range. We can't use the RangeBounds directly in
+    /// scan() since that prevents us from using Store as a trait object.
+    pub fn from<R: RangeBounds<Vec<u8>>>(range: R) -> Self {
+        Self {
+            start: match range.start_bound() {
+                Bound::Included(v) => Bound::Included(v.to_vec()),
+                Bound::Excluded(v) => Bound::Excluded(v.to_vec()),
+                Bound::Unbounded => Bound::Unbounded,
+            },
+            end: match range.end_bound() {
+                Bound::Included(v) => Bound::Included(v.to_vec()),
+                Bound::Excluded(v) => Bound::Excluded(v.to_vec()),
+                Bound::Unbounded => Bound::Unbounded,
+            },
+        }
+    }
+
+    /// Creates a range covering all keys with the given prefix
+    pub fn prefix(prefix: &[u8]) -> Self {
+        // The end bound is the prefix with its last non-0xff byte incremented, or unbounded if
+        // the prefix is all 0xff.
+        let mut end = prefix.to_vec();
+        while let Some(last) = end.pop() {
+            if last < 0xff {
+                end.push(last + 1);
+                return Self { start: Bound::Included(prefix.to_vec()), end: Bound::Excluded(end) };
+            }
+        }
+        Self { start: Bound::Included(prefix.to_vec()), end: Bound::Unbounded }
+    }
+
+    /// Checks if the given value is contained in the range
+    pub fn contains(&self, v: &[u8]) -> bool {
+        (match &self.start {
+            Bound::Included(start) => &**start <= v,
+            Bound::Excluded(start) => &**start < v,
+            Bound::Unbounded => true,
+        }) && (match &self.end {
+            Bound::Included(end) => v <= &**end,
+            Bound::Excluded(end) => v < &**end,
+            Bound::Unbounded => true,
+        })
+    }
+}
+
+impl RangeBounds<Vec<u8>> for Range {
+    fn start_bound(&self) -> Bound<&Vec<u8>> {
+        match &self.start {
+            Bound::Included(v) => Bound::Included(v),
+            Bound::Excluded(v) => Bound::Excluded(v),
+            Bound::Unbounded => Bound::Unbounded,
+        }
+    }
+
+    fn end_bound(&self) -> Bound<&Vec<u8>> {
+        match &self.end {
+            Bound::Included(v) => Bound::Included(v),
+            Bound::Excluded(v) => Bound::Excluded(v),
+            Bound::Unbounded => Bound::Unbounded,
+        }
+    }
+}
+
+/// Iterator over a key/value range
+pub type Scan = Box<dyn DoubleEndedIterator<Item = Result<(Vec<u8>, Vec<u8>)>> + Send>;
+
+#[cfg(test)]
+trait TestSuite<S: Store> {
+    fn setup() -> Result<S>;
+
+    fn test() -> Result<()> {
+        Self::test_delete()?;
+        Self::test_get()?;
+        Self::test_scan()?;
+        Self::test_set()?;
+        Ok(())
+    }
+
+    fn test_delete() -> Result<()> {
+        let mut s = Self::setup()?;
+        s.set(b"a", vec![0x01])?;
+        assert_eq!(Some(vec![0x01]), s.get(b"a")?);
+        s.delete(b"a")?;
+        assert_eq!(None, s.get(b"a")?);
+        s.delete(b"b")?;
+        Ok(())
+    }
+
+    fn test_get() -> Result<()> {
+        let mut s = Self::setup()?;
+        s.set(b"a", vec![0x01])?;
+        assert_eq!(Some(vec![0x01]), s.get(b"a")?);
+        assert_eq!(None, s.get(b"b")?);
+        Ok(())
+    }
+
+    fn test_scan() -> Result<()> {
+        let mut s = Self::setup()?;
+        s.set(b"a", vec![0x01])?;
+        s.set(b"b", vec![0x02])?;
+        s.set(b"ba", vec![0x02, 0x01])?;
+        s.set(b"bb", vec![0x02, 0x02])?;
+        s.set(b"c", vec![0x03])?;
+
+        // Forward/backward ranges
+        assert_eq!(
+            vec![
+                (b"b".to_vec(), vec![0x02]),
+                (b"ba".to_vec(), vec![0x02, 0x01]),
+                (b"bb".to_vec(), vec![0x02, 0x02]),
+            ],
+            s.scan(Range::from(b"b".to_vec()..b"bz".to_vec())).collect::<Result<Vec<_>>>()?
+        );
+        assert_eq!(
+            vec![
+                (b"bb".to_vec(), vec![0x02, 0x02]),
+                (b"ba".to_vec(), vec![0x02, 0x01]),
+                (b"b".to_vec(), vec![0x02]),
+            ],
+            s.scan(Range::from(b"b".to_vec()..b"bz".to_vec())).rev().collect::<Result<Vec<_>>>()?
+        );
+
+        // Inclusive/exclusive ranges
+        assert_eq!(
+            vec![(b"ba".to_vec(), vec![0x02, 0x01]), (b"bb".to_vec(), vec![0x02, 0x02])],
+            s.scan(Range::from(b"ba".to_vec()..=b"bb".to_vec())).collect::<Result<Vec<_>>>()?
+        );
+        assert_eq!(
+            vec![(b"ba".to_vec(), vec![0x02, 0x01])],
+            s.scan(Range::from(b"ba".to_vec()..b"bb".to_vec())).collect::<Result<Vec<_>>>()?
+        );
+
+        // Prefix ranges
+        assert_eq!(
+            vec![
+                (b"b".to_vec(), vec![0x02]),
+                (b"ba".to_vec(), vec![0x02, 0x01]),
+                (b"bb".to_vec(), vec![0x02, 0x02]),
+            ],
+            s.scan(Range::prefix(b"b")).collect::<Result<Vec<_>>>()?
+        );
+
+        // Open ranges
+        assert_eq!(
+            vec![(b"bb".to_vec(), vec![0x02, 0x02]), (b"c".to_vec(), vec![0x03])],
+            s.scan(Range::from(b"bb".to_vec()..)).collect::<Result<Vec<_>>>()?
+        );
+        assert_eq!(
+            vec![(b"a".to_vec(), vec![0x01]), (b"b".to_vec(), vec![0x02])],
+            s.scan(Range::from(..=b"b".to_vec())).collect::<Result<Vec<_>>>()?
+        );
+
+        // Full range
+        assert_eq!(5, s.scan(Range::from(..)).count());
+        Ok(())
+    }
+
+    fn test_set() -> Result<()> {
+        let mut s = Self::setup()?;
+        s.set(b"a", vec![0x01])?;
+        assert_eq!(Some(vec![0x01]), s.get(b"a")?);
+        s.set(b"a", vec![0x02])?;
+        assert_eq!(Some(vec![0x02]), s.get(b"a")?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_prefix() {
+        let range = Range::prefix(&[0x01, 0xff]);
+        assert!(range.contains(&[0x01, 0xff]));
+        assert!(range.contains(&[0x01, 0xff, 0x00]));
+        assert!(!range.contains(&[0x02]));
+        assert!(!range.contains(&[0x01, 0xfe]));
+
+        let range = Range::prefix(&[0xff]);
+        assert!(range.contains(&[0xff, 0xff, 0xff]));
+        assert!(!range.contains(&[0xfe]));
+    }
+}
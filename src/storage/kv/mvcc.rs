@@ -0,0 +1,631 @@
+use super::{encoding, Range, Store};
+use crate::error::{Error, Result};
+
+use serde_derive::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::iter::Peekable;
+use std::ops::{Bound, RangeBounds};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// An MVCC-based transactional key-value store. Each transaction sees a snapshot of the store as
+/// of its start, ignoring the writes of concurrent transactions, and writing a key that a
+/// concurrent transaction has written fails with a serialization error. Old versions are kept
+/// around, such that read-only transactions can also read the store as of an earlier version.
+pub struct MVCC {
+    /// The underlying KV store. It is protected by a mutex so it can be shared between txns.
+    store: Arc<RwLock<Box<dyn Store>>>,
+}
+
+impl Clone for MVCC {
+    fn clone(&self) -> Self {
+        MVCC { store: self.store.clone() }
+    }
+}
+
+impl MVCC {
+    /// Creates a new MVCC key-value store with the given key-value store for storage
+    pub fn new(store: Box<dyn Store>) -> Self {
+        Self { store: Arc::new(RwLock::new(store)) }
+    }
+
+    /// Begins a new transaction in read-write mode
+    pub fn begin(&self) -> Result<Transaction> {
+        Transaction::begin(self.store.clone(), Mode::ReadWrite)
+    }
+
+    /// Begins a new transaction in the given mode
+    pub fn begin_with_mode(&self, mode: Mode) -> Result<Transaction> {
+        Transaction::begin(self.store.clone(), mode)
+    }
+
+    /// Resumes a transaction with the given ID
+    pub fn resume(&self, id: u64) -> Result<Transaction> {
+        Transaction::resume(self.store.clone(), id)
+    }
+}
+
+/// Serializes MVCC metadata
+fn serialize<V: serde::Serialize>(value: &V) -> Result<Vec<u8>> {
+    Ok(bincode::serialize(value)?)
+}
+
+/// Deserializes MVCC metadata
+fn deserialize<'a, V: serde::Deserialize<'a>>(bytes: &'a [u8]) -> Result<V> {
+    Ok(bincode::deserialize(bytes)?)
+}
+
+/// An MVCC transaction
+pub struct Transaction {
+    /// The underlying store for the transaction. Shared between transactions using a mutex.
+    store: Arc<RwLock<Box<dyn Store>>>,
+    /// The unique transaction ID
+    id: u64,
+    /// The transaction mode
+    mode: Mode,
+    /// The snapshot that the transaction is running in
+    snapshot: Snapshot,
+}
+
+impl Transaction {
+    /// Begins a new transaction in the given mode
+    fn begin(store: Arc<RwLock<Box<dyn Store>>>, mode: Mode) -> Result<Self> {
+        let mut session = store.write()?;
+
+        let id = match session.get(&Key::TxnNext.encode())? {
+            Some(ref v) => deserialize(v)?,
+            None => 1,
+        };
+        session.set(&Key::TxnNext.encode(), serialize(&(id + 1))?)?;
+        session.set(&Key::TxnActive(id).encode(), serialize(&mode)?)?;
+
+        // We always take a new snapshot, even for snapshot transactions, because all transactions
+        // increment the transaction ID and we need to properly record currently active
+        // transactions for any future snapshot transactions looking at this one.
+        let mut snapshot = Snapshot::take(&mut session, id)?;
+        std::mem::drop(session);
+        if let Mode::Snapshot { version } = &mode {
+            snapshot = Snapshot::restore(&store.read()?, *version)?
+        }
+
+        Ok(Self { store, id, mode, snapshot })
+    }
+
+    /// Resumes an active transaction with the given ID. Errors if the transaction is not active.
+    fn resume(store: Arc<RwLock<Box<dyn Store>>>, id: u64) -> Result<Self> {
+        let session = store.read()?;
+        let mode = match session.get(&Key::TxnActive(id).encode())? {
+            Some(v) => deserialize(&v)?,
+            None => return Err(Error::Value(format!("No active transaction {}", id))),
+        };
+        let snapshot = match &mode {
+            Mode::Snapshot { version } => Snapshot::restore(&session, *version)?,
+            _ => Snapshot::restore(&session, id)?,
+        };
+        std::mem::drop(session);
+        Ok(Self { store, id, mode, snapshot })
+    }
+
+    /// Returns the transaction ID
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the transaction mode
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Commits the transaction, by removing the txn from the active set
+    pub fn commit(self) -> Result<()> {
+        let mut session = self.store.write()?;
+        session.delete(&Key::TxnActive(self.id).encode())?;
+        session.flush()
+    }
+
+    /// Rolls back the transaction, by removing all updated entries
+    pub fn rollback(self) -> Result<()> {
+        let mut session = self.store.write()?;
+        if self.mode.mutable() {
+            let mut rollback = Vec::new();
+            let mut scan = session.scan(Range::prefix(&Key::TxnUpdate(self.id, None).encode()));
+            while let Some((key, _)) = scan.next().transpose()? {
+                match Key::decode(&key)? {
+                    Key::TxnUpdate(_, Some(updated_key)) => rollback.push(updated_key.into_owned()),
+                    k => return Err(Error::Internal(format!("Expected TxnUpdate, got {:?}", k))),
+                };
+                rollback.push(key);
+            }
+            std::mem::drop(scan);
+            for key in rollback.into_iter() {
+                session.delete(&key)?;
+            }
+        }
+        session.delete(&Key::TxnActive(self.id).encode())
+    }
+
+    /// Deletes a key
+    pub fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.write(key, None)
+    }
+
+    /// Fetches a key
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        get(&self.store, self.id, &self.snapshot, key)
+    }
+
+    /// Returns a read-only handle to the transaction's view of the store, which can fetch keys
+    /// independently of the transaction, e.g. lazily from a scan iterator. It sees the
+    /// transaction's own writes, including ones made after the reader was created.
+    pub fn reader(&self) -> Reader {
+        Reader { store: self.store.clone(), id: self.id, snapshot: self.snapshot.clone() }
+    }
+
+    /// Scans a key range
+    pub fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Result<Scan> {
+        let start = match range.start_bound() {
+            Bound::Excluded(k) => Bound::Excluded(Key::Record(k.into(), u64::MAX).encode()),
+            Bound::Included(k) => Bound::Included(Key::Record(k.into(), 0).encode()),
+            Bound::Unbounded => Bound::Included(Key::Record(vec![].into(), 0).encode()),
+        };
+        let end = match range.end_bound() {
+            Bound::Excluded(k) => Bound::Excluded(Key::Record(k.into(), 0).encode()),
+            Bound::Included(k) => Bound::Included(Key::Record(k.into(), u64::MAX).encode()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let scan = self.store.read()?.scan(Range::from((start, end)));
+        Ok(Scan::new(scan, self.snapshot.clone()))
+    }
+
+    /// Scans keys under a given prefix
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Result<Scan> {
+        self.scan(Range::prefix(prefix))
+    }
+
+    /// Sets a key
+    pub fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.write(key, Some(value))
+    }
+
+    /// Writes a value for a key. None is used for deletion.
+    fn write(&self, key: &[u8], value: Option<Vec<u8>>) -> Result<()> {
+        if !self.mode.mutable() {
+            return Err(Error::ReadOnly);
+        }
+        let mut session = self.store.write()?;
+
+        // Check if the key is dirty, i.e. if it has any uncommitted changes, by scanning for any
+        // versions that aren't visible to us.
+        let min = self.snapshot.invisible.iter().min().cloned().unwrap_or(self.id + 1);
+        let mut scan = session
+            .scan(Range::from(
+                Key::Record(key.into(), min).encode()..=Key::Record(key.into(), u64::MAX).encode(),
+            ))
+            .rev();
+        while let Some((k, _)) = scan.next().transpose()? {
+            match Key::decode(&k)? {
+                Key::Record(_, version) => {
+                    if !self.snapshot.is_visible(version) {
+                        return Err(Error::Serialization);
+                    }
+                }
+                k => return Err(Error::Internal(format!("Expected Txn::Record, got {:?}", k))),
+            };
+        }
+        std::mem::drop(scan);
+
+        // Write the key and its update record.
+        let key = Key::Record(key.into(), self.id).encode();
+        let update = Key::TxnUpdate(self.id, Some((&key).into())).encode();
+        session.set(&update, vec![])?;
+        session.set(&key, serialize(&value)?)
+    }
+}
+
+/// An MVCC transaction mode
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Mode {
+    /// A read-write transaction
+    ReadWrite,
+    /// A read-only transaction
+    ReadOnly,
+    /// A read-only transaction running in a snapshot of a given version.
+    ///
+    /// The version must refer to a committed transaction ID. Any changes visible to the original
+    /// transaction will be visible in the snapshot (i.e. transactions that had not committed
+    /// before the snapshot transaction started will not be visible, even though they have a lower
+    /// version).
+    Snapshot { version: u64 },
+}
+
+impl Mode {
+    /// Checks whether the transaction mode can mutate data
+    pub fn mutable(&self) -> bool {
+        match self {
+            Self::ReadWrite => true,
+            Self::ReadOnly => false,
+            Self::Snapshot { .. } => false,
+        }
+    }
+}
+
+/// A read-only handle to a transaction's view of the store, see Transaction::reader()
+pub struct Reader {
+    store: Arc<RwLock<Box<dyn Store>>>,
+    id: u64,
+    snapshot: Snapshot,
+}
+
+impl Reader {
+    /// Fetches a key
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        get(&self.store, self.id, &self.snapshot, key)
+    }
+}
+
+/// Fetches the latest version of a key visible to a snapshot, up to the given transaction ID
+fn get(
+    store: &RwLock<Box<dyn Store>>,
+    id: u64,
+    snapshot: &Snapshot,
+    key: &[u8],
+) -> Result<Option<Vec<u8>>> {
+    let session = store.read()?;
+    let mut scan = session
+        .scan(Range::from(
+            Key::Record(key.into(), 0).encode()..=Key::Record(key.into(), id).encode(),
+        ))
+        .rev();
+    while let Some((k, v)) = scan.next().transpose()? {
+        match Key::decode(&k)? {
+            Key::Record(_, version) => {
+                if snapshot.is_visible(version) {
+                    return deserialize(&v);
+                }
+            }
+            k => return Err(Error::Internal(format!("Expected Txn::Record, got {:?}", k))),
+        };
+    }
+    Ok(None)
+}
+
+/// A versioned snapshot, containing visibility information about concurrent transactions
+#[derive(Clone)]
+struct Snapshot {
+    /// The version (i.e. transaction ID) that the snapshot belongs to
+    version: u64,
+    /// The set of transaction IDs that were active at the start of the transactions, and thus
+    /// should be invisible to the snapshot.
+    invisible: HashSet<u64>,
+}
+
+impl Snapshot {
+    /// Takes a new snapshot, persisting it as `Key::TxnSnapshot(version)`
+    fn take(session: &mut RwLockWriteGuard<Box<dyn Store>>, version: u64) -> Result<Self> {
+        let mut snapshot = Self { version, invisible: HashSet::new() };
+        let mut scan =
+            session.scan(Range::from(Key::TxnActive(0).encode()..Key::TxnActive(version).encode()));
+        while let Some((key, _)) = scan.next().transpose()? {
+            match Key::decode(&key)? {
+                Key::TxnActive(id) => snapshot.invisible.insert(id),
+                k => return Err(Error::Internal(format!("Expected TxnActive, got {:?}", k))),
+            };
+        }
+        std::mem::drop(scan);
+        session.set(&Key::TxnSnapshot(version).encode(), serialize(&snapshot.invisible)?)?;
+        Ok(snapshot)
+    }
+
+    /// Restores an existing snapshot from `Key::TxnSnapshot(version)`, or errors if not found
+    fn restore(session: &RwLockReadGuard<Box<dyn Store>>, version: u64) -> Result<Self> {
+        match session.get(&Key::TxnSnapshot(version).encode())? {
+            Some(ref v) => Ok(Self { version, invisible: deserialize(v)? }),
+            None => Err(Error::Value(format!("Snapshot not found for version {}", version))),
+        }
+    }
+
+    /// Checks whether the given version is visible in this snapshot
+    fn is_visible(&self, version: u64) -> bool {
+        version <= self.version && !self.invisible.contains(&version)
+    }
+}
+
+/// MVCC keys. The encoding preserves the grouping and ordering of keys, and uses a Cow since we
+/// want to take borrows when encoding and return owned when decoding.
+#[derive(Debug)]
+enum Key<'a> {
+    /// The next available txn ID. Used when starting new txns.
+    TxnNext,
+    /// Active txn markers, containing the mode. Used to detect concurrent txns, and to resume.
+    TxnActive(u64),
+    /// Txn snapshot, containing concurrent active txns at start of txn.
+    TxnSnapshot(u64),
+    /// Update marker for a txn ID and key, used for rollback. None is used as a scan prefix.
+    TxnUpdate(u64, Option<Cow<'a, [u8]>>),
+    /// A record for a key/version pair.
+    Record(Cow<'a, [u8]>, u64),
+}
+
+impl<'a> Key<'a> {
+    /// Encodes a key into a byte vector
+    fn encode(self) -> Vec<u8> {
+        use encoding::*;
+        match self {
+            Self::TxnNext => vec![0x01],
+            Self::TxnActive(id) => [&[0x02][..], &encode_u64(id)].concat(),
+            Self::TxnSnapshot(version) => [&[0x03][..], &encode_u64(version)].concat(),
+            Self::TxnUpdate(id, None) => [&[0x04][..], &encode_u64(id)].concat(),
+            Self::TxnUpdate(id, Some(key)) => {
+                [&[0x04][..], &encode_u64(id), &encode_bytes(&key)].concat()
+            }
+            Self::Record(key, version) => {
+                [&[0xff][..], &encode_bytes(&key), &encode_u64(version)].concat()
+            }
+        }
+    }
+
+    /// Decodes a key from a byte representation
+    fn decode(mut bytes: &[u8]) -> Result<Self> {
+        use encoding::*;
+        let bytes = &mut bytes;
+        let key = match take_byte(bytes)? {
+            0x01 => Self::TxnNext,
+            0x02 => Self::TxnActive(take_u64(bytes)?),
+            0x03 => Self::TxnSnapshot(take_u64(bytes)?),
+            0x04 => Self::TxnUpdate(take_u64(bytes)?, Some(take_bytes(bytes)?.into())),
+            0xff => Self::Record(take_bytes(bytes)?.into(), take_u64(bytes)?),
+            b => return Err(Error::Internal(format!("Unknown MVCC key prefix {:x?}", b))),
+        };
+        if !bytes.is_empty() {
+            return Err(Error::Internal("Unexpected data remaining at end of key".into()));
+        }
+        Ok(key)
+    }
+}
+
+/// A key range scan, returning the latest version of each key visible to the transaction
+pub struct Scan {
+    /// The visible versions of keys, in key and version order
+    scan: Peekable<super::Scan>,
+    /// The last key emitted by next_back(), such that older versions of it are skipped
+    next_back_seen: Option<Vec<u8>>,
+}
+
+impl Scan {
+    /// Creates a new scan over the raw record range of the underlying store
+    fn new(scan: super::Scan, snapshot: Snapshot) -> Self {
+        // Filter out invisible versions and decode keys.
+        let scan: super::Scan = Box::new(scan.filter_map(move |r| {
+            r.and_then(|(k, v)| match Key::decode(&k)? {
+                Key::Record(_, version) if !snapshot.is_visible(version) => Ok(None),
+                Key::Record(key, _) => Ok(Some((key.into_owned(), v))),
+                k => Err(Error::Internal(format!("Expected Record, got {:?}", k))),
+            })
+            .transpose()
+        }));
+        Self { scan: scan.peekable(), next_back_seen: None }
+    }
+
+    /// Fallible next(), emitting the next item, or None if exhausted
+    fn try_next(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        while let Some((key, value)) = self.scan.next().transpose()? {
+            // Only return the item if it is the last version of the key.
+            let last = match self.scan.peek() {
+                Some(Ok((peek_key, _))) => *peek_key != key,
+                Some(Err(err)) => return Err(err.clone()),
+                None => true,
+            };
+            // Only return non-deleted items.
+            if last {
+                if let Some(value) = deserialize::<Option<Vec<u8>>>(&value)? {
+                    return Ok(Some((key, value)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Fallible next_back(), emitting the previous item, or None if exhausted
+    fn try_next_back(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        while let Some((key, value)) = self.scan.next_back().transpose()? {
+            // Only return the last version of the key, i.e. the first one seen in reverse.
+            if self.next_back_seen.as_ref() != Some(&key) {
+                self.next_back_seen = Some(key.clone());
+                if let Some(value) = deserialize::<Option<Vec<u8>>>(&value)? {
+                    return Ok(Some((key, value)));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for Scan {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
+
+impl DoubleEndedIterator for Scan {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.try_next_back().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Memory;
+    use super::*;
+
+    fn setup() -> MVCC {
+        MVCC::new(Box::new(Memory::new()))
+    }
+
+    #[test]
+    fn begin() -> Result<()> {
+        let mvcc = setup();
+        let txn = mvcc.begin()?;
+        assert_eq!(1, txn.id());
+        assert_eq!(Mode::ReadWrite, txn.mode());
+        txn.commit()?;
+
+        let txn = mvcc.begin_with_mode(Mode::ReadOnly)?;
+        assert_eq!(2, txn.id());
+        assert_eq!(Mode::ReadOnly, txn.mode());
+        Ok(())
+    }
+
+    #[test]
+    fn resume() -> Result<()> {
+        let mvcc = setup();
+        let mut t1 = mvcc.begin()?;
+        t1.set(b"a", vec![0x01])?;
+
+        let t2 = mvcc.resume(t1.id())?;
+        assert_eq!(Some(vec![0x01]), t2.get(b"a")?);
+        t2.commit()?;
+
+        assert_eq!(Err(Error::Value("No active transaction 1".into())), mvcc.resume(1).map(|_| ()));
+        Ok(())
+    }
+
+    #[test]
+    fn isolation() -> Result<()> {
+        let mvcc = setup();
+        let mut t1 = mvcc.begin()?;
+        t1.set(b"a", vec![0x01])?;
+
+        // An uncommitted write is invisible to a concurrent transaction, and conflicts with its
+        // writes.
+        let mut t2 = mvcc.begin()?;
+        assert_eq!(None, t2.get(b"a")?);
+        assert_eq!(Err(Error::Serialization), t2.set(b"a", vec![0x02]));
+        t1.commit()?;
+
+        // It remains invisible to the concurrent transaction after commit, but is visible to
+        // transactions started after the commit.
+        assert_eq!(None, t2.get(b"a")?);
+        let t3 = mvcc.begin()?;
+        assert_eq!(Some(vec![0x01]), t3.get(b"a")?);
+        Ok(())
+    }
+
+    #[test]
+    fn reader() -> Result<()> {
+        let mvcc = setup();
+        let mut t1 = mvcc.begin()?;
+        t1.set(b"a", vec![0x01])?;
+        let mut t2 = mvcc.begin()?;
+        t2.set(b"b", vec![0x02])?;
+
+        // A reader sees its transaction's writes, also ones made after it was created, but not
+        // those of concurrent transactions.
+        let reader = t1.reader();
+        assert_eq!(Some(vec![0x01]), reader.get(b"a")?);
+        t1.set(b"a", vec![0x03])?;
+        assert_eq!(Some(vec![0x03]), reader.get(b"a")?);
+        t2.commit()?;
+        assert_eq!(None, reader.get(b"b")?);
+        t1.delete(b"a")?;
+        assert_eq!(None, reader.get(b"a")?);
+        Ok(())
+    }
+
+    #[test]
+    fn rollback() -> Result<()> {
+        let mvcc = setup();
+        let mut t1 = mvcc.begin()?;
+        t1.set(b"a", vec![0x01])?;
+        t1.commit()?;
+
+        let mut t2 = mvcc.begin()?;
+        t2.set(b"a", vec![0x02])?;
+        t2.delete(b"a")?;
+        t2.set(b"b", vec![0x02])?;
+        t2.rollback()?;
+
+        let mut t3 = mvcc.begin()?;
+        assert_eq!(Some(vec![0x01]), t3.get(b"a")?);
+        assert_eq!(None, t3.get(b"b")?);
+        // The rolled back writes no longer conflict.
+        t3.set(b"b", vec![0x03])?;
+        Ok(())
+    }
+
+    #[test]
+    fn read_only() -> Result<()> {
+        let mvcc = setup();
+        let mut txn = mvcc.begin_with_mode(Mode::ReadOnly)?;
+        assert_eq!(Err(Error::ReadOnly), txn.set(b"a", vec![0x01]));
+        assert_eq!(Err(Error::ReadOnly), txn.delete(b"a"));
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot() -> Result<()> {
+        let mvcc = setup();
+        let mut t1 = mvcc.begin()?;
+        t1.set(b"a", vec![0x01])?;
+        t1.commit()?;
+
+        let mut t2 = mvcc.begin()?;
+        t2.set(b"a", vec![0x02])?;
+        t2.commit()?;
+
+        let t3 = mvcc.begin_with_mode(Mode::Snapshot { version: 1 })?;
+        assert_eq!(Some(vec![0x01]), t3.get(b"a")?);
+        let t4 = mvcc.begin_with_mode(Mode::Snapshot { version: 2 })?;
+        assert_eq!(Some(vec![0x02]), t4.get(b"a")?);
+        Ok(())
+    }
+
+    #[test]
+    fn scan() -> Result<()> {
+        let mvcc = setup();
+        let mut t1 = mvcc.begin()?;
+        t1.set(b"a", vec![0x01])?;
+        t1.set(b"b", vec![0x02])?;
+        t1.set(b"ba", vec![0x03])?;
+        t1.set(b"c", vec![0x04])?;
+        t1.commit()?;
+
+        let mut t2 = mvcc.begin()?;
+        t2.set(b"b", vec![0x05])?;
+        t2.delete(b"c")?;
+
+        assert_eq!(
+            vec![
+                (b"a".to_vec(), vec![0x01]),
+                (b"b".to_vec(), vec![0x05]),
+                (b"ba".to_vec(), vec![0x03]),
+            ],
+            t2.scan(..)?.collect::<Result<Vec<_>>>()?
+        );
+        assert_eq!(
+            vec![
+                (b"ba".to_vec(), vec![0x03]),
+                (b"b".to_vec(), vec![0x05]),
+                (b"a".to_vec(), vec![0x01]),
+            ],
+            t2.scan(..)?.rev().collect::<Result<Vec<_>>>()?
+        );
+        assert_eq!(
+            vec![(b"b".to_vec(), vec![0x05]), (b"ba".to_vec(), vec![0x03])],
+            t2.scan_prefix(b"b")?.collect::<Result<Vec<_>>>()?
+        );
+
+        // A concurrent transaction sees the committed state.
+        let t3 = mvcc.begin()?;
+        assert_eq!(
+            vec![
+                (b"a".to_vec(), vec![0x01]),
+                (b"b".to_vec(), vec![0x02]),
+                (b"ba".to_vec(), vec![0x03]),
+                (b"c".to_vec(), vec![0x04]),
+            ],
+            t3.scan(..)?.collect::<Result<Vec<_>>>()?
+        );
+        Ok(())
+    }
+}